@@ -0,0 +1,748 @@
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::config::AiConfig;
+use super::jobs::JobManager;
+use super::memory::MemoryStore;
+use super::permission::PermissionStore;
+use super::session::SessionStore;
+use super::skill::registry::SkillRegistry;
+use super::confirm::{ConfirmRequest, CONFIRM_TIMEOUT};
+use super::streaming::{AiStreamEvent, ChatMessage};
+use super::tool::registry::ToolRegistry;
+use super::ConfirmState;
+
+/// Receives everything an agent run reports. The app forwards it to the
+/// webview; the headless CLI prints it.
+#[async_trait]
+pub trait AgentSink: Send + Sync {
+    /// Stream event: delta, thinking, tool_call, tool_result, done, error, ...
+    fn emit(&self, event: AiStreamEvent);
+
+    /// The active skill changed after detection
+    fn skill_changed(&self, _session_id: &str, _skill_id: &str, _skill_name: &str) {}
+
+    /// Ask whether a tool action that needs approval (a shell command, a
+    /// file write, ...) may run.
+    /// Ok(false) means denied; Err explains why no answer was obtained.
+    async fn confirm_command(&self, request: &ConfirmRequest) -> Result<bool, String>;
+
+    /// Ask the host to show a file to the user
+    fn open_file(&self, _path: &str) {}
+}
+
+/// Sink for the desktop app: events go to the webview, confirmations use
+/// the shell-confirm dialog, one per request id.
+pub struct TauriSink {
+    app: AppHandle,
+    /// The run's cancel flag; an open confirmation is dropped when it is set
+    cancel_flag: Arc<AtomicBool>,
+}
+
+impl TauriSink {
+    pub fn new(app: AppHandle, cancel_flag: Arc<AtomicBool>) -> Self {
+        Self { app, cancel_flag }
+    }
+}
+
+#[async_trait]
+impl AgentSink for TauriSink {
+    fn emit(&self, event: AiStreamEvent) {
+        let _ = self.app.emit("ai-stream", event);
+    }
+
+    fn skill_changed(&self, session_id: &str, skill_id: &str, skill_name: &str) {
+        let _ = self.app.emit("skill-changed", serde_json::json!({
+            "session_id": session_id,
+            "skill_id": skill_id,
+            "skill_name": skill_name,
+        }));
+    }
+
+    async fn confirm_command(&self, request: &ConfirmRequest) -> Result<bool, String> {
+        let state = self.app.state::<ConfirmState>();
+        let rx = state.registry.register(request.clone());
+        let _ = self.app.emit("shell-confirm", request);
+
+        let answer = state.registry.wait(&request.request_id, rx, CONFIRM_TIMEOUT, &self.cancel_flag).await;
+        if answer.is_err() {
+            // Let the dialog drop the request it may still show
+            let _ = self.app.emit("shell-confirm-expired", serde_json::json!({
+                "request_id": request.request_id,
+            }));
+        }
+        answer
+    }
+
+    fn open_file(&self, path: &str) {
+        let _ = self.app.emit("open-file-request", serde_json::json!({ "path": path }));
+    }
+}
+
+/// Shared services an agent run works with
+pub struct AgentEnv<'a> {
+    pub tools: Arc<ToolRegistry>,
+    pub skills: &'a SkillRegistry,
+    pub memory_store: Arc<dyn MemoryStore>,
+    pub permissions: Arc<PermissionStore>,
+    /// Background jobs started with start_job
+    pub jobs: Arc<JobManager>,
+    pub sessions: &'a SessionStore,
+    /// App data directory (`<APP_DATA>/inkess`) for the usage ledger,
+    /// transcripts and the decay cache
+    pub data_dir: PathBuf,
+    /// Whether a full-text search index is loaded for the workspace
+    pub has_search_index: bool,
+    /// Set when running inside the app; tools needing app state degrade without it
+    pub app_handle: Option<AppHandle>,
+    pub sink: Arc<dyn AgentSink>,
+}
+
+/// One user turn to run through the agent loop
+pub struct AgentRequest {
+    pub session_id: String,
+    pub messages: Vec<ChatMessage>,
+    pub config: AiConfig,
+    pub deep_mode: bool,
+    pub cwd: Option<String>,
+    pub current_skill_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use serde_json::Value;
+
+    use crate::ai::memory::FileMemoryStore;
+    use crate::ai::mock_server::*;
+    use crate::ai::skill::{Skill, SkillState};
+    use crate::ai::todo::{TodoItem, TodoStatus};
+    use crate::ai::tool::registry::ToolFilter;
+    use crate::ai::tool::{ToolContext, ToolError, ToolOutput, ToolPlugin};
+
+    /// Records every event; confirmations are always denied
+    #[derive(Default)]
+    struct RecordingSink {
+        events: Mutex<Vec<AiStreamEvent>>,
+    }
+
+    impl RecordingSink {
+        fn types(&self) -> Vec<String> {
+            self.events.lock().unwrap().iter().map(|e| e.event_type.clone()).collect()
+        }
+
+        fn contents(&self, event_type: &str) -> Vec<String> {
+            self.events.lock().unwrap().iter()
+                .filter(|e| e.event_type == event_type)
+                .map(|e| e.content.clone())
+                .collect()
+        }
+
+        fn last(&self) -> AiStreamEvent {
+            self.events.lock().unwrap().last().cloned().expect("no events")
+        }
+    }
+
+    #[async_trait]
+    impl AgentSink for RecordingSink {
+        fn emit(&self, event: AiStreamEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        async fn confirm_command(&self, _request: &ConfirmRequest) -> Result<bool, String> {
+            Ok(false)
+        }
+    }
+
+    type CallLog = Arc<Mutex<Vec<(String, Value)>>>;
+
+    /// Tool that records its calls and returns `output_len` bytes (or a short ack)
+    struct ScriptedTool {
+        name: &'static str,
+        calls: CallLog,
+        output_len: usize,
+        safe: bool,
+        cancel: Option<Arc<AtomicBool>>,
+    }
+
+    #[async_trait]
+    impl ToolPlugin for ScriptedTool {
+        fn name(&self) -> &str { self.name }
+        fn description(&self) -> &str { "scripted test tool" }
+        fn input_schema(&self) -> Value { serde_json::json!({ "type": "object", "properties": {} }) }
+        fn is_concurrency_safe(&self) -> bool { self.safe }
+        async fn execute(&self, _ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+            self.calls.lock().unwrap().push((self.name.to_string(), input));
+            if let Some(flag) = &self.cancel {
+                flag.store(true, Ordering::Relaxed);
+            }
+            if self.output_len > 0 {
+                Ok(ToolOutput::success("x".repeat(self.output_len)))
+            } else {
+                Ok(ToolOutput::success(format!("{} ok", self.name)))
+            }
+        }
+    }
+
+    fn text_message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.into(), content: Some(content.into()), tool_calls: None, tool_call_id: None, reasoning: None, thinking_blocks: None, images: None }
+    }
+
+    struct TestSkill {
+        max_rounds: usize,
+        /// Tools the skill hides
+        hidden: Vec<&'static str>,
+    }
+
+    impl Skill for TestSkill {
+        fn id(&self) -> &str { "test" }
+        fn display_name(&self) -> &str { "Test" }
+        fn description(&self) -> &str { "test skill" }
+        fn should_activate(&self, _message: &str, _has_files: bool, _current: &str) -> bool { false }
+        fn system_prompt(&self, _state: &SkillState) -> String { "You are a test agent.".into() }
+        fn tool_filter(&self, _state: &SkillState) -> ToolFilter {
+            if self.hidden.is_empty() {
+                ToolFilter::All
+            } else {
+                ToolFilter::Exclude(self.hidden.iter().map(|n| n.to_string()).collect())
+            }
+        }
+        fn max_iterations(&self, _state: &SkillState) -> usize { self.max_rounds }
+    }
+
+    struct Fixture {
+        server: MockServer,
+        tools: Arc<ToolRegistry>,
+        skills: SkillRegistry,
+        sessions: SessionStore,
+        memory_store: Arc<dyn MemoryStore>,
+        permissions: Arc<PermissionStore>,
+        sink: Arc<RecordingSink>,
+        calls: CallLog,
+        cancel_flag: Arc<AtomicBool>,
+        data_dir: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        async fn new(responses: Vec<MockResponse>, max_rounds: usize) -> Self {
+            // Everything the run writes (sessions, ledger, transcripts) stays in here
+            let dir = tempfile::tempdir().unwrap();
+            let calls: CallLog = Arc::new(Mutex::new(Vec::new()));
+            let cancel_flag = Arc::new(AtomicBool::new(false));
+            let tools = ToolRegistry::new();
+            for (name, output_len, safe, cancels) in [
+                ("lookup", 0, true, false),
+                ("write_note", 0, false, false),
+                ("dump", 40 * 1024, true, false),
+                ("stop", 0, false, true),
+            ] {
+                tools.register(Arc::new(ScriptedTool {
+                    name,
+                    calls: calls.clone(),
+                    output_len,
+                    safe,
+                    cancel: cancels.then(|| cancel_flag.clone()),
+                })).await;
+            }
+            let skills = SkillRegistry::new("test");
+            skills.register(Arc::new(TestSkill { max_rounds, hidden: Vec::new() })).await;
+            Self {
+                server: MockServer::start(responses).await,
+                tools: Arc::new(tools),
+                skills,
+                sessions: SessionStore::new(dir.path().join("sessions")).unwrap(),
+                memory_store: Arc::new(FileMemoryStore::new(dir.path().join("memories")).unwrap()),
+                permissions: Arc::new(PermissionStore::new(dir.path().join("permissions.json")).unwrap()),
+                sink: Arc::new(RecordingSink::default()),
+                calls,
+                cancel_flag,
+                data_dir: dir,
+            }
+        }
+
+        async fn run(&self, prompt: &str) -> Result<(), String> {
+            self.run_with_config(prompt, serde_json::json!({})).await
+        }
+
+        /// Run with extra AI config fields merged over the defaults
+        async fn run_with_config(&self, prompt: &str, extra: Value) -> Result<(), String> {
+            self.run_history(vec![text_message("user", prompt)], extra).await
+        }
+
+        /// Run on a whole history, after the system prompt
+        async fn run_history(&self, history: Vec<ChatMessage>, extra: Value) -> Result<(), String> {
+            let env = AgentEnv {
+                tools: self.tools.clone(),
+                skills: &self.skills,
+                memory_store: self.memory_store.clone(),
+                permissions: self.permissions.clone(),
+                jobs: Default::default(),
+                sessions: &self.sessions,
+                data_dir: self.data_dir.path().to_path_buf(),
+                has_search_index: false,
+                app_handle: None,
+                sink: self.sink.clone(),
+            };
+            let mut config = serde_json::json!({
+                "api_url": self.server.url,
+                "api_key": "test",
+                "model": "mock-model",
+                "temperature": 0.0,
+                "max_tokens": 256,
+                "max_retries": 1,
+                "retry_base_delay_ms": 1,
+            });
+            for (key, value) in extra.as_object().cloned().unwrap_or_default() {
+                config[key] = value;
+            }
+            let config: AiConfig = serde_json::from_value(config).unwrap();
+            let request = AgentRequest {
+                session_id: "e2e".into(),
+                messages: std::iter::once(text_message("system", "sys")).chain(history).collect(),
+                config,
+                deep_mode: false,
+                cwd: None,
+                current_skill_id: Some("test".into()),
+            };
+            crate::ai::run_agent(&env, request, self.cancel_flag.clone()).await
+        }
+
+        fn tool_calls(&self) -> Vec<(String, Value)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_answer_with_malformed_lines_and_split_chunks() {
+        let body = format!(
+            ": keep-alive\n\ndata: {}\n\nevent: ping\ndata: {{not json\n\ndata: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            text_chunk("Hel"), text_chunk("lo"), finish_chunk("stop"),
+        );
+        let fx = Fixture::new(vec![MockResponse::raw_sse(body).chunked(7)], 5).await;
+        fx.run("hi").await.unwrap();
+
+        assert_eq!(fx.sink.contents("delta").concat(), "Hello");
+        let done = fx.sink.last();
+        assert_eq!(done.event_type, "done");
+        assert_eq!(done.content, "Hello");
+        assert!(fx.tool_calls().is_empty());
+
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["model"], "mock-model");
+        assert_eq!(requests[0]["stream"], true);
+        assert!(requests[0]["messages"][0]["content"].as_str().unwrap().starts_with("You are a test agent."));
+
+        let session = fx.sessions.load("e2e").unwrap();
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.messages[1].content.as_deref(), Some("Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_tool_calls_execute_in_order() {
+        let round1 = MockResponse::sse(&[
+            text_chunk("Checking."),
+            tool_call_chunk(0, Some("c1"), Some("lookup"), "{\"ke"),
+            tool_call_chunk(0, None, None, "y\":\"a\"}"),
+            tool_call_chunk(1, Some("c2"), Some("write_"), ""),
+            tool_call_chunk(1, None, Some("note"), "{\"text\":"),
+            tool_call_chunk(1, None, None, "\"b\"}"),
+            finish_chunk("tool_calls"),
+        ]).chunked(16);
+        let fx = Fixture::new(vec![round1, text_response("All done")], 5).await;
+        fx.run("take notes").await.unwrap();
+
+        assert_eq!(fx.tool_calls(), vec![
+            ("lookup".to_string(), serde_json::json!({ "key": "a" })),
+            ("write_note".to_string(), serde_json::json!({ "text": "b" })),
+        ]);
+        // lookup is concurrency-safe, write_note is not: two batches, each call reported before its result
+        let tool_events: Vec<String> = fx.sink.types().into_iter().filter(|t| t.starts_with("tool_")).collect();
+        assert_eq!(tool_events, vec!["tool_call", "tool_result", "tool_call", "tool_result"]);
+
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 2);
+        let messages = requests[1]["messages"].as_array().unwrap();
+        let roles: Vec<&str> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant", "tool", "tool"]);
+        assert_eq!(messages[2]["content"], "Checking.");
+        assert_eq!(messages[2]["tool_calls"][1]["function"]["name"], "write_note");
+        assert_eq!(messages[3]["tool_call_id"], "c1");
+        assert_eq!(messages[3]["content"], "lookup ok");
+        assert_eq!(messages[4]["tool_call_id"], "c2");
+
+        assert_eq!(fx.sink.last().content, "All done");
+        let stored: Vec<String> = fx.sessions.load("e2e").unwrap().messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(stored, vec!["user", "assistant", "tool", "tool", "assistant"]);
+    }
+
+    #[tokio::test]
+    async fn test_large_tool_result_is_moved_to_decay_cache() {
+        let fx = Fixture::new(vec![tool_call_response("c1", "dump", "{}"), text_response("ok")], 5).await;
+        fx.run("dump it").await.unwrap();
+
+        let requests = fx.server.requests();
+        let tool_msg = requests[1]["messages"].as_array().unwrap().last().unwrap()["content"]
+            .as_str().unwrap().to_string();
+        assert!(tool_msg.len() < 40 * 1024);
+        let marker = "full content saved to: ";
+        let start = tool_msg.find(marker).expect("decay reference") + marker.len();
+        let path = tool_msg[start..].split(']').next().unwrap();
+        assert!(std::path::Path::new(path).starts_with(fx.data_dir.path().join("decay-cache")));
+        let saved = std::fs::read_to_string(path).expect("decay file");
+        assert_eq!(saved.len(), 40 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_max_rounds_notice() {
+        let fx = Fixture::new(vec![
+            tool_call_response("c1", "lookup", "{}"),
+            tool_call_response("c2", "lookup", "{}"),
+            text_response("never requested"),
+        ], 2).await;
+        fx.run("loop").await.unwrap();
+
+        assert_eq!(fx.server.requests().len(), 2);
+        assert_eq!(fx.tool_calls().len(), 2);
+        let notice = fx.sink.contents("delta").concat();
+        assert!(notice.contains("Reached the maximum of 2 tool call rounds"));
+        assert_eq!(fx.sink.last().event_type, "done");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_during_tool_round_stops_the_loop() {
+        let fx = Fixture::new(vec![tool_call_response("c1", "stop", "{}"), text_response("never requested")], 5).await;
+        fx.run("stop soon").await.unwrap();
+
+        assert_eq!(fx.server.requests().len(), 1);
+        assert_eq!(fx.tool_calls().len(), 1);
+        let done = fx.sink.last();
+        assert_eq!(done.event_type, "done");
+        assert!(done.content.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_server_error() {
+        let fx = Fixture::new(vec![
+            MockResponse::error(503, r#"{"error":"overloaded"}"#).header("Retry-After", "0"),
+            text_response("recovered"),
+        ], 5).await;
+        fx.run("hi").await.unwrap();
+
+        assert_eq!(fx.server.requests().len(), 2);
+        assert_eq!(fx.sink.contents("retrying").len(), 1);
+        assert_eq!(fx.sink.last().content, "recovered");
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_overload_inside_the_stream() {
+        let text = |t: &str| serde_json::json!({
+            "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": t },
+        }).to_string();
+        let overloaded = format!(
+            "data: {}\n\ndata: {}\n\n",
+            text("Part"),
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        );
+        let answer = format!(
+            "data: {}\n\ndata: {}\n\n",
+            text("recovered"),
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"}}"#,
+        );
+        let fx = Fixture::new(vec![MockResponse::raw_sse(overloaded), MockResponse::raw_sse(answer)], 5).await;
+        fx.run_with_config("hi", serde_json::json!({ "provider": "anthropic" })).await.unwrap();
+
+        assert_eq!(fx.server.requests().len(), 2);
+        assert_eq!(fx.sink.contents("retrying").len(), 1);
+        assert!(fx.sink.contents("model_fallback").is_empty());
+        assert_eq!(fx.sink.last().content, "recovered");
+    }
+
+    #[tokio::test]
+    async fn test_client_error_is_reported_without_retry() {
+        let fx = Fixture::new(vec![MockResponse::error(400, r#"{"error":"bad request"}"#)], 5).await;
+        let err = fx.run("hi").await.unwrap_err();
+
+        assert!(err.contains("400"));
+        assert_eq!(fx.server.requests().len(), 1);
+        assert_eq!(fx.sink.last().event_type, "error");
+    }
+
+    #[tokio::test]
+    async fn test_skill_model_settings_apply_to_requests() {
+        let fx = Fixture::new(vec![text_response("ok")], 5).await;
+        fx.run_with_config("hi", serde_json::json!({
+            "skill_models": { "test": { "model": "skill-model", "max_tokens": 1024 } },
+        })).await.unwrap();
+
+        let requests = fx.server.requests();
+        assert_eq!(requests[0]["model"], "skill-model");
+        assert_eq!(requests[0]["max_tokens"], 1024);
+        assert_eq!(requests[0]["temperature"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_model_after_unknown_model() {
+        let fx = Fixture::new(vec![
+            MockResponse::error(404, r#"{"error":"model not found"}"#),
+            text_response("from backup"),
+        ], 5).await;
+        fx.run_with_config("hi", serde_json::json!({
+            "fallback_models": ["mock-model", "backup-model"],
+        })).await.unwrap();
+
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["model"], "backup-model");
+        let fallback: Value = serde_json::from_str(&fx.sink.contents("model_fallback")[0]).unwrap();
+        assert_eq!(fallback["from"], "mock-model");
+        assert_eq!(fallback["to"], "backup-model");
+        assert_eq!(fx.sink.last().content, "from backup");
+    }
+
+    #[tokio::test]
+    async fn test_request_error_skips_fallback() {
+        let fx = Fixture::new(vec![MockResponse::error(400, r#"{"error":"context too long"}"#)], 5).await;
+        let err = fx.run_with_config("hi", serde_json::json!({ "fallback_models": ["backup-model"] })).await.unwrap_err();
+
+        assert!(err.contains("400"));
+        assert_eq!(fx.server.requests().len(), 1);
+        assert!(fx.sink.contents("model_fallback").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_auth_error_skips_fallback() {
+        let fx = Fixture::new(vec![MockResponse::error(401, r#"{"error":"bad key"}"#)], 5).await;
+        let err = fx.run_with_config("hi", serde_json::json!({ "fallback_models": ["backup-model"] })).await.unwrap_err();
+
+        assert!(err.contains("401"));
+        assert_eq!(fx.server.requests().len(), 1);
+        assert!(fx.sink.contents("model_fallback").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_arguments_are_reported_for_repair() {
+        let fx = Fixture::new(vec![
+            tool_call_response("c1", "lookup", "{\"key\": \"a"),
+            tool_call_response("c2", "lookup", "{\"key\": \"a\"}"),
+            text_response("fixed"),
+        ], 5).await;
+        fx.run("look it up").await.unwrap();
+
+        // Only the repaired call reaches the tool
+        assert_eq!(fx.tool_calls().len(), 1);
+        let requests = fx.server.requests();
+        let rejected = requests[1]["messages"].as_array().unwrap().last().unwrap();
+        assert_eq!(rejected["tool_call_id"], "c1");
+        let text = rejected["content"].as_str().unwrap();
+        assert!(text.starts_with("Tool error: Invalid arguments"));
+        assert!(text.contains("truncated"));
+        assert_eq!(fx.tools.validation_failures().get("lookup"), Some(&1));
+        assert_eq!(fx.sink.last().content, "fixed");
+    }
+
+    #[tokio::test]
+    async fn test_memories_go_after_the_stable_prefix() {
+        let fx = Fixture::new(vec![text_response("ok")], 5).await;
+        fx.memory_store.save(crate::ai::memory::Memory {
+            id: "m1".into(),
+            content: "The user prefers tabs".into(),
+            memory_type: crate::ai::memory::MemoryType::Core,
+            importance: 0.9,
+            metadata: crate::ai::memory::MemoryMetadata { tags: Vec::new(), source: "test".into(), workspace_path: None },
+            created_at: 0,
+            accessed_at: 0,
+            access_count: 0,
+        }).await.unwrap();
+        fx.run("hi").await.unwrap();
+
+        let requests = fx.server.requests();
+        let messages = requests[0]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(!messages[0]["content"].as_str().unwrap().contains("prefers tabs"));
+        assert_eq!(messages[1]["role"], "system");
+        assert!(messages[1]["content"].as_str().unwrap().contains("The user prefers tabs"));
+        assert_eq!(messages[2]["content"], "hi");
+    }
+
+    #[tokio::test]
+    async fn test_compaction_summary_is_reused_by_the_next_run() {
+        let mut history: Vec<ChatMessage> = (0..12)
+            .map(|i| text_message(if i % 2 == 0 { "user" } else { "assistant" }, &format!("turn {} {}", i, "x".repeat(400))))
+            .collect();
+        let fx = Fixture::new(vec![
+            completion_response("- the user asked about x"),
+            text_response("first"),
+            text_response("second"),
+        ], 5).await;
+        let config = serde_json::json!({ "context_window": 2_000 });
+        fx.run_history(history.clone(), config.clone()).await.unwrap();
+
+        let stored = fx.sessions.load("e2e").unwrap().compaction.expect("summary stored");
+        assert_eq!(stored.messages, 4);
+        assert_eq!(stored.summary, "- the user asked about x");
+
+        // The next turn re-sends the whole history; the summary stands in for it again
+        history.push(text_message("assistant", "first"));
+        history.push(text_message("user", "and then?"));
+        fx.run_history(history, config).await.unwrap();
+
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["stream"], false);
+        assert_eq!(requests[2]["stream"], true);
+        assert_eq!(requests[1]["messages"][1], requests[2]["messages"][1]);
+        let summary = requests[2]["messages"][1]["content"].as_str().unwrap();
+        assert!(summary.starts_with(crate::ai::compact::SUMMARY_PREFIX));
+        assert!(summary.ends_with("- the user asked about x"));
+        assert_eq!(fx.sink.last().content, "second");
+
+        // The summary request is in the usage ledger, marked as compaction
+        let ledger = std::fs::read_to_string(fx.data_dir.path().join("usage-ledger.jsonl")).unwrap();
+        let compaction: Vec<crate::ai::usage::UsageRecord> = ledger.lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .filter(|r: &crate::ai::usage::UsageRecord| r.kind.as_deref() == Some("compaction"))
+            .collect();
+        assert_eq!(compaction.len(), 1);
+        assert_eq!((compaction[0].session_id.as_str(), compaction[0].prompt_tokens), ("e2e", 900));
+    }
+
+    #[tokio::test]
+    async fn test_todo_updates_are_streamed_and_stored() {
+        let plan = r#"{"todos":[{"id":"1","text":"Look it up","status":"done"},{"id":"2","text":"Answer","status":"in_progress"}]}"#;
+        let fx = Fixture::new(vec![tool_call_response("c1", "todo_write", plan), text_response("done")], 5).await;
+        fx.tools.register(Arc::new(crate::ai::tools::todo_write::TodoWriteTool)).await;
+        fx.run("plan it").await.unwrap();
+
+        let updates = fx.sink.contents("todos");
+        assert_eq!(updates.len(), 1);
+        let items: Vec<TodoItem> = serde_json::from_str(&updates[0]).unwrap();
+        assert_eq!(items[1].status, TodoStatus::InProgress);
+
+        let requests = fx.server.requests();
+        let tool_result = &requests[1]["messages"].as_array().unwrap().last().unwrap()["content"];
+        assert!(tool_result.as_str().unwrap().starts_with("Task list (1/2 done):"));
+        assert_eq!(fx.sessions.load("e2e").unwrap().todos, items);
+    }
+
+    #[tokio::test]
+    async fn test_permission_rules_are_consulted_by_file_tools() {
+        use crate::ai::permission::{Decision, PermissionRule, RuleScope};
+        let fx = Fixture::new(vec![
+            tool_call_response("c1", "write_file", r#"{"path":"secrets/key.txt","content":"x"}"#),
+            text_response("ok"),
+        ], 5).await;
+        fx.tools.register(Arc::new(crate::ai::tools::write_file::WriteFileTool)).await;
+        fx.permissions.add(RuleScope::Global, None, PermissionRule {
+            id: String::new(),
+            tool: "write_file".into(),
+            command: None,
+            path: Some("secrets/*".into()),
+            decision: Decision::Deny,
+            created_at: 0,
+        }).unwrap();
+        fx.run("save the key").await.unwrap();
+
+        let results = fx.sink.contents("tool_result");
+        let result: Value = serde_json::from_str(&results[0]).unwrap();
+        assert_eq!(result["result"], "Denied by a permission rule: write_file secrets/key.txt");
+    }
+
+    #[tokio::test]
+    async fn test_delegated_task_returns_only_the_summary() {
+        let delegate = r#"{"task":"Find the answer","tools":["lookup"],"max_rounds":3}"#;
+        let sub_calls = MockResponse::sse(&[
+            tool_call_chunk(0, Some("s1"), Some("lookup"), "{}"),
+            tool_call_chunk(1, Some("s2"), Some("write_note"), "{}"),
+            finish_chunk("tool_calls"),
+            usage_chunk(50, 5),
+        ]);
+        let fx = Fixture::new(vec![
+            MockResponse::sse(&[
+                tool_call_chunk(0, Some("d1"), Some("delegate_task"), delegate),
+                finish_chunk("tool_calls"),
+                usage_chunk(100, 10),
+            ]),
+            sub_calls,
+            text_response("Summary: 42"),
+            text_response("The answer is 42"),
+        ], 5).await;
+        fx.tools.register(Arc::new(crate::ai::tools::delegate_task::DelegateTaskTool)).await;
+        fx.run("what is the answer?").await.unwrap();
+
+        // write_note was not granted, so it is unknown to the sub-agent
+        let calls: Vec<String> = fx.tool_calls().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(calls, vec!["lookup"]);
+
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 4);
+        let sub_messages = requests[1]["messages"].as_array().unwrap();
+        assert_eq!(sub_messages.len(), 2);
+        assert_eq!(sub_messages[1]["content"], "Find the answer");
+        assert_eq!(requests[1]["tools"].as_array().unwrap().len(), 1);
+        let sub_results = requests[2]["messages"].as_array().unwrap();
+        assert!(sub_results.last().unwrap()["content"].as_str().unwrap().contains("Unknown tool: write_note"));
+
+        // The parent only sees the summary
+        let parent_messages = requests[3]["messages"].as_array().unwrap();
+        assert_eq!(parent_messages.len(), 4);
+        assert_eq!(parent_messages[3]["content"], "Summary: 42");
+        assert_eq!(fx.sink.contents("delta").concat(), "The answer is 42");
+        assert_eq!(fx.sink.contents("tool_call").len(), 1);
+
+        // Sub-agent progress is streamed nested under the delegating call
+        let nested: Vec<Value> = fx.sink.contents("subagent").iter()
+            .map(|c| serde_json::from_str(c).unwrap())
+            .collect();
+        assert!(nested.iter().all(|e| e["call_id"] == "d1" && e["depth"] == 1));
+        let inner: Vec<&str> = nested.iter().filter_map(|e| e["event_type"].as_str()).collect();
+        assert_eq!(inner.iter().filter(|t| **t == "tool_call").count(), 2);
+        assert!(inner.contains(&"delta"));
+
+        // Both first rounds are in the ledger, told apart by the sub-agent tag
+        let ledger = std::fs::read_to_string(fx.data_dir.path().join("usage-ledger.jsonl")).unwrap();
+        let records: Vec<crate::ai::usage::UsageRecord> = ledger.lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let first_rounds: Vec<(Option<&str>, u64)> = records.iter()
+            .filter(|r| r.round == 0)
+            .map(|r| (r.subagent.as_deref(), r.prompt_tokens))
+            .collect();
+        assert_eq!(first_rounds, vec![(None, 100), (Some("d1"), 50)]);
+    }
+
+    #[tokio::test]
+    async fn test_delegation_cannot_reach_tools_the_skill_hides() {
+        let fx = Fixture::new(vec![
+            tool_call_response("d1", "delegate_task", r#"{"task":"Save a note","tools":["write_note"]}"#),
+            tool_call_response("d2", "delegate_task", r#"{"task":"Look around"}"#),
+            text_response("Done looking"),
+            text_response("Could not save the note"),
+        ], 5).await;
+        fx.tools.register(Arc::new(crate::ai::tools::delegate_task::DelegateTaskTool)).await;
+        fx.skills.register(Arc::new(TestSkill { max_rounds: 5, hidden: vec!["write_note", "dump"] })).await;
+        fx.run("save a note").await.unwrap();
+
+        // An explicit request for a hidden tool is rejected before the sub-agent starts
+        let results = fx.sink.contents("tool_result");
+        let first: Value = serde_json::from_str(&results[0]).unwrap();
+        assert!(first["result"].as_str().unwrap().contains("unavailable tools: write_note"));
+        assert!(fx.tool_calls().is_empty());
+
+        // The read-only default leaves out hidden tools too
+        let requests = fx.server.requests();
+        assert_eq!(requests.len(), 4);
+        let mut sub_tools: Vec<&str> = requests[2]["tools"].as_array().unwrap().iter()
+            .filter_map(|t| t["function"]["name"].as_str())
+            .collect();
+        sub_tools.sort();
+        assert_eq!(sub_tools, vec!["lookup"]);
+    }
+}
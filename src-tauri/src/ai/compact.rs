@@ -0,0 +1,362 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::config::AiConfig;
+use super::context::{self, ContextBudget};
+use super::memory::distill;
+use super::streaming::ChatMessage;
+use super::usage::UsageTag;
+
+/// Compact once the history uses this share of the available budget
+const COMPACT_TRIGGER_PERCENT: usize = 80;
+/// Most recent messages are always kept verbatim
+const KEEP_RECENT_MESSAGES: usize = 8;
+/// Don't bother summarising fewer messages than this
+const MIN_COMPACT_MESSAGES: usize = 4;
+/// Per-message cap when formatting the history for the summariser
+const MAX_MESSAGE_CHARS: usize = 2_000;
+const MAX_TRANSCRIPT_CHARS: usize = 60_000;
+const SUMMARY_MAX_TOKENS: u32 = 1_500;
+const SUMMARY_TIMEOUT_SECS: u64 = 60;
+
+pub const SUMMARY_PREFIX: &str = "[Conversation so far]";
+
+/// A compaction summary kept with the session, so later runs over the same
+/// history reuse it instead of summarising the same messages again
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StoredSummary {
+    /// Number of leading non-system history messages the summary replaces
+    pub messages: usize,
+    /// Chained hash of those messages, to notice a history that changed
+    pub fingerprint: String,
+    pub summary: String,
+    /// Where the original messages were saved
+    pub archive: String,
+}
+
+impl StoredSummary {
+    /// The summary for `range` of `conversation`. `earlier` is the stored
+    /// summary the range starts with, if it was reused.
+    pub fn new(conversation: &[ChatMessage], range: Range<usize>, earlier: Option<&StoredSummary>, summary: &str, archive: &str) -> Self {
+        let covered = &conversation[range];
+        Self {
+            messages: earlier.map_or(0, |e| e.messages) + covered.iter().filter(|m| m.role != "system").count(),
+            fingerprint: fingerprint(earlier.map_or("", |e| e.fingerprint.as_str()), covered),
+            summary: summary.to_string(),
+            archive: archive.to_string(),
+        }
+    }
+}
+
+/// Extend a fingerprint with the non-system messages of `messages`. System
+/// messages are skipped: they are rebuilt on every run.
+fn fingerprint(earlier: &str, messages: &[ChatMessage]) -> String {
+    let mut state = earlier.to_string();
+    for msg in messages.iter().filter(|m| m.role != "system") {
+        let mut hasher = Sha256::new();
+        hasher.update(state.as_bytes());
+        for part in [msg.role.as_str(), msg.content.as_deref().unwrap_or(""), msg.tool_call_id.as_deref().unwrap_or("")] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        for tc in msg.tool_calls.iter().flatten() {
+            for part in [tc.id.as_str(), tc.function.name.as_str(), tc.function.arguments.as_str()] {
+                hasher.update(part.as_bytes());
+                hasher.update([0]);
+            }
+        }
+        state = hex::encode(hasher.finalize());
+    }
+    state
+}
+
+/// Replace the history a stored summary covers with that summary. Returns
+/// false, leaving the conversation alone, if the history no longer starts
+/// with the summarised messages.
+pub fn reuse_summary(conversation: &mut Vec<ChatMessage>, stored: &StoredSummary) -> bool {
+    let start = match conversation.first() {
+        Some(m) if m.role == "system" => 1,
+        _ => 0,
+    };
+    let mut seen = 0;
+    let mut end = start;
+    while seen < stored.messages {
+        match conversation.get(end) {
+            Some(m) => {
+                if m.role != "system" {
+                    seen += 1;
+                }
+                end += 1;
+            }
+            None => return false,
+        }
+    }
+    if stored.messages == 0 || fingerprint("", &conversation[start..end]) != stored.fingerprint {
+        return false;
+    }
+    apply_summary(conversation, start..end, &stored.summary, &stored.archive);
+    true
+}
+
+/// Whether the conversation is close enough to the context limit to compact
+pub fn needs_compaction(conversation: &[ChatMessage], budget: &ContextBudget) -> bool {
+    context::estimate_conversation_tokens(conversation) * 100 > budget.available * COMPACT_TRIGGER_PERCENT
+}
+
+/// Range of older messages to summarise: everything after the system prompt
+/// except the recent tail. The split never separates tool results from the
+/// assistant message that requested them. Returns None if there is too little
+/// to gain.
+pub fn compaction_range(conversation: &[ChatMessage]) -> Option<Range<usize>> {
+    let start = match conversation.first() {
+        Some(m) if m.role == "system" => 1,
+        _ => 0,
+    };
+    let mut end = conversation.len().checked_sub(KEEP_RECENT_MESSAGES)?;
+    while end > start && conversation[end].role == "tool" {
+        end -= 1;
+    }
+    if end < start + MIN_COMPACT_MESSAGES {
+        return None;
+    }
+    // Skip compaction that would free less than a quarter of the history
+    let range_tokens = context::estimate_conversation_tokens(&conversation[start..end]);
+    if range_tokens * 4 < context::estimate_conversation_tokens(conversation) {
+        return None;
+    }
+    Some(start..end)
+}
+
+/// Render messages as plain text for the summariser. Unlike distill, tool
+/// results are kept (truncated) since they usually hold the findings.
+fn format_for_summary(messages: &[ChatMessage]) -> String {
+    let mut lines = Vec::new();
+    for msg in messages {
+        let content = msg.content.as_deref().unwrap_or("").trim();
+        match msg.role.as_str() {
+            "system" if content.starts_with(SUMMARY_PREFIX) => {
+                lines.push(format!("Earlier summary:\n{}", content[SUMMARY_PREFIX.len()..].trim()));
+            }
+            "system" if !content.is_empty() => lines.push(format!("[System] {}", truncate_chars(content))),
+            "user" => lines.push(format!("User: {}", truncate_chars(content))),
+            "assistant" => {
+                if !content.is_empty() {
+                    lines.push(format!("Assistant: {}", truncate_chars(content)));
+                }
+                for tc in msg.tool_calls.iter().flatten() {
+                    lines.push(format!("  [Tool call: {} {}]", tc.function.name, truncate_chars(&tc.function.arguments)));
+                }
+            }
+            "tool" if !content.is_empty() => lines.push(format!("  [Tool result] {}", truncate_chars(content))),
+            _ => {}
+        }
+    }
+    let text = lines.join("\n");
+    let excess = text.chars().count().saturating_sub(MAX_TRANSCRIPT_CHARS);
+    if excess == 0 {
+        return text;
+    }
+    // Keep the most recent part, plus the earlier summary at the top
+    let cut = text.char_indices().nth(excess).map(|(i, _)| i).unwrap_or(0);
+    let head = lines.first().filter(|l| l.starts_with("Earlier summary:")).cloned().unwrap_or_default();
+    format!("{}\n[...]\n{}", head, &text[cut..])
+}
+
+fn truncate_chars(s: &str) -> String {
+    if s.chars().count() > MAX_MESSAGE_CHARS {
+        let head: String = s.chars().take(MAX_MESSAGE_CHARS).collect();
+        format!("{}…", head)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Ask the model to summarise older messages
+pub async fn summarize(messages: &[ChatMessage], ai_config: &AiConfig, usage: &UsageTag) -> Result<String, String> {
+    let transcript = format_for_summary(messages);
+    if transcript.trim().is_empty() {
+        return Err("Nothing to summarize".to_string());
+    }
+    let prompt = format!(
+        r#"You are compacting the history of an ongoing agent session so work can continue with less context.
+Write a summary of the conversation below that the assistant will use instead of the original messages.
+
+Keep:
+- The user's requests and constraints, as close to verbatim as possible
+- Key findings, facts, numbers, file paths and URLs discovered so far
+- Decisions made and work already completed (files written, commands run)
+- Open questions and what remains to be done
+
+Be dense and factual. Use bullet points. Do not add commentary.
+
+Conversation:
+{}"#,
+        transcript
+    );
+    let summary = distill::complete(prompt, ai_config, 0.2, SUMMARY_MAX_TOKENS, SUMMARY_TIMEOUT_SECS, Some(usage))
+        .await
+        .map_err(|e| format!("Compaction {}", e))?;
+    if summary.trim().is_empty() {
+        return Err("Compaction returned an empty summary".to_string());
+    }
+    Ok(summary.trim().to_string())
+}
+
+/// Replace `range` with a single system summary message. `archive` points to
+/// the saved original messages.
+pub fn apply_summary(conversation: &mut Vec<ChatMessage>, range: Range<usize>, summary: &str, archive: &str) {
+    let start = range.start;
+    let count = range.len();
+    conversation.drain(range);
+    conversation.insert(start, ChatMessage {
+        role: "system".into(),
+        content: Some(format!(
+            "{}\n{} earlier messages were summarized to save context. Original messages saved to: {}\n\n{}",
+            SUMMARY_PREFIX, count, archive, summary
+        )),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::{FunctionCall, ToolCall};
+
+    fn make_msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: None,
+            tool_call_id: if role == "tool" { Some("tc1".into()) } else { None },
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    fn make_tool_call_msg() -> ChatMessage {
+        ChatMessage {
+            role: "assistant".into(),
+            content: None,
+            tool_calls: Some(vec![ToolCall {
+                id: "tc1".into(),
+                r#type: "function".into(),
+                function: FunctionCall { name: "web_search".into(), arguments: r#"{"query":"rust"}"#.into() },
+            }]),
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    fn long_conversation(turns: usize) -> Vec<ChatMessage> {
+        let mut conv = vec![make_msg("system", "sys"), make_msg("user", "research rust async")];
+        for i in 0..turns {
+            conv.push(make_tool_call_msg());
+            conv.push(make_msg("tool", &format!("finding {} {}", i, "x".repeat(400))));
+        }
+        conv
+    }
+
+    #[test]
+    fn needs_compaction_uses_trigger_share() {
+        let conv = long_conversation(4);
+        let tokens = context::estimate_conversation_tokens(&conv);
+        let loose = ContextBudget { limit: tokens * 2, available: tokens * 2 };
+        let tight = ContextBudget { limit: tokens, available: tokens };
+        assert!(!needs_compaction(&conv, &loose));
+        assert!(needs_compaction(&conv, &tight));
+    }
+
+    #[test]
+    fn compaction_range_keeps_recent_tail() {
+        let conv = long_conversation(10); // 22 messages
+        let range = compaction_range(&conv).unwrap();
+        assert_eq!(range.start, 1);
+        assert_eq!(range.end, 22 - KEEP_RECENT_MESSAGES);
+        assert_ne!(conv[range.end].role, "tool");
+    }
+
+    #[test]
+    fn compaction_range_does_not_split_tool_results() {
+        let mut conv = long_conversation(10);
+        conv.push(make_msg("assistant", "done")); // shifts the split onto a tool message
+        let range = compaction_range(&conv).unwrap();
+        assert_eq!(conv[range.end].role, "assistant");
+        assert!(conv[range.end].tool_calls.is_some());
+    }
+
+    #[test]
+    fn compaction_range_none_for_short_conversation() {
+        assert!(compaction_range(&long_conversation(2)).is_none());
+        assert!(compaction_range(&[]).is_none());
+    }
+
+    #[test]
+    fn format_keeps_tool_results_and_earlier_summary() {
+        let messages = vec![
+            make_msg("system", &format!("{}\nprevious facts", SUMMARY_PREFIX)),
+            make_msg("user", "question"),
+            make_tool_call_msg(),
+            make_msg("tool", &"r".repeat(5_000)),
+        ];
+        let text = format_for_summary(&messages);
+        assert!(text.starts_with("Earlier summary:\nprevious facts"));
+        assert!(text.contains("User: question"));
+        assert!(text.contains("[Tool call: web_search"));
+        assert!(text.contains("[Tool result] rrr"));
+        assert!(!text.contains(&"r".repeat(MAX_MESSAGE_CHARS + 1)));
+    }
+
+    #[test]
+    fn apply_summary_replaces_range() {
+        let mut conv = long_conversation(10);
+        let range = compaction_range(&conv).unwrap();
+        let removed = range.len();
+        let before = conv.len();
+        apply_summary(&mut conv, range, "- found things", "/tmp/t.json");
+        assert_eq!(conv.len(), before - removed + 1);
+        assert_eq!(conv[0].content.as_deref(), Some("sys"));
+        let summary = conv[1].content.as_deref().unwrap();
+        assert_eq!(conv[1].role, "system");
+        assert!(summary.starts_with(SUMMARY_PREFIX));
+        assert!(summary.contains("/tmp/t.json"));
+        assert!(summary.ends_with("- found things"));
+    }
+
+    #[test]
+    fn stored_summary_is_reused_only_for_the_same_history() {
+        let conv = long_conversation(10);
+        let range = compaction_range(&conv).unwrap();
+        let first = StoredSummary::new(&conv, range.clone(), None, "- first", "/tmp/a.json");
+        assert_eq!(first.messages, range.len());
+
+        let mut again = conv.clone();
+        assert!(reuse_summary(&mut again, &first));
+        assert_eq!(again.len(), conv.len() - range.len() + 1);
+
+        // A later compaction folds the earlier summary into the new one
+        again.extend(long_conversation(6).into_iter().skip(1));
+        let range = compaction_range(&again).unwrap();
+        let second = StoredSummary::new(&again, range.clone(), Some(&first), "- second", "/tmp/b.json");
+        assert_eq!(second.messages, first.messages + range.len() - 1);
+        let mut full = conv.clone();
+        full.extend(long_conversation(6).into_iter().skip(1));
+        assert!(reuse_summary(&mut full, &second));
+        assert!(full[1].content.as_deref().unwrap().ends_with("- second"));
+
+        let mut edited = conv.clone();
+        edited[1] = make_msg("user", "a different question");
+        assert!(!reuse_summary(&mut edited, &first));
+        assert_eq!(edited.len(), conv.len());
+        assert!(!reuse_summary(&mut long_conversation(1), &first));
+    }
+}
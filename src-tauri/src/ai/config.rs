@@ -3,8 +3,13 @@ use std::path::PathBuf;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use crate::app_warn;
+use super::vault::{self, Secrets, Vault, VaultStatus, SECRET_REF_PREFIX};
+
 // --- Data structures ---
 
+/// Key fields (`api_key`, `search_api_key`, `provider_keys`) hold `vault:` references
+/// once loaded or saved; `resolve_keys` swaps in the real keys right before use.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AiConfig {
     pub api_url: String,
@@ -22,8 +27,81 @@ pub struct AiConfig {
     pub search_provider: String,
     #[serde(default)]
     pub provider_keys: std::collections::HashMap<String, String>,
+    /// Chat backend kind: "openai" (default, any OpenAI-compatible API), "anthropic"
+    /// or "local" (Ollama / llama.cpp server)
+    #[serde(default)]
+    pub provider: String,
+    /// Tool calling for the local provider: "native", "prompt", or "" to auto-detect
+    #[serde(default)]
+    pub local_tool_mode: String,
+    /// Retries for rate limits, gateway errors and dropped streams (0 disables)
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Initial backoff delay; doubled on every retry
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+    /// Context window in tokens; 0 uses the built-in limit for the model
+    #[serde(default)]
+    pub context_window: u32,
+    /// Prices per model id (or id prefix) for the usage ledger
+    #[serde(default)]
+    pub model_prices: std::collections::HashMap<String, super::usage::ModelPrice>,
+    /// Days a chat session is kept after its last activity (0 keeps sessions forever)
+    #[serde(default = "default_session_retention_days")]
+    pub session_retention_days: u32,
+    /// Model settings per skill id, overriding the skill's own declaration
+    #[serde(default)]
+    pub skill_models: std::collections::HashMap<String, SkillModelConfig>,
+    /// Models tried in order when the active model keeps failing (same endpoint and key)
+    #[serde(default)]
+    pub fallback_models: Vec<String>,
+    /// Commands run before or after matching tool calls
+    #[serde(default)]
+    pub hooks: Vec<super::hooks::ToolHook>,
+    /// Also run the hooks in a workspace's `.inkess/hooks.json`, once the
+    /// user trusts that workspace's file
+    #[serde(default)]
+    pub workspace_hooks: bool,
+    /// Mark the stable prompt prefix for caching (Anthropic `cache_control`)
+    #[serde(default = "default_prompt_caching")]
+    pub prompt_caching: bool,
 }
 
+/// Model settings for one skill; unset fields keep the next level's value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SkillModelConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl AiConfig {
+    /// Config for a run of `skill_id`. The user's per-skill settings win over
+    /// what the skill declares, which wins over the global model settings.
+    pub fn for_skill(&self, skill_id: &str, declared: &SkillModelConfig) -> AiConfig {
+        let configured = self.skill_models.get(skill_id).cloned().unwrap_or_default();
+        let mut config = self.clone();
+        if let Some(model) = configured.model.or_else(|| declared.model.clone()).filter(|m| !m.trim().is_empty()) {
+            config.model = model;
+        }
+        if let Some(temperature) = configured.temperature.or(declared.temperature) {
+            config.temperature = temperature;
+        }
+        if let Some(max_tokens) = configured.max_tokens.or(declared.max_tokens) {
+            config.max_tokens = max_tokens;
+        }
+        config
+    }
+}
+
+fn default_max_retries() -> u32 { 3 }
+fn default_retry_base_delay_ms() -> u64 { 1000 }
+fn default_session_retention_days() -> u32 { 30 }
+fn default_prompt_caching() -> bool { true }
+
 // --- Config file path ---
 
 fn config_path() -> PathBuf {
@@ -51,18 +129,157 @@ pub struct MemoryEntry {
     pub created_at: String,
 }
 
+fn read_config_file() -> Option<AiConfig> {
+    let data = fs::read_to_string(config_path()).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn write_config_file(config: &AiConfig) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(config_path(), json).map_err(|e| format!("Failed to save config: {}", e))
+}
+
+/// Saves the keys to the vault and the rest, with references, to the config file
 #[tauri::command]
 pub fn ai_save_config(config: AiConfig) -> Result<(), String> {
-    let path = config_path();
-    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
-    fs::write(&path, json).map_err(|e| format!("Failed to save config: {}", e))
+    let mut config = config;
+    let secrets = extract_keys(&mut config, &stored_keys()?, "");
+    store_keys(vault::vault(), "", secrets)?;
+    write_config_file(&config)
 }
 
+/// The saved config with keys replaced by references
 #[tauri::command]
 pub fn ai_load_config() -> Option<AiConfig> {
-    let path = config_path();
-    let data = fs::read_to_string(&path).ok()?;
-    serde_json::from_str(&data).ok()
+    let mut config = read_config_file()?;
+    if has_plain_keys(&config) {
+        if let Err(e) = migrate_plain_keys() {
+            app_warn!("ai:vault", "API keys stay in the config file until the vault can be written: {}", e);
+        }
+    }
+    mask_keys(&mut config);
+    Some(config)
+}
+
+// --- API keys ---
+
+/// Vault names of a profile's keys start with this; the saved config's keys have no prefix
+pub const PROFILE_SCOPE: &str = "profile:";
+
+/// Each key field with the name it is stored under in the vault
+fn key_fields<'a>(config: &'a mut AiConfig, scope: &str) -> Vec<(String, &'a mut String)> {
+    let mut fields = vec![
+        (format!("{}api_key", scope), &mut config.api_key),
+        (format!("{}search_api_key", scope), &mut config.search_api_key),
+    ];
+    fields.extend(config.provider_keys.iter_mut().map(|(url, key)| (format!("{}provider:{}", scope, url), key)));
+    fields
+}
+
+/// Whether a key field holds a key rather than a reference; same fields as `key_fields`
+fn has_plain_keys(config: &AiConfig) -> bool {
+    [&config.api_key, &config.search_api_key].into_iter()
+        .chain(config.provider_keys.values())
+        .any(|value| !value.is_empty() && !vault::is_secret_ref(value))
+}
+
+/// Replace every set key with its reference
+fn mask_keys(config: &mut AiConfig) {
+    for (name, value) in key_fields(config, "") {
+        if !value.is_empty() {
+            *value = vault::secret_ref(&name);
+        }
+    }
+}
+
+/// Take the keys out of `config`, leaving references to names under `scope`
+/// behind. References are looked up in `known`; ones that aren't there are cleared.
+pub(crate) fn extract_keys(config: &mut AiConfig, known: &Secrets, scope: &str) -> Secrets {
+    let mut secrets = Secrets::new();
+    for (name, value) in key_fields(config, scope) {
+        let key = match value.strip_prefix(SECRET_REF_PREFIX) {
+            Some(stored) => known.get(stored).cloned().unwrap_or_default(),
+            None => value.clone(),
+        };
+        if key.is_empty() {
+            value.clear();
+        } else {
+            *value = vault::secret_ref(&name);
+            secrets.insert(name, key);
+        }
+    }
+    secrets
+}
+
+/// Replace the keys stored under `scope` with `secrets`, keeping the other scopes
+pub(crate) fn store_keys(vault: &Vault, scope: &str, secrets: Secrets) -> Result<(), String> {
+    let mut all = vault.read()?;
+    let before = all.len();
+    all.retain(|name, _| if scope.is_empty() { name.starts_with(PROFILE_SCOPE) } else { !name.starts_with(scope) });
+    if secrets.is_empty() && all.len() == before {
+        return Ok(());
+    }
+    all.extend(secrets);
+    vault.write(&all)
+}
+
+/// All stored keys: the vault, plus keys still in a config file from before the vault
+fn stored_keys() -> Result<Secrets, String> {
+    let mut known = vault::vault().read()?;
+    if let Some(mut saved) = read_config_file() {
+        let legacy = extract_keys(&mut saved, &known, "");
+        known.extend(legacy);
+    }
+    Ok(known)
+}
+
+/// Move plaintext keys from the config file into the vault
+fn migrate_plain_keys() -> Result<(), String> {
+    let Some(mut saved) = read_config_file() else { return Ok(()) };
+    if !has_plain_keys(&saved) {
+        return Ok(());
+    }
+    let secrets = extract_keys(&mut saved, &vault::vault().read()?, "");
+    store_keys(vault::vault(), "", secrets)?;
+    write_config_file(&saved)
+}
+
+/// Swap `vault:` references for the stored keys. Keys typed in but not saved yet pass through.
+pub fn resolve_keys(config: &mut AiConfig) -> Result<(), String> {
+    if !key_fields(config, "").iter().any(|(_, value)| vault::is_secret_ref(value)) {
+        return Ok(());
+    }
+    let stored = stored_keys()?;
+    for (_, value) in key_fields(config, "") {
+        if let Some(name) = value.strip_prefix(SECRET_REF_PREFIX).map(str::to_string) {
+            *value = stored.get(&name).cloned().unwrap_or_default();
+        }
+    }
+    Ok(())
+}
+
+/// `resolve_keys` for a single key
+pub fn resolve_key(value: &str) -> Result<String, String> {
+    match value.strip_prefix(SECRET_REF_PREFIX) {
+        Some(name) => Ok(stored_keys()?.get(name).cloned().unwrap_or_default()),
+        None => Ok(value.to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn ai_vault_status() -> VaultStatus {
+    vault::vault().status()
+}
+
+/// Unlock the passphrase-protected vault, or set the passphrase for a new one
+/// when there is no OS keyring
+#[tauri::command]
+pub fn ai_vault_unlock(passphrase: String) -> Result<VaultStatus, String> {
+    vault::vault().unlock(&passphrase)?;
+    if let Err(e) = migrate_plain_keys() {
+        app_warn!("ai:vault", "failed to move API keys into the vault: {}", e);
+    }
+    Ok(vault::vault().status())
 }
 
 #[tauri::command]
@@ -97,22 +314,43 @@ pub fn ai_load_memories(dir: String) -> Vec<MemoryEntry> {
 
 #[tauri::command]
 pub async fn ai_test_connection(config: AiConfig) -> Result<String, String> {
+    let mut config = config;
+    resolve_keys(&mut config)?;
     let client = Client::new();
-    let url = format!("{}/chat/completions", config.api_url.trim_end_matches('/'));
-    let body = serde_json::json!({
-        "model": config.model,
-        "messages": [{"role": "user", "content": "Hi"}],
-        "max_tokens": 16,
-    });
-    let resp = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .header("Content-Type", "application/json")
-        .json(&body)
+    let provider = super::provider::get_provider(&config.provider);
+    let messages = vec![super::ChatMessage {
+        role: "user".to_string(),
+        content: Some("Hi".to_string()),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    }];
+    let req = super::provider::ChatRequest {
+        model: &config.model,
+        messages: &messages,
+        tools: &[],
+        temperature: config.temperature,
+        max_tokens: 16,
+        stream: false,
+        reasoning: false,
+        cache: false,
+    };
+    let resp = provider
+        .build_request(&client, &config, &req)
         .send()
         .await
         .map_err(|e| format!("Connection failed: {}", e))?;
     if resp.status().is_success() {
+        if config.provider == "local" {
+            let tools = match super::provider::local::detect_tool_support(&config.api_url, &config.model).await {
+                Some(true) => "native tools",
+                Some(false) => "prompt-based tools",
+                None => "tool support unknown",
+            };
+            return Ok(format!("Connection successful ({}, {})", config.model, tools));
+        }
         Ok(format!("Connection successful ({})", config.model))
     } else {
         let status = resp.status();
@@ -121,8 +359,25 @@ pub async fn ai_test_connection(config: AiConfig) -> Result<String, String> {
     }
 }
 
+/// One non-streamed completion, for summaries made by the chat panel
+#[tauri::command]
+pub async fn ai_complete(config: AiConfig, prompt: String, max_tokens: u32, timeout_secs: Option<u64>) -> Result<String, String> {
+    let mut config = config;
+    resolve_keys(&mut config)?;
+    super::memory::distill::complete(prompt, &config, 0.3, max_tokens, timeout_secs.unwrap_or(30), None)
+        .await
+        .map_err(|e| format!("Completion {}", e))
+}
+
+/// List models served by a local Ollama / llama.cpp server
+#[tauri::command]
+pub async fn ai_list_local_models(api_url: String) -> Result<Vec<super::provider::local::LocalModelInfo>, String> {
+    super::provider::local::discover_models(&api_url).await
+}
+
 #[tauri::command]
 pub async fn ai_test_search(provider: String, api_key: String) -> Result<String, String> {
+    let api_key = resolve_key(&api_key)?;
     let engine = super::search::get_engine(&provider);
     match engine.search("test", &api_key, 3).await {
         Ok(results) if !results.is_empty() => Ok(format!("Search OK ({})", engine.name())),
@@ -156,6 +411,31 @@ mod tests {
                 m.insert("https://api.anthropic.com".to_string(), "sk-key2".to_string());
                 m
             },
+            provider: "anthropic".to_string(),
+            local_tool_mode: "prompt".to_string(),
+            max_retries: 5,
+            retry_base_delay_ms: 250,
+            context_window: 64_000,
+            model_prices: {
+                let mut m = std::collections::HashMap::new();
+                m.insert("gpt-4".to_string(), crate::ai::usage::ModelPrice { input_per_million: 2.5, output_per_million: 10.0, cached_input_per_million: None });
+                m
+            },
+            session_retention_days: 7,
+            skill_models: {
+                let mut m = std::collections::HashMap::new();
+                m.insert("file_processing".to_string(), SkillModelConfig { model: Some("gpt-4o-mini".to_string()), temperature: None, max_tokens: Some(2048) });
+                m
+            },
+            fallback_models: vec!["gpt-4o".to_string()],
+            hooks: vec![crate::ai::hooks::ToolHook {
+                event: crate::ai::hooks::HookEvent::PostToolUse,
+                matcher: "edit_file".to_string(),
+                command: "cargo fmt".to_string(),
+                timeout_secs: 60,
+            }],
+            workspace_hooks: true,
+            prompt_caching: false,
         };
         let json_str = serde_json::to_string(&config).unwrap();
         let restored: AiConfig = serde_json::from_str(&json_str).unwrap();
@@ -170,6 +450,19 @@ mod tests {
         assert_eq!(restored.search_provider, "tavily");
         assert_eq!(restored.provider_keys.len(), 2);
         assert_eq!(restored.provider_keys.get("https://api.openai.com/v1").unwrap(), "sk-key1");
+        assert_eq!(restored.provider, "anthropic");
+        assert_eq!(restored.local_tool_mode, "prompt");
+        assert_eq!(restored.max_retries, 5);
+        assert_eq!(restored.retry_base_delay_ms, 250);
+        assert_eq!(restored.context_window, 64_000);
+        assert_eq!(restored.model_prices["gpt-4"].output_per_million, 10.0);
+        assert_eq!(restored.session_retention_days, 7);
+        assert_eq!(restored.skill_models["file_processing"].model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(restored.skill_models["file_processing"].max_tokens, Some(2048));
+        assert_eq!(restored.fallback_models, vec!["gpt-4o".to_string()]);
+        assert_eq!(restored.hooks, config.hooks);
+        assert!(restored.workspace_hooks);
+        assert!(!restored.prompt_caching);
     }
 
     #[test]
@@ -190,6 +483,63 @@ mod tests {
         assert_eq!(config.search_api_key, ""); // default
         assert_eq!(config.search_provider, ""); // default
         assert!(config.provider_keys.is_empty()); // default
+        assert_eq!(config.provider, ""); // default
+        assert_eq!(config.local_tool_mode, ""); // default
+        assert_eq!(config.max_retries, 3); // default
+        assert_eq!(config.retry_base_delay_ms, 1000); // default
+        assert_eq!(config.context_window, 0); // default
+        assert!(config.model_prices.is_empty()); // default
+        assert_eq!(config.session_retention_days, 30); // default
+        assert!(config.skill_models.is_empty()); // default
+        assert!(config.fallback_models.is_empty()); // default
+        assert!(config.hooks.is_empty()); // default
+        assert!(!config.workspace_hooks); // default
+        assert!(config.prompt_caching); // default
+    }
+
+    // --- API key tests ---
+
+    fn config_with_keys(api_key: &str, search_api_key: &str, provider_keys: &[(&str, &str)]) -> AiConfig {
+        let mut config: AiConfig = serde_json::from_str(
+            r#"{"api_url": "u", "api_key": "", "model": "m", "temperature": 0.5, "max_tokens": 100}"#
+        ).unwrap();
+        config.api_key = api_key.to_string();
+        config.search_api_key = search_api_key.to_string();
+        config.provider_keys = provider_keys.iter().map(|(u, k)| (u.to_string(), k.to_string())).collect();
+        config
+    }
+
+    #[test]
+    fn extract_keys_leaves_references() {
+        let known: Secrets = [("api_key".to_string(), "sk-old".to_string())].into_iter().collect();
+        // The header copies a provider key reference into api_key when switching models
+        let mut config = config_with_keys("vault:api_key", "tvly-new", &[
+            ("https://a", "vault:api_key"),
+            ("https://b", "vault:provider:https://gone"),
+        ]);
+        let secrets = extract_keys(&mut config, &known, "");
+
+        assert_eq!(config.api_key, "vault:api_key");
+        assert_eq!(config.search_api_key, "vault:search_api_key");
+        assert_eq!(config.provider_keys["https://a"], "vault:provider:https://a");
+        // Unknown references are dropped rather than stored
+        assert_eq!(config.provider_keys["https://b"], "");
+        assert_eq!(secrets.len(), 3);
+        assert_eq!(secrets["api_key"], "sk-old");
+        assert_eq!(secrets["search_api_key"], "tvly-new");
+        assert_eq!(secrets["provider:https://a"], "sk-old");
+        assert!(!serde_json::to_string(&config).unwrap().contains("sk-old"));
+    }
+
+    #[test]
+    fn mask_keys_hides_plain_keys() {
+        let mut config = config_with_keys("sk-plain", "", &[("https://a", "sk-a")]);
+        assert!(has_plain_keys(&config));
+        mask_keys(&mut config);
+        assert!(!has_plain_keys(&config));
+        assert_eq!(config.api_key, "vault:api_key");
+        assert_eq!(config.search_api_key, "");
+        assert_eq!(config.provider_keys["https://a"], "vault:provider:https://a");
     }
 
     #[test]
@@ -218,6 +568,18 @@ mod tests {
             search_api_key: "".to_string(),
             search_provider: "".to_string(),
             provider_keys: std::collections::HashMap::new(),
+            provider: "".to_string(),
+            local_tool_mode: "".to_string(),
+            max_retries: 0,
+            retry_base_delay_ms: 0,
+            context_window: 0,
+            model_prices: std::collections::HashMap::new(),
+            session_retention_days: 0,
+            skill_models: std::collections::HashMap::new(),
+            fallback_models: Vec::new(),
+            hooks: Vec::new(),
+            workspace_hooks: false,
+            prompt_caching: false,
         };
         let json = serde_json::to_value(&config).unwrap();
         // All fields present even if empty
@@ -231,6 +593,44 @@ mod tests {
         assert!(json.get("search_api_key").is_some());
         assert!(json.get("search_provider").is_some());
         assert!(json.get("provider_keys").is_some());
+        assert!(json.get("provider").is_some());
+        assert!(json.get("local_tool_mode").is_some());
+        assert!(json.get("max_retries").is_some());
+        assert!(json.get("retry_base_delay_ms").is_some());
+        assert!(json.get("context_window").is_some());
+        assert!(json.get("model_prices").is_some());
+        assert!(json.get("session_retention_days").is_some());
+        assert!(json.get("skill_models").is_some());
+        assert!(json.get("fallback_models").is_some());
+        assert!(json.get("hooks").is_some());
+        assert!(json.get("workspace_hooks").is_some());
+        assert!(json.get("prompt_caching").is_some());
+    }
+
+    #[test]
+    fn ai_config_for_skill_precedence() {
+        let mut config: AiConfig = serde_json::from_str(
+            r#"{"api_url":"u","api_key":"k","model":"base","temperature":0.5,"max_tokens":4096}"#
+        ).unwrap();
+        config.skill_models.insert("research".to_string(), SkillModelConfig {
+            model: Some("strong".to_string()),
+            temperature: None,
+            max_tokens: None,
+        });
+        let declared = SkillModelConfig { model: Some("declared".to_string()), temperature: Some(0.2), max_tokens: Some(8192) };
+
+        let research = config.for_skill("research", &declared);
+        assert_eq!(research.model, "strong"); // user setting wins
+        assert_eq!(research.temperature, 0.2); // then the skill's declaration
+        assert_eq!(research.max_tokens, 8192);
+
+        let other = config.for_skill("other", &SkillModelConfig::default());
+        assert_eq!(other.model, "base"); // global settings otherwise
+        assert_eq!(other.temperature, 0.5);
+        assert_eq!(other.max_tokens, 4096);
+
+        let blank = SkillModelConfig { model: Some(" ".to_string()), ..Default::default() };
+        assert_eq!(config.for_skill("other", &blank).model, "base");
     }
 
     // --- AiMemories tests ---
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use super::retry::sleep_unless_cancelled;
+
+/// How long a confirmation waits for the user
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A tool action waiting for the user's approval
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConfirmRequest {
+    pub request_id: String,
+    pub session_id: String,
+    pub tool: String,
+    /// The command or action to approve, as shown to the user
+    pub command: String,
+    /// Unix timestamp (seconds)
+    pub created_at: i64,
+}
+
+impl ConfirmRequest {
+    pub fn new(session_id: &str, tool: &str, command: &str) -> Self {
+        Self {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            tool: tool.to_string(),
+            command: command.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+struct Pending {
+    request: ConfirmRequest,
+    sender: oneshot::Sender<bool>,
+}
+
+/// Open confirmations keyed by request id, so concurrent prompts from one or
+/// more sessions can't answer each other.
+#[derive(Default)]
+pub struct ConfirmRegistry {
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl ConfirmRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a confirmation; the receiver gets the user's answer
+    pub fn register(&self, request: ConfirmRequest) -> oneshot::Receiver<bool> {
+        let (sender, receiver) = oneshot::channel();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.insert(request.request_id.clone(), Pending { request, sender });
+        receiver
+    }
+
+    /// Answer an open confirmation. Fails if it was already answered or has
+    /// expired, or if `session_id` is given and doesn't match.
+    pub fn respond(&self, request_id: &str, session_id: Option<&str>, approved: bool) -> Result<(), String> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = pending.get(request_id) else {
+            return Err(format!("No pending confirmation {} (already answered or expired)", request_id));
+        };
+        if session_id.is_some_and(|s| s != entry.request.session_id) {
+            return Err(format!("Confirmation {} belongs to another session", request_id));
+        }
+        if let Some(entry) = pending.remove(request_id) {
+            let _ = entry.sender.send(approved);
+        }
+        Ok(())
+    }
+
+    /// Drop an open confirmation; false if it was already answered
+    pub fn cancel(&self, request_id: &str) -> bool {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(request_id).is_some()
+    }
+
+    /// Open confirmations, oldest first, optionally of one session
+    pub fn pending(&self, session_id: Option<&str>) -> Vec<ConfirmRequest> {
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let mut requests: Vec<ConfirmRequest> = pending.values()
+            .filter(|p| session_id.is_none() || session_id == Some(p.request.session_id.as_str()))
+            .map(|p| p.request.clone())
+            .collect();
+        requests.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.request_id.cmp(&b.request_id)));
+        requests
+    }
+
+    /// Wait for the answer to a registered request. On timeout or when the
+    /// run is cancelled the request is dropped, so a late answer is rejected
+    /// instead of approving anything.
+    pub async fn wait(
+        &self,
+        request_id: &str,
+        receiver: oneshot::Receiver<bool>,
+        timeout: Duration,
+        cancel_flag: &AtomicBool,
+    ) -> Result<bool, String> {
+        tokio::select! {
+            answer = receiver => match answer {
+                Ok(approved) => Ok(approved),
+                Err(_) => {
+                    self.cancel(request_id);
+                    Err("Confirmation channel closed unexpectedly.".to_string())
+                }
+            },
+            timed_out = sleep_unless_cancelled(timeout, cancel_flag) => {
+                self.cancel(request_id);
+                if timed_out {
+                    Err(format!("Confirmation timed out ({}s). Not executed.", timeout.as_secs()))
+                } else {
+                    Err("Cancelled. Not executed.".to_string())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_requests_are_answered_independently() {
+        let registry = ConfirmRegistry::new();
+        let first = ConfirmRequest::new("s1", "run_shell", "npm install");
+        let second = ConfirmRequest::new("s2", "write_file", "write_file a.txt");
+        let rx1 = registry.register(first.clone());
+        let rx2 = registry.register(second.clone());
+        assert_eq!(registry.pending(None).len(), 2);
+        assert_eq!(registry.pending(Some("s2")), vec![second.clone()]);
+
+        registry.respond(&second.request_id, Some("s2"), false).unwrap();
+        registry.respond(&first.request_id, None, true).unwrap();
+        let not_cancelled = AtomicBool::new(false);
+        assert_eq!(registry.wait(&first.request_id, rx1, CONFIRM_TIMEOUT, &not_cancelled).await, Ok(true));
+        assert_eq!(registry.wait(&second.request_id, rx2, CONFIRM_TIMEOUT, &not_cancelled).await, Ok(false));
+        assert!(registry.pending(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn wrong_session_and_late_answers_are_rejected() {
+        let registry = ConfirmRegistry::new();
+        let request = ConfirmRequest::new("s1", "run_shell", "make");
+        let rx = registry.register(request.clone());
+        assert!(registry.respond(&request.request_id, Some("s2"), true).unwrap_err().contains("another session"));
+
+        let err = registry.wait(&request.request_id, rx, Duration::from_millis(10), &AtomicBool::new(false)).await.unwrap_err();
+        assert!(err.contains("timed out"));
+        assert!(registry.respond(&request.request_id, Some("s1"), true).unwrap_err().contains("expired"));
+        assert!(registry.pending(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_run_drops_the_request() {
+        let registry = ConfirmRegistry::new();
+        let request = ConfirmRequest::new("s1", "run_shell", "make");
+        let rx = registry.register(request.clone());
+        let started = std::time::Instant::now();
+        let err = registry.wait(&request.request_id, rx, CONFIRM_TIMEOUT, &AtomicBool::new(true)).await.unwrap_err();
+        assert!(err.contains("Cancelled"));
+        assert!(started.elapsed() < CONFIRM_TIMEOUT);
+        assert!(registry.respond(&request.request_id, Some("s1"), true).unwrap_err().contains("expired"));
+        assert!(registry.pending(None).is_empty());
+    }
+}
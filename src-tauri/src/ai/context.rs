@@ -0,0 +1,425 @@
+use serde_json::Value;
+
+use super::config::AiConfig;
+use super::streaming::{ChatMessage, ThinkingBlock};
+
+/// Fixed per-message overhead (role, separators)
+const MESSAGE_OVERHEAD: usize = 4;
+/// Flat estimate per image part; providers bill images by resolution
+const IMAGE_TOKENS: usize = 1_500;
+/// Fallback context window when the model is unknown
+const DEFAULT_CONTEXT_WINDOW: usize = 32_000;
+/// Fraction of the window kept free to absorb estimation error
+const SAFETY_MARGIN_PERCENT: usize = 10;
+/// Most recent tool results are never blanked
+const KEEP_RECENT_TOOL_RESULTS: usize = 3;
+
+pub const OMITTED_TOOL_RESULT: &str = "[Previous tool result omitted for context efficiency]";
+pub const DROPPED_MESSAGES_NOTE: &str = "[Earlier messages were removed to fit the context window]";
+
+/// Known context windows, matched by substring of the lowercased model id.
+/// More specific entries come first.
+const MODEL_LIMITS: &[(&str, usize)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5", 16_385),
+    ("gpt-5", 400_000),
+    ("claude", 200_000),
+    ("gemini", 1_048_576),
+    ("deepseek", 64_000),
+    ("qwen-long", 1_000_000),
+    ("qwen", 131_072),
+    ("glm-4", 128_000),
+    ("moonshot-v1-8k", 8_192),
+    ("moonshot-v1-32k", 32_768),
+    ("moonshot-v1-128k", 131_072),
+    ("kimi", 131_072),
+    ("doubao", 128_000),
+    ("mistral", 32_000),
+    ("llama3.1", 128_000),
+    ("llama3.2", 128_000),
+    ("llama3.3", 128_000),
+    ("llama-3.1", 128_000),
+    ("llama-3.2", 128_000),
+    ("llama-3.3", 128_000),
+    ("llama3", 8_192),
+    ("llama", 128_000),
+];
+
+/// Model families with short names that occur inside unrelated ids, matched
+/// only at the start of the id or after a separator ("o3-mini", "openai/o1")
+const PREFIX_LIMITS: &[(&str, usize)] = &[
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+];
+
+/// Context window of a model in tokens
+pub fn context_limit(model: &str) -> usize {
+    let model = model.to_lowercase();
+    MODEL_LIMITS.iter()
+        .find(|(pattern, _)| model.contains(pattern))
+        .or_else(|| PREFIX_LIMITS.iter().find(|(pattern, _)| starts_word(&model, pattern)))
+        .map(|(_, limit)| *limit)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Whether `pattern` occurs in `model` at the start or right after a non-alphanumeric character
+fn starts_word(model: &str, pattern: &str) -> bool {
+    model.match_indices(pattern)
+        .any(|(i, _)| !matches!(model[..i].chars().next_back(), Some(c) if c.is_ascii_alphanumeric()))
+}
+
+/// Estimate the token count of a text without a tokenizer: ASCII at ~4 chars
+/// per token, other characters (CJK etc.) at ~1 token each
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other) = text.chars().fold((0usize, 0usize), |(a, o), c| {
+        if c.is_ascii() { (a + 1, o) } else { (a, o + 1) }
+    });
+    ascii.div_ceil(4) + other
+}
+
+/// Estimate the token count of a message, including tool call payloads
+pub fn estimate_message_tokens(msg: &ChatMessage) -> usize {
+    let mut tokens = MESSAGE_OVERHEAD;
+    if let Some(content) = &msg.content {
+        tokens += estimate_tokens(content);
+    }
+    for tc in msg.tool_calls.iter().flatten() {
+        tokens += MESSAGE_OVERHEAD + estimate_tokens(&tc.function.name) + estimate_tokens(&tc.function.arguments);
+    }
+    if let Some(id) = &msg.tool_call_id {
+        tokens += estimate_tokens(id);
+    }
+    // Only thinking blocks are sent back to the API
+    for block in msg.thinking_blocks.iter().flatten() {
+        tokens += match block {
+            ThinkingBlock::Thinking { thinking, .. } => estimate_tokens(thinking),
+            ThinkingBlock::RedactedThinking { data } => estimate_tokens(data),
+        };
+    }
+    tokens += msg.images.as_ref().map_or(0, |images| images.len() * IMAGE_TOKENS);
+    tokens
+}
+
+pub fn estimate_conversation_tokens(conversation: &[ChatMessage]) -> usize {
+    conversation.iter().map(estimate_message_tokens).sum()
+}
+
+/// Token budget for the messages of one request
+#[derive(Clone, Copy, Debug)]
+pub struct ContextBudget {
+    /// Model context window
+    pub limit: usize,
+    /// Tokens available for messages after output, tool schemas and margin
+    pub available: usize,
+}
+
+impl ContextBudget {
+    pub fn new(limit: usize, max_output: usize, tools_tokens: usize) -> Self {
+        let margin = limit * SAFETY_MARGIN_PERCENT / 100;
+        let available = limit.saturating_sub(max_output + tools_tokens + margin);
+        Self { limit, available }
+    }
+
+    /// Budget for a request with the given config and tool schemas.
+    /// `AiConfig::context_window` overrides the built-in model table.
+    pub fn for_request(config: &AiConfig, tools: &[Value]) -> Self {
+        let limit = if config.context_window > 0 {
+            config.context_window as usize
+        } else {
+            context_limit(&config.model)
+        };
+        let tools_tokens = tools.iter().map(|t| estimate_tokens(&t.to_string())).sum();
+        Self::new(limit, config.max_tokens as usize, tools_tokens)
+    }
+}
+
+/// What `fit_to_budget` did to the conversation
+#[derive(Debug, Default, PartialEq)]
+pub struct TrimReport {
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+    /// Old tool results replaced by a placeholder
+    pub omitted_tool_results: usize,
+    /// Messages removed from the start of the history
+    pub dropped_messages: usize,
+}
+
+impl TrimReport {
+    pub fn trimmed(&self) -> bool {
+        self.omitted_tool_results > 0 || self.dropped_messages > 0
+    }
+}
+
+/// Trim the conversation in place until it fits the budget. Cheapest first:
+/// 1. blank old tool results (oldest first, the last few are kept),
+/// 2. drop the oldest turns after the system prompt, keeping tool calls and
+///    their results together and never touching the latest user message.
+///
+/// A conversation that already fits is left untouched.
+pub fn fit_to_budget(conversation: &mut Vec<ChatMessage>, budget: &ContextBudget) -> TrimReport {
+    let mut total = estimate_conversation_tokens(conversation);
+    let mut report = TrimReport { tokens_before: total, ..Default::default() };
+
+    if total > budget.available {
+        let tool_indices: Vec<usize> = conversation.iter()
+            .enumerate()
+            .filter(|(_, m)| m.role == "tool")
+            .map(|(i, _)| i)
+            .collect();
+        let old_count = tool_indices.len().saturating_sub(KEEP_RECENT_TOOL_RESULTS);
+        for &idx in &tool_indices[..old_count] {
+            if total <= budget.available {
+                break;
+            }
+            let before = estimate_message_tokens(&conversation[idx]);
+            let placeholder = ChatMessage { content: Some(OMITTED_TOOL_RESULT.to_string()), ..conversation[idx].clone() };
+            let after = estimate_message_tokens(&placeholder);
+            if after < before {
+                conversation[idx] = placeholder;
+                total = total - before + after;
+                report.omitted_tool_results += 1;
+            }
+        }
+    }
+
+    while total > budget.available {
+        let Some(range) = oldest_droppable_turn(conversation) else { break };
+        let removed: usize = conversation[range.clone()].iter().map(estimate_message_tokens).sum();
+        report.dropped_messages += range.len();
+        conversation.drain(range);
+        total -= removed;
+    }
+
+    if report.dropped_messages > 0 {
+        let start = first_history_index(conversation);
+        let has_note = conversation.get(start)
+            .map(|m| m.role == "system" && m.content.as_deref() == Some(DROPPED_MESSAGES_NOTE))
+            .unwrap_or(false);
+        if !has_note {
+            let note = ChatMessage {
+                role: "system".into(),
+                content: Some(DROPPED_MESSAGES_NOTE.to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
+            };
+            total += estimate_message_tokens(&note);
+            conversation.insert(start, note);
+        }
+    }
+
+    report.tokens_after = total;
+    report
+}
+
+/// Index of the first message after the leading system prompt
+fn first_history_index(conversation: &[ChatMessage]) -> usize {
+    match conversation.first() {
+        Some(m) if m.role == "system" => 1,
+        _ => 0,
+    }
+}
+
+/// Range of the oldest droppable turn: one message, or an assistant tool call
+/// message together with its tool results. Returns None when only the latest
+/// user message and what follows it remain.
+fn oldest_droppable_turn(conversation: &[ChatMessage]) -> Option<std::ops::Range<usize>> {
+    let protected_from = conversation.iter().rposition(|m| m.role == "user")?;
+    let mut start = first_history_index(conversation);
+    // Skip our own note so it stays at the top of the history
+    if conversation.get(start).and_then(|m| m.content.as_deref()) == Some(DROPPED_MESSAGES_NOTE) {
+        start += 1;
+    }
+    if start >= protected_from {
+        return None;
+    }
+    let mut end = start + 1;
+    if conversation[start].tool_calls.is_some() {
+        while end < protected_from && conversation[end].role == "tool" {
+            end += 1;
+        }
+    }
+    Some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::{FunctionCall, ToolCall};
+
+    fn make_msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: None,
+            tool_call_id: if role == "tool" { Some("tc1".into()) } else { None },
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    fn make_tool_call_msg() -> ChatMessage {
+        ChatMessage {
+            role: "assistant".into(),
+            content: None,
+            tool_calls: Some(vec![ToolCall {
+                id: "tc1".into(),
+                r#type: "function".into(),
+                function: FunctionCall { name: "read_file".into(), arguments: r#"{"path":"a.md"}"#.into() },
+            }]),
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    fn budget(available: usize) -> ContextBudget {
+        ContextBudget { limit: available, available }
+    }
+
+    #[test]
+    fn estimate_tokens_ascii_and_cjk() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("你好"), 2);
+        assert_eq!(estimate_tokens("hi你好"), 3);
+    }
+
+    #[test]
+    fn estimate_message_counts_tool_calls() {
+        let plain = estimate_message_tokens(&make_msg("assistant", ""));
+        assert_eq!(plain, MESSAGE_OVERHEAD);
+        assert!(estimate_message_tokens(&make_tool_call_msg()) > plain);
+    }
+
+    #[test]
+    fn context_limit_lookup() {
+        assert_eq!(context_limit("gpt-4o-mini"), 128_000);
+        assert_eq!(context_limit("gpt-4"), 8_192);
+        assert_eq!(context_limit("claude-sonnet-4-5"), 200_000);
+        assert_eq!(context_limit("DeepSeek-Chat"), 64_000);
+        assert_eq!(context_limit("my-custom-model"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(context_limit("llama3.1:8b"), 128_000);
+        assert_eq!(context_limit("llama3.3:70b"), 128_000);
+        assert_eq!(context_limit("meta-llama/Llama-3.2-3B-Instruct"), 128_000);
+        assert_eq!(context_limit("llama3:8b"), 8_192);
+        assert_eq!(context_limit("o3-mini"), 200_000);
+        assert_eq!(context_limit("openai/o1"), 200_000);
+        assert_eq!(context_limit("yolo3-tiny"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(context_limit("proto4-chat"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn budget_reserves_output_tools_and_margin() {
+        let b = ContextBudget::new(10_000, 2_000, 500);
+        assert_eq!(b.available, 10_000 - 2_000 - 500 - 1_000);
+        assert_eq!(ContextBudget::new(1_000, 4_096, 0).available, 0);
+    }
+
+    #[test]
+    fn fit_leaves_small_conversation_untouched() {
+        let mut conv = vec![
+            make_msg("system", "You are helpful"),
+            make_msg("user", "Hello"),
+            make_msg("tool", &"x".repeat(400)),
+        ];
+        let report = fit_to_budget(&mut conv, &budget(10_000));
+        assert!(!report.trimmed());
+        assert_eq!(conv.len(), 3);
+        assert_eq!(conv[2].content.as_ref().unwrap().len(), 400);
+    }
+
+    #[test]
+    fn fit_blanks_oldest_tool_results_first() {
+        let large = "x".repeat(4_000); // ~1000 tokens
+        let mut conv = vec![
+            make_msg("user", "q1"),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+        ];
+        let report = fit_to_budget(&mut conv, &budget(4_500));
+        assert_eq!(report.omitted_tool_results, 1);
+        assert_eq!(report.dropped_messages, 0);
+        assert_eq!(conv[1].content.as_deref(), Some(OMITTED_TOOL_RESULT));
+        assert_eq!(conv[2].content.as_ref().unwrap().len(), 4_000);
+        assert!(report.tokens_after <= 4_500);
+    }
+
+    #[test]
+    fn fit_keeps_recent_tool_results() {
+        let large = "x".repeat(4_000);
+        let mut conv = vec![
+            make_msg("user", "q1"),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+            make_msg("tool", &large),
+        ];
+        let report = fit_to_budget(&mut conv, &budget(100));
+        assert_eq!(report.omitted_tool_results, 0);
+        assert_eq!(conv[1].content.as_ref().unwrap().len(), 4_000);
+    }
+
+    #[test]
+    fn fit_drops_oldest_turns_with_their_tool_results() {
+        let large = "x".repeat(4_000);
+        let mut conv = vec![
+            make_msg("system", "sys"),
+            make_msg("user", &large),
+            make_tool_call_msg(),
+            make_msg("tool", "ok"),
+            make_msg("assistant", &large),
+            make_msg("user", "latest question"),
+        ];
+        let report = fit_to_budget(&mut conv, &budget(100));
+        assert_eq!(report.dropped_messages, 4);
+        assert_eq!(conv.len(), 3);
+        assert_eq!(conv[0].content.as_deref(), Some("sys"));
+        assert_eq!(conv[1].content.as_deref(), Some(DROPPED_MESSAGES_NOTE));
+        assert_eq!(conv[2].content.as_deref(), Some("latest question"));
+    }
+
+    #[test]
+    fn fit_never_leaves_orphan_tool_results() {
+        let large = "x".repeat(4_000);
+        let mut conv = vec![
+            make_msg("system", "sys"),
+            make_tool_call_msg(),
+            make_msg("tool", &large),
+            make_msg("user", "next"),
+            make_msg("assistant", "answer"),
+        ];
+        fit_to_budget(&mut conv, &budget(50));
+        assert!(conv.iter().all(|m| m.role != "tool"));
+        assert_eq!(conv.last().unwrap().content.as_deref(), Some("answer"));
+    }
+
+    #[test]
+    fn fit_is_idempotent() {
+        let large = "x".repeat(4_000);
+        let mut conv = vec![
+            make_msg("system", "sys"),
+            make_msg("user", &large),
+            make_msg("assistant", &large),
+            make_msg("user", "latest"),
+        ];
+        fit_to_budget(&mut conv, &budget(100));
+        let after_first: Vec<Option<String>> = conv.iter().map(|m| m.content.clone()).collect();
+        let report = fit_to_budget(&mut conv, &budget(100));
+        assert!(!report.trimmed());
+        let after_second: Vec<Option<String>> = conv.iter().map(|m| m.content.clone()).collect();
+        assert_eq!(after_first, after_second);
+    }
+}
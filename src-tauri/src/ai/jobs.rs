@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::Notify;
+
+use crate::app_info;
+
+/// Jobs a session may have running at once
+pub const MAX_RUNNING_JOBS: usize = 8;
+
+/// Jobs kept per session, finished ones included; the oldest finished go first
+const MAX_KEPT_JOBS: usize = 20;
+
+/// Output kept per job; older output is dropped
+const MAX_JOB_OUTPUT: usize = 1024 * 1024;
+
+/// Most output returned by one read
+pub const MAX_READ: usize = 16 * 1024;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Exited { code: Option<i32> },
+    /// Ended by stop_job, session deletion or app exit
+    Stopped,
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Exited { code: Some(code) } => write!(f, "exited with code {}", code),
+            JobStatus::Exited { code: None } => write!(f, "exited (killed by a signal)"),
+            JobStatus::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobInfo {
+    pub id: String,
+    pub session_id: String,
+    pub command: String,
+    pub status: JobStatus,
+    /// Unix timestamp (seconds)
+    pub started_at: i64,
+}
+
+/// Output read from a job
+#[derive(Debug)]
+pub struct JobRead {
+    pub output: String,
+    /// Pass back to continue after this output
+    pub cursor: u64,
+    /// Bytes before the requested cursor that were already dropped
+    pub dropped: u64,
+    /// More output is available right away
+    pub truncated: bool,
+    pub status: JobStatus,
+}
+
+struct OutputState {
+    /// The kept tail of stdout and stderr, interleaved as they arrived
+    data: Vec<u8>,
+    /// Stream offset of `data[0]`
+    start: u64,
+    status: JobStatus,
+    stopping: bool,
+}
+
+struct JobOutput {
+    state: Mutex<OutputState>,
+    changed: Notify,
+}
+
+impl JobOutput {
+    fn push(&self, chunk: &[u8]) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.data.extend_from_slice(chunk);
+        if state.data.len() > MAX_JOB_OUTPUT {
+            let excess = state.data.len() - MAX_JOB_OUTPUT;
+            state.data.drain(..excess);
+            state.start += excess as u64;
+        }
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    fn finish(&self, code: Option<i32>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.status = if state.stopping { JobStatus::Stopped } else { JobStatus::Exited { code } };
+        drop(state);
+        self.changed.notify_waiters();
+    }
+
+    fn status(&self) -> JobStatus {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).status.clone()
+    }
+
+    /// Output from `cursor` on, or None if there is nothing new and the job runs
+    fn read_from(&self, cursor: u64) -> Option<JobRead> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let end = state.start + state.data.len() as u64;
+        let from = cursor.clamp(state.start, end);
+        if from == end && state.status == JobStatus::Running {
+            return None;
+        }
+        let rest = &state.data[(from - state.start) as usize..];
+        let mut take = rest.len().min(MAX_READ);
+        // Don't split a character at the end; the rest comes with the next read
+        if let Err(e) = std::str::from_utf8(&rest[..take]) {
+            if e.error_len().is_none() && take < rest.len() {
+                take = e.valid_up_to();
+            }
+        }
+        Some(JobRead {
+            output: String::from_utf8_lossy(&rest[..take]).into_owned(),
+            cursor: from + take as u64,
+            dropped: from - cursor.min(from),
+            truncated: take < rest.len(),
+            status: state.status.clone(),
+        })
+    }
+}
+
+struct Job {
+    info: JobInfo,
+    pid: Option<u32>,
+    output: Arc<JobOutput>,
+}
+
+/// Long-running shell commands started by the agent, e.g. dev servers and
+/// watchers. Each job belongs to the session that started it.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Job>>,
+    next_id: AtomicU64,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start `command` in `cwd`. Must be called within a Tokio runtime.
+    pub fn start(&self, session_id: &str, command: &str, cwd: &str) -> Result<JobInfo, String> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let running = jobs.values()
+            .filter(|j| j.info.session_id == session_id && j.output.status() == JobStatus::Running)
+            .count();
+        if running >= MAX_RUNNING_JOBS {
+            return Err(format!("Too many running jobs ({}). Stop one with stop_job first.", running));
+        }
+        prune_finished(&mut jobs, session_id);
+
+        let (shell, arg) = if cfg!(target_os = "windows") { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut cmd = tokio::process::Command::new(shell);
+        cmd.arg(arg)
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if !cwd.is_empty() {
+            cmd.current_dir(cwd);
+        }
+        // Own process group, so stopping the job also ends what the shell started
+        #[cfg(unix)]
+        cmd.process_group(0);
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to start job: {}", e))?;
+
+        let id = format!("job_{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let output = Arc::new(JobOutput {
+            state: Mutex::new(OutputState { data: Vec::new(), start: 0, status: JobStatus::Running, stopping: false }),
+            changed: Notify::new(),
+        });
+        let stdout = child.stdout.take().map(|out| tokio::spawn(collect(out, output.clone())));
+        let stderr = child.stderr.take().map(|err| tokio::spawn(collect(err, output.clone())));
+        let pid = child.id();
+        {
+            let output = output.clone();
+            tokio::spawn(async move {
+                let code = child.wait().await.ok().and_then(|status| status.code());
+                // Keep the last output, but don't hang on pipes held open by leftover processes
+                for task in [stdout, stderr].into_iter().flatten() {
+                    let _ = tokio::time::timeout(Duration::from_secs(2), task).await;
+                }
+                output.finish(code);
+            });
+        }
+
+        let info = JobInfo {
+            id: id.clone(),
+            session_id: session_id.to_string(),
+            command: command.to_string(),
+            status: JobStatus::Running,
+            started_at: chrono::Utc::now().timestamp(),
+        };
+        app_info!("ai:jobs", "started {} (pid {:?}): {}", id, pid, command);
+        jobs.insert(id, Job { info: info.clone(), pid, output });
+        Ok(info)
+    }
+
+    /// Output of a job from `cursor` on. Waits up to `wait` for new output
+    /// while the job is running.
+    pub async fn read(&self, session_id: &str, job_id: &str, cursor: u64, wait: Duration) -> Result<JobRead, String> {
+        let output = self.job(session_id, job_id, |job| job.output.clone())?;
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // Register before checking, so output arriving in between wakes us
+            let changed = output.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if let Some(read) = output.read_from(cursor) {
+                return Ok(read);
+            }
+            if tokio::time::timeout_at(deadline, changed).await.is_err() {
+                return Ok(JobRead { output: String::new(), cursor, dropped: 0, truncated: false, status: JobStatus::Running });
+            }
+        }
+    }
+
+    /// Stop a job and what it started. Returns the job's status; stopping a
+    /// finished job is not an error.
+    pub fn stop(&self, session_id: &str, job_id: &str) -> Result<JobStatus, String> {
+        self.job(session_id, job_id, |job| stop_job(job, false))
+    }
+
+    /// Stop the jobs of a session, e.g. when it is deleted
+    pub fn stop_session(&self, session_id: &str) {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        for job in jobs.values().filter(|j| j.info.session_id == session_id) {
+            stop_job(job, false);
+        }
+    }
+
+    /// Kill every running job; used when the app or a headless run exits
+    pub fn stop_all(&self) {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        for job in jobs.values() {
+            stop_job(job, true);
+        }
+    }
+
+    fn job<T>(&self, session_id: &str, job_id: &str, f: impl FnOnce(&Job) -> T) -> Result<T, String> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        match jobs.get(job_id) {
+            Some(job) if job.info.session_id == session_id => Ok(f(job)),
+            _ => {
+                let ids: Vec<&str> = jobs.values()
+                    .filter(|j| j.info.session_id == session_id)
+                    .map(|j| j.info.id.as_str())
+                    .collect();
+                Err(if ids.is_empty() {
+                    format!("No job {} in this session (no jobs started).", job_id)
+                } else {
+                    format!("No job {} in this session. Jobs: {}", job_id, ids.join(", "))
+                })
+            }
+        }
+    }
+}
+
+fn job_number(id: &str) -> u64 {
+    id.trim_start_matches("job_").parse().unwrap_or(0)
+}
+
+/// Drop the oldest finished jobs of a session beyond MAX_KEPT_JOBS
+fn prune_finished(jobs: &mut HashMap<String, Job>, session_id: &str) {
+    let mut finished: Vec<(u64, String)> = jobs.values()
+        .filter(|j| j.info.session_id == session_id && j.output.status() != JobStatus::Running)
+        .map(|j| (job_number(&j.info.id), j.info.id.clone()))
+        .collect();
+    let kept = jobs.values().filter(|j| j.info.session_id == session_id).count();
+    if kept < MAX_KEPT_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.into_iter().take(kept + 1 - MAX_KEPT_JOBS) {
+        jobs.remove(&id);
+    }
+}
+
+async fn collect<R: AsyncRead + Unpin>(mut reader: R, output: Arc<JobOutput>) {
+    let mut buf = vec![0u8; 8 * 1024];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => output.push(&buf[..n]),
+        }
+    }
+}
+
+fn stop_job(job: &Job, force: bool) -> JobStatus {
+    {
+        let mut state = job.output.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.status != JobStatus::Running {
+            return state.status.clone();
+        }
+        state.stopping = true;
+    }
+    if let Some(pid) = job.pid {
+        app_info!("ai:jobs", "stopping {} (pid {})", job.info.id, pid);
+        kill_tree(pid, force);
+    }
+    JobStatus::Stopped
+}
+
+/// Signal the job's process group (the whole tree on Windows)
+fn kill_tree(pid: u32, force: bool) {
+    #[cfg(unix)]
+    let _ = std::process::Command::new("kill")
+        .arg(if force { "-KILL" } else { "-TERM" })
+        .arg("--")
+        .arg(format!("-{}", pid))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    #[cfg(windows)]
+    {
+        let _ = force;
+        let _ = std::process::Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn output_is_read_incrementally_until_exit() {
+        let jobs = JobManager::new();
+        let job = jobs.start("s1", "echo one; sleep 0.2; echo two", "").unwrap();
+        assert_eq!(job.id, "job_1");
+
+        let first = jobs.read("s1", &job.id, 0, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(first.output, "one\n");
+        let mut cursor = first.cursor;
+        let mut rest = String::new();
+        let status = loop {
+            let read = jobs.read("s1", &job.id, cursor, Duration::from_secs(5)).await.unwrap();
+            rest.push_str(&read.output);
+            cursor = read.cursor;
+            if read.status != JobStatus::Running {
+                break read.status;
+            }
+        };
+        assert_eq!(rest, "two\n");
+        assert_eq!(status, JobStatus::Exited { code: Some(0) });
+        assert!(jobs.read("s2", &job.id, 0, Duration::ZERO).await.unwrap_err().contains("No job"));
+    }
+
+    #[tokio::test]
+    async fn stopping_ends_the_process_group() {
+        let jobs = JobManager::new();
+        // The sleep is a child of the shell; it must end with the job
+        let job = jobs.start("s1", "sleep 30 & wait", "").unwrap();
+        assert!(jobs.stop("s2", &job.id).is_err());
+        assert_eq!(jobs.stop("s1", &job.id).unwrap(), JobStatus::Stopped);
+
+        let read = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let read = jobs.read("s1", &job.id, 0, Duration::from_millis(100)).await.unwrap();
+                if read.status != JobStatus::Running {
+                    break read;
+                }
+            }
+        }).await.unwrap();
+        assert_eq!(read.status, JobStatus::Stopped);
+        assert_eq!(jobs.stop("s1", &job.id).unwrap(), JobStatus::Stopped);
+    }
+
+    #[test]
+    fn reads_start_at_the_kept_output() {
+        let output = JobOutput {
+            state: Mutex::new(OutputState { data: Vec::new(), start: 0, status: JobStatus::Running, stopping: false }),
+            changed: Notify::new(),
+        };
+        output.push(&vec![b'a'; MAX_JOB_OUTPUT + 10]);
+        assert!(output.read_from(MAX_JOB_OUTPUT as u64 + 10).is_none());
+        let read = output.read_from(0).unwrap();
+        assert_eq!(read.dropped, 10);
+        assert_eq!(read.output.len(), MAX_READ);
+        assert!(read.truncated);
+        assert_eq!(read.cursor, 10 + MAX_READ as u64);
+    }
+}
@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use reqwest::Client;
 use crate::ai::{AiConfig, ChatMessage};
+use crate::ai::provider::ChatRequest;
+use crate::ai::usage::UsageTag;
 use super::{Memory, MemoryMetadata, MemoryType};
 
 /// Minimum number of messages before auto-distill triggers
@@ -63,6 +65,7 @@ fn format_conversation(messages: &[ChatMessage]) -> String {
 async fn call_llm_for_distill(
     conversation_text: String,
     ai_config: &AiConfig,
+    usage: &UsageTag,
 ) -> Result<String, String> {
     let distill_prompt = format!(
         r#"Analyze this conversation and extract the most important learning or fact.
@@ -88,60 +91,66 @@ Conversation:
         conversation_text
     );
 
+    // Lower temperature for more consistent JSON output
+    complete(distill_prompt, ai_config, 0.3, 500, DISTILL_TIMEOUT_SECS, Some(usage))
+        .await
+        .map_err(|e| format!("Distill {}", e))
+}
+
+/// Single non-streamed completion for background LLM tasks (distill, compaction).
+/// Error messages are meant to be prefixed with the task name, e.g. "Distill request failed: ...".
+/// With a `usage` tag the tokens it used go to the usage ledger.
+pub(crate) async fn complete(
+    prompt: String,
+    ai_config: &AiConfig,
+    temperature: f64,
+    max_tokens: u32,
+    timeout_secs: u64,
+    usage: Option<&UsageTag>,
+) -> Result<String, String> {
     let client = Client::new();
-    let url = format!("{}/chat/completions", ai_config.api_url.trim_end_matches('/'));
-
-    let body = serde_json::json!({
-        "model": ai_config.model,
-        "messages": [
-            {
-                "role": "user",
-                "content": distill_prompt
-            }
-        ],
-        "temperature": 0.3, // Lower temperature for more consistent JSON output
-        "max_tokens": 500,
-        "stream": false,
-    });
-
-    let resp = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", ai_config.api_key))
-        .header("Content-Type", "application/json")
-        .timeout(std::time::Duration::from_secs(DISTILL_TIMEOUT_SECS))
-        .json(&body)
+    let provider = crate::ai::provider::get_provider(&ai_config.provider);
+    let messages = vec![ChatMessage {
+        role: "user".to_string(),
+        content: Some(prompt),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    }];
+    let req = ChatRequest {
+        model: &ai_config.model,
+        messages: &messages,
+        tools: &[],
+        temperature,
+        max_tokens,
+        stream: false,
+        reasoning: false,
+        cache: false,
+    };
+
+    let resp = provider
+        .build_request(&client, ai_config, &req)
+        .timeout(std::time::Duration::from_secs(timeout_secs))
         .send()
         .await
-        .map_err(|e| format!("Distill request failed: {}", e))?;
+        .map_err(|e| format!("request failed: {}", e))?;
 
     if !resp.status().is_success() {
         let status = resp.status();
         let text = resp.text().await.unwrap_or_default();
-        return Err(format!("Distill API error ({}): {}", status, text));
+        return Err(format!("API error ({}): {}", status, text));
     }
 
-    #[derive(Deserialize)]
-    struct CompletionResponse {
-        choices: Vec<CompletionChoice>,
-    }
-
-    #[derive(Deserialize)]
-    struct CompletionChoice {
-        message: CompletionMessage,
-    }
+    let response: serde_json::Value = resp.json().await
+        .map_err(|e| format!("response could not be parsed: {}", e))?;
 
-    #[derive(Deserialize)]
-    struct CompletionMessage {
-        content: String,
+    if let (Some(tag), Some(tokens)) = (usage, provider.parse_completion_usage(&response)) {
+        tag.record(ai_config, &tokens);
     }
-
-    let response: CompletionResponse = resp.json().await
-        .map_err(|e| format!("Failed to parse distill response: {}", e))?;
-
-    response.choices
-        .first()
-        .map(|c| c.message.content.clone())
-        .ok_or_else(|| "No content in distill response".to_string())
+    provider.parse_completion(&response)
+        .ok_or_else(|| "response has no content".to_string())
 }
 
 /// Distill a conversation into a Memory
@@ -149,6 +158,7 @@ pub async fn distill_conversation(
     messages: &[ChatMessage],
     ai_config: &AiConfig,
     workspace_path: Option<String>,
+    usage: &UsageTag,
 ) -> Result<Memory, String> {
     // Format conversation
     let conversation_text = format_conversation(messages);
@@ -158,7 +168,7 @@ pub async fn distill_conversation(
     }
 
     // Call LLM
-    let response = call_llm_for_distill(conversation_text, ai_config).await?;
+    let response = call_llm_for_distill(conversation_text, ai_config, usage).await?;
 
     // Parse JSON response (strip markdown code blocks if present)
     let json_str = response.trim()
@@ -206,12 +216,18 @@ mod tests {
                 content: Some("How do I use Rust async?".to_string()),
                 tool_calls: None,
                 tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
             },
             ChatMessage {
                 role: "assistant".to_string(),
                 content: Some("You need to use tokio runtime.".to_string()),
                 tool_calls: None,
                 tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
             },
         ];
 
@@ -229,6 +245,9 @@ mod tests {
                 content: Some(long_content),
                 tool_calls: None,
                 tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
             },
         ];
 
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// One scripted HTTP response
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// Write the body in pieces of this many bytes, to exercise SSE buffering
+    pub chunk_size: Option<usize>,
+}
+
+impl MockResponse {
+    /// SSE stream of raw `data:` payloads, terminated by `[DONE]`
+    pub fn sse(events: &[String]) -> Self {
+        let mut body: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+        body.push_str("data: [DONE]\n\n");
+        Self::raw_sse(body)
+    }
+
+    /// SSE stream with a verbatim body (for comments, malformed lines, ...)
+    pub fn raw_sse(body: String) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".into(), "text/event-stream".into())],
+            body,
+            chunk_size: None,
+        }
+    }
+
+    /// Non-streamed JSON answer, as to `stream: false` requests
+    pub fn json(body: Value) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: body.to_string(),
+            chunk_size: None,
+        }
+    }
+
+    pub fn error(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: body.to_string(),
+            chunk_size: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn chunked(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+}
+
+// --- OpenAI-compatible chunk builders ---
+
+pub fn text_chunk(text: &str) -> String {
+    serde_json::json!({ "choices": [{ "index": 0, "delta": { "content": text } }] }).to_string()
+}
+
+/// Final chunk with the token usage of the round
+pub fn usage_chunk(prompt_tokens: u64, completion_tokens: u64) -> String {
+    serde_json::json!({
+        "choices": [],
+        "usage": { "prompt_tokens": prompt_tokens, "completion_tokens": completion_tokens },
+    }).to_string()
+}
+
+/// Tool-call delta; the first fragment of a call carries id and name
+pub fn tool_call_chunk(index: usize, id: Option<&str>, name: Option<&str>, arguments: &str) -> String {
+    let mut function = serde_json::json!({ "arguments": arguments });
+    if let Some(name) = name {
+        function["name"] = Value::String(name.into());
+    }
+    let mut call = serde_json::json!({ "index": index, "type": "function", "function": function });
+    if let Some(id) = id {
+        call["id"] = Value::String(id.into());
+    }
+    serde_json::json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [call] } }] }).to_string()
+}
+
+pub fn finish_chunk(reason: &str) -> String {
+    serde_json::json!({ "choices": [{ "index": 0, "delta": {}, "finish_reason": reason }] }).to_string()
+}
+
+/// A complete text answer
+pub fn text_response(text: &str) -> MockResponse {
+    MockResponse::sse(&[text_chunk(text), finish_chunk("stop")])
+}
+
+/// A non-streamed answer, e.g. to a summary request
+pub fn completion_response(text: &str) -> MockResponse {
+    MockResponse::json(serde_json::json!({
+        "choices": [{ "index": 0, "message": { "role": "assistant", "content": text }, "finish_reason": "stop" }],
+        "usage": { "prompt_tokens": 900, "completion_tokens": 40 },
+    }))
+}
+
+/// A round requesting one tool call, with the arguments sent in one piece
+pub fn tool_call_response(id: &str, name: &str, arguments: &str) -> MockResponse {
+    MockResponse::sse(&[tool_call_chunk(0, Some(id), Some(name), arguments), finish_chunk("tool_calls")])
+}
+
+/// Local HTTP server replaying scripted responses in order and recording the
+/// JSON bodies it receives. Requests beyond the script get a 500.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Value>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+        let url = format!("http://{}", listener.local_addr().expect("mock server addr"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let script = Arc::new(Mutex::new(VecDeque::from(responses)));
+        let recorded = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                let script = script.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, recorded, script).await;
+                });
+            }
+        });
+        Self { url, requests, task }
+    }
+
+    /// JSON bodies of all requests received so far
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    recorded: Arc<Mutex<Vec<Value>>>,
+    script: Arc<Mutex<VecDeque<MockResponse>>>,
+) -> std::io::Result<()> {
+    // Read headers, then the body by Content-Length
+    let mut buf = Vec::new();
+    let mut tmp = [0u8; 8192];
+    let header_end = loop {
+        let n = stream.read(&mut tmp).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&tmp[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+    let content_length = head.lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let n = stream.read(&mut tmp).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&tmp[..n]);
+    }
+    let body = &buf[header_end..buf.len().min(header_end + content_length)];
+    recorded.lock().unwrap().push(serde_json::from_slice(body).unwrap_or(Value::Null));
+
+    let response = script.lock().unwrap().pop_front()
+        .unwrap_or_else(|| MockResponse::error(500, r#"{"error":"no scripted response left"}"#));
+    let mut head = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+
+    // Body until close, so chunked writes reach the client as separate reads
+    let bytes = response.body.as_bytes();
+    match response.chunk_size {
+        Some(size) => {
+            for piece in bytes.chunks(size) {
+                stream.write_all(piece).await?;
+                stream.flush().await?;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+        None => stream.write_all(bytes).await?,
+    }
+    stream.shutdown().await
+}
@@ -1,3 +1,4 @@
+pub mod agent;
 pub mod config;
 pub mod streaming;
 pub mod gateway;
@@ -8,6 +9,24 @@ pub mod skills;
 pub mod search;
 pub mod sandbox;
 pub mod memory;
+pub mod provider;
+pub mod context;
+pub mod compact;
+pub mod usage;
+pub mod retry;
+pub mod session;
+pub mod headless;
+pub mod image;
+pub mod todo;
+pub mod subagent;
+pub mod hooks;
+pub mod permission;
+pub mod confirm;
+pub mod jobs;
+pub mod vault;
+pub mod profile;
+#[cfg(test)]
+pub(crate) mod mock_server;
 
 pub use config::*;
 pub use streaming::*;
@@ -16,20 +35,20 @@ pub use memory::MemoryStore;
 use std::fs;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use futures_util::StreamExt;
 use reqwest::Client;
-use tauri::{AppHandle, Emitter, Manager};
+use tauri::{AppHandle, Manager};
 
-use crate::app_info;
+use crate::{app_info, app_warn};
 
-use self::streaming::SseChunk;
+use self::provider::{ChatRequest, StreamDelta};
 
 // --- ToolRegistry as Tauri managed state ---
 
 pub struct AiToolRegistryState {
-    pub registry: tool::registry::ToolRegistry,
+    pub registry: Arc<tool::registry::ToolRegistry>,
 }
 
 // --- SkillRegistry as Tauri managed state ---
@@ -44,22 +63,52 @@ pub struct MemoryStoreState {
     pub store: Arc<dyn MemoryStore>,
 }
 
-// --- Shell confirm state for run_shell tool ---
-// NOTE: Known limitation — single-slot design. If multiple concurrent tool calls
-// require confirmation, later senders will overwrite earlier ones. This is acceptable
-// because the LLM processes tool calls sequentially in the current architecture.
-pub struct ShellConfirmState {
-    pub sender: std::sync::Mutex<Option<tokio::sync::oneshot::Sender<bool>>>,
+// --- SessionStore as Tauri managed state ---
+
+pub struct SessionStoreState {
+    pub store: session::SessionStore,
+}
+
+// --- PermissionStore as Tauri managed state ---
+
+pub struct PermissionStoreState {
+    pub store: Arc<permission::PermissionStore>,
+}
+
+// --- ProfileStore as Tauri managed state ---
+
+pub struct ProfileStoreState {
+    pub store: Arc<profile::ProfileStore>,
+}
+
+// --- Background shell jobs as Tauri managed state ---
+
+pub struct JobManagerState {
+    pub manager: Arc<jobs::JobManager>,
+}
+
+// --- Open tool confirmations (run_shell, permission rules) ---
+
+pub struct ConfirmState {
+    pub registry: confirm::ConfirmRegistry,
 }
 
+/// Answer the confirmation `request_id`. Answers for requests that were
+/// already answered or have timed out are rejected.
 #[tauri::command]
-pub async fn shell_confirm_response(app: AppHandle, approved: bool) -> Result<(), String> {
-    let state = app.state::<ShellConfirmState>();
-    let sender = state.sender.lock().map_err(|e| e.to_string())?.take();
-    if let Some(tx) = sender {
-        let _ = tx.send(approved);
-    }
-    Ok(())
+pub async fn shell_confirm_response(
+    app: AppHandle,
+    request_id: String,
+    session_id: Option<String>,
+    approved: bool,
+) -> Result<(), String> {
+    app.state::<ConfirmState>().registry.respond(&request_id, session_id.as_deref(), approved)
+}
+
+/// Confirmations waiting for the user, oldest first
+#[tauri::command]
+pub fn ai_pending_confirmations(app: AppHandle, session_id: Option<String>) -> Vec<confirm::ConfirmRequest> {
+    app.state::<ConfirmState>().registry.pending(session_id.as_deref())
 }
 
 // --- Cancel registry for active sessions ---
@@ -140,23 +189,90 @@ impl Drop for CancelGuard {
     }
 }
 
+/// The config a chat runs with, keys resolved: the named profile, else the
+/// config sent by the frontend, else the workspace's pinned or the default
+/// profile, else the saved config
+fn chat_config(
+    profiles: &profile::ProfileStore,
+    config: Option<AiConfig>,
+    profile_id: Option<&str>,
+    cwd: Option<&str>,
+) -> Result<AiConfig, String> {
+    let mut config = match (profile_id, config) {
+        (Some(id), _) => profiles.get(id)?.config,
+        (None, Some(config)) => config,
+        (None, None) => profiles.resolve(cwd).map(|p| p.config)
+            .or_else(ai_load_config)
+            .ok_or("No AI configuration. Configure a model or create a profile first.")?,
+    };
+    config::resolve_keys(&mut config)?;
+    Ok(config)
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn ai_chat(
     app: AppHandle,
     session_id: String,
     messages: Vec<ChatMessage>,
-    config: AiConfig,
+    config: Option<AiConfig>,
+    profile_id: Option<String>,
     deep_mode: Option<bool>,
     cwd: Option<String>,
     current_skill_id: Option<String>,
 ) -> Result<(), String> {
-    let client = Client::new();
-    let url = format!("{}/chat/completions", config.api_url.trim_end_matches('/'));
+    let config = chat_config(&app.state::<ProfileStoreState>().store, config, profile_id.as_deref(), cwd.as_deref())?;
+
+    // Register cancel flag for this session
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    {
+        let registry = app.state::<AiCancelRegistry>();
+        let mut flags = registry.flags.lock().map_err(|e| e.to_string())?;
+        flags.insert(session_id.clone(), cancel_flag.clone());
+    }
+    let _cancel_guard = CancelGuard { app: app.clone(), session_id: session_id.clone() };
+
+    let tool_registry_state = app.state::<AiToolRegistryState>();
+    let skill_registry_state = app.state::<AiSkillRegistryState>();
+    let session_state = app.state::<SessionStoreState>();
+    let has_search_index = app.state::<crate::bm25::Bm25State>().index.lock()
+        .map(|g| g.is_some())
+        .unwrap_or(false);
+    let env = agent::AgentEnv {
+        tools: tool_registry_state.registry.clone(),
+        skills: &skill_registry_state.registry,
+        memory_store: app.state::<MemoryStoreState>().store.clone(),
+        permissions: app.state::<PermissionStoreState>().store.clone(),
+        jobs: app.state::<JobManagerState>().manager.clone(),
+        sessions: &session_state.store,
+        data_dir: crate::app_data_dir().join("inkess"),
+        has_search_index,
+        app_handle: Some(app.clone()),
+        sink: Arc::new(agent::TauriSink::new(app.clone(), cancel_flag.clone())),
+    };
+    let request = agent::AgentRequest {
+        session_id,
+        messages,
+        config,
+        deep_mode: deep_mode.unwrap_or(false),
+        cwd,
+        current_skill_id,
+    };
+    run_agent(&env, request, cancel_flag).await
+}
+
+/// The agent loop behind `ai_chat` and the headless CLI: streams model rounds,
+/// executes tool calls and reports everything through `env.sink`.
+pub async fn run_agent(
+    env: &agent::AgentEnv<'_>,
+    request: agent::AgentRequest,
+    cancel_flag: Arc<AtomicBool>,
+) -> Result<(), String> {
+    let agent::AgentRequest { session_id, messages, config, deep_mode: is_deep, cwd, current_skill_id } = request;
     let mut conversation = messages.clone();
-    let is_deep = deep_mode.unwrap_or(false);
+    image::resolve_images(&mut conversation, cwd.as_deref().unwrap_or(""));
 
     // Skill detection and activation
-    let skill_registry_state = app.state::<AiSkillRegistryState>();
     let has_files = cwd.is_some();
     let user_message = messages.last()
         .and_then(|m| m.content.as_ref())
@@ -164,22 +280,18 @@ pub async fn ai_chat(
         .unwrap_or("");
     let prev_skill_id = current_skill_id.as_deref().unwrap_or("default");
 
-    let activated_skill_id = skill_registry_state.registry
+    let activated_skill_id = env.skills
         .detect_activation(user_message, has_files, prev_skill_id)
         .await;
 
-    let skill = skill_registry_state.registry
+    let skill = env.skills
         .get(&activated_skill_id)
         .await
         .ok_or_else(|| format!("Skill not found: {}", activated_skill_id))?;
 
     // Emit skill-changed event if skill switched
     if activated_skill_id != prev_skill_id {
-        let _ = app.emit("skill-changed", serde_json::json!({
-            "session_id": session_id,
-            "skill_id": activated_skill_id,
-            "skill_name": skill.display_name(),
-        }));
+        env.sink.skill_changed(&session_id, &activated_skill_id, skill.display_name());
     }
 
     // Build skill state
@@ -187,6 +299,28 @@ pub async fn ai_chat(
         skill_id: activated_skill_id.clone(),
     };
 
+    // The skill's model settings decide the model, and with it the provider
+    let mut model = ModelState::new(config.for_skill(&activated_skill_id, &skill.model_config(&skill_state))).await;
+
+    // Session log: the full history including tool calls, persisted so the
+    // thread can be resumed after a restart
+    let session_store = env.sessions;
+    let mut session_log = match session_store.get(&session_id) {
+        Some(stored) if session::is_fresh_start(&stored, &messages) => {
+            // The chat was cleared but kept its id: move the old thread aside
+            if let Err(e) = session_store.rekey(&session_id, &uuid::Uuid::new_v4().to_string()) {
+                app_warn!("ai:session", "failed to archive session {}: {}", session_id, e);
+            }
+            session::Session::new(&session_id, cwd.as_deref(), &activated_skill_id)
+        }
+        Some(stored) => stored,
+        None => session::Session::new(&session_id, cwd.as_deref(), &activated_skill_id),
+    };
+    session_log.meta.skill_id = activated_skill_id.clone();
+    session_log.sync_incoming(&messages);
+    persist_session(session_store, &mut session_log, &model.config);
+    let todos = todo::TodoList::new(session_log.todos.clone());
+
     // Apply skill's [REDACTED]
     let skill_system_prompt = skill.system_prompt(&skill_state);
     if let Some(first) = conversation.first_mut() {
@@ -203,55 +337,24 @@ pub async fn ai_chat(
             content: Some(skill_system_prompt),
             tool_calls: None,
             tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
         });
     }
 
     // Get max iterations from skill (overrides deep_mode)
     let max_tool_rounds = skill.max_iterations(&skill_state);
 
-    app_info!("ai", "chat start: model={}, skill={}, deep={}, msgs={}, max_rounds={}, url={}",
-        config.model, activated_skill_id, is_deep, messages.len(), max_tool_rounds, url);
-
-    // Register cancel flag for this session
-    let cancel_flag = Arc::new(AtomicBool::new(false));
-    {
-        let registry = app.state::<AiCancelRegistry>();
-        let mut flags = registry.flags.lock().map_err(|e| e.to_string())?;
-        flags.insert(session_id.clone(), cancel_flag.clone());
-    }
-    let _cancel_guard = CancelGuard { app: app.clone(), session_id: session_id.clone() };
+    app_info!("ai", "chat start: provider={}, model={}, skill={}, deep={}, msgs={}, max_rounds={}, url={}",
+        model.provider.name(), model.config.model, activated_skill_id, is_deep, messages.len(), max_tool_rounds, model.config.api_url);
 
     // Deep analysis mode prompt is now injected by the frontend (AIChatPanel.tsx)
     // to keep all prompt logic transparent and user-configurable.
 
-    // Inject relevant memories into context
-    {
-        let memory_store_state = app.state::<MemoryStoreState>();
-        let memory_store = &memory_store_state.store;
-
-        if let Ok(memory_text) = load_relevant_memories(
-            memory_store.as_ref(),
-            user_message,
-            cwd.as_deref(),
-        ).await {
-            if !memory_text.is_empty() {
-                if let Some(first) = conversation.first_mut() {
-                    if first.role == "system" {
-                        if let Some(ref mut content) = first.content {
-                            content.push_str("\n\n");
-                            content.push_str(&memory_text);
-                        }
-                    }
-                }
-            }
-        }
-    }
-
     // Inject search hint if BM25 index is initialized
     {
-        let bm25_state = app.state::<crate::bm25::Bm25State>();
-        let has_index = bm25_state.index.lock().map(|g| g.is_some()).unwrap_or(false);
-        if has_index {
+        if env.has_search_index {
             let hint = "\n\n[Full-Text Search]\nA full-text search index is available for this project. Use the search_knowledge tool to find relevant content across all indexed files when the user asks about project content, code, or documentation.";
             if let Some(first) = conversation.first_mut() {
                 if first.role == "system" {
@@ -263,20 +366,57 @@ pub async fn ai_chat(
         }
     }
 
+    // Relevant memories and the task list change from message to message. They
+    // go in their own message just before the latest user turn, so the system
+    // prompt and the earlier history stay a byte-stable, cacheable prefix.
+    {
+        let mut volatile = Vec::new();
+        if let Ok(memory_text) = load_relevant_memories(
+            env.memory_store.as_ref(),
+            user_message,
+            cwd.as_deref(),
+        ).await {
+            if !memory_text.is_empty() {
+                volatile.push(memory_text);
+            }
+        }
+        // Resumed work: show the task list the agent left behind
+        let plan = todos.items();
+        if !plan.is_empty() {
+            volatile.push(format!("[Task List]\n{}\nKeep it up to date with todo_write.", todo::render(&plan)));
+        }
+        if !volatile.is_empty() {
+            let at = conversation.iter().rposition(|m| m.role == "user").unwrap_or(conversation.len());
+            conversation.insert(at, ChatMessage {
+                role: "system".to_string(),
+                content: Some(volatile.join("\n\n")),
+                tool_calls: None,
+                tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
+            });
+        }
+    }
+
+    // History an earlier run already compacted: use its summary again
+    let mut summary_base = session_log.compaction.clone()
+        .filter(|stored| compact::reuse_summary(&mut conversation, stored));
+    if let Some(stored) = &summary_base {
+        app_info!("ai:context", "reused summary of {} earlier messages", stored.messages);
+    }
+
     // Get tool schemas from ToolRegistry with skill's filter
     // MCP tools are already registered in ToolRegistry via McpBridgeTool
-    let tool_registry_state = app.state::<AiToolRegistryState>();
-    let tool_filter = skill.tool_filter(&skill_state);
-    let all_tool_schemas = tool_registry_state.registry.get_schemas_filtered(&tool_filter).await;
-    let tools_json = serde_json::Value::Array(all_tool_schemas);
+    let tool_filter = Arc::new(skill.tool_filter(&skill_state));
+    let tool_schemas = env.tools.get_schemas_filtered(&tool_filter).await;
 
     let mut python_fail_count: u32 = 0;
-    let mut silent_rounds: u32 = 0; // Rounds with tool calls but no text output
 
-    for _round in 0..max_tool_rounds {
+    for round in 0..max_tool_rounds {
         // Check cancel flag at start of each round
         if cancel_flag.load(Ordering::Relaxed) {
-            let _ = app.emit("ai-stream", AiStreamEvent {
+            env.sink.emit(AiStreamEvent {
                 session_id: session_id.clone(),
                 event_type: "done".into(),
                 content: String::new(),
@@ -284,40 +424,318 @@ pub async fn ai_chat(
             return Ok(());
         }
 
-        // Micro-compact: silently replace old tool results with placeholders
-        micro_compact(&mut conversation);
+        // Per round, since a fallback model may have a different window
+        let context_budget = context::ContextBudget::for_request(&model.config, &tool_schemas);
 
-        let body = serde_json::json!({
-            "model": config.model,
-            "messages": conversation,
-            "temperature": config.temperature,
-            "max_tokens": config.max_tokens,
-            "stream": true,
-            "tools": tools_json,
-        });
+        // Near the context limit: summarise older turns with the model
+        if compact::needs_compaction(&conversation, &context_budget) {
+            if let Some(range) = compact::compaction_range(&conversation) {
+                env.sink.emit(AiStreamEvent {
+                    session_id: session_id.clone(),
+                    event_type: "compacting".into(),
+                    content: range.len().to_string(),
+                });
+                match compact::summarize(&conversation[range.clone()], &model.config, &usage_tag(env, &session_id, round, cwd.as_deref(), "compaction")).await {
+                    Ok(summary) => {
+                        // Originals stay recoverable next to the session for as long as it exists
+                        let archive = session_store.archive(&session_id, &conversation[range.clone()]).unwrap_or_else(|e| {
+                            app_warn!("ai:context", "failed to archive compacted messages: {}", e);
+                            save_transcript(&env.data_dir, &format!("{}-compacted", session_id), &conversation[range.clone()], model.config.session_retention_days)
+                        });
+                        app_info!("ai:context", "compacted {} messages into summary ({} chars), originals: {}",
+                            range.len(), summary.len(), archive.display());
+                        let archive = archive.display().to_string();
+                        // The range starts with the earlier summary when there is one
+                        let earlier = summary_base.as_ref()
+                            .filter(|_| conversation[range.start].content.as_deref().is_some_and(|c| c.starts_with(compact::SUMMARY_PREFIX)));
+                        let stored = compact::StoredSummary::new(&conversation, range.clone(), earlier, &summary, &archive);
+                        compact::apply_summary(&mut conversation, range, &summary, &archive);
+                        // Kept with the session, so the next run doesn't summarise these messages again
+                        session_log.compaction = Some(stored.clone());
+                        persist_session(session_store, &mut session_log, &model.config);
+                        summary_base = Some(stored);
+                    }
+                    Err(e) => app_warn!("ai:context", "compaction failed, falling back to trimming: {}", e),
+                }
+            }
+        }
+
+        // Trim the history to the model's context window
+        let trim = context::fit_to_budget(&mut conversation, &context_budget);
+        if trim.trimmed() {
+            app_info!("ai:context", "trimmed {} -> {} tokens (limit {}): {} tool results omitted, {} messages dropped",
+                trim.tokens_before, trim.tokens_after, context_budget.limit, trim.omitted_tool_results, trim.dropped_messages);
+        }
 
-        let resp = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| {
-                let _ = app.emit("ai-stream", AiStreamEvent {
+        let round_output = match stream_round(
+            &mut model, env.sink.as_ref(), &session_id, &conversation, &tool_schemas, is_deep, &cancel_flag,
+        ).await? {
+            Some(output) => output,
+            None => {
+                env.sink.emit(AiStreamEvent {
                     session_id: session_id.clone(),
+                    event_type: "done".into(),
+                    content: String::new(),
+                });
+                return Ok(());
+            }
+        };
+        let wants_tools = round_output.wants_tools();
+        let RoundOutput { content: full_content, reasoning: full_reasoning, thinking_blocks, tool_calls, finish_reason: _, usage: round_usage } = round_output;
+
+        // Usage ledger (best-effort)
+        if let Some(u) = round_usage {
+            let entry = usage::make_record(&model.config, &session_id, round as u32, cwd.as_deref(), &u);
+            env.sink.emit(AiStreamEvent {
+                session_id: session_id.clone(),
+                event_type: "usage".into(),
+                content: serde_json::json!({
+                    "prompt_tokens": entry.prompt_tokens,
+                    "completion_tokens": entry.completion_tokens,
+                    "cached_tokens": entry.cached_tokens,
+                    "cost": entry.cost,
+                }).to_string(),
+            });
+            if let Err(e) = usage::record(&env.data_dir, &entry) {
+                app_warn!("ai:usage", "failed to record usage: {}", e);
+            }
+        }
+
+        // Check if we got tool calls
+        if wants_tools {
+
+            // Add assistant message with tool_calls
+            let assistant_msg = ChatMessage {
+                role: "assistant".into(),
+                content: if full_content.is_empty() { None } else { Some(full_content.clone()) },
+                tool_calls: Some(tool_calls.clone()),
+                tool_call_id: None,
+                // Kept for providers that need it back; stripped by the others
+                reasoning: if full_reasoning.is_empty() { None } else { Some(full_reasoning) },
+                thinking_blocks,
+                images: None,
+            };
+            session_log.messages.push(assistant_msg.clone());
+            conversation.push(assistant_msg);
+
+            let cwd_str = cwd.as_deref().unwrap_or("");
+            let tool_ctx = tool::ToolContext {
+                session_id: session_id.clone(),
+                workspace_path: cwd_str.to_string(),
+                app_handle: env.app_handle.clone(),
+                sink: env.sink.clone(),
+                ai_config: model.config.clone(),
+                memory_store: env.memory_store.clone(),
+                permissions: env.permissions.clone(),
+                todos: todos.clone(),
+                jobs: env.jobs.clone(),
+                call_id: String::new(),
+                progress: None,
+                tools: env.tools.clone(),
+                tool_filter: tool_filter.clone(),
+                cancel_flag: cancel_flag.clone(),
+                data_dir: env.data_dir.clone(),
+            };
+            let tool_round = match execute_tool_calls(&env.tools, &tool_ctx, &tool_calls, &mut python_fail_count, &cancel_flag).await {
+                Some(result) => result,
+                None => {
+                    env.sink.emit(AiStreamEvent {
+                        session_id: session_id.clone(),
+                        event_type: "done".into(),
+                        content: String::new(),
+                    });
+                    return Ok(());
+                }
+            };
+            session_log.messages.extend(tool_round.messages.iter().cloned());
+            conversation.extend(tool_round.messages);
+            let attached_images = tool_round.images;
+
+            // Images from attach_image follow the tool results as a user turn,
+            // since tool messages can't carry image parts
+            if !attached_images.is_empty() {
+                let names: Vec<&str> = attached_images.iter().filter_map(|img| img.path.as_deref()).collect();
+                let image_msg = ChatMessage {
+                    role: "user".into(),
+                    content: Some(format!("[Attached images: {}]", names.join(", "))),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    reasoning: None,
+                    thinking_blocks: None,
+                    images: Some(image::without_data(&attached_images)),
+                };
+                session_log.messages.push(image_msg.clone());
+                conversation.push(ChatMessage { images: Some(attached_images), ..image_msg });
+            }
+            session_log.todos = todos.items();
+            persist_session(session_store, &mut session_log, &model.config);
+
+            // Continue loop to send tool results back to LLM
+            continue;
+        }
+
+        // No tool calls — we're done
+        session_log.messages.push(ChatMessage {
+            role: "assistant".into(),
+            content: Some(full_content.clone()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: if full_reasoning.is_empty() { None } else { Some(full_reasoning) },
+            thinking_blocks,
+            images: None,
+        });
+        persist_session(session_store, &mut session_log, &model.config);
+        env.sink.emit(AiStreamEvent {
+            session_id: session_id.clone(),
+            event_type: "done".into(),
+            content: full_content,
+        });
+
+        // Save transcript for recoverability
+        save_transcript(&env.data_dir, &session_id, &conversation, model.config.session_retention_days);
+
+        // Auto-distill: compress long conversations into persistent memories
+        maybe_spawn_distill(env.memory_store.clone(), &conversation, &model.config, cwd.as_deref(),
+            usage_tag(env, &session_id, round, cwd.as_deref(), "distill"));
+
+        return Ok(());
+    }
+
+    // Exceeded max tool rounds — send accumulated content + friendly notice
+    let notice = format!("\n\n---\n⚠️ Reached the maximum of {} tool call rounds. You can continue the conversation to ask for more analysis.", max_tool_rounds);
+    env.sink.emit(AiStreamEvent {
+        session_id: session_id.clone(),
+        event_type: "delta".into(),
+        content: notice,
+    });
+    env.sink.emit(AiStreamEvent {
+        session_id: session_id.clone(),
+        event_type: "done".into(),
+        content: String::new(),
+    });
+
+    // Save transcript for recoverability
+    save_transcript(&env.data_dir, &session_id, &conversation, model.config.session_retention_days);
+    persist_session(session_store, &mut session_log, &model.config);
+
+    // Auto-distill for max-rounds exit too
+    maybe_spawn_distill(env.memory_store.clone(), &conversation, &model.config, cwd.as_deref(),
+        usage_tag(env, &session_id, max_tool_rounds, cwd.as_deref(), "distill"));
+
+    Ok(())
+}
+
+/// The model a run talks to. A failing model is replaced by the next
+/// fallback, for the rest of the run.
+pub(crate) struct ModelState {
+    pub client: Client,
+    pub config: AiConfig,
+    pub provider: Box<dyn provider::ChatProvider>,
+    pub fallbacks: VecDeque<String>,
+}
+
+impl ModelState {
+    pub async fn new(config: AiConfig) -> Self {
+        let provider = provider::resolve_provider(&config).await;
+        let fallbacks = config.fallback_models.iter()
+            .filter(|m| !m.trim().is_empty() && **m != config.model)
+            .cloned()
+            .collect();
+        Self { client: Client::new(), config, provider, fallbacks }
+    }
+}
+
+/// One complete model response
+pub(crate) struct RoundOutput {
+    pub content: String,
+    pub reasoning: String,
+    /// Anthropic thinking blocks, None if there were none
+    pub thinking_blocks: Option<Vec<ThinkingBlock>>,
+    /// In the order the model sent them
+    pub tool_calls: Vec<ToolCall>,
+    pub finish_reason: Option<String>,
+    pub usage: Option<usage::TokenUsage>,
+}
+
+impl RoundOutput {
+    pub fn wants_tools(&self) -> bool {
+        self.finish_reason.as_deref() == Some("tool_calls") && !self.tool_calls.is_empty()
+    }
+}
+
+/// Stream one model round to the sink, retrying transient failures and moving
+/// to fallback models. Errors are reported to the sink before returning.
+/// Returns None if cancelled; the caller reports the end of the run.
+pub(crate) async fn stream_round(
+    model: &mut ModelState,
+    sink: &dyn agent::AgentSink,
+    session_id: &str,
+    conversation: &[ChatMessage],
+    tool_schemas: &[serde_json::Value],
+    reasoning: bool,
+    cancel_flag: &AtomicBool,
+) -> Result<Option<RoundOutput>, String> {
+    // The caller only extends the conversation after a complete response, so a
+    // retry replays the round from the last complete state.
+    let retry_policy = retry::RetryPolicy::from_config(&model.config);
+    let mut attempt: u32 = 0;
+    'attempt: loop {
+        if attempt > 0 && cancel_flag.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let request = ChatRequest {
+            model: &model.config.model,
+            messages: conversation,
+            tools: tool_schemas,
+            temperature: model.config.temperature,
+            max_tokens: model.config.max_tokens,
+            stream: true,
+            reasoning,
+            cache: model.config.prompt_caching,
+        };
+
+        let resp = match model.provider.build_request(&model.client, &model.config, &request).send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                let err_msg = format!("Request failed: {}", e);
+                if wait_for_retry(sink, session_id, &retry_policy, &mut attempt, &err_msg, None, cancel_flag).await {
+                    continue 'attempt;
+                }
+                if switch_to_fallback(sink, session_id, model, &err_msg).await {
+                    attempt = 0;
+                    continue 'attempt;
+                }
+                sink.emit(AiStreamEvent {
+                    session_id: session_id.to_string(),
                     event_type: "error".into(),
-                    content: format!("Request failed: {}", e),
+                    content: err_msg.clone(),
                 });
-                format!("Request failed: {}", e)
-            })?;
+                return Err(err_msg);
+            }
+        };
 
         if !resp.status().is_success() {
             let status = resp.status();
+            let retry_after = resp.headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(retry::parse_retry_after);
             let text = resp.text().await.unwrap_or_default();
             let err_msg = format!("API error ({}): {}", status, text);
-            let _ = app.emit("ai-stream", AiStreamEvent {
-                session_id: session_id.clone(),
+            if retry::is_retryable_status(status.as_u16())
+                && wait_for_retry(sink, session_id, &retry_policy, &mut attempt, &err_msg, retry_after, cancel_flag).await
+            {
+                continue 'attempt;
+            }
+            if retry::allows_fallback(status.as_u16())
+                && switch_to_fallback(sink, session_id, model, &err_msg).await
+            {
+                attempt = 0;
+                continue 'attempt;
+            }
+            sink.emit(AiStreamEvent {
+                session_id: session_id.to_string(),
                 event_type: "error".into(),
                 content: err_msg.clone(),
             });
@@ -326,31 +744,39 @@ pub async fn ai_chat(
 
         // Parse SSE stream
         let mut stream = resp.bytes_stream();
+        let mut parser = model.provider.stream_parser();
         let mut full_content = String::new();
+        let mut full_reasoning = String::new();
+        let mut thinking_blocks: Vec<ThinkingBlock> = Vec::new();
         let mut tool_calls_map: std::collections::HashMap<usize, ToolCall> = std::collections::HashMap::new();
         let mut finish_reason: Option<String> = None;
+        let mut round_usage: Option<usage::TokenUsage> = None;
         let mut buffer = String::new();
         const MAX_SSE_BUFFER: usize = 512 * 1024; // 512KB cap for SSE buffer
 
         while let Some(chunk_result) = stream.next().await {
             // Check cancel flag during streaming
             if cancel_flag.load(Ordering::Relaxed) {
-                let _ = app.emit("ai-stream", AiStreamEvent {
-                    session_id: session_id.clone(),
-                    event_type: "done".into(),
-                    content: String::new(),
-                });
-                return Ok(());
+                return Ok(None);
             }
             let chunk = match chunk_result {
                 Ok(c) => c,
                 Err(e) => {
-                    let _ = app.emit("ai-stream", AiStreamEvent {
-                        session_id: session_id.clone(),
+                    // Dropped connection mid-stream: discard the partial round and replay it
+                    let err_msg = format!("Stream read error: {}", e);
+                    if wait_for_retry(sink, session_id, &retry_policy, &mut attempt, &err_msg, None, cancel_flag).await {
+                        continue 'attempt;
+                    }
+                    if switch_to_fallback(sink, session_id, model, &err_msg).await {
+                        attempt = 0;
+                        continue 'attempt;
+                    }
+                    sink.emit(AiStreamEvent {
+                        session_id: session_id.to_string(),
                         event_type: "error".into(),
-                        content: format!("Stream read error: {}", e),
+                        content: err_msg.clone(),
                     });
-                    return Err(e.to_string());
+                    return Err(err_msg);
                 }
             };
 
@@ -375,266 +801,267 @@ pub async fn ai_chat(
                     buffer.drain(..pos + 1);
                     continue;
                 }
-                let json_str = &line[6..];
-                let sse_chunk: SseChunk = match serde_json::from_str(json_str) {
-                    Ok(c) => c,
-                    Err(_) => { buffer.drain(..pos + 1); continue; }
-                };
-
+                let deltas = parser.parse(&line[6..]);
                 buffer.drain(..pos + 1);
 
-                if let Some(choices) = &sse_chunk.choices {
-                    for choice in choices {
-                        if let Some(reason) = &choice.finish_reason {
-                            finish_reason = Some(reason.clone());
+                for delta in deltas {
+                    match delta {
+                        StreamDelta::Text(text) => {
+                            full_content.push_str(&text);
+                            sink.emit(AiStreamEvent {
+                                session_id: session_id.to_string(),
+                                event_type: "delta".into(),
+                                content: text,
+                            });
+                        }
+                        StreamDelta::Thinking(text) => {
+                            full_reasoning.push_str(&text);
+                            sink.emit(AiStreamEvent {
+                                session_id: session_id.to_string(),
+                                event_type: "thinking".into(),
+                                content: text,
+                            });
+                        }
+                        StreamDelta::ThinkingBlock(block) => thinking_blocks.push(block),
+                        StreamDelta::ToolCall { index, id, name, arguments } => {
+                            let entry = tool_calls_map.entry(index).or_insert_with(|| ToolCall {
+                                id: String::new(),
+                                r#type: "function".into(),
+                                function: FunctionCall {
+                                    name: String::new(),
+                                    arguments: String::new(),
+                                },
+                            });
+                            if let Some(id) = id {
+                                entry.id = id;
+                            }
+                            if let Some(name) = name {
+                                entry.function.name.push_str(&name);
+                            }
+                            if let Some(args) = arguments {
+                                entry.function.arguments.push_str(&args);
+                            }
+                        }
+                        StreamDelta::Usage(u) => {
+                            round_usage = Some(u);
                         }
-                        if let Some(delta) = &choice.delta {
-                            // Text content
-                            if let Some(text) = &delta.content {
-                                full_content.push_str(text);
-                                let _ = app.emit("ai-stream", AiStreamEvent {
-                                    session_id: session_id.clone(),
-                                    event_type: "delta".into(),
-                                    content: text.clone(),
-                                });
+                        StreamDelta::Finish(reason) => {
+                            finish_reason = Some(reason);
+                        }
+                        StreamDelta::Error { message, retryable } => {
+                            let err_msg = format!("Stream error: {}", message);
+                            if retryable
+                                && wait_for_retry(sink, session_id, &retry_policy, &mut attempt, &err_msg, None, cancel_flag).await
+                            {
+                                continue 'attempt;
                             }
-                            // Tool calls
-                            if let Some(tcs) = &delta.tool_calls {
-                                for tc in tcs {
-                                    let idx = tc.index.unwrap_or(0);
-                                    let entry = tool_calls_map.entry(idx).or_insert_with(|| ToolCall {
-                                        id: String::new(),
-                                        r#type: "function".into(),
-                                        function: FunctionCall {
-                                            name: String::new(),
-                                            arguments: String::new(),
-                                        },
-                                    });
-                                    if let Some(id) = &tc.id {
-                                        entry.id = id.clone();
-                                    }
-                                    if let Some(f) = &tc.function {
-                                        if let Some(name) = &f.name {
-                                            entry.function.name.push_str(name);
-                                        }
-                                        if let Some(args) = &f.arguments {
-                                            entry.function.arguments.push_str(args);
-                                        }
-                                    }
-                                }
+                            if switch_to_fallback(sink, session_id, model, &err_msg).await {
+                                attempt = 0;
+                                continue 'attempt;
                             }
+                            sink.emit(AiStreamEvent {
+                                session_id: session_id.to_string(),
+                                event_type: "error".into(),
+                                content: err_msg.clone(),
+                            });
+                            return Err(err_msg);
                         }
                     }
                 }
             }
         }
+        let mut sorted_calls: Vec<(usize, ToolCall)> = tool_calls_map.into_iter().collect();
+        sorted_calls.sort_by_key(|(idx, _)| *idx);
+        return Ok(Some(RoundOutput {
+            content: full_content,
+            reasoning: full_reasoning,
+            thinking_blocks: (!thinking_blocks.is_empty()).then_some(thinking_blocks),
+            tool_calls: sorted_calls.into_iter().map(|(_, tc)| tc).collect(),
+            finish_reason,
+            usage: round_usage,
+        }));
+    }
+}
 
-        // Check if we got tool calls
-        if finish_reason.as_deref() == Some("tool_calls") && !tool_calls_map.is_empty() {
-            let mut sorted_calls: Vec<(usize, ToolCall)> = tool_calls_map.into_iter().collect();
-            sorted_calls.sort_by_key(|(idx, _)| *idx);
-            let tool_calls: Vec<ToolCall> = sorted_calls.into_iter().map(|(_, tc)| tc).collect();
+/// Results of one round of tool calls, in call order
+pub(crate) struct ToolRoundResult {
+    pub messages: Vec<ChatMessage>,
+    /// Images from attach_image, to send after the tool messages
+    pub images: Vec<ImagePart>,
+}
 
-            // Add assistant message with tool_calls
-            conversation.push(ChatMessage {
-                role: "assistant".into(),
-                content: if full_content.is_empty() { None } else { Some(full_content.clone()) },
-                tool_calls: Some(tool_calls.clone()),
-                tool_call_id: None,
+/// Execute tool calls in batches: consecutive concurrency-safe calls run in
+/// parallel, everything else runs alone. Results keep the original order.
+/// Returns None if cancelled between batches.
+pub(crate) async fn execute_tool_calls(
+    tools: &tool::registry::ToolRegistry,
+    tool_ctx: &tool::ToolContext,
+    tool_calls: &[ToolCall],
+    python_fail_count: &mut u32,
+    cancel_flag: &AtomicBool,
+) -> Option<ToolRoundResult> {
+    let mut safe_flags = Vec::with_capacity(tool_calls.len());
+    for tc in tool_calls {
+        safe_flags.push(tools.is_concurrency_safe(&tc.function.name).await);
+    }
+    let mut messages = Vec::with_capacity(tool_calls.len());
+    let mut images: Vec<ImagePart> = Vec::new();
+    for batch in batch_tool_calls(&safe_flags) {
+        // Check cancel before each batch
+        if cancel_flag.load(Ordering::Relaxed) {
+            return None;
+        }
+        let batch_calls = &tool_calls[batch];
+        for tc in batch_calls {
+            tool_ctx.sink.emit(AiStreamEvent {
+                session_id: tool_ctx.session_id.clone(),
+                event_type: "tool_call".into(),
+                content: serde_json::json!({
+                    "id": tc.id,
+                    "name": tc.function.name,
+                    "arguments": tc.function.arguments,
+                }).to_string(),
             });
+        }
 
-            // Execute each tool and add results
-            for tc in &tool_calls {
-                // Check cancel before each tool execution
-                if cancel_flag.load(Ordering::Relaxed) {
-                    let _ = app.emit("ai-stream", AiStreamEvent {
-                        session_id: session_id.clone(),
-                        event_type: "done".into(),
-                        content: String::new(),
-                    });
-                    return Ok(());
+        // All tools (builtin + MCP bridge) go through ToolRegistry
+        let results = futures_util::future::join_all(batch_calls.iter().map(|tc| {
+            let args_preview_end = char_boundary(&tc.function.arguments, 200);
+            app_info!("ai:tool", "execute: {} args={}", tc.function.name, &tc.function.arguments[..args_preview_end]);
+            let registry = tools;
+            let call_ctx = tool::ToolContext {
+                call_id: tc.id.clone(),
+                progress: Some(tool::progress::ToolProgress::new(tool_ctx.sink.clone(), &tool_ctx.session_id, &tc.id, &tc.function.name)),
+                ..tool_ctx.clone()
+            };
+            async move {
+                // Arguments are parsed and checked against the schema by the registry
+                match registry.execute_raw(&tc.function.name, &call_ctx, &tc.function.arguments).await {
+                    Ok(output) => (output.content, output.images),
+                    Err(e) => (format!("Tool error: {}", e), Vec::new()),
                 }
-                let _ = app.emit("ai-stream", AiStreamEvent {
-                    session_id: session_id.clone(),
-                    event_type: "tool_call".into(),
-                    content: serde_json::json!({
-                        "id": tc.id,
-                        "name": tc.function.name,
-                        "arguments": tc.function.arguments,
-                    }).to_string(),
-                });
-
-                let cwd_str = cwd.as_deref().unwrap_or("");
-
-                // All tools (builtin + MCP bridge) go through ToolRegistry
-                let args_preview_end = char_boundary(&tc.function.arguments, 200);
-                app_info!("ai:tool", "execute: {} args={}", tc.function.name, &tc.function.arguments[..args_preview_end]);
-                let args: serde_json::Value = serde_json::from_str(&tc.function.arguments).unwrap_or_default();
-                let memory_store_state = app.state::<MemoryStoreState>();
-                let tool_ctx = tool::ToolContext {
-                    workspace_path: cwd_str.to_string(),
-                    app_handle: app.clone(),
-                    ai_config: config.clone(),
-                    memory_store: memory_store_state.store.clone(),
-                };
-                let result = match tool_registry_state.registry.execute(&tc.function.name, &tool_ctx, args).await {
-                    Ok(output) => output.content,
-                    Err(e) => format!("Tool error: {}", e),
-                };
+            }
+        })).await;
+
+        for (tc, (result, result_images)) in batch_calls.iter().zip(results) {
+            images.extend(result_images);
+            // Auto-decay: save large tool results to file, replace with reference + hint
+            const DECAY_THRESHOLD: usize = 32 * 1024; // 32KB
+            let result = if result.len() > DECAY_THRESHOLD {
+                let decay_dir = tool_ctx.data_dir.join("decay-cache");
+                let _ = fs::create_dir_all(&decay_dir);
+                // Sanitize tool name for safe file naming
+                let safe_name: String = tc.function.name.chars()
+                    .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+                    .collect();
+                let file_name = format!(
+                    "decay-{}-{}.txt",
+                    &safe_name[..safe_name.len().min(32)],
+                    &uuid::Uuid::new_v4().to_string()[..8]
+                );
+                let decay_path = decay_dir.join(&file_name);
+                let original_size = result.len();
+                let decay_path_str = decay_path.display().to_string();
+
+                // Smart truncation: structure-aware preview based on tool type
+                let (preview, extra_info) = smart_truncate(&result, &tc.function.name);
+                let hint = decay_tool_hint(&tc.function.name, &decay_path_str, original_size, &extra_info);
+
+                // Memory-aware decay: save brief episodic memory (best-effort)
+                save_decay_memory(
+                    tool_ctx.memory_store.clone(),
+                    tc.function.name.clone(),
+                    original_size,
+                    extra_info,
+                );
 
-                // Auto-decay: save large tool results to file, replace with reference + hint
-                const DECAY_THRESHOLD: usize = 32 * 1024; // 32KB
-                let result = if result.len() > DECAY_THRESHOLD {
-                    let decay_dir = crate::app_data_dir().join("inkess").join("decay-cache");
-                    let _ = fs::create_dir_all(&decay_dir);
-                    // Sanitize tool name for safe file naming
-                    let safe_name: String = tc.function.name.chars()
-                        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
-                        .collect();
-                    let file_name = format!(
-                        "decay-{}-{}.txt",
-                        &safe_name[..safe_name.len().min(32)],
-                        &uuid::Uuid::new_v4().to_string()[..8]
-                    );
-                    let decay_path = decay_dir.join(&file_name);
-                    let original_size = result.len();
-                    let decay_path_str = decay_path.display().to_string();
-
-                    // Smart truncation: structure-aware preview based on tool type
-                    let (preview, extra_info) = smart_truncate(&result, &tc.function.name);
-                    let hint = decay_tool_hint(&tc.function.name, &decay_path_str, original_size, &extra_info);
-
-                    // Memory-aware decay: save brief episodic memory (best-effort)
-                    let memory_store_state = app.state::<MemoryStoreState>();
-                    save_decay_memory(
-                        memory_store_state.store.clone(),
-                        tc.function.name.clone(),
-                        original_size,
-                        extra_info,
-                    );
-
-                    match fs::write(&decay_path, &result) {
-                        Ok(_) => format!(
-                            "{}\n\n[Output too large ({:.0}KB) — full content saved to: {}]\n{}",
-                            preview,
-                            original_size as f64 / 1024.0,
-                            decay_path_str,
-                            hint
-                        ),
-                        Err(_) => {
-                            // Fallback: simple truncation if file write fails
-                            let (fallback_preview, _) = default_truncate(&result);
-                            format!(
-                                "{}\n[Truncated: result was {} bytes]\n{}",
-                                fallback_preview, original_size, hint
-                            )
-                        }
+                match fs::write(&decay_path, &result) {
+                    Ok(_) => format!(
+                        "{}\n\n[Output too large ({:.0}KB) — full content saved to: {}]\n{}",
+                        preview,
+                        original_size as f64 / 1024.0,
+                        decay_path_str,
+                        hint
+                    ),
+                    Err(_) => {
+                        // Fallback: simple truncation if file write fails
+                        let (fallback_preview, _) = default_truncate(&result);
+                        format!(
+                            "{}\n[Truncated: result was {} bytes]\n{}",
+                            fallback_preview, original_size, hint
+                        )
                     }
-                } else {
-                    result
-                };
+                }
+            } else {
+                result
+            };
 
-                // Track consecutive Python failures
-                let mut result = result;
-                if tc.function.name == "run_python" {
-                    if result.contains("Python execution failed") || result.contains("Code blocked for security") {
-                        python_fail_count += 1;
-                        if python_fail_count >= 2 {
-                            result.push_str("\n\nPython execution failed twice consecutively. Please review the approach or ask the user for guidance.");
-                        }
-                    } else {
-                        python_fail_count = 0;
+            // Track consecutive Python failures
+            let mut result = result;
+            if tc.function.name == "run_python" {
+                if result.contains("Python execution failed") || result.contains("Code blocked for security") {
+                    *python_fail_count += 1;
+                    if *python_fail_count >= 2 {
+                        result.push_str("\n\nPython execution failed twice consecutively. Please review the approach or ask the user for guidance.");
                     }
+                } else {
+                    *python_fail_count = 0;
                 }
-
-                let _ = app.emit("ai-stream", AiStreamEvent {
-                    session_id: session_id.clone(),
-                    event_type: "tool_result".into(),
-                    content: serde_json::json!({
-                        "id": tc.id,
-                        "name": tc.function.name,
-                        "result": result,
-                    }).to_string(),
-                });
-
-                conversation.push(ChatMessage {
-                    role: "tool".into(),
-                    content: Some(result),
-                    tool_calls: None,
-                    tool_call_id: Some(tc.id.clone()),
-                });
             }
 
-            // Track silent rounds (tool calls without user-visible text)
-            if full_content.trim().is_empty() {
-                silent_rounds += 1;
-            } else {
-                silent_rounds = 0;
-            }
-
-            // Nag reminder: after 3+ silent rounds, nudge the LLM to provide progress
-            if silent_rounds >= 3 {
-                conversation.push(ChatMessage {
-                    role: "system".into(),
-                    content: Some(
-                        "[Progress reminder] You've been working silently for several rounds. Please briefly update the user on your progress and what you're doing next.".to_string()
-                    ),
-                    tool_calls: None,
-                    tool_call_id: None,
-                });
-                silent_rounds = 0; // Reset after reminder
-            }
+            tool_ctx.sink.emit(AiStreamEvent {
+                session_id: tool_ctx.session_id.clone(),
+                event_type: "tool_result".into(),
+                content: serde_json::json!({
+                    "id": tc.id,
+                    "name": tc.function.name,
+                    "result": result,
+                }).to_string(),
+            });
 
-            // Continue loop to send tool results back to LLM
-            continue;
+            let tool_msg = ChatMessage {
+                role: "tool".into(),
+                content: Some(result),
+                tool_calls: None,
+                tool_call_id: Some(tc.id.clone()),
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
+            };
+            messages.push(tool_msg);
         }
-
-        // No tool calls — we're done
-        let _ = app.emit("ai-stream", AiStreamEvent {
-            session_id: session_id.clone(),
-            event_type: "done".into(),
-            content: full_content,
-        });
-
-        // Save transcript for recoverability
-        save_transcript(&session_id, &conversation);
-
-        // Auto-distill: compress long conversations into persistent memories
-        maybe_spawn_distill(&app, &conversation, &config, cwd.as_deref());
-
-        return Ok(());
     }
+    Some(ToolRoundResult { messages, images })
+}
 
-    // Exceeded max tool rounds — send accumulated content + friendly notice
-    let notice = format!("\n\n---\n⚠️ Reached the maximum of {} tool call rounds. You can continue the conversation to ask for more analysis.", max_tool_rounds);
-    let _ = app.emit("ai-stream", AiStreamEvent {
-        session_id: session_id.clone(),
-        event_type: "delta".into(),
-        content: notice,
-    });
-    let _ = app.emit("ai-stream", AiStreamEvent {
-        session_id: session_id.clone(),
-        event_type: "done".into(),
-        content: String::new(),
-    });
-
-    // Save transcript for recoverability
-    save_transcript(&session_id, &conversation);
-
-    // Auto-distill for max-rounds exit too
-    maybe_spawn_distill(&app, &conversation, &config, cwd.as_deref());
+/// Ledger tag for a background completion made in `round` of this run
+fn usage_tag(env: &agent::AgentEnv<'_>, session_id: &str, round: usize, cwd: Option<&str>, kind: &'static str) -> usage::UsageTag {
+    usage::UsageTag {
+        data_dir: env.data_dir.clone(),
+        session_id: session_id.to_string(),
+        round: round as u32,
+        workspace: cwd.filter(|w| !w.is_empty()).map(|w| w.to_string()),
+        kind,
+    }
+}
 
-    Ok(())
+/// Save the session log (best-effort) and drop sessions past the retention period
+fn persist_session(store: &session::SessionStore, session: &mut session::Session, config: &AiConfig) {
+    if let Err(e) = store.save(session) {
+        app_warn!("ai:session", "failed to save session {}: {}", session.meta.id, e);
+    }
+    store.prune(config.session_retention_days);
 }
 
 /// Spawn a background task to distill long conversations into persistent memories.
 /// Only triggers when the conversation exceeds DISTILL_THRESHOLD messages.
 /// Failures are logged but never crash the application.
 fn maybe_spawn_distill(
-    app: &AppHandle,
+    memory_store: Arc<dyn MemoryStore>,
     conversation: &[ChatMessage],
     config: &AiConfig,
     workspace_path: Option<&str>,
+    usage: usage::UsageTag,
 ) {
     use memory::distill::DISTILL_THRESHOLD;
 
@@ -642,14 +1069,13 @@ fn maybe_spawn_distill(
         return;
     }
 
-    let memory_store = app.state::<MemoryStoreState>().store.clone();
     let messages = conversation.to_vec();
     let ai_config = config.clone();
     let ws = workspace_path.map(|s| s.to_string());
 
     tokio::spawn(async move {
         app_info!("ai:distill", "starting auto-distill ({} messages)", messages.len());
-        match memory::distill::distill_conversation(&messages, &ai_config, ws).await {
+        match memory::distill::distill_conversation(&messages, &ai_config, ws, &usage).await {
             Ok(mem) => {
                 let content_preview: String = mem.content.chars().take(80).collect();
                 match memory_store.save(mem).await {
@@ -808,6 +1234,82 @@ fn default_truncate(content: &str) -> (String, String) {
     (preview, String::new())
 }
 
+/// Wait before retrying a failed round. Emits a "retrying" event so the UI can
+/// show the pending retry and drop partial output of the failed attempt.
+/// Returns false when retries are exhausted.
+async fn wait_for_retry(
+    sink: &dyn agent::AgentSink,
+    session_id: &str,
+    policy: &retry::RetryPolicy,
+    attempt: &mut u32,
+    reason: &str,
+    retry_after: Option<std::time::Duration>,
+    cancel_flag: &AtomicBool,
+) -> bool {
+    let Some(delay) = policy.delay(*attempt, retry_after) else {
+        return false;
+    };
+    *attempt += 1;
+    app_warn!("ai", "retry {}/{} in {}ms: {}", attempt, policy.max_retries, delay.as_millis(), reason);
+    sink.emit(AiStreamEvent {
+        session_id: session_id.to_string(),
+        event_type: "retrying".into(),
+        content: serde_json::json!({
+            "attempt": *attempt,
+            "max_retries": policy.max_retries,
+            "delay_ms": delay.as_millis() as u64,
+            "reason": reason,
+        }).to_string(),
+    });
+    // A cancel during the wait is picked up at the top of the next attempt
+    retry::sleep_unless_cancelled(delay, cancel_flag).await;
+    true
+}
+
+/// Move on to the next fallback model once the current one has failed for
+/// good. Returns false when no fallback is left.
+async fn switch_to_fallback(
+    sink: &dyn agent::AgentSink,
+    session_id: &str,
+    model: &mut ModelState,
+    reason: &str,
+) -> bool {
+    let Some(next) = model.fallbacks.pop_front() else {
+        return false;
+    };
+    app_warn!("ai", "model {} failed, falling back to {}: {}", model.config.model, next, reason);
+    sink.emit(AiStreamEvent {
+        session_id: session_id.to_string(),
+        event_type: "model_fallback".into(),
+        content: serde_json::json!({
+            "from": model.config.model,
+            "to": next,
+            "reason": reason,
+        }).to_string(),
+    });
+    model.config.model = next;
+    model.provider = provider::resolve_provider(&model.config).await;
+    true
+}
+
+/// Split tool calls into execution batches: each run of consecutive
+/// concurrency-safe calls forms one batch, every other call runs alone.
+fn batch_tool_calls(safe: &[bool]) -> Vec<std::ops::Range<usize>> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    while start < safe.len() {
+        let mut end = start + 1;
+        if safe[start] {
+            while end < safe.len() && safe[end] {
+                end += 1;
+            }
+        }
+        batches.push(start..end);
+        start = end;
+    }
+    batches
+}
+
 /// Find the largest valid char boundary <= target position.
 fn char_boundary(s: &str, target: usize) -> usize {
     let mut pos = target.min(s.len());
@@ -978,56 +1480,35 @@ fn format_memories_for_context(memories: &[memory::Memory]) -> String {
     format!("## Relevant Memories\n\n{}", sections.join("\n\n"))
 }
 
-/// Micro-compact: silently replace old tool results with placeholders to save context.
-/// Keeps the most recent `keep_recent` tool messages intact; for older ones, if the
-/// content exceeds `COMPACT_THRESHOLD` bytes, replace with a brief placeholder.
-/// This is zero-LLM-cost — pure in-memory string replacement each round.
-const MICRO_COMPACT_KEEP_RECENT: usize = 3;
-const MICRO_COMPACT_THRESHOLD: usize = 200;
-
-fn micro_compact(conversation: &mut Vec<ChatMessage>) {
-    // Collect indices of all tool-role messages (in order)
-    let tool_indices: Vec<usize> = conversation
-        .iter()
-        .enumerate()
-        .filter(|(_, m)| m.role == "tool")
-        .map(|(i, _)| i)
-        .collect();
-
-    if tool_indices.len() <= MICRO_COMPACT_KEEP_RECENT {
-        return; // Not enough tool messages to compact
-    }
-
-    // Compact all but the last KEEP_RECENT tool messages
-    let compact_count = tool_indices.len() - MICRO_COMPACT_KEEP_RECENT;
-    for &idx in &tool_indices[..compact_count] {
-        if let Some(ref content) = conversation[idx].content {
-            if content.len() > MICRO_COMPACT_THRESHOLD {
-                conversation[idx].content = Some(
-                    "[Previous tool result omitted for context efficiency]".to_string()
-                );
-            }
-        }
-    }
-}
-
 /// Save the full conversation transcript to disk for recoverability.
 /// Runs in background (tokio::spawn) to avoid blocking the response.
-/// Transcripts are saved to `<APP_DATA>/inkess/transcripts/` with 7-day auto-cleanup.
-fn save_transcript(session_id: &str, conversation: &[ChatMessage]) {
-    let transcript_dir = crate::app_data_dir().join("inkess").join("transcripts");
+/// Transcripts are saved to `<data_dir>/transcripts/` and removed after
+/// the session retention period.
+/// Returns the path the transcript is written to.
+fn save_transcript(data_dir: &std::path::Path, session_id: &str, conversation: &[ChatMessage], retention_days: u32) -> std::path::PathBuf {
+    let transcript_dir = data_dir.join("transcripts");
     let session = session_id.to_string();
     let messages = conversation.to_vec();
 
+    let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+    let safe_session: String = session.chars()
+        .take(48)
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    let filename = format!("{}_{}.json", timestamp, safe_session);
+    let path = transcript_dir.join(&filename);
+    let written_path = path.clone();
+
     tokio::spawn(async move {
         if let Err(e) = fs::create_dir_all(&transcript_dir) {
             safe_eprintln!("[ai:transcript] failed to create dir: {}", e);
             return;
         }
 
-        // Cleanup old transcripts (>7 days)
-        if let Ok(entries) = fs::read_dir(&transcript_dir) {
-            let cutoff = std::time::SystemTime::now() - std::time::Duration::from_secs(7 * 24 * 3600);
+        // Transcripts are kept as long as sessions (0 keeps them forever)
+        let entries = fs::read_dir(&transcript_dir).ok().filter(|_| retention_days > 0);
+        if let Some(entries) = entries {
+            let cutoff = std::time::SystemTime::now() - std::time::Duration::from_secs(retention_days as u64 * 24 * 3600);
             for entry in entries.flatten() {
                 if let Ok(meta) = entry.metadata() {
                     if meta.modified().map(|t| t < cutoff).unwrap_or(false) {
@@ -1038,14 +1519,6 @@ fn save_transcript(session_id: &str, conversation: &[ChatMessage]) {
         }
 
         // Save current transcript
-        let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        let safe_session: String = session.chars()
-            .take(32)
-            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
-            .collect();
-        let filename = format!("{}_{}.json", timestamp, safe_session);
-        let path = transcript_dir.join(&filename);
-
         match serde_json::to_string(&messages) {
             Ok(json) => {
                 if let Err(e) = fs::write(&path, &json) {
@@ -1057,6 +1530,7 @@ fn save_transcript(session_id: &str, conversation: &[ChatMessage]) {
             }
         }
     });
+    written_path
 }
 
 pub fn cleanup_decay_cache() {
@@ -1340,95 +1814,18 @@ mod tests {
     }
 
     // =========================================================================
-    // micro_compact tests
+    // batch_tool_calls tests
     // =========================================================================
 
-    fn make_msg(role: &str, content: &str) -> ChatMessage {
-        ChatMessage {
-            role: role.into(),
-            content: Some(content.into()),
-            tool_calls: None,
-            tool_call_id: if role == "tool" { Some("tc1".into()) } else { None },
-        }
-    }
-
-    #[test]
-    fn micro_compact_no_tool_messages() {
-        let mut conv = vec![
-            make_msg("system", "You are helpful"),
-            make_msg("user", "Hello"),
-            make_msg("assistant", "Hi there"),
-        ];
-        micro_compact(&mut conv);
-        assert_eq!(conv[0].content.as_deref(), Some("You are helpful"));
-        assert_eq!(conv[1].content.as_deref(), Some("Hello"));
-        assert_eq!(conv[2].content.as_deref(), Some("Hi there"));
-    }
-
-    #[test]
-    fn micro_compact_fewer_than_keep_recent() {
-        let mut conv = vec![
-            make_msg("user", "Hello"),
-            make_msg("tool", "a".repeat(500).as_str()),
-            make_msg("tool", "b".repeat(500).as_str()),
-        ];
-        micro_compact(&mut conv);
-        // Only 2 tool messages <= KEEP_RECENT(3), nothing compacted
-        assert!(conv[1].content.as_ref().unwrap().len() == 500);
-        assert!(conv[2].content.as_ref().unwrap().len() == 500);
-    }
-
-    #[test]
-    fn micro_compact_compacts_old_large_tool_results() {
-        let large = "x".repeat(300);
-        let mut conv = vec![
-            make_msg("user", "q1"),
-            make_msg("tool", &large),      // old — should be compacted
-            make_msg("tool", &large),      // old — should be compacted
-            make_msg("tool", "small"),     // recent 3rd from end — kept
-            make_msg("tool", &large),      // recent 2nd — kept
-            make_msg("tool", &large),      // recent 1st — kept
-        ];
-        micro_compact(&mut conv);
-        // First two tool messages (idx 1,2) should be compacted
-        assert_eq!(conv[1].content.as_deref(), Some("[Previous tool result omitted for context efficiency]"));
-        assert_eq!(conv[2].content.as_deref(), Some("[Previous tool result omitted for context efficiency]"));
-        // Last three tool messages (idx 3,4,5) should remain intact
-        assert_eq!(conv[3].content.as_deref(), Some("small"));
-        assert_eq!(conv[4].content.as_ref().unwrap().len(), 300);
-        assert_eq!(conv[5].content.as_ref().unwrap().len(), 300);
-    }
-
     #[test]
-    fn micro_compact_preserves_small_old_results() {
-        let mut conv = vec![
-            make_msg("user", "q1"),
-            make_msg("tool", "short"),     // old but small — kept
-            make_msg("tool", "also short"), // old but small — kept
-            make_msg("tool", "r3"),
-            make_msg("tool", "r4"),
-            make_msg("tool", "r5"),
-        ];
-        micro_compact(&mut conv);
-        // Old but under threshold — preserved
-        assert_eq!(conv[1].content.as_deref(), Some("short"));
-        assert_eq!(conv[2].content.as_deref(), Some("also short"));
+    fn batch_tool_calls_groups_consecutive_safe_calls() {
+        assert_eq!(batch_tool_calls(&[true, true, false, true]), vec![0..2, 2..3, 3..4]);
     }
 
     #[test]
-    fn micro_compact_idempotent() {
-        let large = "x".repeat(300);
-        let mut conv = vec![
-            make_msg("user", "q1"),
-            make_msg("tool", &large),
-            make_msg("tool", "r2"),
-            make_msg("tool", "r3"),
-            make_msg("tool", "r4"),
-        ];
-        micro_compact(&mut conv);
-        let after_first = conv[1].content.clone();
-        micro_compact(&mut conv);
-        // Should be idempotent — placeholder is under threshold so won't be re-compacted
-        assert_eq!(conv[1].content, after_first);
+    fn batch_tool_calls_unsafe_calls_run_alone() {
+        assert_eq!(batch_tool_calls(&[false, false]), vec![0..1, 1..2]);
+        assert_eq!(batch_tool_calls(&[true, true, true]), vec![0..3]);
+        assert!(batch_tool_calls(&[]).is_empty());
     }
 }
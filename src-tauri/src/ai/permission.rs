@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_warn;
+use super::confirm::ConfirmRequest;
+use super::hooks::glob_match;
+use super::tool::ToolContext;
+use super::{sandbox_path, PermissionStoreState};
+
+/// What happens to a tool call a rule matches. Ordered from least to most
+/// restrictive.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Allow,
+    Ask,
+    Deny,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleScope {
+    /// Applies in one workspace only
+    Workspace,
+    /// Applies everywhere
+    Global,
+}
+
+/// One permission rule. Unset matchers match anything.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PermissionRule {
+    /// Assigned when the rule is added
+    #[serde(default)]
+    pub id: String,
+    /// Tool name or glob, e.g. "run_shell" or "mcp__github__*"
+    pub tool: String,
+    /// Shell command prefix, matched on whole words ("cargo test" matches
+    /// "cargo test --release" but not "cargo testing")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Glob on the workspace-relative path of file tools, e.g. "src/*"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub decision: Decision,
+    /// Unix timestamp (seconds), assigned when the rule is added
+    #[serde(default)]
+    pub created_at: i64,
+}
+
+impl PermissionRule {
+    fn matches(&self, request: &PermissionRequest) -> bool {
+        if !glob_match(&self.tool, request.tool) {
+            return false;
+        }
+        if let Some(prefix) = &self.command {
+            let Some(command) = request.command else { return false };
+            if !command_has_prefix(command, prefix) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.path {
+            let Some(path) = request.path else { return false };
+            if !glob_match(pattern, path) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A tool call to decide on
+pub struct PermissionRequest<'a> {
+    pub tool: &'a str,
+    pub command: Option<&'a str>,
+    /// Relative to the workspace
+    pub path: Option<&'a str>,
+}
+
+impl PermissionRequest<'_> {
+    /// Short text for confirmation prompts and errors
+    pub fn describe(&self) -> String {
+        match (self.command, self.path) {
+            (Some(command), _) => command.to_string(),
+            (None, Some(path)) => format!("{} {}", self.tool, path),
+            (None, None) => self.tool.to_string(),
+        }
+    }
+}
+
+/// A rule with where it applies, as listed to the user
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScopedRule {
+    pub scope: RuleScope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    #[serde(flatten)]
+    pub rule: PermissionRule,
+}
+
+/// On-disk format
+#[derive(Serialize, Deserialize, Default)]
+struct PermissionFile {
+    #[serde(default)]
+    global: Vec<PermissionRule>,
+    /// Rules per workspace path
+    #[serde(default)]
+    workspaces: HashMap<String, Vec<PermissionRule>>,
+    /// Workspace hooks files the user agreed to run: sha256 of the file by
+    /// canonical workspace path
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    trusted_hooks: HashMap<String, String>,
+}
+
+/// Permission rules of the user, kept in the app data directory (never in
+/// the workspace, so a repository can't grant itself permissions).
+pub struct PermissionStore {
+    path: PathBuf,
+    rules: Mutex<PermissionFile>,
+    /// Hooks files declined since startup, as (workspace, sha256)
+    declined_hooks: Mutex<HashSet<(String, String)>>,
+}
+
+impl PermissionStore {
+    pub fn new(path: PathBuf) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create permission directory: {}", e))?;
+        }
+        let rules = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                app_warn!("ai:permission", "ignoring unreadable {}: {}", path.display(), e);
+                PermissionFile::default()
+            }),
+            Err(_) => PermissionFile::default(),
+        };
+        Ok(Self { path, rules: Mutex::new(rules), declined_hooks: Mutex::default() })
+    }
+
+    /// The decision of the rules for a call, None if no rule matches.
+    /// Workspace rules take precedence over global ones; within a scope the
+    /// most restrictive matching rule wins.
+    pub fn decide(&self, workspace: &str, request: &PermissionRequest) -> Option<Decision> {
+        let rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let strictest = |list: &[PermissionRule]| {
+            list.iter().filter(|r| r.matches(request)).map(|r| r.decision).max()
+        };
+        rules.workspaces.get(&workspace_key(workspace))
+            .and_then(|list| strictest(list))
+            .or_else(|| strictest(&rules.global))
+    }
+
+    pub fn add(&self, scope: RuleScope, workspace: Option<&str>, mut rule: PermissionRule) -> Result<PermissionRule, String> {
+        if rule.tool.trim().is_empty() {
+            return Err("A rule needs a tool name or pattern".to_string());
+        }
+        rule.id = uuid::Uuid::new_v4().to_string();
+        rule.created_at = chrono::Utc::now().timestamp();
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        match scope {
+            RuleScope::Global => rules.global.push(rule.clone()),
+            RuleScope::Workspace => {
+                let workspace = workspace.filter(|w| !w.trim().is_empty())
+                    .ok_or("A workspace rule needs a workspace")?;
+                rules.workspaces.entry(workspace_key(workspace)).or_default().push(rule.clone());
+            }
+        }
+        self.save(&rules)?;
+        Ok(rule)
+    }
+
+    /// Global rules and those of `workspace`, or of every workspace if None
+    pub fn list(&self, workspace: Option<&str>) -> Vec<ScopedRule> {
+        let rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let mut listed: Vec<ScopedRule> = rules.global.iter()
+            .map(|rule| ScopedRule { scope: RuleScope::Global, workspace: None, rule: rule.clone() })
+            .collect();
+        let key = workspace.map(workspace_key);
+        let mut workspaces: Vec<(&String, &Vec<PermissionRule>)> = rules.workspaces.iter()
+            .filter(|(ws, _)| key.is_none() || key.as_ref() == Some(*ws))
+            .collect();
+        workspaces.sort_by(|a, b| a.0.cmp(b.0));
+        for (ws, list) in workspaces {
+            listed.extend(list.iter().map(|rule| ScopedRule {
+                scope: RuleScope::Workspace,
+                workspace: Some(ws.clone()),
+                rule: rule.clone(),
+            }));
+        }
+        listed
+    }
+
+    /// Remove a rule by id; false if there is none
+    pub fn revoke(&self, id: &str) -> Result<bool, String> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        let before = rules.global.len() + rules.workspaces.values().map(Vec::len).sum::<usize>();
+        rules.global.retain(|r| r.id != id);
+        for list in rules.workspaces.values_mut() {
+            list.retain(|r| r.id != id);
+        }
+        rules.workspaces.retain(|_, list| !list.is_empty());
+        let after = rules.global.len() + rules.workspaces.values().map(Vec::len).sum::<usize>();
+        if before == after {
+            return Ok(false);
+        }
+        self.save(&rules)?;
+        Ok(true)
+    }
+
+    /// Whether the user trusts the hooks file with `digest` in `workspace`;
+    /// None if they weren't asked yet
+    pub fn hooks_trust(&self, workspace: &str, digest: &str) -> Option<bool> {
+        let key = canonical_workspace(workspace);
+        let rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        if rules.trusted_hooks.get(&key).map(String::as_str) == Some(digest) {
+            return Some(true);
+        }
+        let declined = self.declined_hooks.lock().unwrap_or_else(|e| e.into_inner());
+        declined.contains(&(key, digest.to_string())).then_some(false)
+    }
+
+    /// Record the user's answer for a hooks file. Trust is saved and holds
+    /// until the file changes; declining lasts until the app restarts.
+    pub fn set_hooks_trust(&self, workspace: &str, digest: &str, trusted: bool) -> Result<(), String> {
+        let key = canonical_workspace(workspace);
+        if !trusted {
+            self.declined_hooks.lock().unwrap_or_else(|e| e.into_inner()).insert((key, digest.to_string()));
+            return Ok(());
+        }
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        rules.trusted_hooks.insert(key, digest.to_string());
+        self.save(&rules)
+    }
+
+    fn save(&self, rules: &PermissionFile) -> Result<(), String> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(rules)
+            .map_err(|e| format!("Failed to serialize permissions: {}", e))?;
+        fs::write(&tmp_path, content).map_err(|e| format!("Failed to write permissions: {}", e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| format!("Failed to save permissions: {}", e))
+    }
+}
+
+/// Workspace paths as typed by different callers, with or without a trailing separator
+pub(crate) fn workspace_key(workspace: &str) -> String {
+    let trimmed = workspace.trim_end_matches(['/', '\\']);
+    if trimmed.is_empty() { workspace.to_string() } else { trimmed.to_string() }
+}
+
+/// The real path of a workspace, so links and `..` can't dodge a trust decision
+fn canonical_workspace(workspace: &str) -> String {
+    Path::new(workspace).canonicalize()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| workspace_key(workspace))
+}
+
+fn command_has_prefix(command: &str, prefix: &str) -> bool {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let prefix_words: Vec<&str> = prefix.split_whitespace().collect();
+    !prefix_words.is_empty() && words.starts_with(&prefix_words)
+}
+
+/// Path of a file tool argument relative to the workspace, for rule matching.
+/// Uses the file the tools would actually touch, so `a/../secrets/x` matches
+/// rules for `secrets/*`. Paths outside the workspace stay absolute.
+pub fn relative_path(raw_path: &str, workspace: &str) -> String {
+    if let Some(resolved) = sandbox_path(raw_path, workspace) {
+        let base = Path::new(workspace).canonicalize().unwrap_or_else(|_| PathBuf::from(workspace));
+        if let Ok(rest) = Path::new(&resolved).strip_prefix(&base) {
+            return rest.to_string_lossy().replace('\\', "/");
+        }
+    }
+    // Not resolvable on disk (e.g. a new directory): normalize lexically
+    let root = normalize_path(workspace);
+    let path = if root.is_empty() || is_absolute_path(raw_path) {
+        normalize_path(raw_path)
+    } else {
+        normalize_path(&format!("{}/{}", root, raw_path))
+    };
+    if root.is_empty() {
+        return path;
+    }
+    if path == root {
+        return String::new();
+    }
+    let prefix = if root.ends_with('/') { root } else { format!("{}/", root) };
+    path.strip_prefix(&prefix).map(String::from).unwrap_or(path)
+}
+
+fn is_absolute_path(path: &str) -> bool {
+    path.starts_with(['/', '\\']) || (path.len() >= 2 && path.as_bytes()[1] == b':')
+}
+
+/// Forward slashes, `.` and `..` applied, no trailing separator
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|p| *p != "..") => { parts.pop(); }
+            ".." if absolute => {}
+            _ => parts.push(part),
+        }
+    }
+    let joined = parts.join("/");
+    if absolute { format!("/{}", joined) } else { joined }
+}
+
+/// Apply the rules to a file or MCP tool call. Calls no rule matches are
+/// allowed; `ask` goes through the host's confirmation.
+pub async fn check(ctx: &ToolContext, request: &PermissionRequest<'_>) -> Result<(), String> {
+    match ctx.permissions.decide(&ctx.workspace_path, request) {
+        None | Some(Decision::Allow) => Ok(()),
+        Some(Decision::Deny) => Err(format!("Denied by a permission rule: {}", request.describe())),
+        Some(Decision::Ask) => match ctx.sink.confirm_command(&ConfirmRequest::new(&ctx.session_id, request.tool, &request.describe())).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("Denied by user: {}", request.describe())),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+// --- Tauri commands ---
+
+/// Global rules plus those of `workspace` (all workspaces if omitted)
+#[tauri::command]
+pub fn ai_permission_list(
+    state: tauri::State<'_, PermissionStoreState>,
+    workspace: Option<String>,
+) -> Vec<ScopedRule> {
+    state.store.list(workspace.as_deref())
+}
+
+#[tauri::command]
+pub fn ai_permission_add(
+    state: tauri::State<'_, PermissionStoreState>,
+    scope: RuleScope,
+    workspace: Option<String>,
+    rule: PermissionRule,
+) -> Result<PermissionRule, String> {
+    state.store.add(scope, workspace.as_deref(), rule)
+}
+
+#[tauri::command]
+pub fn ai_permission_revoke(
+    state: tauri::State<'_, PermissionStoreState>,
+    id: String,
+) -> Result<bool, String> {
+    state.store.revoke(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (tempfile::TempDir, PermissionStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = PermissionStore::new(dir.path().join("permissions.json")).unwrap();
+        (dir, store)
+    }
+
+    fn rule(tool: &str, command: Option<&str>, path: Option<&str>, decision: Decision) -> PermissionRule {
+        PermissionRule {
+            id: String::new(),
+            tool: tool.into(),
+            command: command.map(String::from),
+            path: path.map(String::from),
+            decision,
+            created_at: 0,
+        }
+    }
+
+    fn shell(command: &str) -> PermissionRequest<'_> {
+        PermissionRequest { tool: "run_shell", command: Some(command), path: None }
+    }
+
+    #[test]
+    fn command_prefix_matches_whole_words() {
+        assert!(command_has_prefix("cargo test --release", "cargo test"));
+        assert!(command_has_prefix("cargo  test", "cargo test"));
+        assert!(!command_has_prefix("cargo testing", "cargo test"));
+        assert!(!command_has_prefix("cargo", "cargo test"));
+        assert!(!command_has_prefix("anything", " "));
+    }
+
+    #[test]
+    fn workspace_rules_win_over_global() {
+        let (_dir, store) = store();
+        store.add(RuleScope::Global, None, rule("run_shell", Some("cargo"), None, Decision::Ask)).unwrap();
+        store.add(RuleScope::Workspace, Some("/p/"), rule("run_shell", Some("cargo test"), None, Decision::Allow)).unwrap();
+
+        assert_eq!(store.decide("/p", &shell("cargo test")), Some(Decision::Allow));
+        assert_eq!(store.decide("/p", &shell("cargo build")), Some(Decision::Ask));
+        assert_eq!(store.decide("/other", &shell("cargo test")), Some(Decision::Ask));
+        assert_eq!(store.decide("/p", &shell("ls")), None);
+    }
+
+    #[test]
+    fn strictest_rule_wins_within_a_scope() {
+        let (_dir, store) = store();
+        store.add(RuleScope::Global, None, rule("*_file", None, None, Decision::Allow)).unwrap();
+        store.add(RuleScope::Global, None, rule("write_file", None, Some("secrets/*"), Decision::Deny)).unwrap();
+        let write = |path| PermissionRequest { tool: "write_file", command: None, path: Some(path) };
+
+        assert_eq!(store.decide("/p", &write("secrets/key.txt")), Some(Decision::Deny));
+        assert_eq!(store.decide("/p", &write("notes.md")), Some(Decision::Allow));
+        // A path rule never matches calls without a path
+        let mcp = PermissionRequest { tool: "write_file", command: None, path: None };
+        assert_eq!(store.decide("/p", &mcp), Some(Decision::Allow));
+    }
+
+    #[test]
+    fn rules_persist_and_can_be_revoked() {
+        let (_dir, store) = store();
+        let added = store.add(RuleScope::Workspace, Some("/p"), rule("mcp__*", None, None, Decision::Deny)).unwrap();
+        store.add(RuleScope::Global, None, rule("edit_file", None, None, Decision::Ask)).unwrap();
+        assert!(!added.id.is_empty());
+        assert!(store.add(RuleScope::Workspace, None, rule("x", None, None, Decision::Deny)).is_err());
+
+        let reloaded = PermissionStore::new(store.path.clone()).unwrap();
+        let listed = reloaded.list(Some("/p"));
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].scope, RuleScope::Global);
+        assert_eq!(listed[1].workspace.as_deref(), Some("/p"));
+        assert_eq!(reloaded.list(Some("/other")).len(), 1);
+
+        assert!(reloaded.revoke(&added.id).unwrap());
+        assert!(!reloaded.revoke(&added.id).unwrap());
+        assert_eq!(PermissionStore::new(store.path.clone()).unwrap().list(None).len(), 1);
+    }
+
+    #[test]
+    fn relative_paths_for_matching() {
+        assert_eq!(relative_path("./src/main.rs", "/p"), "src/main.rs");
+        assert_eq!(relative_path("/p/src/main.rs", "/p/"), "src/main.rs");
+        assert_eq!(relative_path("C:\\p\\src\\a.rs", "C:\\p"), "src/a.rs");
+        assert_eq!(relative_path("notes.md", ""), "notes.md");
+    }
+
+    #[test]
+    fn relative_paths_resolve_parent_components() {
+        assert_eq!(relative_path("src/../secrets/key.txt", "/p"), "secrets/key.txt");
+        assert_eq!(relative_path("./a/./../secrets/x", "/p"), "secrets/x");
+        assert_eq!(relative_path("/p/a/../../p/secrets/x", "/p"), "secrets/x");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("secrets")).unwrap();
+        let workspace = dir.to_string_lossy().to_string();
+        assert_eq!(relative_path("src/../secrets/key.txt", &workspace), "secrets/key.txt");
+        let store = PermissionStore::new(dir.join("permissions.json")).unwrap();
+        store.add(RuleScope::Global, None, PermissionRule {
+            id: String::new(),
+            tool: "write_file".into(),
+            command: None,
+            path: Some("secrets/*".into()),
+            decision: Decision::Deny,
+            created_at: 0,
+        }).unwrap();
+        let path = relative_path("./src/./../secrets/key.txt", &workspace);
+        let request = PermissionRequest { tool: "write_file", command: None, path: Some(&path) };
+        assert_eq!(store.decide(&workspace, &request), Some(Decision::Deny));
+    }
+
+    #[test]
+    fn hooks_trust_is_saved_per_canonical_workspace() {
+        let (dir, store) = store();
+        let ws = dir.path().join("ws");
+        fs::create_dir_all(&ws).unwrap();
+        let ws = ws.to_string_lossy().into_owned();
+        let via_parent = format!("{}/../ws", ws);
+        assert_eq!(store.hooks_trust(&ws, "abc"), None);
+        store.set_hooks_trust(&ws, "abc", false).unwrap();
+        assert_eq!(store.hooks_trust(&via_parent, "abc"), Some(false));
+        store.set_hooks_trust(&via_parent, "abc", true).unwrap();
+        assert_eq!(store.hooks_trust(&ws, "abc"), Some(true));
+        assert_eq!(store.hooks_trust(&ws, "changed"), None);
+
+        // Trust survives a restart, declining doesn't
+        let reloaded = PermissionStore::new(dir.path().join("permissions.json")).unwrap();
+        assert_eq!(reloaded.hooks_trust(&ws, "abc"), Some(true));
+        reloaded.set_hooks_trust(&ws, "other", false).unwrap();
+        let reloaded = PermissionStore::new(dir.path().join("permissions.json")).unwrap();
+        assert_eq!(reloaded.hooks_trust(&ws, "other"), None);
+    }
+
+    #[test]
+    fn relative_paths_match_whole_components() {
+        assert_eq!(relative_path("/p2/x", "/p"), "/p2/x");
+        assert_eq!(relative_path("/p", "/p"), "");
+        assert_eq!(relative_path("../outside.txt", "/p"), "/outside.txt");
+    }
+}
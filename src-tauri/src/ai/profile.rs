@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_warn;
+use super::config::{extract_keys, store_keys, AiConfig, PROFILE_SCOPE};
+use super::permission::workspace_key;
+use super::vault::{Secrets, Vault};
+use super::ProfileStoreState;
+
+/// A named model setup, e.g. a company gateway, a personal key or a local
+/// model. Keys in `config` are `vault:` references.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AiProfile {
+    pub id: String,
+    pub name: String,
+    pub config: AiConfig,
+    /// Unix timestamps (seconds)
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// All profiles with the default and the workspace pins; also the on-disk format
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProfileSet {
+    #[serde(default)]
+    pub profiles: Vec<AiProfile>,
+    /// Used when a chat names no profile and its workspace has no pin
+    #[serde(default)]
+    pub default_id: Option<String>,
+    /// Pinned profile id per workspace path
+    #[serde(default)]
+    pub workspaces: HashMap<String, String>,
+}
+
+/// Named AI configs, kept in the app data directory. Their keys go to the vault.
+pub struct ProfileStore {
+    path: PathBuf,
+    vault: &'static Vault,
+    set: Mutex<ProfileSet>,
+}
+
+impl ProfileStore {
+    pub fn new(path: PathBuf, vault: &'static Vault) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create profile directory: {}", e))?;
+        }
+        let set = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                app_warn!("ai:profile", "ignoring unreadable {}: {}", path.display(), e);
+                ProfileSet::default()
+            }),
+            Err(_) => ProfileSet::default(),
+        };
+        Ok(Self { path, vault, set: Mutex::new(set) })
+    }
+
+    pub fn list(&self) -> ProfileSet {
+        self.set.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<AiProfile, String> {
+        let set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        set.profiles.iter().find(|p| p.id == id).cloned()
+            .ok_or_else(|| format!("No AI profile {}", id))
+    }
+
+    /// A profile by id, or by name ignoring case
+    pub fn find(&self, id_or_name: &str) -> Option<AiProfile> {
+        let set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        set.profiles.iter().find(|p| p.id == id_or_name)
+            .or_else(|| set.profiles.iter().find(|p| p.name.eq_ignore_ascii_case(id_or_name.trim())))
+            .cloned()
+    }
+
+    /// The profile pinned to `workspace`, else the default one
+    pub fn resolve(&self, workspace: Option<&str>) -> Option<AiProfile> {
+        let set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        let id = workspace.and_then(|ws| set.workspaces.get(&workspace_key(ws)))
+            .or(set.default_id.as_ref())?;
+        set.profiles.iter().find(|p| &p.id == id).cloned()
+    }
+
+    pub fn create(&self, name: &str, config: AiConfig) -> Result<AiProfile, String> {
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        let name = checked_name(&set, name, None)?;
+        let id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        let (config, previous_keys) = self.store_config_keys(&id, config)?;
+        let now = chrono::Utc::now().timestamp();
+        let profile = AiProfile { id, name, config, created_at: now, updated_at: now };
+        set.profiles.push(profile.clone());
+        if let Err(e) = self.save(&set) {
+            set.profiles.pop();
+            self.restore_keys(&profile.id, previous_keys);
+            return Err(e);
+        }
+        Ok(profile)
+    }
+
+    /// Rename a profile and/or replace its config
+    pub fn update(&self, id: &str, name: Option<&str>, config: Option<AiConfig>) -> Result<AiProfile, String> {
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        let index = set.profiles.iter().position(|p| p.id == id)
+            .ok_or_else(|| format!("No AI profile {}", id))?;
+        let name = name.map(|n| checked_name(&set, n, Some(id))).transpose()?;
+        let (config, previous_keys) = match config {
+            Some(c) => self.store_config_keys(id, c).map(|(c, keys)| (Some(c), Some(keys)))?,
+            None => (None, None),
+        };
+        let before = set.profiles[index].clone();
+        let profile = &mut set.profiles[index];
+        if let Some(name) = name {
+            profile.name = name;
+        }
+        if let Some(config) = config {
+            profile.config = config;
+        }
+        profile.updated_at = chrono::Utc::now().timestamp();
+        let profile = profile.clone();
+        if let Err(e) = self.save(&set) {
+            set.profiles[index] = before;
+            if let Some(keys) = previous_keys {
+                self.restore_keys(id, keys);
+            }
+            return Err(e);
+        }
+        Ok(profile)
+    }
+
+    /// Remove a profile, its keys, and the default and pins pointing at it; false if there is none
+    pub fn delete(&self, id: &str) -> Result<bool, String> {
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        let before = set.profiles.len();
+        set.profiles.retain(|p| p.id != id);
+        if set.profiles.len() == before {
+            return Ok(false);
+        }
+        if set.default_id.as_deref() == Some(id) {
+            set.default_id = None;
+        }
+        set.workspaces.retain(|_, pinned| pinned != id);
+        store_keys(self.vault, &key_scope(id), Default::default())?;
+        self.save(&set)?;
+        Ok(true)
+    }
+
+    /// Set or clear the default profile
+    pub fn set_default(&self, id: Option<&str>) -> Result<(), String> {
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        set.default_id = self.checked_id(&set, id)?;
+        self.save(&set)
+    }
+
+    /// Pin a profile to a workspace, or unpin with None
+    pub fn pin_workspace(&self, workspace: &str, id: Option<&str>) -> Result<(), String> {
+        if workspace.trim().is_empty() {
+            return Err("A workspace is required".to_string());
+        }
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        match self.checked_id(&set, id)? {
+            Some(id) => set.workspaces.insert(workspace_key(workspace), id),
+            None => set.workspaces.remove(&workspace_key(workspace)),
+        };
+        self.save(&set)
+    }
+
+    fn checked_id(&self, set: &ProfileSet, id: Option<&str>) -> Result<Option<String>, String> {
+        match id {
+            Some(id) if !set.profiles.iter().any(|p| p.id == id) => Err(format!("No AI profile {}", id)),
+            id => Ok(id.map(str::to_string)),
+        }
+    }
+
+    /// Move the keys of a profile's config into the vault, under the profile's own names
+    /// Move the config's keys to the vault. Returns the config with `vault:`
+    /// references and the profile's previous keys, to roll back with.
+    fn store_config_keys(&self, id: &str, config: AiConfig) -> Result<(AiConfig, Secrets), String> {
+        let mut config = config;
+        let scope = key_scope(id);
+        let known = self.vault.read()?;
+        let previous: Secrets = known.iter()
+            .filter(|(name, _)| name.starts_with(&scope))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let secrets = extract_keys(&mut config, &known, &scope);
+        store_keys(self.vault, &scope, secrets)?;
+        Ok((config, previous))
+    }
+
+    /// Put a profile's keys back after its change could not be saved
+    fn restore_keys(&self, id: &str, previous: Secrets) {
+        if let Err(e) = store_keys(self.vault, &key_scope(id), previous) {
+            app_warn!("ai:profile", "failed to restore the keys of profile {}: {}", id, e);
+        }
+    }
+
+    fn save(&self, set: &ProfileSet) -> Result<(), String> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(set)
+            .map_err(|e| format!("Failed to serialize profiles: {}", e))?;
+        fs::write(&tmp_path, content).map_err(|e| format!("Failed to write profiles: {}", e))?;
+        fs::rename(&tmp_path, &self.path).map_err(|e| format!("Failed to save profiles: {}", e))
+    }
+}
+
+fn key_scope(id: &str) -> String {
+    format!("{}{}:", PROFILE_SCOPE, id)
+}
+
+/// A trimmed, non-empty name no other profile uses
+fn checked_name(set: &ProfileSet, name: &str, own_id: Option<&str>) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("A profile needs a name".to_string());
+    }
+    if set.profiles.iter().any(|p| Some(p.id.as_str()) != own_id && p.name.eq_ignore_ascii_case(name)) {
+        return Err(format!("A profile named '{}' already exists", name));
+    }
+    Ok(name.to_string())
+}
+
+// --- Tauri commands ---
+
+#[tauri::command]
+pub fn ai_profile_list(state: tauri::State<'_, ProfileStoreState>) -> ProfileSet {
+    state.store.list()
+}
+
+#[tauri::command]
+pub fn ai_profile_create(
+    state: tauri::State<'_, ProfileStoreState>,
+    name: String,
+    config: AiConfig,
+) -> Result<AiProfile, String> {
+    state.store.create(&name, config)
+}
+
+#[tauri::command]
+pub fn ai_profile_update(
+    state: tauri::State<'_, ProfileStoreState>,
+    id: String,
+    name: Option<String>,
+    config: Option<AiConfig>,
+) -> Result<AiProfile, String> {
+    state.store.update(&id, name.as_deref(), config)
+}
+
+#[tauri::command]
+pub fn ai_profile_delete(
+    state: tauri::State<'_, ProfileStoreState>,
+    id: String,
+) -> Result<bool, String> {
+    state.store.delete(&id)
+}
+
+#[tauri::command]
+pub fn ai_profile_set_default(
+    state: tauri::State<'_, ProfileStoreState>,
+    id: Option<String>,
+) -> Result<(), String> {
+    state.store.set_default(id.as_deref())
+}
+
+#[tauri::command]
+pub fn ai_profile_pin_workspace(
+    state: tauri::State<'_, ProfileStoreState>,
+    workspace: String,
+    id: Option<String>,
+) -> Result<(), String> {
+    state.store.pin_workspace(&workspace, id.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (tempfile::TempDir, ProfileStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let vault: &'static Vault = Box::leak(Box::new(Vault::new(dir.path().join("ai-keys.vault"), false)));
+        vault.unlock("test passphrase").unwrap();
+        let store = ProfileStore::new(dir.path().join("ai-profiles.json"), vault).unwrap();
+        (dir, store)
+    }
+
+    fn config(api_url: &str, api_key: &str) -> AiConfig {
+        let mut config: AiConfig = serde_json::from_str(
+            r#"{"api_url": "", "api_key": "", "model": "gpt-4o", "temperature": 0.7, "max_tokens": 4096}"#
+        ).unwrap();
+        config.api_url = api_url.to_string();
+        config.api_key = api_key.to_string();
+        config
+    }
+
+    #[test]
+    fn profile_keys_go_to_the_vault() {
+        let (_dir, store) = store();
+        let work = store.create("Work gateway", config("https://gw.corp/v1", "sk-work")).unwrap();
+        let home = store.create("Personal", config("https://api.openai.com/v1", "sk-home")).unwrap();
+        assert_eq!(work.config.api_key, format!("vault:profile:{}:api_key", work.id));
+        assert!(!fs::read_to_string(&store.path).unwrap().contains("sk-work"));
+
+        let keys = store.vault.read().unwrap();
+        assert_eq!(keys[&format!("profile:{}:api_key", work.id)], "sk-work");
+        assert_eq!(keys[&format!("profile:{}:api_key", home.id)], "sk-home");
+
+        // Saving the profile back with its reference keeps the key
+        let updated = store.update(&work.id, Some("Work"), Some(work.config.clone())).unwrap();
+        assert_eq!(updated.name, "Work");
+        assert_eq!(store.vault.read().unwrap()[&format!("profile:{}:api_key", work.id)], "sk-work");
+
+        assert!(store.delete(&work.id).unwrap());
+        let keys = store.vault.read().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys.contains_key(&format!("profile:{}:api_key", home.id)));
+    }
+
+    #[test]
+    fn failed_saves_leave_no_keys_behind() {
+        let (_dir, store) = store();
+        let work = store.create("Work", config("https://gw.corp/v1", "sk-work")).unwrap();
+        // A directory in the way of the temp file makes every save fail
+        fs::create_dir(store.path.with_extension("json.tmp")).unwrap();
+
+        assert!(store.create("Personal", config("https://api.openai.com/v1", "sk-home")).is_err());
+        assert!(store.update(&work.id, None, Some(config("https://gw.corp/v1", "sk-new"))).is_err());
+        let keys = store.vault.read().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[&format!("profile:{}:api_key", work.id)], "sk-work");
+        let set = store.list();
+        assert_eq!(set.profiles.len(), 1);
+        assert_eq!(set.profiles[0].config.api_key, format!("vault:profile:{}:api_key", work.id));
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        let (_dir, store) = store();
+        let work = store.create("Work", config("u", "")).unwrap();
+        assert!(store.create(" work ", config("u", "")).unwrap_err().contains("already exists"));
+        assert!(store.create("  ", config("u", "")).is_err());
+        // Keeping its own name is fine
+        store.update(&work.id, Some("Work"), None).unwrap();
+        assert_eq!(store.find("WORK").map(|p| p.id), Some(work.id));
+    }
+
+    #[test]
+    fn workspace_pin_wins_over_default() {
+        let (_dir, store) = store();
+        let work = store.create("Work", config("https://gw.corp/v1", "")).unwrap();
+        let local = store.create("Local", config("http://localhost:11434/v1", "")).unwrap();
+        assert!(store.resolve(Some("/repo")).is_none());
+
+        store.set_default(Some(&work.id)).unwrap();
+        store.pin_workspace("/repo/", Some(&local.id)).unwrap();
+        assert_eq!(store.resolve(Some("/repo")).map(|p| p.id), Some(local.id.clone()));
+        assert_eq!(store.resolve(Some("/other")).map(|p| p.id), Some(work.id.clone()));
+        assert_eq!(store.resolve(None).map(|p| p.id), Some(work.id.clone()));
+        assert!(store.set_default(Some("missing")).is_err());
+
+        // Deleting a profile drops its pins; the default takes over again
+        store.delete(&local.id).unwrap();
+        assert_eq!(store.resolve(Some("/repo")).map(|p| p.id), Some(work.id.clone()));
+        let reloaded = ProfileStore::new(store.path.clone(), store.vault).unwrap();
+        assert_eq!(reloaded.list().default_id, Some(work.id));
+        assert!(reloaded.list().workspaces.is_empty());
+    }
+}
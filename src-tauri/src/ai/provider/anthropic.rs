@@ -0,0 +1,704 @@
+use std::collections::HashMap;
+
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+use crate::ai::config::AiConfig;
+use crate::ai::retry;
+use crate::ai::streaming::{ChatMessage, ImagePart, ThinkingBlock};
+use crate::ai::usage::TokenUsage;
+use super::{ChatProvider, ChatRequest, StreamDelta, StreamParser};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u32 = 1024;
+/// Cache breakpoints placed on the latest user turns; with the tool list and
+/// system prompt this is the API's limit of four
+const MESSAGE_CACHE_BREAKPOINTS: usize = 2;
+
+/// Native Anthropic Messages API backend (`/v1/messages`)
+pub struct AnthropicProvider;
+
+impl ChatProvider for AnthropicProvider {
+    fn name(&self) -> &str { "Anthropic" }
+
+    fn build_request(&self, client: &Client, config: &AiConfig, req: &ChatRequest) -> RequestBuilder {
+        client
+            .post(messages_url(&config.api_url))
+            .header("x-api-key", &config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(&build_body(req))
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::new(AnthropicStreamParser::default())
+    }
+
+    fn parse_completion(&self, body: &Value) -> Option<String> {
+        let blocks = body["content"].as_array()?;
+        let text: String = blocks.iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .collect();
+        Some(text)
+    }
+
+    fn parse_completion_usage(&self, body: &Value) -> Option<TokenUsage> {
+        let (prompt_tokens, cached_tokens) = prompt_usage(&body["usage"])?;
+        Some(TokenUsage {
+            prompt_tokens,
+            completion_tokens: body["usage"]["output_tokens"].as_u64().unwrap_or(0),
+            cached_tokens,
+        })
+    }
+}
+
+/// `https://api.anthropic.com` and `https://api.anthropic.com/v1` both resolve to `/v1/messages`.
+fn messages_url(api_url: &str) -> String {
+    let base = api_url.trim_end_matches('/');
+    if base.ends_with("anthropic.com") {
+        format!("{}/v1/messages", base)
+    } else {
+        format!("{}/messages", base)
+    }
+}
+
+fn build_body(req: &ChatRequest) -> Value {
+    let (system, mut messages) = convert_messages(req.messages);
+    if req.cache {
+        mark_cached_turns(&mut messages);
+    }
+    let mut body = serde_json::json!({
+        "model": req.model,
+        "messages": messages,
+        "temperature": req.temperature,
+        "max_tokens": req.max_tokens,
+        "stream": req.stream,
+    });
+    if !system.is_empty() {
+        body["system"] = if req.cache {
+            Value::Array(vec![with_cache_control(text_block(&system))])
+        } else {
+            Value::String(system)
+        };
+    }
+    if !req.tools.is_empty() {
+        let mut tools: Vec<Value> = req.tools.iter().map(convert_tool).collect();
+        if req.cache {
+            if let Some(last) = tools.pop() {
+                tools.push(with_cache_control(last));
+            }
+        }
+        body["tools"] = Value::Array(tools);
+    }
+    // Extended thinking needs a budget below max_tokens and the default temperature
+    if req.reasoning && req.max_tokens > MIN_THINKING_BUDGET {
+        let budget = (req.max_tokens / 2).max(MIN_THINKING_BUDGET);
+        body["thinking"] = serde_json::json!({ "type": "enabled", "budget_tokens": budget });
+        if let Some(obj) = body.as_object_mut() {
+            obj.remove("temperature");
+        }
+    }
+    body
+}
+
+/// Mark the last block of the latest user turns as cache breakpoints, so the
+/// next request reads everything before them from the prompt cache. Earlier
+/// breakpoints stay valid as the conversation grows by one turn.
+fn mark_cached_turns(messages: &mut [Value]) {
+    let user_turns = messages.iter_mut()
+        .rev()
+        .filter(|m| m["role"] == "user")
+        .take(MESSAGE_CACHE_BREAKPOINTS);
+    for turn in user_turns {
+        if let Some(last) = turn["content"].as_array_mut().and_then(|blocks| blocks.last_mut()) {
+            last["cache_control"] = serde_json::json!({ "type": "ephemeral" });
+        }
+    }
+}
+
+fn with_cache_control(mut block: Value) -> Value {
+    block["cache_control"] = serde_json::json!({ "type": "ephemeral" });
+    block
+}
+
+/// Convert an OpenAI function schema into an Anthropic tool definition
+fn convert_tool(schema: &Value) -> Value {
+    let f = &schema["function"];
+    serde_json::json!({
+        "name": f["name"],
+        "description": f["description"],
+        "input_schema": f["parameters"],
+    })
+}
+
+/// Convert OpenAI-shaped chat history into the Messages API format.
+/// Returns (top-level system prompt, messages).
+///
+/// - The leading system message becomes the top-level `system` field;
+///   later system messages (e.g. progress reminders) become user text blocks.
+/// - Assistant `tool_calls` become `tool_use` blocks; the turn's thinking and
+///   redacted thinking blocks lead it unchanged, as required during tool use.
+/// - `tool` role results become `tool_result` blocks in a user turn.
+/// - Inline images become base64 `image` blocks ahead of the turn's text.
+/// - Consecutive turns with the same role are merged, as the API expects
+///   user/assistant alternation.
+fn convert_messages(messages: &[ChatMessage]) -> (String, Vec<Value>) {
+    let mut system = String::new();
+    let mut out: Vec<(String, Vec<Value>)> = Vec::new();
+
+    for (i, msg) in messages.iter().enumerate() {
+        let text = msg.content.as_deref().unwrap_or("");
+        let (role, blocks) = match msg.role.as_str() {
+            "system" if i == 0 => {
+                system = text.to_string();
+                continue;
+            }
+            "system" => ("user", vec![text_block(&format!("[System] {}", text))]),
+            "assistant" => {
+                let mut blocks: Vec<Value> = msg.thinking_blocks.iter().flatten()
+                    .filter_map(|block| serde_json::to_value(block).ok())
+                    .collect();
+                if !text.trim().is_empty() {
+                    blocks.push(text_block(text));
+                }
+                for tc in msg.tool_calls.iter().flatten() {
+                    let input: Value = serde_json::from_str(&tc.function.arguments)
+                        .ok()
+                        .filter(|v: &Value| v.is_object())
+                        .unwrap_or_else(|| serde_json::json!({}));
+                    blocks.push(serde_json::json!({
+                        "type": "tool_use",
+                        "id": tc.id,
+                        "name": tc.function.name,
+                        "input": input,
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            "tool" => ("user", vec![serde_json::json!({
+                "type": "tool_result",
+                "tool_use_id": msg.tool_call_id.as_deref().unwrap_or(""),
+                "content": text,
+            })]),
+            _ => {
+                let mut blocks: Vec<Value> = msg.images.iter().flatten()
+                    .filter_map(image_block)
+                    .collect();
+                if !text.is_empty() {
+                    blocks.push(text_block(text));
+                }
+                ("user", blocks)
+            }
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        match out.last_mut() {
+            Some((last_role, last_blocks)) if last_role == role => last_blocks.extend(blocks),
+            _ => out.push((role.to_string(), blocks)),
+        }
+    }
+
+    let messages = out.into_iter()
+        .map(|(role, content)| serde_json::json!({ "role": role, "content": content }))
+        .collect();
+    (system, messages)
+}
+
+fn text_block(text: &str) -> Value {
+    serde_json::json!({ "type": "text", "text": text })
+}
+
+fn image_block(image: &ImagePart) -> Option<Value> {
+    image.data.as_ref().map(|data| serde_json::json!({
+        "type": "image",
+        "source": { "type": "base64", "media_type": image.media_type, "data": data },
+    }))
+}
+
+/// Map Messages API stop reasons onto the OpenAI finish reasons used by the tool loop
+fn normalize_stop_reason(reason: &str) -> String {
+    match reason {
+        "tool_use" => "tool_calls".to_string(),
+        "max_tokens" => "length".to_string(),
+        _ => "stop".to_string(),
+    }
+}
+
+/// Tracks which content block index belongs to which tool call, thinking
+/// blocks until they are complete, and the input token counts from
+/// `message_start` until usage is complete
+#[derive(Default)]
+struct AnthropicStreamParser {
+    tool_indices: HashMap<u64, usize>,
+    /// Text and signature of open thinking blocks, by block index
+    thinking: HashMap<u64, (String, String)>,
+    input_tokens: u64,
+    cached_tokens: u64,
+}
+
+/// (total prompt tokens, tokens read from cache). The API reports uncached
+/// input, cache reads and cache writes separately.
+fn prompt_usage(usage: &Value) -> Option<(u64, u64)> {
+    let input = usage["input_tokens"].as_u64()?;
+    let read = usage["cache_read_input_tokens"].as_u64().unwrap_or(0);
+    let written = usage["cache_creation_input_tokens"].as_u64().unwrap_or(0);
+    Some((input + read + written, read))
+}
+
+impl StreamParser for AnthropicStreamParser {
+    fn parse(&mut self, data: &str) -> Vec<StreamDelta> {
+        let event: Value = match serde_json::from_str(data) {
+            Ok(v) => v,
+            Err(_) => return Vec::new(),
+        };
+        let block_index = event["index"].as_u64().unwrap_or(0);
+
+        match event["type"].as_str().unwrap_or("") {
+            "content_block_start" => {
+                let block = &event["content_block"];
+                match block["type"].as_str() {
+                    Some("tool_use") => {
+                        let index = self.tool_indices.len();
+                        self.tool_indices.insert(block_index, index);
+                        // Input normally streams as input_json_delta; keep a non-empty initial input
+                        let arguments = block["input"].as_object()
+                            .filter(|o| !o.is_empty())
+                            .map(|o| Value::Object(o.clone()).to_string());
+                        vec![StreamDelta::ToolCall {
+                            index,
+                            id: block["id"].as_str().map(|s| s.to_string()),
+                            name: block["name"].as_str().map(|s| s.to_string()),
+                            arguments,
+                        }]
+                    }
+                    Some("text") => match block["text"].as_str() {
+                        Some(text) if !text.is_empty() => vec![StreamDelta::Text(text.to_string())],
+                        _ => Vec::new(),
+                    },
+                    Some("thinking") => {
+                        let text = block["thinking"].as_str().unwrap_or("").to_string();
+                        let signature = block["signature"].as_str().unwrap_or("").to_string();
+                        self.thinking.insert(block_index, (text.clone(), signature));
+                        if text.is_empty() { Vec::new() } else { vec![StreamDelta::Thinking(text)] }
+                    }
+                    Some("redacted_thinking") => match block["data"].as_str() {
+                        Some(data) => vec![StreamDelta::ThinkingBlock(ThinkingBlock::RedactedThinking { data: data.to_string() })],
+                        None => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => delta["text"].as_str()
+                        .map(|t| vec![StreamDelta::Text(t.to_string())])
+                        .unwrap_or_default(),
+                    Some("thinking_delta") => match delta["thinking"].as_str() {
+                        Some(t) => {
+                            self.thinking.entry(block_index).or_default().0.push_str(t);
+                            vec![StreamDelta::Thinking(t.to_string())]
+                        }
+                        None => Vec::new(),
+                    },
+                    Some("signature_delta") => {
+                        if let Some(s) = delta["signature"].as_str() {
+                            self.thinking.entry(block_index).or_default().1.push_str(s);
+                        }
+                        Vec::new()
+                    }
+                    Some("input_json_delta") => match self.tool_indices.get(&block_index) {
+                        Some(&index) => vec![StreamDelta::ToolCall {
+                            index,
+                            id: None,
+                            name: None,
+                            arguments: delta["partial_json"].as_str().map(|s| s.to_string()),
+                        }],
+                        None => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                }
+            }
+            "content_block_stop" => match self.thinking.remove(&block_index) {
+                Some((thinking, signature)) => vec![StreamDelta::ThinkingBlock(ThinkingBlock::Thinking { thinking, signature })],
+                None => Vec::new(),
+            },
+            "message_start" => {
+                (self.input_tokens, self.cached_tokens) = prompt_usage(&event["message"]["usage"]).unwrap_or((0, 0));
+                Vec::new()
+            }
+            "message_delta" => {
+                let mut deltas = Vec::new();
+                // output_tokens in message_delta is cumulative for the message
+                if let Some(output) = event["usage"]["output_tokens"].as_u64() {
+                    let (prompt_tokens, cached_tokens) = prompt_usage(&event["usage"])
+                        .unwrap_or((self.input_tokens, self.cached_tokens));
+                    deltas.push(StreamDelta::Usage(TokenUsage {
+                        prompt_tokens,
+                        completion_tokens: output,
+                        cached_tokens,
+                    }));
+                }
+                if let Some(r) = event["delta"]["stop_reason"].as_str() {
+                    deltas.push(StreamDelta::Finish(normalize_stop_reason(r)));
+                }
+                deltas
+            }
+            "error" => {
+                let error = &event["error"];
+                vec![StreamDelta::Error {
+                    message: error["message"].as_str().unwrap_or("unknown error").to_string(),
+                    retryable: error["type"].as_str().is_some_and(retry::is_retryable_error_type),
+                }]
+            }
+            // content_block_stop, message_stop, ping
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::{FunctionCall, ToolCall};
+
+    fn msg(role: &str, content: Option<&str>) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: content.map(|s| s.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    #[test]
+    fn test_messages_url() {
+        assert_eq!(messages_url("https://api.anthropic.com"), "https://api.anthropic.com/v1/messages");
+        assert_eq!(messages_url("https://api.anthropic.com/v1/"), "https://api.anthropic.com/v1/messages");
+        assert_eq!(messages_url("https://gateway.local/anthropic/v1"), "https://gateway.local/anthropic/v1/messages");
+    }
+
+    #[test]
+    fn test_convert_system_is_top_level() {
+        let (system, messages) = convert_messages(&[
+            msg("system", Some("Be brief.")),
+            msg("user", Some("Hi")),
+        ]);
+        assert_eq!(system, "Be brief.");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["role"], "user");
+        assert_eq!(messages[0]["content"][0]["text"], "Hi");
+    }
+
+    #[test]
+    fn test_convert_tool_round_trip() {
+        let mut assistant = msg("assistant", Some("Let me look."));
+        assistant.tool_calls = Some(vec![
+            ToolCall {
+                id: "toolu_1".into(),
+                r#type: "function".into(),
+                function: FunctionCall { name: "read_file".into(), arguments: r#"{"path":"a.md"}"#.into() },
+            },
+            ToolCall {
+                id: "toolu_2".into(),
+                r#type: "function".into(),
+                function: FunctionCall { name: "list_directory".into(), arguments: String::new() },
+            },
+        ]);
+        let mut r1 = msg("tool", Some("content of a"));
+        r1.tool_call_id = Some("toolu_1".into());
+        let mut r2 = msg("tool", Some("a.md"));
+        r2.tool_call_id = Some("toolu_2".into());
+
+        let (_, messages) = convert_messages(&[msg("user", Some("Read a.md")), assistant, r1, r2]);
+        assert_eq!(messages.len(), 3);
+
+        let blocks = messages[1]["content"].as_array().unwrap();
+        assert_eq!(blocks[0]["type"], "text");
+        assert_eq!(blocks[1]["type"], "tool_use");
+        assert_eq!(blocks[1]["input"]["path"], "a.md");
+        // Empty arguments become an empty object
+        assert_eq!(blocks[2]["input"], serde_json::json!({}));
+
+        // Both tool results are merged into one user turn
+        assert_eq!(messages[2]["role"], "user");
+        let results = messages[2]["content"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["type"], "tool_result");
+        assert_eq!(results[0]["tool_use_id"], "toolu_1");
+        assert_eq!(results[1]["tool_use_id"], "toolu_2");
+    }
+
+    #[test]
+    fn test_convert_mid_conversation_system_merges_into_user_turn() {
+        let mut r = msg("tool", Some("ok"));
+        r.tool_call_id = Some("toolu_1".into());
+        let (_, messages) = convert_messages(&[r, msg("system", Some("[Progress reminder]"))]);
+        assert_eq!(messages.len(), 1);
+        let blocks = messages[0]["content"].as_array().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[1]["text"].as_str().unwrap().contains("[Progress reminder]"));
+    }
+
+    #[test]
+    fn test_convert_images_follow_tool_result() {
+        let mut r = msg("tool", Some("Attached shot.png (image/png, 1 KB)."));
+        r.tool_call_id = Some("toolu_1".into());
+        let mut u = msg("user", Some("[Attached images: shot.png]"));
+        u.images = Some(vec![ImagePart {
+            media_type: "image/png".into(),
+            data: Some("iVBORw==".into()),
+            path: Some("shot.png".into()),
+        }]);
+        let (_, messages) = convert_messages(&[r, u]);
+        assert_eq!(messages.len(), 1);
+        let blocks = messages[0]["content"].as_array().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0]["type"], "tool_result");
+        assert_eq!(blocks[1]["type"], "image");
+        assert_eq!(blocks[1]["source"]["media_type"], "image/png");
+        assert_eq!(blocks[1]["source"]["data"], "iVBORw==");
+        assert_eq!(blocks[2]["type"], "text");
+    }
+
+    #[test]
+    fn test_convert_tool_schema() {
+        let schema = serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "grep_files",
+                "description": "Search",
+                "parameters": { "type": "object", "properties": {} }
+            }
+        });
+        let tool = convert_tool(&schema);
+        assert_eq!(tool["name"], "grep_files");
+        assert_eq!(tool["input_schema"]["type"], "object");
+    }
+
+    #[test]
+    fn test_stream_text_and_tool_use() {
+        let mut parser = AnthropicStreamParser::default();
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1"}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Reading"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"read_file","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\":"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"a.md\"}"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let deltas: Vec<StreamDelta> = events.iter().flat_map(|e| parser.parse(e)).collect();
+        assert_eq!(deltas, vec![
+            StreamDelta::Text("Reading".into()),
+            StreamDelta::ToolCall { index: 0, id: Some("toolu_1".into()), name: Some("read_file".into()), arguments: None },
+            StreamDelta::ToolCall { index: 0, id: None, name: None, arguments: Some("{\"path\":".into()) },
+            StreamDelta::ToolCall { index: 0, id: None, name: None, arguments: Some("\"a.md\"}".into()) },
+            StreamDelta::Finish("tool_calls".into()),
+        ]);
+    }
+
+    #[test]
+    fn test_stream_usage() {
+        let mut parser = AnthropicStreamParser::default();
+        assert!(parser.parse(r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":250,"output_tokens":1}}}"#).is_empty());
+        assert_eq!(
+            parser.parse(r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":42}}"#),
+            vec![
+                StreamDelta::Usage(TokenUsage { prompt_tokens: 250, completion_tokens: 42, cached_tokens: 0 }),
+                StreamDelta::Finish("stop".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_usage_counts_cached_tokens() {
+        let mut parser = AnthropicStreamParser::default();
+        parser.parse(r#"{"type":"message_start","message":{"usage":{"input_tokens":50,"cache_read_input_tokens":3000,"cache_creation_input_tokens":200,"output_tokens":1}}}"#);
+        assert_eq!(
+            parser.parse(r#"{"type":"message_delta","delta":{},"usage":{"output_tokens":10}}"#),
+            vec![StreamDelta::Usage(TokenUsage { prompt_tokens: 3250, completion_tokens: 10, cached_tokens: 3000 })]
+        );
+    }
+
+    #[test]
+    fn test_body_places_cache_breakpoints() {
+        let tool = |name: &str| serde_json::json!({
+            "type": "function",
+            "function": { "name": name, "description": "", "parameters": { "type": "object" } }
+        });
+        let tools = vec![tool("grep_files"), tool("read_file")];
+        let mut r = msg("tool", Some("ok"));
+        r.tool_call_id = Some("toolu_1".into());
+        let messages = vec![
+            msg("system", Some("Be brief.")),
+            msg("user", Some("First")),
+            msg("assistant", Some("Reply")),
+            msg("user", Some("Second")),
+            msg("assistant", Some("Reply")),
+            r,
+        ];
+        let req = ChatRequest {
+            model: "claude-sonnet-4-5",
+            messages: &messages,
+            tools: &tools,
+            temperature: 0.7,
+            max_tokens: 1000,
+            stream: true,
+            reasoning: false,
+            cache: true,
+        };
+        let body = build_body(&req);
+        let ephemeral = serde_json::json!({ "type": "ephemeral" });
+        assert_eq!(body["system"][0]["text"], "Be brief.");
+        assert_eq!(body["system"][0]["cache_control"], ephemeral);
+        assert!(body["tools"][0].get("cache_control").is_none());
+        assert_eq!(body["tools"][1]["cache_control"], ephemeral);
+        let marked: Vec<usize> = body["messages"].as_array().unwrap().iter().enumerate()
+            .filter(|(_, m)| m["content"].as_array().unwrap().iter().any(|b| b.get("cache_control").is_some()))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(marked, vec![2, 4]);
+
+        let body = build_body(&ChatRequest { cache: false, ..req });
+        assert_eq!(body["system"], "Be brief.");
+        assert!(!body.to_string().contains("cache_control"));
+    }
+
+    #[test]
+    fn test_stream_thinking() {
+        let mut parser = AnthropicStreamParser::default();
+        let events = [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Check the file"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig=="}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+        ];
+        let deltas: Vec<StreamDelta> = events.iter().flat_map(|e| parser.parse(e)).collect();
+        assert_eq!(deltas, vec![
+            StreamDelta::Thinking("Check the file".into()),
+            StreamDelta::ThinkingBlock(ThinkingBlock::Thinking {
+                thinking: "Check the file".into(),
+                signature: "sig==".into(),
+            }),
+        ]);
+    }
+
+    #[test]
+    fn test_stream_keeps_each_thinking_block() {
+        let mut parser = AnthropicStreamParser::default();
+        let events = [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"First"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig1"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"redacted_thinking","data":"opaque"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"content_block_start","index":2,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"thinking_delta","thinking":"Second"}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"signature_delta","signature":"sig2"}}"#,
+            r#"{"type":"content_block_stop","index":2}"#,
+        ];
+        let blocks: Vec<ThinkingBlock> = events.iter()
+            .flat_map(|e| parser.parse(e))
+            .filter_map(|d| match d {
+                StreamDelta::ThinkingBlock(block) => Some(block),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(blocks, vec![
+            ThinkingBlock::Thinking { thinking: "First".into(), signature: "sig1".into() },
+            ThinkingBlock::RedactedThinking { data: "opaque".into() },
+            ThinkingBlock::Thinking { thinking: "Second".into(), signature: "sig2".into() },
+        ]);
+
+        // The blocks go back in order, each with its own signature
+        let mut assistant = msg("assistant", Some("Answer"));
+        assistant.thinking_blocks = Some(blocks);
+        let (_, messages) = convert_messages(&[msg("user", Some("Q")), assistant]);
+        let sent = messages[1]["content"].as_array().unwrap();
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[0], serde_json::json!({"type": "thinking", "thinking": "First", "signature": "sig1"}));
+        assert_eq!(sent[1], serde_json::json!({"type": "redacted_thinking", "data": "opaque"}));
+        assert_eq!(sent[2], serde_json::json!({"type": "thinking", "thinking": "Second", "signature": "sig2"}));
+        assert_eq!(sent[3]["text"], "Answer");
+    }
+
+    #[test]
+    fn test_convert_signed_thinking_leads_assistant_turn() {
+        let mut assistant = msg("assistant", Some("Answer"));
+        assistant.reasoning = Some("unsigned".into());
+        let (_, messages) = convert_messages(&[msg("user", Some("Q")), assistant.clone()]);
+        assert_eq!(messages[1]["content"].as_array().unwrap().len(), 1);
+
+        assistant.thinking_blocks = Some(vec![ThinkingBlock::Thinking {
+            thinking: "unsigned".into(),
+            signature: "sig==".into(),
+        }]);
+        let (_, messages) = convert_messages(&[msg("user", Some("Q")), assistant]);
+        let blocks = messages[1]["content"].as_array().unwrap();
+        assert_eq!(blocks[0]["type"], "thinking");
+        assert_eq!(blocks[0]["signature"], "sig==");
+        assert_eq!(blocks[1]["text"], "Answer");
+    }
+
+    #[test]
+    fn test_body_enables_thinking_in_deep_mode() {
+        let messages = vec![msg("user", Some("Hi"))];
+        let req = ChatRequest {
+            model: "claude-sonnet-4-5",
+            messages: &messages,
+            tools: &[],
+            temperature: 0.7,
+            max_tokens: 8192,
+            stream: true,
+            reasoning: true,
+            cache: false,
+        };
+        let body = build_body(&req);
+        assert_eq!(body["thinking"]["budget_tokens"], 4096);
+        assert!(body.get("temperature").is_none());
+
+        let body = build_body(&ChatRequest { max_tokens: 1000, ..req });
+        assert!(body.get("thinking").is_none());
+        assert_eq!(body["temperature"], 0.7);
+    }
+
+    #[test]
+    fn test_stream_end_turn_and_error() {
+        let mut parser = AnthropicStreamParser::default();
+        assert_eq!(
+            parser.parse(r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"}}"#),
+            vec![StreamDelta::Finish("stop".into())]
+        );
+        assert_eq!(
+            parser.parse(r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#),
+            vec![StreamDelta::Error { message: "Overloaded".into(), retryable: true }]
+        );
+        assert_eq!(
+            parser.parse(r#"{"type":"error","error":{"type":"invalid_request_error","message":"Bad"}}"#),
+            vec![StreamDelta::Error { message: "Bad".into(), retryable: false }]
+        );
+        assert!(parser.parse(r#"{"type":"ping"}"#).is_empty());
+    }
+
+    #[test]
+    fn test_parse_completion_joins_text_blocks() {
+        let body = serde_json::json!({
+            "content": [
+                { "type": "text", "text": "Hello " },
+                { "type": "tool_use", "id": "t", "name": "x", "input": {} },
+                { "type": "text", "text": "world" }
+            ]
+        });
+        assert_eq!(AnthropicProvider.parse_completion(&body).as_deref(), Some("Hello world"));
+    }
+}
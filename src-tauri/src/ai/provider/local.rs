@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ai::config::AiConfig;
+use crate::ai::streaming::ChatMessage;
+use crate::ai::usage::TokenUsage;
+use super::openai::{self, OpenAiProvider};
+use super::{ChatProvider, ChatRequest, StreamDelta, StreamParser};
+
+const DISCOVERY_TIMEOUT_SECS: u64 = 5;
+const TOOL_CALL_OPEN: &str = "<tool_call>";
+const TOOL_CALL_CLOSE: &str = "</tool_call>";
+
+/// Local Ollama / llama.cpp server speaking the OpenAI-compatible API.
+/// When the model has no native tool calling, tools are described in the
+/// system prompt and calls are parsed from `<tool_call>` blocks in the text.
+pub struct LocalProvider {
+    pub native_tools: bool,
+}
+
+/// A model served by a local inference server
+#[derive(Serialize, Clone, Debug)]
+pub struct LocalModelInfo {
+    pub id: String,
+    /// "ollama" or "llama.cpp"
+    pub server: String,
+    pub supports_tools: bool,
+}
+
+impl ChatProvider for LocalProvider {
+    fn name(&self) -> &str { "Local" }
+
+    fn build_request(&self, client: &Client, config: &AiConfig, req: &ChatRequest) -> RequestBuilder {
+        let url = format!("{}/chat/completions", openai_base(&config.api_url));
+        let body = if self.native_tools || req.tools.is_empty() {
+            openai::build_body(req)
+        } else {
+            let messages = to_prompt_protocol(req.messages, req.tools);
+            openai::build_body(&ChatRequest { messages: &messages, tools: &[], ..*req })
+        };
+        let mut builder = client.post(&url).header("Content-Type", "application/json");
+        // Local servers usually run without auth
+        if !config.api_key.is_empty() {
+            builder = builder.header("Authorization", format!("Bearer {}", config.api_key));
+        }
+        builder.json(&body)
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        let inner = OpenAiProvider.stream_parser();
+        if self.native_tools {
+            inner
+        } else {
+            Box::new(PromptToolParser::new(inner))
+        }
+    }
+
+    fn parse_completion(&self, body: &Value) -> Option<String> {
+        OpenAiProvider.parse_completion(body)
+    }
+
+    fn parse_completion_usage(&self, body: &Value) -> Option<TokenUsage> {
+        OpenAiProvider.parse_completion_usage(body)
+    }
+}
+
+/// Server root without a trailing `/v1` (Ollama native endpoints live at `/api/*`)
+fn server_root(api_url: &str) -> &str {
+    let base = api_url.trim_end_matches('/');
+    base.strip_suffix("/v1").unwrap_or(base)
+}
+
+/// OpenAI-compatible base URL (`{root}/v1`)
+fn openai_base(api_url: &str) -> String {
+    format!("{}/v1", server_root(api_url))
+}
+
+// --- Model discovery ---
+
+/// List the models of a local Ollama or llama.cpp server together with
+/// whether each supports native tool calling.
+pub async fn discover_models(api_url: &str) -> Result<Vec<LocalModelInfo>, String> {
+    let client = Client::new();
+    let root = server_root(api_url);
+
+    // Ollama: /api/tags lists installed models; their capabilities are probed concurrently
+    if let Ok(tags) = get_json(&client, &format!("{}/api/tags", root)).await {
+        let names: Vec<&str> = tags["models"].as_array().into_iter().flatten()
+            .filter_map(|m| m["name"].as_str())
+            .collect();
+        let support = futures_util::future::join_all(
+            names.iter().map(|name| ollama_supports_tools(&client, root, name))
+        ).await;
+        return Ok(names.into_iter().zip(support)
+            .map(|(name, supports_tools)| LocalModelInfo {
+                id: name.to_string(),
+                server: "ollama".to_string(),
+                supports_tools: supports_tools.unwrap_or(false),
+            })
+            .collect());
+    }
+
+    // llama.cpp server: OpenAI-style /v1/models, capabilities from /props
+    let listing = get_json(&client, &format!("{}/v1/models", root)).await
+        .map_err(|e| format!("No Ollama or llama.cpp server found at {}: {}", root, e))?;
+    let supports_tools = llama_cpp_supports_tools(&client, root).await.unwrap_or(false);
+    Ok(listing["data"].as_array().into_iter().flatten()
+        .filter_map(|m| m["id"].as_str())
+        .map(|id| LocalModelInfo {
+            id: id.to_string(),
+            server: "llama.cpp".to_string(),
+            supports_tools,
+        })
+        .collect())
+}
+
+/// Detect native tool support for one model. Returns None if the server
+/// exposes no capability information.
+pub async fn detect_tool_support(api_url: &str, model: &str) -> Option<bool> {
+    let client = Client::new();
+    let root = server_root(api_url);
+    if let Some(supported) = ollama_supports_tools(&client, root, model).await {
+        return Some(supported);
+    }
+    llama_cpp_supports_tools(&client, root).await
+}
+
+async fn get_json(client: &Client, url: &str) -> Result<Value, String> {
+    let resp = client.get(url)
+        .timeout(Duration::from_secs(DISCOVERY_TIMEOUT_SECS))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("HTTP {}", resp.status()));
+    }
+    resp.json().await.map_err(|e| e.to_string())
+}
+
+async fn ollama_supports_tools(client: &Client, root: &str, model: &str) -> Option<bool> {
+    let resp = client.post(format!("{}/api/show", root))
+        .timeout(Duration::from_secs(DISCOVERY_TIMEOUT_SECS))
+        .json(&serde_json::json!({ "model": model }))
+        .send()
+        .await
+        .ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let info: Value = resp.json().await.ok()?;
+    Some(ollama_info_supports_tools(&info))
+}
+
+fn ollama_info_supports_tools(info: &Value) -> bool {
+    // Ollama >= 0.6 reports capabilities; older versions only expose the template
+    if let Some(caps) = info["capabilities"].as_array() {
+        return caps.iter().any(|c| c == "tools");
+    }
+    info["template"].as_str().map(|t| t.contains(".Tools")).unwrap_or(false)
+}
+
+async fn llama_cpp_supports_tools(client: &Client, root: &str) -> Option<bool> {
+    let props = get_json(client, &format!("{}/props", root)).await.ok()?;
+    let template = props["chat_template"].as_str()?;
+    Some(template.contains("tools"))
+}
+
+/// How long a detected tool support is trusted; a model pulled again under
+/// the same name may have changed
+const TOOL_SUPPORT_TTL: Duration = Duration::from_secs(10 * 60);
+
+static TOOL_SUPPORT_CACHE: std::sync::LazyLock<Mutex<HashMap<String, (bool, Instant)>>> =
+    std::sync::LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Build the provider for a chat, honouring `AiConfig::local_tool_mode`
+/// ("native", "prompt", or auto-detect when empty). Detection results are cached
+/// per server and model for `TOOL_SUPPORT_TTL`.
+pub async fn resolve(config: &AiConfig) -> LocalProvider {
+    let native_tools = match config.local_tool_mode.as_str() {
+        "native" => true,
+        "prompt" => false,
+        _ => {
+            let key = format!("{}|{}", server_root(&config.api_url), config.model);
+            let cached = TOOL_SUPPORT_CACHE.lock().ok()
+                .and_then(|c| c.get(&key).copied())
+                .filter(|(_, detected_at)| detected_at.elapsed() < TOOL_SUPPORT_TTL)
+                .map(|(supported, _)| supported);
+            match cached {
+                Some(v) => v,
+                None => {
+                    // Unknown servers are assumed to speak native tools
+                    let detected = detect_tool_support(&config.api_url, &config.model).await.unwrap_or(true);
+                    if let Ok(mut c) = TOOL_SUPPORT_CACHE.lock() {
+                        c.insert(key, (detected, Instant::now()));
+                    }
+                    detected
+                }
+            }
+        }
+    };
+    LocalProvider { native_tools }
+}
+
+// --- Prompt-based tool protocol ---
+
+/// Rewrite history for models without native tool calling: tool schemas go into
+/// the system prompt, assistant tool calls become `<tool_call>` blocks and tool
+/// results become user messages.
+fn to_prompt_protocol(messages: &[ChatMessage], tools: &[Value]) -> Vec<ChatMessage> {
+    let instructions = prompt_tool_instructions(tools);
+    let mut out: Vec<ChatMessage> = Vec::with_capacity(messages.len() + 1);
+    let mut call_names: HashMap<String, String> = HashMap::new();
+
+    for (i, msg) in messages.iter().enumerate() {
+        let text = msg.content.clone().unwrap_or_default();
+        match msg.role.as_str() {
+            "system" if i == 0 => {
+                out.push(text_message("system", format!("{}\n\n{}", text, instructions)));
+            }
+            "assistant" if msg.tool_calls.is_some() => {
+                let mut content = text;
+                for tc in msg.tool_calls.iter().flatten() {
+                    call_names.insert(tc.id.clone(), tc.function.name.clone());
+                    let arguments: Value = serde_json::from_str(&tc.function.arguments)
+                        .unwrap_or_else(|_| serde_json::json!({}));
+                    let call = serde_json::json!({ "name": tc.function.name, "arguments": arguments });
+                    if !content.is_empty() {
+                        content.push('\n');
+                    }
+                    content.push_str(&format!("{}{}{}", TOOL_CALL_OPEN, call, TOOL_CALL_CLOSE));
+                }
+                out.push(text_message("assistant", content));
+            }
+            "tool" => {
+                let id = msg.tool_call_id.clone().unwrap_or_default();
+                let name = call_names.get(&id).cloned().unwrap_or_default();
+                out.push(text_message("user", format!(
+                    "<tool_result name=\"{}\">\n{}\n</tool_result>", name, text
+                )));
+            }
+            _ => out.push(msg.clone()),
+        }
+    }
+    if out.first().map(|m| m.role != "system").unwrap_or(true) {
+        out.insert(0, text_message("system", instructions));
+    }
+    out
+}
+
+fn text_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Some(content),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    }
+}
+
+fn prompt_tool_instructions(tools: &[Value]) -> String {
+    let mut lines = vec![
+        "[Tools]".to_string(),
+        "You can call the tools below. To call a tool, reply with a block of exactly this form and then stop:".to_string(),
+        format!("{}{{\"name\": \"tool_name\", \"arguments\": {{...}}}}{}", TOOL_CALL_OPEN, TOOL_CALL_CLOSE),
+        "You may emit several blocks to call several tools. Results are returned in <tool_result> blocks. Answer normally when no tool is needed.".to_string(),
+        String::new(),
+    ];
+    for t in tools {
+        let f = &t["function"];
+        lines.push(format!(
+            "- {}: {}\n  parameters: {}",
+            f["name"].as_str().unwrap_or(""),
+            f["description"].as_str().unwrap_or(""),
+            f["parameters"]
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Wraps the OpenAI stream parser and lifts `<tool_call>` blocks out of the
+/// text stream. Text that may be the start of a tag is held back until it can
+/// be decided.
+struct PromptToolParser {
+    inner: Box<dyn StreamParser>,
+    pending: String,
+    in_call: bool,
+    calls: usize,
+}
+
+impl PromptToolParser {
+    fn new(inner: Box<dyn StreamParser>) -> Self {
+        Self { inner, pending: String::new(), in_call: false, calls: 0 }
+    }
+
+    fn feed_text(&mut self, text: &str, out: &mut Vec<StreamDelta>) {
+        self.pending.push_str(text);
+        loop {
+            if self.in_call {
+                let Some(end) = self.pending.find(TOOL_CALL_CLOSE) else { return };
+                let body = self.pending[..end].to_string();
+                self.pending.drain(..end + TOOL_CALL_CLOSE.len());
+                self.in_call = false;
+                self.emit_call(&body, out);
+            } else if let Some(start) = self.pending.find(TOOL_CALL_OPEN) {
+                if start > 0 {
+                    out.push(StreamDelta::Text(self.pending[..start].to_string()));
+                }
+                self.pending.drain(..start + TOOL_CALL_OPEN.len());
+                self.in_call = true;
+            } else {
+                // Emit everything except a suffix that could begin a tag
+                let keep = partial_tag_suffix(&self.pending, TOOL_CALL_OPEN);
+                let emit_len = self.pending.len() - keep;
+                if emit_len > 0 {
+                    out.push(StreamDelta::Text(self.pending[..emit_len].to_string()));
+                    self.pending.drain(..emit_len);
+                }
+                return;
+            }
+        }
+    }
+
+    fn emit_call(&mut self, body: &str, out: &mut Vec<StreamDelta>) {
+        let parsed: Value = match serde_json::from_str(body.trim()) {
+            Ok(v) => v,
+            Err(_) => {
+                // Not a valid call — surface it as text so nothing is lost
+                out.push(StreamDelta::Text(format!("{}{}{}", TOOL_CALL_OPEN, body, TOOL_CALL_CLOSE)));
+                return;
+            }
+        };
+        let arguments = match &parsed["arguments"] {
+            Value::String(s) => s.clone(),
+            Value::Null => "{}".to_string(),
+            v => v.to_string(),
+        };
+        out.push(StreamDelta::ToolCall {
+            index: self.calls,
+            id: Some(format!("call_{}", &uuid::Uuid::new_v4().simple().to_string()[..12])),
+            name: parsed["name"].as_str().map(|s| s.to_string()),
+            arguments: Some(arguments),
+        });
+        self.calls += 1;
+    }
+
+    fn flush(&mut self, out: &mut Vec<StreamDelta>) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let rest = std::mem::take(&mut self.pending);
+        if self.in_call {
+            // Unterminated block: models often stop right before the closing tag
+            self.in_call = false;
+            self.emit_call(&rest, out);
+        } else {
+            out.push(StreamDelta::Text(rest));
+        }
+    }
+}
+
+impl StreamParser for PromptToolParser {
+    fn parse(&mut self, data: &str) -> Vec<StreamDelta> {
+        let mut out = Vec::new();
+        for delta in self.inner.parse(data) {
+            match delta {
+                StreamDelta::Text(text) => self.feed_text(&text, &mut out),
+                StreamDelta::Finish(reason) => {
+                    self.flush(&mut out);
+                    let reason = if self.calls > 0 { "tool_calls".to_string() } else { reason };
+                    out.push(StreamDelta::Finish(reason));
+                }
+                other => out.push(other),
+            }
+        }
+        out
+    }
+}
+
+/// Length of the longest suffix of `s` that is a proper prefix of `tag`
+fn partial_tag_suffix(s: &str, tag: &str) -> usize {
+    (1..tag.len().min(s.len() + 1))
+        .rev()
+        .find(|&n| s.is_char_boundary(s.len() - n) && s.ends_with(&tag[..n]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::{FunctionCall, ToolCall};
+
+    fn text_chunk(text: &str) -> String {
+        serde_json::json!({ "choices": [{ "delta": { "content": text }, "finish_reason": null }] }).to_string()
+    }
+
+    fn finish_chunk() -> String {
+        r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#.to_string()
+    }
+
+    fn tools() -> Vec<Value> {
+        vec![serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": "read_file",
+                "description": "Read file content",
+                "parameters": { "type": "object", "properties": { "path": { "type": "string" } } }
+            }
+        })]
+    }
+
+    #[test]
+    fn test_server_root_and_base() {
+        assert_eq!(server_root("http://localhost:11434/v1/"), "http://localhost:11434");
+        assert_eq!(server_root("http://localhost:8080"), "http://localhost:8080");
+        assert_eq!(openai_base("http://localhost:11434"), "http://localhost:11434/v1");
+    }
+
+    #[test]
+    fn test_ollama_capabilities() {
+        assert!(ollama_info_supports_tools(&serde_json::json!({ "capabilities": ["completion", "tools"] })));
+        assert!(!ollama_info_supports_tools(&serde_json::json!({ "capabilities": ["completion"] })));
+        assert!(ollama_info_supports_tools(&serde_json::json!({ "template": "{{ if .Tools }}..." })));
+        assert!(!ollama_info_supports_tools(&serde_json::json!({})));
+    }
+
+    #[test]
+    fn test_partial_tag_suffix() {
+        assert_eq!(partial_tag_suffix("hello <tool", TOOL_CALL_OPEN), 5);
+        assert_eq!(partial_tag_suffix("hello <", TOOL_CALL_OPEN), 1);
+        assert_eq!(partial_tag_suffix("hello", TOOL_CALL_OPEN), 0);
+        assert_eq!(partial_tag_suffix("你好", TOOL_CALL_OPEN), 0);
+    }
+
+    #[test]
+    fn test_prompt_parser_extracts_split_tool_call() {
+        let mut parser = PromptToolParser::new(OpenAiProvider.stream_parser());
+        let mut deltas = Vec::new();
+        for piece in ["Let me check.<tool", "_call>{\"name\": \"read_file\", ", "\"arguments\": {\"path\": \"a.md\"}}</tool_call>"] {
+            deltas.extend(parser.parse(&text_chunk(piece)));
+        }
+        deltas.extend(parser.parse(&finish_chunk()));
+
+        assert_eq!(deltas[0], StreamDelta::Text("Let me check.".into()));
+        match &deltas[1] {
+            StreamDelta::ToolCall { index, name, arguments, .. } => {
+                assert_eq!(*index, 0);
+                assert_eq!(name.as_deref(), Some("read_file"));
+                assert_eq!(arguments.as_deref(), Some(r#"{"path":"a.md"}"#));
+            }
+            other => panic!("expected tool call, got {:?}", other),
+        }
+        assert_eq!(deltas.last(), Some(&StreamDelta::Finish("tool_calls".into())));
+    }
+
+    #[test]
+    fn test_prompt_parser_plain_text_passes_through() {
+        let mut parser = PromptToolParser::new(OpenAiProvider.stream_parser());
+        let mut deltas = parser.parse(&text_chunk("a < b"));
+        deltas.extend(parser.parse(&finish_chunk()));
+        let text: String = deltas.iter().filter_map(|d| match d {
+            StreamDelta::Text(t) => Some(t.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(text, "a < b");
+        assert_eq!(deltas.last(), Some(&StreamDelta::Finish("stop".into())));
+    }
+
+    #[test]
+    fn test_prompt_parser_invalid_call_becomes_text() {
+        let mut parser = PromptToolParser::new(OpenAiProvider.stream_parser());
+        let mut deltas = parser.parse(&text_chunk("<tool_call>not json</tool_call>"));
+        deltas.extend(parser.parse(&finish_chunk()));
+        assert_eq!(deltas[0], StreamDelta::Text("<tool_call>not json</tool_call>".into()));
+        assert_eq!(deltas[1], StreamDelta::Finish("stop".into()));
+    }
+
+    #[test]
+    fn test_to_prompt_protocol_rewrites_history() {
+        let messages = vec![
+            text_message("system", "Be helpful.".into()),
+            text_message("user", "Read a.md".into()),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_1".into(),
+                    r#type: "function".into(),
+                    function: FunctionCall { name: "read_file".into(), arguments: r#"{"path":"a.md"}"#.into() },
+                }]),
+                tool_call_id: None,
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("# A".into()),
+                tool_calls: None,
+                tool_call_id: Some("call_1".into()),
+                reasoning: None,
+                thinking_blocks: None,
+                images: None,
+            },
+        ];
+        let out = to_prompt_protocol(&messages, &tools());
+        assert_eq!(out.len(), 4);
+        let system = out[0].content.as_deref().unwrap();
+        assert!(system.starts_with("Be helpful."));
+        assert!(system.contains("- read_file: Read file content"));
+        assert!(out[2].tool_calls.is_none());
+        assert!(out[2].content.as_deref().unwrap().contains("<tool_call>{\"arguments\":{\"path\":\"a.md\"},\"name\":\"read_file\"}</tool_call>"));
+        assert_eq!(out[3].role, "user");
+        assert!(out[3].content.as_deref().unwrap().contains("<tool_result name=\"read_file\">"));
+    }
+
+    #[test]
+    fn test_to_prompt_protocol_inserts_system_message() {
+        let out = to_prompt_protocol(&[text_message("user", "hi".into())], &tools());
+        assert_eq!(out[0].role, "system");
+        assert!(out[0].content.as_deref().unwrap().starts_with("[Tools]"));
+    }
+}
@@ -0,0 +1,114 @@
+pub mod openai;
+pub mod anthropic;
+pub mod local;
+
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+use super::config::AiConfig;
+use super::streaming::{ChatMessage, ThinkingBlock};
+use super::usage::TokenUsage;
+
+/// A single model request, independent of the wire format.
+/// `tools` holds OpenAI-shaped function schemas as produced by ToolRegistry.
+pub struct ChatRequest<'a> {
+    pub model: &'a str,
+    pub messages: &'a [ChatMessage],
+    pub tools: &'a [Value],
+    pub temperature: f64,
+    pub max_tokens: u32,
+    pub stream: bool,
+    /// Deep mode: ask models that support it for extended reasoning
+    pub reasoning: bool,
+    /// Mark the stable prefix (system prompt, tools, older turns) for
+    /// caching, for providers that need explicit breakpoints
+    pub cache: bool,
+}
+
+/// Provider-neutral piece of a streamed response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamDelta {
+    /// Visible assistant text
+    Text(String),
+    /// Reasoning / thinking text, shown separately from the answer
+    Thinking(String),
+    /// A complete Anthropic thinking block, to be sent back with the turn
+    ThinkingBlock(ThinkingBlock),
+    /// Fragment of a tool call; fields are accumulated by `index`
+    ToolCall {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: Option<String>,
+    },
+    /// Token usage of the whole response
+    Usage(TokenUsage),
+    /// Normalized finish reason: "stop", "tool_calls", "length"
+    Finish(String),
+    /// Error reported inside the stream by the provider. Retryable errors
+    /// (overload, rate limits) are worth sending the round again.
+    Error { message: String, retryable: bool },
+}
+
+/// Stateful parser for the `data:` payloads of one streamed response.
+/// A fresh parser is created for every round.
+pub trait StreamParser: Send {
+    fn parse(&mut self, data: &str) -> Vec<StreamDelta>;
+}
+
+/// The core trait every chat backend must implement
+pub trait ChatProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Build the HTTP request (URL, auth headers and body) for a chat request
+    fn build_request(&self, client: &Client, config: &AiConfig, req: &ChatRequest) -> RequestBuilder;
+
+    /// Create a parser for a streamed response
+    fn stream_parser(&self) -> Box<dyn StreamParser>;
+
+    /// Extract the assistant text from a non-streamed response body
+    fn parse_completion(&self, body: &Value) -> Option<String>;
+
+    /// Extract the token usage from a non-streamed response body
+    fn parse_completion_usage(&self, body: &Value) -> Option<TokenUsage>;
+}
+
+/// Get chat provider by `AiConfig::provider` kind
+pub fn get_provider(kind: &str) -> Box<dyn ChatProvider> {
+    match kind {
+        "anthropic" => Box::new(anthropic::AnthropicProvider),
+        "local" => Box::new(local::LocalProvider { native_tools: true }),
+        _ => Box::new(openai::OpenAiProvider),
+    }
+}
+
+/// Like `get_provider`, but resolves provider capabilities that need a network
+/// probe (tool calling support of local models)
+pub async fn resolve_provider(config: &AiConfig) -> Box<dyn ChatProvider> {
+    match config.provider.as_str() {
+        "local" => Box::new(local::resolve(config).await),
+        kind => get_provider(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_provider_anthropic() {
+        assert_eq!(get_provider("anthropic").name(), "Anthropic");
+    }
+
+    #[test]
+    fn test_get_provider_local() {
+        assert_eq!(get_provider("local").name(), "Local");
+    }
+
+    #[test]
+    fn test_get_provider_defaults_to_openai() {
+        assert_eq!(get_provider("").name(), "OpenAI");
+        assert_eq!(get_provider("openai").name(), "OpenAI");
+        assert_eq!(get_provider("unknown").name(), "OpenAI");
+    }
+}
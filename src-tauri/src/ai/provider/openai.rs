@@ -0,0 +1,323 @@
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+use crate::ai::config::AiConfig;
+use crate::ai::image::data_url;
+use crate::ai::streaming::{ChatMessage, SseChunk};
+use crate::ai::usage::TokenUsage;
+use super::{ChatProvider, ChatRequest, StreamDelta, StreamParser};
+
+/// OpenAI-compatible `/chat/completions` backend
+pub struct OpenAiProvider;
+
+impl ChatProvider for OpenAiProvider {
+    fn name(&self) -> &str { "OpenAI" }
+
+    fn build_request(&self, client: &Client, config: &AiConfig, req: &ChatRequest) -> RequestBuilder {
+        let url = format!("{}/chat/completions", config.api_url.trim_end_matches('/'));
+        client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", config.api_key))
+            .header("Content-Type", "application/json")
+            .json(&build_body(req))
+    }
+
+    fn stream_parser(&self) -> Box<dyn StreamParser> {
+        Box::new(OpenAiStreamParser)
+    }
+
+    fn parse_completion(&self, body: &Value) -> Option<String> {
+        body["choices"][0]["message"]["content"].as_str().map(|s| s.to_string())
+    }
+
+    fn parse_completion_usage(&self, body: &Value) -> Option<TokenUsage> {
+        let usage = &body["usage"];
+        Some(TokenUsage {
+            prompt_tokens: usage["prompt_tokens"].as_u64()?,
+            completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+            cached_tokens: usage["prompt_tokens_details"]["cached_tokens"].as_u64()
+                .or_else(|| usage["prompt_cache_hit_tokens"].as_u64())
+                .unwrap_or(0),
+        })
+    }
+}
+
+pub(super) fn build_body(req: &ChatRequest) -> Value {
+    // Reasoning from earlier turns must not be sent back (DeepSeek rejects it)
+    let messages: Vec<Value> = req.messages.iter()
+        .filter_map(|msg| serde_json::to_value(msg).ok().map(|m| (msg, m)))
+        .map(|(msg, mut m)| {
+            if let Some(obj) = m.as_object_mut() {
+                obj.remove("reasoning");
+                obj.remove("thinking_blocks");
+                obj.remove("images");
+                if let Some(parts) = content_parts(msg) {
+                    obj.insert("content".into(), parts);
+                }
+            }
+            m
+        })
+        .collect();
+    let mut body = serde_json::json!({
+        "model": req.model,
+        "messages": messages,
+        "temperature": req.temperature,
+        "max_tokens": req.max_tokens,
+        "stream": req.stream,
+    });
+    if req.stream {
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+    // Some compatible servers reject an empty `tools` array
+    if !req.tools.is_empty() {
+        body["tools"] = Value::Array(req.tools.to_vec());
+    }
+    if req.reasoning {
+        apply_reasoning_params(&mut body, req.model);
+    }
+    body
+}
+
+/// Content-part array for a message with inline images: the text first, then
+/// one `image_url` part per image. None keeps the plain string content.
+fn content_parts(msg: &ChatMessage) -> Option<Value> {
+    let urls: Vec<String> = msg.images.iter().flatten().filter_map(data_url).collect();
+    if urls.is_empty() {
+        return None;
+    }
+    let mut parts = Vec::with_capacity(urls.len() + 1);
+    if let Some(text) = msg.content.as_deref().filter(|t| !t.is_empty()) {
+        parts.push(serde_json::json!({ "type": "text", "text": text }));
+    }
+    for url in urls {
+        parts.push(serde_json::json!({ "type": "image_url", "image_url": { "url": url } }));
+    }
+    Some(Value::Array(parts))
+}
+
+/// Deep mode request parameters for model families with a reasoning switch.
+/// Unknown models get nothing, as strict servers reject unknown fields.
+fn apply_reasoning_params(body: &mut Value, model: &str) {
+    let model = model.to_lowercase();
+    if ["o1", "o3", "o4", "gpt-5"].iter().any(|p| model.starts_with(p)) {
+        body["reasoning_effort"] = Value::String("high".into());
+    } else if model.starts_with("qwen") {
+        body["enable_thinking"] = Value::Bool(true);
+    }
+}
+
+struct OpenAiStreamParser;
+
+impl StreamParser for OpenAiStreamParser {
+    fn parse(&mut self, data: &str) -> Vec<StreamDelta> {
+        let chunk: SseChunk = match serde_json::from_str(data) {
+            Ok(c) => c,
+            Err(_) => return Vec::new(),
+        };
+        let mut deltas = Vec::new();
+        for choice in chunk.choices.unwrap_or_default() {
+            if let Some(delta) = choice.delta {
+                if let Some(thinking) = delta.reasoning_content.or(delta.reasoning) {
+                    if !thinking.is_empty() {
+                        deltas.push(StreamDelta::Thinking(thinking));
+                    }
+                }
+                if let Some(text) = delta.content {
+                    deltas.push(StreamDelta::Text(text));
+                }
+                for tc in delta.tool_calls.unwrap_or_default() {
+                    let (name, arguments) = match tc.function {
+                        Some(f) => (f.name, f.arguments),
+                        None => (None, None),
+                    };
+                    deltas.push(StreamDelta::ToolCall {
+                        index: tc.index.unwrap_or(0),
+                        id: tc.id,
+                        name,
+                        arguments,
+                    });
+                }
+            }
+            if let Some(reason) = choice.finish_reason {
+                deltas.push(StreamDelta::Finish(reason));
+            }
+        }
+        if let Some(usage) = chunk.usage {
+            let cached_tokens = usage.prompt_tokens_details.map(|d| d.cached_tokens)
+                .or(usage.prompt_cache_hit_tokens)
+                .unwrap_or(0);
+            deltas.push(StreamDelta::Usage(TokenUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                cached_tokens,
+            }));
+        }
+        deltas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::ChatMessage;
+
+    fn parse(data: &str) -> Vec<StreamDelta> {
+        OpenAiProvider.stream_parser().parse(data)
+    }
+
+    #[test]
+    fn test_parse_text_delta() {
+        let deltas = parse(r#"{"choices":[{"delta":{"content":"Hello"},"finish_reason":null}]}"#);
+        assert_eq!(deltas, vec![StreamDelta::Text("Hello".into())]);
+    }
+
+    #[test]
+    fn test_parse_tool_call_fragment() {
+        let deltas = parse(r#"{"choices":[{"delta":{"tool_calls":[{"index":1,"id":"call_1","type":"function","function":{"name":"read_file","arguments":"{\"pa"}}]}}]}"#);
+        assert_eq!(deltas, vec![StreamDelta::ToolCall {
+            index: 1,
+            id: Some("call_1".into()),
+            name: Some("read_file".into()),
+            arguments: Some("{\"pa".into()),
+        }]);
+    }
+
+    #[test]
+    fn test_parse_finish_reason() {
+        let deltas = parse(r#"{"choices":[{"delta":{},"finish_reason":"tool_calls"}]}"#);
+        assert_eq!(deltas, vec![StreamDelta::Finish("tool_calls".into())]);
+    }
+
+    #[test]
+    fn test_parse_malformed_is_ignored() {
+        assert!(parse("{not json").is_empty());
+        assert!(parse("{}").is_empty());
+    }
+
+    #[test]
+    fn test_body_omits_empty_tools() {
+        let messages = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("Hi".into()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }];
+        let req = ChatRequest {
+            model: "gpt-4o",
+            messages: &messages,
+            tools: &[],
+            temperature: 0.5,
+            max_tokens: 100,
+            stream: true,
+            reasoning: false,
+            cache: true,
+        };
+        let body = build_body(&req);
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(body["messages"][0]["content"], "Hi");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert!(body.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_parse_usage_chunk() {
+        let deltas = parse(r#"{"choices":[],"usage":{"prompt_tokens":120,"completion_tokens":30,"total_tokens":150}}"#);
+        assert_eq!(deltas, vec![StreamDelta::Usage(TokenUsage { prompt_tokens: 120, completion_tokens: 30, cached_tokens: 0 })]);
+        let deltas = parse(r#"{"choices":[],"usage":{"prompt_tokens":2000,"completion_tokens":30,"prompt_tokens_details":{"cached_tokens":1920}}}"#);
+        assert_eq!(deltas, vec![StreamDelta::Usage(TokenUsage { prompt_tokens: 2000, completion_tokens: 30, cached_tokens: 1920 })]);
+        let deltas = parse(r#"{"choices":[],"usage":{"prompt_tokens":2000,"completion_tokens":30,"prompt_cache_hit_tokens":1536}}"#);
+        assert_eq!(deltas, vec![StreamDelta::Usage(TokenUsage { prompt_tokens: 2000, completion_tokens: 30, cached_tokens: 1536 })]);
+    }
+
+    #[test]
+    fn test_parse_reasoning_delta() {
+        let deltas = parse(r#"{"choices":[{"delta":{"reasoning_content":"Let me think","content":null}}]}"#);
+        assert_eq!(deltas, vec![StreamDelta::Thinking("Let me think".into())]);
+        let deltas = parse(r#"{"choices":[{"delta":{"reasoning":"Hmm","content":"Hi"}}]}"#);
+        assert_eq!(deltas, vec![StreamDelta::Thinking("Hmm".into()), StreamDelta::Text("Hi".into())]);
+    }
+
+    #[test]
+    fn test_body_strips_reasoning_from_history() {
+        let messages = vec![ChatMessage {
+            role: "assistant".into(),
+            content: Some("Answer".into()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: Some("private chain".into()),
+            thinking_blocks: None,
+            images: None,
+        }];
+        let req = ChatRequest {
+            model: "deepseek-reasoner",
+            messages: &messages,
+            tools: &[],
+            temperature: 0.5,
+            max_tokens: 100,
+            stream: true,
+            reasoning: true,
+            cache: true,
+        };
+        let body = build_body(&req);
+        assert_eq!(body["messages"][0]["content"], "Answer");
+        assert!(body["messages"][0].get("reasoning").is_none());
+        assert!(body.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_body_sends_images_as_content_parts() {
+        let messages = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("What is in this chart?".into()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: Some(vec![crate::ai::streaming::ImagePart {
+                media_type: "image/png".into(),
+                data: Some("iVBORw==".into()),
+                path: Some("chart.png".into()),
+            }]),
+        }];
+        let req = ChatRequest {
+            model: "gpt-4o",
+            messages: &messages,
+            tools: &[],
+            temperature: 0.5,
+            max_tokens: 100,
+            stream: true,
+            reasoning: false,
+            cache: true,
+        };
+        let body = build_body(&req);
+        let msg = &body["messages"][0];
+        assert!(msg.get("images").is_none());
+        assert_eq!(msg["content"][0], serde_json::json!({ "type": "text", "text": "What is in this chart?" }));
+        assert_eq!(msg["content"][1]["type"], "image_url");
+        assert_eq!(msg["content"][1]["image_url"]["url"], "data:image/png;base64,iVBORw==");
+    }
+
+    #[test]
+    fn test_reasoning_params_by_model() {
+        let mut body = serde_json::json!({});
+        apply_reasoning_params(&mut body, "o3-mini");
+        assert_eq!(body["reasoning_effort"], "high");
+        let mut body = serde_json::json!({});
+        apply_reasoning_params(&mut body, "qwen3-235b-a22b");
+        assert_eq!(body["enable_thinking"], true);
+        let mut body = serde_json::json!({});
+        apply_reasoning_params(&mut body, "gpt-4o");
+        assert_eq!(body, serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_parse_completion() {
+        let body = serde_json::json!({"choices":[{"message":{"content":"done"}}]});
+        assert_eq!(OpenAiProvider.parse_completion(&body).as_deref(), Some("done"));
+        assert!(OpenAiProvider.parse_completion(&serde_json::json!({})).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use super::config::AiConfig;
+
+/// Upper bound for a single backoff wait, including server-provided Retry-After
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// Granularity of cancel checks while waiting
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+/// Exponential backoff policy for transient API failures
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &AiConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based), or None when retries are
+    /// exhausted. A server-provided Retry-After wins over the computed backoff.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        let backoff = self.base_delay.saturating_mul(1u32 << attempt.min(16));
+        Some(retry_after.unwrap_or(backoff).min(MAX_DELAY))
+    }
+}
+
+/// HTTP statuses worth retrying: timeouts, rate limits and gateway/overload errors
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Error types providers report inside a stream that are worth retrying
+pub fn is_retryable_error_type(kind: &str) -> bool {
+    matches!(kind, "overloaded_error" | "rate_limit_error" | "api_error" | "timeout_error")
+}
+
+/// Whether a failed status may succeed with a different model: rate limits,
+/// server errors and an unknown model. Auth and request errors (a bad tool
+/// schema, an overlong context) would fail the same way on the next model.
+pub fn allows_fallback(status: u16) -> bool {
+    matches!(status, 404 | 429 | 500..=599)
+}
+
+/// Parse a `Retry-After` header value: delay in seconds or an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_milliseconds();
+    Some(Duration::from_millis(secs.max(0) as u64))
+}
+
+/// Sleep for `delay`, waking early if the cancel flag is set.
+/// Returns false if cancelled.
+pub async fn sleep_unless_cancelled(delay: Duration, cancel_flag: &AtomicBool) -> bool {
+    let mut remaining = delay;
+    while !remaining.is_zero() {
+        if cancel_flag.load(Ordering::Relaxed) {
+            return false;
+        }
+        let step = remaining.min(CANCEL_POLL);
+        tokio::time::sleep(step).await;
+        remaining -= step;
+    }
+    !cancel_flag.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy { max_retries, base_delay: Duration::from_millis(500) }
+    }
+
+    #[test]
+    fn test_delay_is_exponential() {
+        let p = policy(3);
+        assert_eq!(p.delay(0, None), Some(Duration::from_millis(500)));
+        assert_eq!(p.delay(1, None), Some(Duration::from_millis(1000)));
+        assert_eq!(p.delay(2, None), Some(Duration::from_millis(2000)));
+        assert_eq!(p.delay(3, None), None);
+    }
+
+    #[test]
+    fn test_delay_honours_retry_after_and_cap() {
+        let p = policy(2);
+        assert_eq!(p.delay(0, Some(Duration::from_secs(7))), Some(Duration::from_secs(7)));
+        assert_eq!(p.delay(0, Some(Duration::from_secs(600))), Some(MAX_DELAY));
+        assert_eq!(policy(0).delay(0, Some(Duration::from_secs(1))), None);
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable_status(429));
+        assert!(is_retryable_status(502));
+        assert!(is_retryable_status(529));
+        assert!(!is_retryable_status(400));
+        assert!(!is_retryable_status(401));
+        assert!(!is_retryable_status(404));
+    }
+
+    #[test]
+    fn test_fallback_statuses() {
+        assert!(allows_fallback(404));
+        assert!(allows_fallback(429));
+        assert!(allows_fallback(503));
+        assert!(!allows_fallback(400));
+        assert!(!allows_fallback(401));
+        assert!(!allows_fallback(403));
+        assert!(!allows_fallback(422));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after(" 1.5 "), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_unless_cancelled() {
+        let flag = AtomicBool::new(false);
+        assert!(sleep_unless_cancelled(Duration::from_millis(10), &flag).await);
+        flag.store(true, Ordering::Relaxed);
+        assert!(!sleep_unless_cancelled(Duration::from_secs(10), &flag).await);
+    }
+}
@@ -0,0 +1,739 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Manager};
+
+use super::compact::StoredSummary;
+use super::config::AiConfig;
+use super::streaming::ChatMessage;
+use super::todo::TodoItem;
+use super::{JobManagerState, SessionStoreState};
+
+/// Max chars of the first user message used as a default title
+const TITLE_MAX_CHARS: usize = 50;
+
+/// Summary of a stored chat session, as shown in the session list
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionMeta {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    #[serde(default)]
+    pub skill_id: String,
+    /// Unix timestamps (seconds)
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(default)]
+    pub message_count: usize,
+    /// Session this one was forked from
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Number of parent messages copied into the fork
+    #[serde(default)]
+    pub forked_at: Option<usize>,
+}
+
+/// A stored chat session: the full user/assistant/tool history, without the
+/// system prompt (rebuilt on every request)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(flatten)]
+    pub meta: SessionMeta,
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
+    /// The agent's task list, kept by todo_write
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub todos: Vec<TodoItem>,
+    /// Summary of the oldest messages, once the history was compacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compaction: Option<StoredSummary>,
+}
+
+impl Session {
+    pub fn new(id: &str, workspace: Option<&str>, skill_id: &str) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            meta: SessionMeta {
+                id: id.to_string(),
+                title: String::new(),
+                workspace: workspace.filter(|w| !w.is_empty()).map(|w| w.to_string()),
+                skill_id: skill_id.to_string(),
+                created_at: now,
+                updated_at: now,
+                message_count: 0,
+                parent_id: None,
+                forked_at: None,
+            },
+            messages: Vec::new(),
+            todos: Vec::new(),
+            compaction: None,
+        }
+    }
+
+    /// Bring the stored history up to date with the messages sent by the
+    /// frontend. A new session takes the whole incoming history; an existing
+    /// one only appends the new user message, since the frontend only sends a
+    /// window of recent messages and no tool results.
+    pub fn sync_incoming(&mut self, incoming: &[ChatMessage]) {
+        if self.messages.is_empty() {
+            self.messages = incoming.iter().filter(|m| m.role != "system").cloned().collect();
+        } else if let Some(last) = incoming.last().filter(|m| m.role == "user") {
+            self.messages.push(last.clone());
+        }
+        if self.meta.title.is_empty() {
+            self.meta.title = default_title(&self.messages);
+        }
+    }
+}
+
+/// Whether the incoming messages start a new conversation under an id that
+/// already has history (the chat panel was cleared but kept its session id).
+/// A lone message that repeats the stored first question is a resend of the
+/// same thread, not a reset.
+pub fn is_fresh_start(stored: &Session, incoming: &[ChatMessage]) -> bool {
+    let mut incoming = incoming.iter().filter(|m| m.role != "system");
+    let (Some(first), None) = (incoming.next(), incoming.next()) else { return false };
+    match stored.messages.iter().find(|m| m.role == "user") {
+        Some(stored_first) => stored_first.content != first.content,
+        None => !stored.messages.is_empty(),
+    }
+}
+
+fn default_title(messages: &[ChatMessage]) -> String {
+    let first = messages.iter()
+        .find(|m| m.role == "user")
+        .and_then(|m| m.content.as_deref())
+        .and_then(|c| c.lines().map(str::trim).find(|l| !l.is_empty()))
+        .unwrap_or("");
+    if first.chars().count() > TITLE_MAX_CHARS {
+        let head: String = first.chars().take(TITLE_MAX_CHARS).collect();
+        format!("{}…", head.trim_end())
+    } else {
+        first.to_string()
+    }
+}
+
+/// Adjust a fork point so the prefix never ends inside a tool-call group: an
+/// assistant message with tool calls must be followed by all of its results.
+fn fork_point(messages: &[ChatMessage], keep: usize) -> usize {
+    let mut end = keep.min(messages.len());
+    while end > 0 && end < messages.len() && messages[end].role == "tool" {
+        end -= 1;
+    }
+    end
+}
+
+/// Session ids come from the frontend; keep file names safe. Ids that had to
+/// be rewritten or shortened get a hash of the raw id so they can't collide.
+fn safe_file_stem(id: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !id.is_empty() && id.len() <= 128 && id.chars().all(is_safe) {
+        return id.to_string();
+    }
+    let readable: String = id.chars()
+        .map(|c| if is_safe(c) { c } else { '_' })
+        .take(96)
+        .collect();
+    let digest = Sha256::digest(id.as_bytes());
+    format!("{}-{}", readable, &hex::encode(digest)[..12])
+}
+
+/// On-disk index format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SessionIndex {
+    version: u32,
+    sessions: HashMap<String, SessionMeta>,
+}
+
+impl SessionIndex {
+    fn new() -> Self {
+        Self { version: 1, sessions: HashMap::new() }
+    }
+}
+
+/// File-based session store: one JSON file per session plus an index of
+/// the metadata, rebuilt from the session files if missing or corrupt.
+pub struct SessionStore {
+    base_dir: PathBuf,
+    index: Mutex<SessionIndex>,
+}
+
+impl SessionStore {
+    pub fn new(base_dir: PathBuf) -> Result<Self, String> {
+        fs::create_dir_all(&base_dir)
+            .map_err(|e| format!("Failed to create session directory: {}", e))?;
+        let index = Self::load_or_rebuild_index(&base_dir)?;
+        Ok(Self {
+            base_dir,
+            index: Mutex::new(index),
+        })
+    }
+
+    fn index_path(base_dir: &Path) -> PathBuf {
+        base_dir.join("index.json")
+    }
+
+    fn session_path(&self, id: &str) -> PathBuf {
+        self.base_dir.join(format!("{}.json", safe_file_stem(id)))
+    }
+
+    /// Where the messages compacted out of a session are kept
+    fn archive_dir(&self, id: &str) -> PathBuf {
+        self.base_dir.join("archives").join(safe_file_stem(id))
+    }
+
+    /// Keep messages compacted out of a session next to it. They are removed
+    /// with the session, never earlier. Returns the file written.
+    pub fn archive(&self, id: &str, messages: &[ChatMessage]) -> Result<PathBuf, String> {
+        let dir = self.archive_dir(id);
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create archive directory: {}", e))?;
+        let name = format!(
+            "{}-{}.json",
+            chrono::Utc::now().format("%Y%m%d_%H%M%S"),
+            &uuid::Uuid::new_v4().to_string()[..8]
+        );
+        let path = dir.join(name);
+        Self::write_atomic(&path, &messages)?;
+        Ok(path)
+    }
+
+    fn load_or_rebuild_index(base_dir: &Path) -> Result<SessionIndex, String> {
+        if let Ok(content) = fs::read_to_string(Self::index_path(base_dir)) {
+            if let Ok(index) = serde_json::from_str::<SessionIndex>(&content) {
+                return Ok(index);
+            }
+            // Corrupt index, fall through to rebuild
+        }
+        Self::rebuild_index(base_dir)
+    }
+
+    fn rebuild_index(base_dir: &Path) -> Result<SessionIndex, String> {
+        let mut index = SessionIndex::new();
+        let entries = fs::read_dir(base_dir)
+            .map_err(|e| format!("Failed to read session directory: {}", e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") || path == Self::index_path(base_dir) {
+                continue;
+            }
+            if let Ok(content) = fs::read_to_string(&path) {
+                if let Ok(session) = serde_json::from_str::<Session>(&content) {
+                    index.sessions.insert(session.meta.id.clone(), session.meta);
+                }
+            }
+        }
+        Self::write_atomic(&Self::index_path(base_dir), &index)?;
+        Ok(index)
+    }
+
+    fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+        let tmp_path = path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(value)
+            .map_err(|e| format!("Failed to serialize session data: {}", e))?;
+        fs::write(&tmp_path, &content)
+            .map_err(|e| format!("Failed to write temp session file: {}", e))?;
+        fs::rename(&tmp_path, path)
+            .map_err(|e| format!("Failed to rename session file: {}", e))
+    }
+
+    fn flush_index(&self, index: &SessionIndex) -> Result<(), String> {
+        Self::write_atomic(&Self::index_path(&self.base_dir), index)
+    }
+
+    /// Stored session by id, if any
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let content = fs::read_to_string(self.session_path(id)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn load(&self, id: &str) -> Result<Session, String> {
+        self.get(id).ok_or_else(|| format!("Session not found: {}", id))
+    }
+
+    /// Write the session and refresh its index entry
+    pub fn save(&self, session: &mut Session) -> Result<(), String> {
+        session.meta.updated_at = chrono::Utc::now().timestamp();
+        session.meta.message_count = session.messages.len();
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        Self::write_atomic(&self.session_path(&session.meta.id), session)?;
+        index.sessions.insert(session.meta.id.clone(), session.meta.clone());
+        self.flush_index(&index)
+    }
+
+    /// Sessions ordered by last activity, newest first
+    pub fn list(&self, workspace: Option<&str>) -> Vec<SessionMeta> {
+        let index = match self.index.lock() {
+            Ok(index) => index,
+            Err(_) => return Vec::new(),
+        };
+        let mut sessions: Vec<SessionMeta> = index.sessions.values()
+            .filter(|m| workspace.is_none() || m.workspace.as_deref() == workspace)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|m| std::cmp::Reverse(m.updated_at));
+        sessions
+    }
+
+    pub fn rename(&self, id: &str, title: &str) -> Result<SessionMeta, String> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err("Session title cannot be empty".to_string());
+        }
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let mut session = self.load(id)?;
+        session.meta.title = title.to_string();
+        // Renaming is not activity: updated_at is left alone so the list order is stable
+        Self::write_atomic(&self.session_path(id), &session)?;
+        index.sessions.insert(id.to_string(), session.meta.clone());
+        self.flush_index(&index)?;
+        Ok(session.meta)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<(), String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let path = self.session_path(id);
+        if index.sessions.remove(id).is_none() && !path.exists() {
+            return Err(format!("Session not found: {}", id));
+        }
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| format!("Failed to delete session: {}", e))?;
+        }
+        let _ = fs::remove_dir_all(self.archive_dir(id));
+        self.flush_index(&index)
+    }
+
+    /// Move a session to a new id, freeing the old one for a new conversation
+    pub fn rekey(&self, id: &str, new_id: &str) -> Result<(), String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let mut session = self.load(id)?;
+        session.meta.id = new_id.to_string();
+        // The archives follow the thread they belong to
+        let (old_archives, new_archives) = (self.archive_dir(id), self.archive_dir(new_id));
+        if old_archives.exists() && fs::rename(&old_archives, &new_archives).is_ok() {
+            if let Some(compaction) = session.compaction.as_mut() {
+                if let Ok(rest) = Path::new(&compaction.archive).strip_prefix(&old_archives) {
+                    compaction.archive = new_archives.join(rest).display().to_string();
+                }
+            }
+        }
+        Self::write_atomic(&self.session_path(new_id), &session)?;
+        let _ = fs::remove_file(self.session_path(id));
+        index.sessions.remove(id);
+        index.sessions.insert(new_id.to_string(), session.meta);
+        self.flush_index(&index)
+    }
+
+    /// Create a new session from the first `keep` messages of `id`. The fork
+    /// inherits workspace and skill and records its parent.
+    pub fn fork(&self, id: &str, keep: usize) -> Result<SessionMeta, String> {
+        let parent = self.load(id)?;
+        if keep == 0 || keep > parent.messages.len() {
+            return Err(format!(
+                "Invalid fork point {}: session {} has {} messages",
+                keep, id, parent.messages.len()
+            ));
+        }
+        let end = fork_point(&parent.messages, keep);
+        if end == 0 {
+            return Err("Fork point leaves no messages".to_string());
+        }
+        let mut fork = Session::new(
+            &uuid::Uuid::new_v4().to_string(),
+            parent.meta.workspace.as_deref(),
+            &parent.meta.skill_id,
+        );
+        fork.meta.parent_id = Some(parent.meta.id);
+        fork.meta.forked_at = Some(end);
+        fork.messages = parent.messages[..end].to_vec();
+        let title = match parent.meta.title.as_str() {
+            "" => default_title(&fork.messages),
+            title => title.to_string(),
+        };
+        if !title.is_empty() {
+            fork.meta.title = format!("{} (fork)", title);
+        }
+        fork.compaction = parent.compaction.filter(|c| c.messages <= end);
+        // The fork gets its own copy of the archive, which goes away with the parent
+        if let Some(compaction) = fork.compaction.as_mut() {
+            let copied = fs::read_to_string(&compaction.archive).ok()
+                .and_then(|data| serde_json::from_str::<Vec<ChatMessage>>(&data).ok())
+                .and_then(|messages| self.archive(&fork.meta.id, &messages).ok());
+            if let Some(path) = copied {
+                compaction.archive = path.display().to_string();
+            }
+        }
+        self.save(&mut fork)?;
+        Ok(fork.meta)
+    }
+
+    /// Delete sessions inactive for more than `retention_days` (0 keeps all).
+    /// Returns the number of deleted sessions.
+    pub fn prune(&self, retention_days: u32) -> usize {
+        if retention_days == 0 {
+            return 0;
+        }
+        let cutoff = chrono::Utc::now().timestamp() - retention_days as i64 * 24 * 3600;
+        let mut index = match self.index.lock() {
+            Ok(index) => index,
+            Err(_) => return 0,
+        };
+        let expired: Vec<String> = index.sessions.values()
+            .filter(|m| m.updated_at < cutoff)
+            .map(|m| m.id.clone())
+            .collect();
+        for id in &expired {
+            let _ = fs::remove_file(self.session_path(id));
+            let _ = fs::remove_dir_all(self.archive_dir(id));
+            index.sessions.remove(id);
+        }
+        if !expired.is_empty() {
+            let _ = self.flush_index(&index);
+        }
+        expired.len()
+    }
+}
+
+// --- Tauri commands ---
+
+/// Stored sessions, newest first, optionally limited to one workspace
+#[tauri::command]
+pub fn ai_session_list(
+    state: tauri::State<'_, SessionStoreState>,
+    workspace: Option<String>,
+) -> Vec<SessionMeta> {
+    state.store.list(workspace.as_deref())
+}
+
+#[tauri::command]
+pub fn ai_session_load(
+    state: tauri::State<'_, SessionStoreState>,
+    id: String,
+) -> Result<Session, String> {
+    state.store.load(&id)
+}
+
+#[tauri::command]
+pub fn ai_session_rename(
+    state: tauri::State<'_, SessionStoreState>,
+    id: String,
+    title: String,
+) -> Result<SessionMeta, String> {
+    state.store.rename(&id, &title)
+}
+
+/// Fork a session, keeping its first `message_count` messages
+#[tauri::command]
+pub fn ai_session_fork(
+    state: tauri::State<'_, SessionStoreState>,
+    id: String,
+    message_count: usize,
+) -> Result<SessionMeta, String> {
+    state.store.fork(&id, message_count)
+}
+
+#[tauri::command]
+pub fn ai_session_delete(
+    state: tauri::State<'_, SessionStoreState>,
+    jobs: tauri::State<'_, JobManagerState>,
+    id: String,
+) -> Result<(), String> {
+    jobs.manager.stop_session(&id);
+    state.store.delete(&id)
+}
+
+/// Continue a stored session with new messages. The stored history (including
+/// tool calls and results) replaces whatever history the caller sends; system
+/// messages from the caller are kept in front.
+#[tauri::command]
+pub async fn ai_session_continue(
+    app: AppHandle,
+    session_id: String,
+    messages: Vec<ChatMessage>,
+    config: Option<AiConfig>,
+    profile_id: Option<String>,
+    deep_mode: Option<bool>,
+) -> Result<(), String> {
+    let session = app.state::<SessionStoreState>().store.load(&session_id)?;
+    let (system, new_messages): (Vec<ChatMessage>, Vec<ChatMessage>) =
+        messages.into_iter().partition(|m| m.role == "system");
+    if new_messages.last().map(|m| m.role != "user").unwrap_or(true) {
+        return Err("ai_session_continue expects a new user message".to_string());
+    }
+    let mut conversation = system;
+    conversation.extend(session.messages);
+    conversation.extend(new_messages);
+    super::ai_chat(
+        app,
+        session_id,
+        conversation,
+        config,
+        profile_id,
+        deep_mode,
+        session.meta.workspace,
+        Some(session.meta.skill_id),
+    ).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::streaming::{FunctionCall, ToolCall};
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: None,
+            tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
+        }
+    }
+
+    fn saved(store: &SessionStore, id: &str, workspace: Option<&str>, question: &str) -> Session {
+        let mut session = Session::new(id, workspace, "default");
+        session.sync_incoming(&[msg("system", "sys"), msg("user", question)]);
+        store.save(&mut session).unwrap();
+        session
+    }
+
+    #[test]
+    fn test_unsafe_ids_get_distinct_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        assert_eq!(safe_file_stem("chat-1_a"), "chat-1_a");
+        assert_ne!(safe_file_stem("a/b"), safe_file_stem("a:b"));
+        assert_ne!(safe_file_stem("a/b"), safe_file_stem("a_b"));
+        let long = "x".repeat(200);
+        assert_ne!(safe_file_stem(&long), safe_file_stem(&"x".repeat(201)));
+        assert!(safe_file_stem(&long).len() < 128);
+
+        saved(&store, "a/b", None, "slash");
+        saved(&store, "a:b", None, "colon");
+        assert_eq!(store.load("a/b").unwrap().meta.title, "slash");
+        assert_eq!(store.load("a:b").unwrap().meta.title, "colon");
+    }
+
+    #[test]
+    fn test_sync_incoming_new_and_existing() {
+        let mut session = Session::new("s1", Some("/ws"), "default");
+        session.sync_incoming(&[msg("system", "sys"), msg("user", "first question\nmore detail")]);
+        assert_eq!(session.messages.len(), 1);
+        assert_eq!(session.meta.title, "first question");
+
+        session.messages.push(msg("assistant", "answer"));
+        // The frontend resends its window of history plus the new message
+        session.sync_incoming(&[
+            msg("system", "sys"),
+            msg("user", "first question\nmore detail"),
+            msg("assistant", "answer"),
+            msg("user", "follow up"),
+        ]);
+        assert_eq!(session.messages.len(), 3);
+        assert_eq!(session.messages[2].content.as_deref(), Some("follow up"));
+        assert_eq!(session.meta.title, "first question");
+    }
+
+    #[test]
+    fn test_default_title_truncates() {
+        let title = default_title(&[msg("user", &"x".repeat(80))]);
+        assert_eq!(title.chars().count(), TITLE_MAX_CHARS + 1);
+        assert!(title.ends_with('…'));
+    }
+
+    #[test]
+    fn test_is_fresh_start() {
+        let mut session = Session::new("s1", None, "default");
+        let incoming = [msg("system", "sys"), msg("user", "hi")];
+        assert!(!is_fresh_start(&session, &incoming));
+        session.messages.push(msg("user", "old"));
+        session.messages.push(msg("assistant", "answer"));
+        assert!(is_fresh_start(&session, &incoming));
+        assert!(!is_fresh_start(&session, &[msg("user", "old"), msg("assistant", "a"), msg("user", "hi")]));
+        // Resending or retrying the first question keeps the thread
+        assert!(!is_fresh_start(&session, &[msg("system", "sys"), msg("user", "old")]));
+        assert!(!is_fresh_start(&session, &[msg("system", "sys")]));
+    }
+
+    #[test]
+    fn test_save_load_and_list_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        saved(&store, "a", Some("/ws1"), "alpha");
+        saved(&store, "b", Some("/ws2"), "beta");
+        store.index.lock().unwrap().sessions.get_mut("b").unwrap().updated_at -= 60;
+        let list = store.list(None);
+        assert_eq!(list.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(store.list(Some("/ws2")).len(), 1);
+        assert_eq!(store.list(Some("/ws2"))[0].title, "beta");
+
+        let loaded = store.load("a").unwrap();
+        assert_eq!(loaded.messages.len(), 1);
+        assert_eq!(loaded.meta.message_count, 1);
+        assert!(store.load("missing").is_err());
+    }
+
+    #[test]
+    fn test_rename_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        saved(&store, "a", None, "alpha");
+        assert_eq!(store.rename("a", "  Research thread ").unwrap().title, "Research thread");
+        assert_eq!(store.list(None)[0].title, "Research thread");
+        assert!(store.rename("a", " ").is_err());
+
+        store.delete("a").unwrap();
+        assert!(store.get("a").is_none());
+        assert!(store.list(None).is_empty());
+        assert!(store.delete("a").is_err());
+    }
+
+    #[test]
+    fn test_rekey_frees_old_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        saved(&store, "a", None, "alpha");
+        store.rekey("a", "a-old").unwrap();
+        assert!(store.get("a").is_none());
+        assert_eq!(store.load("a-old").unwrap().meta.id, "a-old");
+        assert_eq!(store.list(None).len(), 1);
+    }
+
+    fn tool_round(id: &str) -> Vec<ChatMessage> {
+        let mut call = msg("assistant", "");
+        call.content = None;
+        call.tool_calls = Some(vec![ToolCall {
+            id: id.into(),
+            r#type: "function".into(),
+            function: FunctionCall { name: "read_file".into(), arguments: "{}".into() },
+        }]);
+        let mut result = msg("tool", "contents");
+        result.tool_call_id = Some(id.into());
+        vec![call, result]
+    }
+
+    #[test]
+    fn test_fork_copies_prefix_and_tracks_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        let mut parent = Session::new("p", Some("/ws"), "coding");
+        parent.meta.title = "Refactor".into();
+        parent.messages = vec![msg("user", "q1"), msg("assistant", "a1"), msg("user", "q2"), msg("assistant", "a2")];
+        store.save(&mut parent).unwrap();
+
+        let fork = store.fork("p", 2).unwrap();
+        assert_ne!(fork.id, "p");
+        assert_eq!(fork.parent_id.as_deref(), Some("p"));
+        assert_eq!(fork.forked_at, Some(2));
+        assert_eq!(fork.workspace.as_deref(), Some("/ws"));
+        assert_eq!(fork.skill_id, "coding");
+        assert_eq!(fork.title, "Refactor (fork)");
+        let loaded = store.load(&fork.id).unwrap();
+        assert_eq!(loaded.messages.len(), 2);
+        assert_eq!(loaded.messages[1].content.as_deref(), Some("a1"));
+        // The parent is untouched
+        assert_eq!(store.load("p").unwrap().messages.len(), 4);
+        assert_eq!(store.list(None).len(), 2);
+    }
+
+    #[test]
+    fn test_fork_never_splits_tool_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        let mut parent = Session::new("p", None, "default");
+        parent.messages.push(msg("user", "q1"));
+        parent.messages.extend(tool_round("tc1"));
+        parent.messages.push(msg("assistant", "a1"));
+        store.save(&mut parent).unwrap();
+
+        // Cutting between the tool call and its result drops the call
+        let fork = store.fork("p", 2).unwrap();
+        assert_eq!(fork.forked_at, Some(1));
+        // An untitled parent gives the fork its first question as title
+        assert_eq!(fork.title, "q1 (fork)");
+        assert_eq!(store.fork("p", 3).unwrap().forked_at, Some(3));
+        assert!(store.fork("p", 0).is_err());
+        assert!(store.fork("p", 5).is_err());
+        assert!(store.fork("missing", 1).is_err());
+    }
+
+    #[test]
+    fn test_archives_live_as_long_as_their_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        let mut session = saved(&store, "s", None, "q1");
+        session.messages.push(msg("assistant", "a1"));
+        let archive = store.archive("s", &session.messages).unwrap();
+        let conversation: Vec<ChatMessage> = session.messages.clone();
+        session.compaction = Some(StoredSummary::new(&conversation, 0..2, None, "- q1", &archive.display().to_string()));
+        store.save(&mut session).unwrap();
+
+        // A fork keeps its own copy
+        let fork = store.fork("s", 2).unwrap();
+        let fork_archive = store.load(&fork.id).unwrap().compaction.unwrap().archive;
+        assert_ne!(fork_archive, archive.display().to_string());
+
+        // Rekeying moves the archive along
+        store.rekey("s", "old").unwrap();
+        let moved = store.load("old").unwrap().compaction.unwrap().archive;
+        assert!(!archive.exists());
+        assert!(Path::new(&moved).exists());
+
+        store.delete("old").unwrap();
+        assert!(!Path::new(&moved).exists());
+        assert!(Path::new(&fork_archive).exists());
+        store.index.lock().unwrap().sessions.get_mut(&fork.id).unwrap().updated_at -= 40 * 24 * 3600;
+        assert_eq!(store.prune(30), 1);
+        assert!(!Path::new(&fork_archive).exists());
+    }
+
+    #[test]
+    fn test_prune_by_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        saved(&store, "fresh", None, "new");
+        saved(&store, "stale", None, "old");
+        {
+            let mut index = store.index.lock().unwrap();
+            index.sessions.get_mut("stale").unwrap().updated_at -= 40 * 24 * 3600;
+        }
+        assert_eq!(store.prune(0), 0);
+        assert_eq!(store.prune(30), 1);
+        assert!(store.get("stale").is_none());
+        assert!(store.get("fresh").is_some());
+    }
+
+    #[test]
+    fn test_index_rebuild_on_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+            saved(&store, "a", Some("/ws"), "alpha");
+        }
+        fs::write(dir.path().join("index.json"), "{broken").unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        let list = store.list(None);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].workspace.as_deref(), Some("/ws"));
+    }
+
+    #[test]
+    fn test_unsafe_ids_stay_in_store_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf()).unwrap();
+        saved(&store, "../escape", None, "x");
+        let files: Vec<String> = fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("___escape-") && name.ends_with(".json"))
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert!(store.get("../escape").is_some());
+    }
+}
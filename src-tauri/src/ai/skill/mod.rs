@@ -1,6 +1,7 @@
 pub mod registry;
 
 use serde::Serialize;
+use crate::ai::config::SkillModelConfig;
 use crate::ai::tool::registry::ToolFilter;
 
 /// State passed to Skill methods, persisted per conversation
@@ -36,8 +37,24 @@ pub trait Skill: Send + Sync + 'static {
     /// Maximum tool call iterations
     fn max_iterations(&self, state: &SkillState) -> usize;
 
-    /// Output token budget hint
-    fn token_budget(&self, _state: &SkillState) -> u32 { 4096 }
+    /// Preferred model id; None uses the configured model
+    fn model(&self, _state: &SkillState) -> Option<String> { None }
+
+    /// Sampling temperature; None uses the configured temperature
+    fn temperature(&self, _state: &SkillState) -> Option<f64> { None }
+
+    /// Output token budget; None uses the configured max_tokens
+    fn token_budget(&self, _state: &SkillState) -> Option<u32> { None }
+
+    /// Model settings this skill declares. Per-skill entries in the AI config
+    /// take precedence (see `AiConfig::for_skill`).
+    fn model_config(&self, state: &SkillState) -> SkillModelConfig {
+        SkillModelConfig {
+            model: self.model(state),
+            temperature: self.temperature(state),
+            max_tokens: self.token_budget(state),
+        }
+    }
 }
 
 /// Skill info for frontend display
@@ -33,7 +33,7 @@ impl Skill for DeepResearchSkill {
     }
 
     fn max_iterations(&self, _state: &SkillState) -> usize { 30 }
-    fn token_budget(&self, _state: &SkillState) -> u32 { 8192 }
+    fn token_budget(&self, _state: &SkillState) -> Option<u32> { Some(8192) }
 }
 
 #[cfg(test)]
@@ -125,6 +125,6 @@ mod tests {
     fn token_budget_returns_8192() {
         let skill = DeepResearchSkill;
         let state = SkillState::default();
-        assert_eq!(skill.token_budget(&state), 8192);
+        assert_eq!(skill.token_budget(&state), Some(8192));
     }
 }
@@ -21,7 +21,6 @@ impl Skill for DefaultSkill {
     }
 
     fn max_iterations(&self, _state: &SkillState) -> usize { 20 }
-    fn token_budget(&self, _state: &SkillState) -> u32 { 4096 }
 }
 
 #[cfg(test)]
@@ -74,9 +73,10 @@ mod tests {
     }
 
     #[test]
-    fn token_budget_returns_4096() {
+    fn model_settings_follow_config() {
         let skill = DefaultSkill;
         let state = SkillState::default();
-        assert_eq!(skill.token_budget(&state), 4096);
+        assert_eq!(skill.token_budget(&state), None);
+        assert_eq!(skill.model_config(&state), Default::default());
     }
 }
@@ -11,6 +11,38 @@ pub struct ChatMessage {
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    /// Reasoning / thinking text of an assistant turn. Stripped from requests to
+    /// OpenAI-compatible APIs, which reject it in history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    /// Anthropic thinking blocks of the turn, in order, sent back unchanged
+    /// as the API requires during tool use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking_blocks: Option<Vec<ThinkingBlock>>,
+    /// Images attached to a user turn. Providers turn these into content parts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<ImagePart>>,
+}
+
+/// A thinking block as the Messages API sends it: signed text, or an
+/// encrypted block the model's safety systems redacted
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingBlock {
+    Thinking { thinking: String, signature: String },
+    RedactedThinking { data: String },
+}
+
+/// An image content part: inline base64 data, or a workspace file reference
+/// that is read into `data` before the request is sent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImagePart {
+    #[serde(default)]
+    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -38,6 +70,10 @@ pub struct AiStreamEvent {
 #[derive(Deserialize, Debug)]
 pub(super) struct SseDelta {
     pub content: Option<String>,
+    /// DeepSeek / Qwen reasoning models
+    pub reasoning_content: Option<String>,
+    /// OpenRouter and other OpenAI-compatible reasoning models
+    pub reasoning: Option<String>,
     pub tool_calls: Option<Vec<SseDeltaToolCall>>,
 }
 
@@ -65,6 +101,28 @@ pub(super) struct SseChoice {
 #[derive(Deserialize, Debug)]
 pub(super) struct SseChunk {
     pub choices: Option<Vec<SseChoice>>,
+    /// Sent in a final chunk when `stream_options.include_usage` is set
+    pub usage: Option<SseUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct SseUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    /// OpenAI: `cached_tokens` inside
+    #[serde(default)]
+    pub prompt_tokens_details: Option<SsePromptDetails>,
+    /// DeepSeek reports cache hits at the top level
+    #[serde(default)]
+    pub prompt_cache_hit_tokens: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct SsePromptDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
 }
 
 #[cfg(test)]
@@ -81,6 +139,9 @@ mod tests {
             content: Some("Hello".to_string()),
             tool_calls: None,
             tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
         };
         let json = serde_json::to_value(&msg).unwrap();
         assert_eq!(json["role"], "user");
@@ -104,6 +165,9 @@ mod tests {
                 },
             }]),
             tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
         };
         let json = serde_json::to_value(&msg).unwrap();
         assert!(json.get("content").is_none());
@@ -151,6 +215,9 @@ mod tests {
                 },
             ]),
             tool_call_id: None,
+            reasoning: None,
+            thinking_blocks: None,
+            images: None,
         };
         let serialized = serde_json::to_string(&original).unwrap();
         let deserialized: ChatMessage = serde_json::from_str(&serialized).unwrap();
@@ -0,0 +1,189 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::app_warn;
+use super::agent::AgentSink;
+use super::confirm::ConfirmRequest;
+use super::streaming::{AiStreamEvent, ChatMessage};
+use super::tool::registry::ToolFilter;
+use super::tool::ToolContext;
+use super::todo::TodoList;
+use super::{context, execute_tool_calls, stream_round, usage, ModelState, RoundOutput};
+
+/// Name of the tool that starts sub-agents; never offered to a sub-agent,
+/// so delegation is one level deep
+pub const DELEGATE_TOOL: &str = "delegate_task";
+
+/// Rounds a sub-agent gets when the caller doesn't say
+pub const DEFAULT_SUBAGENT_ROUNDS: usize = 10;
+
+/// Most rounds a sub-agent may be given
+pub const MAX_SUBAGENT_ROUNDS: usize = 20;
+
+const SUBAGENT_PROMPT: &str = "You are a sub-agent working on one task delegated by another agent. \
+Use the available tools to complete the task, then reply with a concise summary of what you found or did, \
+including file paths and concrete details the other agent needs. That summary is the only thing the other agent will see.";
+
+/// A delegated task with the tools it may use
+pub struct SubagentTask {
+    pub task: String,
+    pub tools: Vec<String>,
+    pub max_rounds: usize,
+}
+
+/// Forwards a sub-agent's events to the parent sink, wrapped as `subagent`
+/// events under the parent session so the UI can nest them below the call
+/// that started the sub-agent.
+pub struct NestedSink {
+    parent: Arc<dyn AgentSink>,
+    call_id: String,
+}
+
+impl NestedSink {
+    pub fn new(parent: Arc<dyn AgentSink>, call_id: &str) -> Self {
+        Self { parent, call_id: call_id.to_string() }
+    }
+}
+
+#[async_trait]
+impl AgentSink for NestedSink {
+    fn emit(&self, event: AiStreamEvent) {
+        self.parent.emit(AiStreamEvent {
+            session_id: event.session_id,
+            event_type: "subagent".into(),
+            content: serde_json::json!({
+                "call_id": self.call_id,
+                "depth": 1,
+                "event_type": event.event_type,
+                "content": event.content,
+            }).to_string(),
+        });
+    }
+
+    async fn confirm_command(&self, request: &ConfirmRequest) -> Result<bool, String> {
+        self.parent.confirm_command(request).await
+    }
+
+    fn open_file(&self, path: &str) {
+        self.parent.open_file(path);
+    }
+}
+
+/// Run a nested agent loop on `task` with a fresh conversation and return its
+/// final answer. The sub-agent shares the parent's model, workspace and
+/// memory, but has its own task list and only the listed tools.
+pub async fn run_subagent(ctx: &ToolContext, task: &SubagentTask) -> Result<String, String> {
+    let sink: Arc<dyn AgentSink> = Arc::new(NestedSink::new(ctx.sink.clone(), &ctx.call_id));
+    let tool_names: Vec<String> = task.tools.iter()
+        .filter(|name| name.as_str() != DELEGATE_TOOL)
+        .cloned()
+        .collect();
+    // A registry of only the allowed tools, so other tools can't be called by name either
+    let filter = ToolFilter::Only(tool_names);
+    let tools = Arc::new(ctx.tools.scoped(&filter).await);
+    let tool_schemas = tools.get_all_schemas().await;
+    let sub_ctx = ToolContext {
+        sink: sink.clone(),
+        todos: TodoList::default(),
+        tools: tools.clone(),
+        tool_filter: Arc::new(filter),
+        ..ctx.clone()
+    };
+    let mut model = ModelState::new(ctx.ai_config.clone()).await;
+
+    let mut system_prompt = SUBAGENT_PROMPT.to_string();
+    if !ctx.workspace_path.is_empty() {
+        system_prompt.push_str(&format!("\n\nWorkspace: {}", ctx.workspace_path));
+    }
+    let mut conversation = vec![text_message("system", system_prompt), text_message("user", task.task.clone())];
+    let mut python_fail_count: u32 = 0;
+
+    for round in 0..task.max_rounds.max(1) {
+        if ctx.cancel_flag.load(Ordering::Relaxed) {
+            return Err("cancelled by the user".into());
+        }
+        let output = next_round(ctx, &mut model, sink.as_ref(), &mut conversation, &tool_schemas, round).await?;
+        if !output.wants_tools() {
+            return Ok(final_answer(output.content));
+        }
+
+        conversation.push(ChatMessage {
+            role: "assistant".into(),
+            content: if output.content.is_empty() { None } else { Some(output.content) },
+            tool_calls: Some(output.tool_calls.clone()),
+            tool_call_id: None,
+            reasoning: if output.reasoning.is_empty() { None } else { Some(output.reasoning) },
+            thinking_blocks: output.thinking_blocks,
+            images: None,
+        });
+        let tool_round = execute_tool_calls(&tools, &sub_ctx, &output.tool_calls, &mut python_fail_count, &ctx.cancel_flag)
+            .await
+            .ok_or("cancelled by the user")?;
+        conversation.extend(tool_round.messages);
+        if !tool_round.images.is_empty() {
+            let names: Vec<&str> = tool_round.images.iter().filter_map(|img| img.path.as_deref()).collect();
+            conversation.push(ChatMessage {
+                images: Some(tool_round.images.clone()),
+                ..text_message("user", format!("[Attached images: {}]", names.join(", ")))
+            });
+        }
+    }
+
+    // Out of rounds: ask for a summary of the work so far, without tools
+    conversation.push(text_message("user", format!(
+        "You have used all {} tool rounds. Stop here and summarize what you found so far, including what is still open.",
+        task.max_rounds
+    )));
+    let output = next_round(ctx, &mut model, sink.as_ref(), &mut conversation, &[], task.max_rounds).await?;
+    Ok(final_answer(output.content))
+}
+
+/// Trim the conversation to the context window, stream one round and record its usage
+async fn next_round(
+    ctx: &ToolContext,
+    model: &mut ModelState,
+    sink: &dyn AgentSink,
+    conversation: &mut Vec<ChatMessage>,
+    tool_schemas: &[serde_json::Value],
+    round: usize,
+) -> Result<RoundOutput, String> {
+    let budget = context::ContextBudget::for_request(&model.config, tool_schemas);
+    context::fit_to_budget(conversation, &budget);
+    let output = stream_round(model, sink, &ctx.session_id, conversation, tool_schemas, false, &ctx.cancel_flag)
+        .await?
+        .ok_or("cancelled by the user")?;
+    if let Some(u) = &output.usage {
+        // Tagged with the delegating call, so the rounds don't read as the parent's
+        let entry = usage::UsageRecord {
+            kind: Some("subagent".into()),
+            subagent: Some(ctx.call_id.clone()),
+            ..usage::make_record(&model.config, &ctx.session_id, round as u32, Some(&ctx.workspace_path), u)
+        };
+        if let Err(e) = usage::record(&ctx.data_dir, &entry) {
+            app_warn!("ai:usage", "failed to record sub-agent usage: {}", e);
+        }
+    }
+    Ok(output)
+}
+
+fn final_answer(content: String) -> String {
+    if content.trim().is_empty() {
+        "The sub-agent finished without a summary.".to_string()
+    } else {
+        content
+    }
+}
+
+fn text_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.into(),
+        content: Some(content),
+        tool_calls: None,
+        tool_call_id: None,
+        reasoning: None,
+        thinking_blocks: None,
+        images: None,
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// Most items a plan may hold
+pub const MAX_TODO_ITEMS: usize = 50;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    Pending,
+    InProgress,
+    Done,
+}
+
+impl TodoStatus {
+    fn marker(self) -> &'static str {
+        match self {
+            TodoStatus::Pending => "[ ]",
+            TodoStatus::InProgress => "[~]",
+            TodoStatus::Done => "[x]",
+        }
+    }
+}
+
+/// One step of the agent's plan
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TodoItem {
+    pub id: String,
+    pub text: String,
+    pub status: TodoStatus,
+}
+
+/// The plan of one chat session, shared between the agent loop and the todo
+/// tools. Stored with the session transcript.
+#[derive(Clone, Default)]
+pub struct TodoList {
+    items: Arc<Mutex<Vec<TodoItem>>>,
+}
+
+impl TodoList {
+    pub fn new(items: Vec<TodoItem>) -> Self {
+        Self { items: Arc::new(Mutex::new(items)) }
+    }
+
+    pub fn items(&self) -> Vec<TodoItem> {
+        self.items.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the whole plan after checking it
+    pub fn replace(&self, items: Vec<TodoItem>) -> Result<(), String> {
+        validate(&items)?;
+        *self.items.lock().unwrap_or_else(|e| e.into_inner()) = items;
+        Ok(())
+    }
+}
+
+fn validate(items: &[TodoItem]) -> Result<(), String> {
+    if items.len() > MAX_TODO_ITEMS {
+        return Err(format!("too many items ({}, max {})", items.len(), MAX_TODO_ITEMS));
+    }
+    for (i, item) in items.iter().enumerate() {
+        if item.id.trim().is_empty() {
+            return Err(format!("item {} has an empty id", i + 1));
+        }
+        if item.text.trim().is_empty() {
+            return Err(format!("item '{}' has an empty text", item.id));
+        }
+        if items[..i].iter().any(|other| other.id == item.id) {
+            return Err(format!("duplicate id '{}'", item.id));
+        }
+    }
+    Ok(())
+}
+
+/// Plain-text checklist for the model, with a progress count
+pub fn render(items: &[TodoItem]) -> String {
+    if items.is_empty() {
+        return "The task list is empty.".to_string();
+    }
+    let done = items.iter().filter(|t| t.status == TodoStatus::Done).count();
+    let mut out = format!("Task list ({}/{} done):", done, items.len());
+    for item in items {
+        out.push_str(&format!("\n{} {}. {}", item.status.marker(), item.id, item.text));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, text: &str, status: TodoStatus) -> TodoItem {
+        TodoItem { id: id.into(), text: text.into(), status }
+    }
+
+    #[test]
+    fn status_serializes_snake_case() {
+        let json = serde_json::to_string(&item("1", "Read", TodoStatus::InProgress)).unwrap();
+        assert_eq!(json, r#"{"id":"1","text":"Read","status":"in_progress"}"#);
+    }
+
+    #[test]
+    fn replace_rejects_bad_plans() {
+        let list = TodoList::default();
+        assert!(list.replace(vec![item("1", "a", TodoStatus::Pending), item("1", "b", TodoStatus::Pending)])
+            .unwrap_err().contains("duplicate"));
+        assert!(list.replace(vec![item("", "a", TodoStatus::Pending)]).is_err());
+        assert!(list.replace(vec![item("1", " ", TodoStatus::Pending)]).is_err());
+        assert!(list.items().is_empty());
+
+        list.replace(vec![item("1", "a", TodoStatus::Done)]).unwrap();
+        assert_eq!(list.clone().items().len(), 1); // clones share the plan
+    }
+
+    #[test]
+    fn render_shows_progress() {
+        let items = vec![
+            item("1", "Read the config", TodoStatus::Done),
+            item("2", "Fix the parser", TodoStatus::InProgress),
+            item("3", "Run tests", TodoStatus::Pending),
+        ];
+        assert_eq!(render(&items), "Task list (1/3 done):\n[x] 1. Read the config\n[~] 2. Fix the parser\n[ ] 3. Run tests");
+        assert_eq!(render(&[]), "The task list is empty.");
+    }
+}
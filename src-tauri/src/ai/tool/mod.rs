@@ -1,33 +1,71 @@
+pub mod progress;
 pub mod registry;
+pub mod schema;
 
 use async_trait::async_trait;
 use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tauri::AppHandle;
+use super::agent::AgentSink;
 use super::config::AiConfig;
+use super::jobs::JobManager;
 use super::memory::MemoryStore;
+use super::permission::PermissionStore;
+use super::streaming::ImagePart;
+use super::todo::TodoList;
+use progress::ToolProgress;
+use registry::{ToolFilter, ToolRegistry};
 
 /// Shared context injected into every tool execution
 #[derive(Clone)]
 pub struct ToolContext {
+    pub session_id: String,
     pub workspace_path: String,
-    pub app_handle: AppHandle,
+    /// None when running headless
+    pub app_handle: Option<AppHandle>,
+    pub sink: Arc<dyn AgentSink>,
     pub ai_config: AiConfig,
     pub memory_store: Arc<dyn MemoryStore>,
+    /// The user's permission rules
+    pub permissions: Arc<PermissionStore>,
+    /// Task list of the session, maintained by todo_write
+    pub todos: TodoList,
+    /// Background jobs started with start_job
+    pub jobs: Arc<JobManager>,
+    /// Id of the tool call being executed
+    pub call_id: String,
+    /// Partial output of the running call, for tools that take a while
+    pub progress: Option<ToolProgress>,
+    /// Registry the call came from, for tools that run nested agents
+    pub tools: Arc<ToolRegistry>,
+    /// Tools the active skill exposes; nested agents get no others
+    pub tool_filter: Arc<ToolFilter>,
+    /// Set when the user stops the run
+    pub cancel_flag: Arc<AtomicBool>,
+    /// App data directory of the run, see `AgentEnv::data_dir`
+    pub data_dir: PathBuf,
 }
 
 /// Structured output from tool execution
 pub struct ToolOutput {
     pub content: String,
     pub is_error: bool,
+    /// Images for the model to look at, sent after the tool results
+    pub images: Vec<ImagePart>,
 }
 
 impl ToolOutput {
     pub fn success(content: String) -> Self {
-        Self { content, is_error: false }
+        Self { content, is_error: false, images: Vec::new() }
     }
     pub fn error(content: String) -> Self {
-        Self { content, is_error: true }
+        Self { content, is_error: true, images: Vec::new() }
+    }
+    pub fn with_images(mut self, images: Vec<ImagePart>) -> Self {
+        self.images = images;
+        self
     }
 }
 
@@ -36,6 +74,10 @@ impl ToolOutput {
 pub enum ToolError {
     MissingArgument(String),
     InvalidArgument(String),
+    /// Arguments that don't match the tool's input schema, one message per problem
+    InvalidArguments(Vec<String>),
+    /// A pre-tool hook refused the call
+    Blocked(String),
     ExecutionFailed(String),
 }
 
@@ -44,6 +86,14 @@ impl std::fmt::Display for ToolError {
         match self {
             ToolError::MissingArgument(s) => write!(f, "Missing argument: {}", s),
             ToolError::InvalidArgument(s) => write!(f, "Invalid argument: {}", s),
+            ToolError::InvalidArguments(errors) => {
+                writeln!(f, "Invalid arguments, the tool was not run:")?;
+                for e in errors {
+                    writeln!(f, "- {}", e)?;
+                }
+                write!(f, "Fix these against the tool's input schema and call it again.")
+            }
+            ToolError::Blocked(reason) => write!(f, "Blocked by a hook, the tool was not run: {}", reason),
             ToolError::ExecutionFailed(s) => write!(f, "Execution failed: {}", s),
         }
     }
@@ -55,5 +105,8 @@ pub trait ToolPlugin: Send + Sync + 'static {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     fn input_schema(&self) -> Value;
+    /// Whether calls may run in parallel with other concurrency-safe calls.
+    /// Only read-only tools without side effects should return true.
+    fn is_concurrency_safe(&self) -> bool { false }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError>;
 }
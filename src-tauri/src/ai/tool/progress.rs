@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::ai::agent::AgentSink;
+use crate::ai::streaming::AiStreamEvent;
+
+/// Largest chunk read from a pipe before it is forwarded
+const CHUNK_SIZE: usize = 4 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressStream {
+    Stdout,
+    Stderr,
+    /// Status messages, e.g. MCP progress notifications
+    Status,
+}
+
+/// Streams partial output of a running tool call to the UI as `tool_progress`
+/// events. Only the final `ToolOutput` goes to the model.
+#[derive(Clone)]
+pub struct ToolProgress {
+    sink: Arc<dyn AgentSink>,
+    session_id: String,
+    call_id: String,
+    tool: String,
+}
+
+impl ToolProgress {
+    pub fn new(sink: Arc<dyn AgentSink>, session_id: &str, call_id: &str, tool: &str) -> Self {
+        Self {
+            sink,
+            session_id: session_id.to_string(),
+            call_id: call_id.to_string(),
+            tool: tool.to_string(),
+        }
+    }
+
+    pub fn send(&self, stream: ProgressStream, chunk: &str) {
+        if chunk.is_empty() {
+            return;
+        }
+        self.sink.emit(AiStreamEvent {
+            session_id: self.session_id.clone(),
+            event_type: "tool_progress".into(),
+            content: serde_json::json!({
+                "call_id": self.call_id,
+                "tool": self.tool,
+                "stream": stream,
+                "chunk": chunk,
+            }).to_string(),
+        });
+    }
+}
+
+/// Read a pipe to the end, forwarding each chunk as it arrives. Returns
+/// everything read. Multi-byte characters split across reads are held back
+/// until complete, so every forwarded chunk is valid UTF-8.
+pub async fn read_streamed<R: AsyncRead + Unpin>(
+    mut reader: R,
+    progress: Option<ToolProgress>,
+    stream: ProgressStream,
+) -> Vec<u8> {
+    let mut all = Vec::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    // Start of the bytes not forwarded yet
+    let mut sent = 0;
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        all.extend_from_slice(&buf[..n]);
+        let Some(progress) = &progress else { continue };
+        let pending = &all[sent..];
+        let valid = match std::str::from_utf8(pending) {
+            Ok(_) => pending.len(),
+            // Incomplete character at the end: wait for the rest
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Invalid bytes: forward them lossily rather than stall
+            Err(_) => pending.len(),
+        };
+        progress.send(stream, &String::from_utf8_lossy(&pending[..valid]));
+        sent += valid;
+    }
+    if let Some(progress) = &progress {
+        progress.send(stream, &String::from_utf8_lossy(&all[sent..]));
+    }
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use async_trait::async_trait;
+    use crate::ai::confirm::ConfirmRequest;
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<AiStreamEvent>>);
+
+    #[async_trait]
+    impl AgentSink for Collect {
+        fn emit(&self, event: AiStreamEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+        async fn confirm_command(&self, _request: &ConfirmRequest) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn open_file(&self, _path: &str) {}
+    }
+
+    #[tokio::test]
+    async fn chunks_are_forwarded_on_character_boundaries() {
+        let sink = Arc::new(Collect::default());
+        let progress = ToolProgress::new(sink.clone(), "s", "call_1", "run_shell");
+        // "é" is two bytes; split it across two reads
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let read = tokio::spawn(read_streamed(reader, Some(progress), ProgressStream::Stdout));
+        tokio::io::AsyncWriteExt::write_all(&mut writer, b"caf\xc3").await.unwrap();
+        tokio::task::yield_now().await;
+        tokio::io::AsyncWriteExt::write_all(&mut writer, b"\xa9!\n").await.unwrap();
+        drop(writer);
+
+        assert_eq!(read.await.unwrap(), "café!\n".as_bytes());
+        let events = sink.0.lock().unwrap();
+        let mut text = String::new();
+        for event in events.iter() {
+            assert_eq!(event.event_type, "tool_progress");
+            let content: serde_json::Value = serde_json::from_str(&event.content).unwrap();
+            assert_eq!(content["call_id"], "call_1");
+            assert_eq!(content["stream"], "stdout");
+            text.push_str(content["chunk"].as_str().unwrap());
+        }
+        assert_eq!(text, "café!\n");
+    }
+}
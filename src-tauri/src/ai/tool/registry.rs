@@ -1,8 +1,10 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
 use serde_json::Value;
-use super::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+use crate::app_warn;
+use crate::ai::hooks;
+use super::{schema, ToolPlugin, ToolContext, ToolOutput, ToolError};
 
 pub enum ToolFilter {
     All,
@@ -10,13 +12,28 @@ pub enum ToolFilter {
     Exclude(Vec<String>),
 }
 
+impl ToolFilter {
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            ToolFilter::All => true,
+            ToolFilter::Only(names) => names.iter().any(|n| n == name),
+            ToolFilter::Exclude(names) => !names.iter().any(|n| n == name),
+        }
+    }
+}
+
 pub struct ToolRegistry {
     tools: RwLock<HashMap<String, Arc<dyn ToolPlugin>>>,
+    /// Rejected calls per tool name, since startup; shared with scoped registries
+    validation_failures: Arc<Mutex<HashMap<String, u64>>>,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
-        Self { tools: RwLock::new(HashMap::new()) }
+        Self {
+            tools: RwLock::new(HashMap::new()),
+            validation_failures: Arc::new(Mutex::new(HashMap::new())),
+        }
     }
 
     pub async fn register(&self, tool: Arc<dyn ToolPlugin>) {
@@ -26,27 +43,18 @@ impl ToolRegistry {
     }
 
     pub async fn get_all_schemas(&self) -> Vec<Value> {
-        let tools = self.tools.read().await;
-        tools.values().map(|t| {
-            serde_json::json!({
-                "type": "function",
-                "function": {
-                    "name": t.name(),
-                    "description": t.description(),
-                    "parameters": t.input_schema(),
-                }
-            })
-        }).collect()
+        self.get_schemas_filtered(&ToolFilter::All).await
     }
 
+    /// Schemas sorted by tool name, so the tool list is byte-identical across
+    /// requests and stays inside the provider's prompt cache
     pub async fn get_schemas_filtered(&self, filter: &ToolFilter) -> Vec<Value> {
         let tools = self.tools.read().await;
-        tools.values()
-            .filter(|t| match filter {
-                ToolFilter::All => true,
-                ToolFilter::Only(names) => names.iter().any(|n| n == t.name()),
-                ToolFilter::Exclude(names) => !names.iter().any(|n| n == t.name()),
-            })
+        let mut allowed: Vec<&Arc<dyn ToolPlugin>> = tools.values()
+            .filter(|t| filter.allows(t.name()))
+            .collect();
+        allowed.sort_by(|a, b| a.name().cmp(b.name()));
+        allowed.into_iter()
             .map(|t| {
                 serde_json::json!({
                     "type": "function",
@@ -60,7 +68,23 @@ impl ToolRegistry {
             .collect()
     }
 
+    /// A new registry holding only the tools the filter allows. Calls to any
+    /// other tool fail as unknown, not just its schema is hidden. Rejected
+    /// calls are counted in this registry's validation failures.
+    pub async fn scoped(&self, filter: &ToolFilter) -> ToolRegistry {
+        let tools = self.tools.read().await;
+        Self {
+            tools: RwLock::new(tools.iter()
+                .filter(|(name, _)| filter.allows(name))
+                .map(|(name, tool)| (name.clone(), tool.clone()))
+                .collect()),
+            validation_failures: self.validation_failures.clone(),
+        }
+    }
+
     /// Execute a tool by name. Releases read lock before calling execute().
+    /// Configured hooks run around the call: a pre-tool hook may block it,
+    /// post-tool hooks may add to the result.
     pub async fn execute(
         &self,
         name: &str,
@@ -71,12 +95,75 @@ impl ToolRegistry {
             let tools = self.tools.read().await;
             tools.get(name).cloned()
         };
-        match tool {
-            Some(t) => t.execute(ctx, input).await,
-            None => Err(ToolError::ExecutionFailed(format!("Unknown tool: {}", name))),
+        let tool = match tool {
+            Some(t) => t,
+            None => return Err(ToolError::ExecutionFailed(format!("Unknown tool: {}", name))),
+        };
+        let errors = schema::validate(&tool.input_schema(), &input);
+        if !errors.is_empty() {
+            return Err(self.reject(name, errors));
+        }
+        let hooks = hooks::hooks_for(ctx).await;
+        if hooks.is_empty() {
+            return tool.execute(ctx, input).await;
+        }
+        if let Err(reason) = hooks::run_pre_hooks(&hooks, ctx, name, &input).await {
+            app_warn!("ai:hooks", "{} blocked: {}", name, reason);
+            return Err(ToolError::Blocked(reason));
+        }
+        let mut output = tool.execute(ctx, input.clone()).await?;
+        hooks::run_post_hooks(&hooks, ctx, name, &input, &mut output).await;
+        Ok(output)
+    }
+
+    /// Execute a tool call with its raw JSON arguments, as sent by the model.
+    /// Unparseable arguments are rejected like schema violations.
+    pub async fn execute_raw(
+        &self,
+        name: &str,
+        ctx: &ToolContext,
+        arguments: &str,
+    ) -> Result<ToolOutput, ToolError> {
+        match schema::parse_arguments(arguments) {
+            Ok(input) => self.execute(name, ctx, input).await,
+            Err(e) => Err(self.reject(name, vec![e])),
         }
     }
 
+    /// Count and log a call rejected before execution
+    fn reject(&self, name: &str, errors: Vec<String>) -> ToolError {
+        let count = {
+            let mut failures = self.validation_failures.lock().unwrap_or_else(|e| e.into_inner());
+            let count = failures.entry(name.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        app_warn!("ai:tool", "invalid arguments for {} (failure #{}): {}", name, count, errors.join("; "));
+        ToolError::InvalidArguments(errors)
+    }
+
+    /// Number of calls rejected for invalid arguments, per tool
+    pub fn validation_failures(&self) -> HashMap<String, u64> {
+        self.validation_failures.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Whether the named tool may run in parallel. Unknown tools are not.
+    pub async fn is_concurrency_safe(&self, name: &str) -> bool {
+        let tools = self.tools.read().await;
+        tools.get(name).map(|t| t.is_concurrency_safe()).unwrap_or(false)
+    }
+
+    /// Names of the tools that may run in parallel, i.e. the read-only ones
+    pub async fn concurrency_safe_names(&self) -> Vec<String> {
+        let tools = self.tools.read().await;
+        let mut names: Vec<String> = tools.values()
+            .filter(|t| t.is_concurrency_safe())
+            .map(|t| t.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
     pub async fn has_tool(&self, name: &str) -> bool {
         let tools = self.tools.read().await;
         tools.contains_key(name)
@@ -96,7 +183,7 @@ impl ToolRegistry {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use async_trait::async_trait;
 
@@ -159,6 +246,18 @@ mod tests {
         assert_eq!(schemas.len(), 2);
     }
 
+    #[tokio::test]
+    async fn test_schemas_sorted_by_name() {
+        let registry = ToolRegistry::new();
+        for name in ["write_file", "grep_files", "read_file", "list_directory"] {
+            registry.register(Arc::new(DummyTool::new(name))).await;
+        }
+        let names: Vec<String> = registry.get_all_schemas().await.iter()
+            .filter_map(|s| s["function"]["name"].as_str().map(String::from))
+            .collect();
+        assert_eq!(names, vec!["grep_files", "list_directory", "read_file", "write_file"]);
+    }
+
     #[tokio::test]
     async fn test_filter_only() {
         let registry = ToolRegistry::new();
@@ -187,6 +286,23 @@ mod tests {
         assert_eq!(schemas[0]["function"]["name"], "b");
     }
 
+    #[tokio::test]
+    async fn test_scoped_registry_drops_other_tools() {
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(DummyTool::new("a"))).await;
+        registry.register(Arc::new(DummyTool::new("b"))).await;
+
+        let scoped = registry.scoped(&ToolFilter::Only(vec!["a".into(), "missing".into()])).await;
+        assert_eq!(scoped.get_all_schemas().await.len(), 1);
+        let (_dir, ctx) = test_context();
+        assert!(scoped.execute("a", &ctx, serde_json::json!({})).await.is_ok());
+        match scoped.execute("b", &ctx, serde_json::json!({})).await {
+            Err(e) => assert!(e.to_string().contains("Unknown tool: b")),
+            Ok(_) => panic!("tool outside the scope was executed"),
+        }
+        assert!(registry.has_tool("b").await);
+    }
+
     #[tokio::test]
     async fn test_remove_by_prefix() {
         let registry = ToolRegistry::new();
@@ -208,4 +324,118 @@ mod tests {
         // Should still be 1, not 2
         assert_eq!(registry.get_all_schemas().await.len(), 1);
     }
+
+    /// A context with stores in a temporary directory (removed when the
+    /// returned guard drops) that denies every confirmation
+    pub(crate) fn test_context() -> (tempfile::TempDir, ToolContext) {
+        use crate::ai::headless::{ApprovalPolicy, OutputFormat, StdoutSink};
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let ctx = ToolContext {
+            session_id: "test".into(),
+            workspace_path: String::new(),
+            app_handle: None,
+            sink: Arc::new(StdoutSink::new(OutputFormat::Json, ApprovalPolicy::Deny)),
+            ai_config: serde_json::from_value(serde_json::json!({
+                "api_url": "", "api_key": "", "model": "m", "temperature": 0.0, "max_tokens": 1,
+            })).unwrap(),
+            memory_store: Arc::new(crate::ai::memory::FileMemoryStore::new(dir.join("memories")).unwrap()),
+            permissions: Arc::new(crate::ai::permission::PermissionStore::new(dir.join("permissions.json")).unwrap()),
+            todos: Default::default(),
+            jobs: Default::default(),
+            call_id: "call_1".into(),
+            progress: None,
+            tools: Arc::new(ToolRegistry::new()),
+            tool_filter: Arc::new(ToolFilter::All),
+            cancel_flag: Default::default(),
+            data_dir: dir.to_path_buf(),
+        };
+        (tmp, ctx)
+    }
+
+    struct PathTool;
+
+    #[async_trait]
+    impl ToolPlugin for PathTool {
+        fn name(&self) -> &str { "read" }
+        fn description(&self) -> &str { "Reads a path" }
+        fn input_schema(&self) -> Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": { "path": { "type": "string" } },
+                "required": ["path"]
+            })
+        }
+        async fn execute(&self, _ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+            Ok(ToolOutput::success(format!("read {}", input["path"].as_str().unwrap_or(""))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_invalid_arguments() {
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(PathTool)).await;
+        let (_dir, ctx) = test_context();
+
+        let ok = registry.execute_raw("read", &ctx, r#"{"path":"a.txt"}"#).await.unwrap();
+        assert_eq!(ok.content, "read a.txt");
+
+        match registry.execute_raw("read", &ctx, r#"{"path":7}"#).await {
+            Err(ToolError::InvalidArguments(errors)) => assert_eq!(errors, vec!["path: expected string, got number"]),
+            _ => panic!("expected a schema error"),
+        }
+        match registry.execute_raw("read", &ctx, r#"{"path":"a.t"#).await {
+            Err(e @ ToolError::InvalidArguments(_)) => {
+                let text = e.to_string();
+                assert!(text.contains("truncated"));
+                assert!(text.contains("call it again"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+        assert_eq!(registry.validation_failures().get("read"), Some(&2));
+
+        // A scoped registry (as a sub-agent uses) counts into the same totals
+        let scoped = registry.scoped(&ToolFilter::Only(vec!["read".into()])).await;
+        assert!(scoped.execute_raw("read", &ctx, "{}").await.is_err());
+        assert_eq!(registry.validation_failures().get("read"), Some(&3));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hooks_block_and_extend_calls() {
+        use crate::ai::hooks::{HookEvent, ToolHook};
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(PathTool)).await;
+        let (_dir, mut ctx) = test_context();
+        ctx.ai_config.hooks = vec![
+            ToolHook {
+                event: HookEvent::PreToolUse,
+                matcher: "re*".into(),
+                command: "grep -q secret && { echo 'secret files are off limits' >&2; exit 1; } || exit 0".into(),
+                timeout_secs: 5,
+            },
+            ToolHook {
+                event: HookEvent::PostToolUse,
+                matcher: "read".into(),
+                command: "grep -o '\"result\":\"[^\"]*\"'".into(),
+                timeout_secs: 5,
+            },
+        ];
+
+        match registry.execute("read", &ctx, serde_json::json!({ "path": "secret.txt" })).await {
+            Err(e @ ToolError::Blocked(_)) => assert!(e.to_string().ends_with("secret files are off limits")),
+            _ => panic!("expected the pre-tool hook to block the call"),
+        }
+        let output = registry.execute("read", &ctx, serde_json::json!({ "path": "a.txt" })).await.unwrap();
+        assert!(output.content.starts_with("read a.txt\n\n[Hook '"));
+        assert!(output.content.ends_with("\"result\":\"read a.txt\""));
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_safe_defaults_to_false() {
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(DummyTool::new("a"))).await;
+        assert!(!registry.is_concurrency_safe("a").await);
+        assert!(!registry.is_concurrency_safe("missing").await);
+    }
 }
@@ -0,0 +1,181 @@
+use serde_json::Value;
+
+/// Parse raw tool-call arguments. An empty string means no arguments.
+pub fn parse_arguments(raw: &str) -> Result<Value, String> {
+    if raw.trim().is_empty() {
+        return Ok(serde_json::json!({}));
+    }
+    serde_json::from_str(raw).map_err(|e| {
+        if e.is_eof() {
+            format!("arguments: JSON is truncated ({}); the call may have been cut off, send it again complete", e)
+        } else {
+            format!("arguments: not valid JSON ({})", e)
+        }
+    })
+}
+
+/// Check `input` against the subset of JSON Schema used by tool input schemas:
+/// `type`, `required`, `properties`, `enum` and `items`. Other keywords are
+/// ignored, so MCP schemas using more of the spec are checked leniently.
+/// Returns one message per violation, prefixed with the argument path.
+pub fn validate(schema: &Value, input: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(schema, input, "arguments", &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(t, value)) {
+            errors.push(format!("{}: expected {}, got {}", path, allowed.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(value) {
+            let listed: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+            errors.push(format!("{}: must be one of {}, got {}", path, listed.join(", "), value));
+        }
+    }
+
+    if let Value::Object(obj) = value {
+        for name in schema.get("required").and_then(|r| r.as_array()).into_iter().flatten().filter_map(|n| n.as_str()) {
+            if matches!(obj.get(name), None | Some(Value::Null)) {
+                errors.push(format!("{}: required property is missing", child_path(path, name)));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, prop_schema) in properties {
+                match obj.get(name) {
+                    // Optional arguments sent as null are treated as absent
+                    Some(Value::Null) | None => {}
+                    Some(v) => check(prop_schema, v, &child_path(path, name), errors),
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(item_schema, item, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+fn child_path(parent: &str, name: &str) -> String {
+    if parent == "arguments" {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        // Tools read integers with as_i64/as_u64, which fail on 3.0
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        // Unknown type keyword: don't reject what we can't check
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn edit_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "edits": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string", "enum": ["replace", "insert", "delete"] },
+                            "line_start": { "type": "integer" }
+                        },
+                        "required": ["action", "line_start"]
+                    }
+                }
+            },
+            "required": ["path", "edits"]
+        })
+    }
+
+    #[test]
+    fn valid_input_has_no_errors() {
+        let input = json!({ "path": "a.txt", "edits": [{ "action": "insert", "line_start": 3 }], "extra": 1 });
+        assert!(validate(&edit_schema(), &input).is_empty());
+    }
+
+    #[test]
+    fn integer_rejects_floats() {
+        let input = json!({ "path": "a.txt", "edits": [{ "action": "insert", "line_start": 3.0 }] });
+        assert_eq!(validate(&edit_schema(), &input), vec!["edits[0].line_start: expected integer, got number"]);
+    }
+
+    #[test]
+    fn reports_missing_wrong_type_and_enum() {
+        let input = json!({ "path": 5, "edits": [{ "action": "rewrite", "line_start": "3" }, {}] });
+        // Property order depends on serde_json's map implementation
+        let mut errors = validate(&edit_schema(), &input);
+        errors.sort();
+        assert_eq!(errors, vec![
+            r#"edits[0].action: must be one of "replace", "insert", "delete", got "rewrite""#,
+            "edits[0].line_start: expected integer, got string",
+            "edits[1].action: required property is missing",
+            "edits[1].line_start: required property is missing",
+            "path: expected string, got number",
+        ]);
+    }
+
+    #[test]
+    fn null_counts_as_missing() {
+        let errors = validate(&edit_schema(), &json!({ "path": null, "edits": [] }));
+        assert_eq!(errors, vec!["path: required property is missing"]);
+        let optional = json!({ "type": "object", "properties": { "limit": { "type": "number" } } });
+        assert!(validate(&optional, &json!({ "limit": null })).is_empty());
+    }
+
+    #[test]
+    fn root_type_and_lenient_keywords() {
+        assert_eq!(validate(&edit_schema(), &json!([])), vec!["arguments: expected object, got array"]);
+        let mcp = json!({ "type": "object", "properties": { "q": { "anyOf": [{ "type": "string" }] } } });
+        assert!(validate(&mcp, &json!({ "q": 1 })).is_empty());
+        assert!(validate(&json!({ "type": ["string", "null"] }), &Value::Null).is_empty());
+    }
+
+    #[test]
+    fn parse_arguments_reports_truncation() {
+        assert_eq!(parse_arguments("").unwrap(), json!({}));
+        assert_eq!(parse_arguments(r#"{"a":1}"#).unwrap(), json!({ "a": 1 }));
+        assert!(parse_arguments(r#"{"path": "a.tx"#).unwrap_err().contains("truncated"));
+        assert!(parse_arguments("{path: 1}").unwrap_err().contains("not valid JSON"));
+    }
+}
@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+use crate::ai::image::load_image;
+
+pub struct AttachImageTool;
+
+#[async_trait]
+impl ToolPlugin for AttachImageTool {
+    fn name(&self) -> &str { "attach_image" }
+    fn description(&self) -> &str {
+        "Look at an image file in the workspace (PNG, JPEG, GIF or WebP, up to 5 MB). The image is shown to you right after the tool results. Use this for screenshots, diagrams and charts instead of read_file."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Image file path" }
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn is_concurrency_safe(&self) -> bool { true }
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let raw_path = input["path"].as_str()
+            .ok_or_else(|| ToolError::MissingArgument("path".into()))?;
+        match load_image(raw_path, &ctx.workspace_path) {
+            Ok(image) => {
+                let size_kb = image.data.as_ref().map(|d| d.len() * 3 / 4 / 1024).unwrap_or(0);
+                Ok(ToolOutput::success(format!("Attached {} ({}, {} KB).", raw_path, image.media_type, size_kb))
+                    .with_images(vec![image]))
+            }
+            Err(e) => Ok(ToolOutput::error(format!("Cannot attach image: {}", e))),
+        }
+    }
+}
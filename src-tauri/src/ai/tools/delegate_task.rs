@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::subagent::{run_subagent, SubagentTask, DEFAULT_SUBAGENT_ROUNDS, DELEGATE_TOOL, MAX_SUBAGENT_ROUNDS};
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+
+pub struct DelegateTaskTool;
+
+#[async_trait]
+impl ToolPlugin for DelegateTaskTool {
+    fn name(&self) -> &str { DELEGATE_TOOL }
+    fn description(&self) -> &str {
+        "Hand a self-contained subtask to a sub-agent with a fresh context, e.g. searching a large codebase or reading many files. The sub-agent only sees the task text, so include everything it needs. You get back its final summary, not its intermediate steps. By default it may only use read-only tools."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "task": { "type": "string", "description": "Complete description of the subtask and what the summary should contain" },
+                "tools": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Tool names the sub-agent may use (default: the read-only tools)"
+                },
+                "max_rounds": {
+                    "type": "integer",
+                    "description": format!("Tool rounds before the sub-agent must answer (default {}, max {})", DEFAULT_SUBAGENT_ROUNDS, MAX_SUBAGENT_ROUNDS)
+                }
+            },
+            "required": ["task"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let task = input["task"].as_str()
+            .ok_or_else(|| ToolError::MissingArgument("task".into()))?;
+        if task.trim().is_empty() {
+            return Err(ToolError::InvalidArgument("task is empty".into()));
+        }
+        let tools = match input["tools"].as_array() {
+            Some(names) => {
+                let names: Vec<String> = names.iter().filter_map(|n| n.as_str()).map(String::from).collect();
+                // The sub-agent may not reach tools the active skill hides
+                let mut unavailable = Vec::new();
+                for name in &names {
+                    if !ctx.tool_filter.allows(name) || !ctx.tools.has_tool(name).await {
+                        unavailable.push(name.as_str());
+                    }
+                }
+                if !unavailable.is_empty() {
+                    return Err(ToolError::InvalidArgument(format!("unavailable tools: {}", unavailable.join(", "))));
+                }
+                if names.iter().any(|n| n == DELEGATE_TOOL) {
+                    return Err(ToolError::InvalidArgument(format!("a sub-agent cannot use {}", DELEGATE_TOOL)));
+                }
+                names
+            }
+            None => ctx.tools.concurrency_safe_names().await
+                .into_iter()
+                .filter(|name| ctx.tool_filter.allows(name))
+                .collect(),
+        };
+        let max_rounds = input["max_rounds"].as_u64()
+            .map(|n| (n as usize).clamp(1, MAX_SUBAGENT_ROUNDS))
+            .unwrap_or(DEFAULT_SUBAGENT_ROUNDS);
+
+        let task = SubagentTask { task: task.to_string(), tools, max_rounds };
+        match run_subagent(ctx, &task).await {
+            Ok(summary) => Ok(ToolOutput::success(summary)),
+            Err(e) => Ok(ToolOutput::error(format!("Sub-agent failed: {}", e))),
+        }
+    }
+}
@@ -24,6 +24,7 @@ impl ToolPlugin for DiffFilesTool {
         })
     }
 
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let raw_a = input["path_a"].as_str().unwrap_or("");
         let path_a = match sandbox_path(raw_a, &ctx.workspace_path) {
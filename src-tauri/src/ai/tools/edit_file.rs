@@ -1,6 +1,7 @@
 use std::fs;
 use async_trait::async_trait;
 use serde_json::Value;
+use crate::ai::permission::{self, PermissionRequest};
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
 use crate::ai::sandbox_path;
 
@@ -59,6 +60,12 @@ impl ToolPlugin for EditFileTool {
             ))),
         };
 
+        let rel_path = permission::relative_path(raw_path, &ctx.workspace_path);
+        let request = PermissionRequest { tool: self.name(), command: None, path: Some(&rel_path) };
+        if let Err(e) = permission::check(ctx, &request).await {
+            return Ok(ToolOutput::error(e));
+        }
+
         // Read file
         let content = match fs::read_to_string(&path) {
             Ok(c) => c,
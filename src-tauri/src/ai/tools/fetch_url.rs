@@ -19,6 +19,7 @@ impl ToolPlugin for FetchUrlTool {
             "required": ["url"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, _ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let url = input["url"].as_str().unwrap_or("");
         let result = fetch_url(url).await;
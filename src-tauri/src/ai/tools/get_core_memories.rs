@@ -1,8 +1,6 @@
 use async_trait::async_trait;
 use serde_json::Value;
-use tauri::Manager;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
-use crate::ai::MemoryStoreState;
 
 pub struct GetCoreMemoriesTool;
 
@@ -21,9 +19,9 @@ impl ToolPlugin for GetCoreMemoriesTool {
         })
     }
 
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, _input: Value) -> Result<ToolOutput, ToolError> {
-        let memory_store_state = ctx.app_handle.state::<MemoryStoreState>();
-        match memory_store_state.store.get_core_memories().await {
+        match ctx.memory_store.get_core_memories().await {
             Ok(memories) => {
                 if memories.is_empty() {
                     Ok(ToolOutput::success("No core memories found.".to_string()))
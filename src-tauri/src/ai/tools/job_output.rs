@@ -0,0 +1,55 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+
+/// Longest a read may wait for new output
+const MAX_WAIT_SECS: u64 = 30;
+
+pub struct JobOutputTool;
+
+#[async_trait]
+impl ToolPlugin for JobOutputTool {
+    fn name(&self) -> &str { "job_output" }
+    fn description(&self) -> &str {
+        "Read the output of a background job started with start_job, from a cursor on. Returns the new output, its status and the cursor to pass next time. Set wait_secs to wait for new output, e.g. until a server is ready."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "job_id": { "type": "string", "description": "Id returned by start_job" },
+                "cursor": { "type": "integer", "description": "Cursor from the previous read (default 0: from the start)" },
+                "wait_secs": { "type": "integer", "description": format!("Seconds to wait for new output if there is none yet (default 0, max {})", MAX_WAIT_SECS) }
+            },
+            "required": ["job_id"]
+        })
+    }
+
+    fn is_concurrency_safe(&self) -> bool { true }
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let job_id = input["job_id"].as_str()
+            .ok_or_else(|| ToolError::MissingArgument("job_id".into()))?;
+        let cursor = input["cursor"].as_u64().unwrap_or(0);
+        let wait = Duration::from_secs(input["wait_secs"].as_u64().unwrap_or(0).min(MAX_WAIT_SECS));
+
+        let read = match ctx.jobs.read(&ctx.session_id, job_id, cursor, wait).await {
+            Ok(read) => read,
+            Err(e) => return Ok(ToolOutput::error(e)),
+        };
+        let mut text = format!("Job {}: {}\nNext cursor: {}\n", job_id, read.status, read.cursor);
+        if read.dropped > 0 {
+            text.push_str(&format!("[{} earlier bytes were dropped]\n", read.dropped));
+        }
+        if read.output.is_empty() {
+            text.push_str("(no new output)");
+        } else {
+            text.push_str("--- output ---\n");
+            text.push_str(&read.output);
+        }
+        if read.truncated {
+            text.push_str("\n[more output available, read again from the next cursor]");
+        }
+        Ok(ToolOutput::success(text))
+    }
+}
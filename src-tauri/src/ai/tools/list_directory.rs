@@ -17,6 +17,7 @@ impl ToolPlugin for ListDirectoryTool {
             "required": ["path"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let raw_path = input["path"].as_str().unwrap_or(".");
         let path = match sandbox_path(raw_path, &ctx.workspace_path) {
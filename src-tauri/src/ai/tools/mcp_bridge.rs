@@ -2,8 +2,11 @@ use async_trait::async_trait;
 use serde_json::Value;
 use tauri::Manager;
 
+use crate::ai::permission::{self, PermissionRequest};
 use crate::ai::tool::{ToolContext, ToolError, ToolOutput, ToolPlugin};
+use crate::ai::tool::progress::ProgressStream;
 use crate::ai::tool::registry::ToolRegistry;
+use crate::mcp::transport::ProgressFn;
 use crate::mcp::McpState;
 
 /// MCP tool name prefix used in ToolRegistry to avoid collisions with builtin tools.
@@ -58,11 +61,23 @@ impl ToolPlugin for McpBridgeTool {
     }
 
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
-        let mcp_state = ctx.app_handle.try_state::<McpState>()
+        let request = PermissionRequest { tool: &self.prefixed_name, command: None, path: None };
+        if let Err(e) = permission::check(ctx, &request).await {
+            return Ok(ToolOutput::error(e));
+        }
+
+        let mcp_state = ctx.app_handle.as_ref()
+            .and_then(|app| app.try_state::<McpState>())
             .ok_or_else(|| ToolError::ExecutionFailed("MCP state not available".into()))?;
 
+        // Show the server's progress notifications as status lines
+        let on_progress = ctx.progress.clone().map(|progress| move |params: &Value| {
+            progress.send(ProgressStream::Status, &progress_line(params));
+        });
+        let on_progress = on_progress.as_ref().map(|f| f as ProgressFn<'_>);
+
         let mut registry = mcp_state.registry.lock().await;
-        match registry.call_tool(&self.server_id, &self.original_name, input).await {
+        match registry.call_tool(&self.server_id, &self.original_name, input, on_progress).await {
             Ok(result) => {
                 let text = result.content.iter()
                     .filter_map(|c| c.text.as_deref())
@@ -80,6 +95,21 @@ impl ToolPlugin for McpBridgeTool {
     }
 }
 
+/// One line for an MCP progress notification: its message, or "progress/total"
+fn progress_line(params: &Value) -> String {
+    let amount = match (params["progress"].as_f64(), params["total"].as_f64()) {
+        (Some(progress), Some(total)) => format!("{}/{}", progress, total),
+        (Some(progress), None) => progress.to_string(),
+        _ => String::new(),
+    };
+    match params["message"].as_str() {
+        Some(message) if amount.is_empty() => format!("{}\n", message),
+        Some(message) => format!("{} ({})\n", message, amount),
+        None if amount.is_empty() => String::new(),
+        None => format!("{}\n", amount),
+    }
+}
+
 /// Synchronize MCP tools into the ToolRegistry.
 ///
 /// 1. Removes all existing MCP bridge tools (by prefix)
@@ -16,12 +16,19 @@ pub mod save_memory;
 pub mod search_memory;
 pub mod get_core_memories;
 pub mod mcp_bridge;
+pub mod attach_image;
+pub mod todo_write;
+pub mod todo_read;
+pub mod delegate_task;
+pub mod start_job;
+pub mod job_output;
+pub mod stop_job;
 
 use std::sync::Arc;
 use super::tool::registry::ToolRegistry;
 
 pub async fn register_builtin_tools(registry: &ToolRegistry) {
-    // 17 builtin tools
+    // 24 builtin tools
     registry.register(Arc::new(list_directory::ListDirectoryTool)).await;
     registry.register(Arc::new(read_file::ReadFileTool)).await;
     registry.register(Arc::new(search_files::SearchFilesTool)).await;
@@ -39,4 +46,11 @@ pub async fn register_builtin_tools(registry: &ToolRegistry) {
     registry.register(Arc::new(save_memory::SaveMemoryTool)).await;
     registry.register(Arc::new(search_memory::SearchMemoryTool)).await;
     registry.register(Arc::new(get_core_memories::GetCoreMemoriesTool)).await;
+    registry.register(Arc::new(attach_image::AttachImageTool)).await;
+    registry.register(Arc::new(todo_write::TodoWriteTool)).await;
+    registry.register(Arc::new(todo_read::TodoReadTool)).await;
+    registry.register(Arc::new(delegate_task::DelegateTaskTool)).await;
+    registry.register(Arc::new(start_job::StartJobTool)).await;
+    registry.register(Arc::new(job_output::JobOutputTool)).await;
+    registry.register(Arc::new(stop_job::StopJobTool)).await;
 }
@@ -1,6 +1,5 @@
 use async_trait::async_trait;
 use serde_json::Value;
-use tauri::Emitter;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
 use crate::ai::sandbox_path;
 
@@ -28,7 +27,7 @@ impl ToolPlugin for OpenFileTool {
             Some(p) => p,
             None => return Ok(ToolOutput::error(format!("Access denied: path '{}' is outside the current workspace.", raw_path))),
         };
-        let _ = ctx.app_handle.emit("open-file-request", serde_json::json!({ "path": path }));
+        ctx.sink.open_file(&path);
         Ok(ToolOutput::success(format!("Opened file: {}", path)))
     }
 }
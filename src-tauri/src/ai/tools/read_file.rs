@@ -2,6 +2,7 @@ use async_trait::async_trait;
 use serde_json::Value;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
 use crate::ai::sandbox_path;
+use crate::ai::image::media_type_for;
 use crate::do_read_file;
 
 pub struct ReadFileTool;
@@ -17,6 +18,7 @@ impl ToolPlugin for ReadFileTool {
             "required": ["path"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let raw_path = input["path"].as_str().unwrap_or("");
         let path = match sandbox_path(raw_path, &ctx.workspace_path) {
@@ -32,6 +34,9 @@ impl ToolPlugin for ReadFileTool {
         let binary_exts = ["xlsx", "xls", "pdf", "docx", "doc", "pptx", "ppt",
             "png", "jpg", "jpeg", "gif", "bmp", "webp", "ico", "svg",
             "zip", "tar", "gz", "rar", "7z", "exe", "dll", "so", "dylib"];
+        if media_type_for(&path).is_some() {
+            return Ok(ToolOutput::error(format!("This file is an image (.{}), cannot be read as text. Use the attach_image tool to look at it.", ext)));
+        }
         if binary_exts.contains(&ext.as_str()) {
             return Ok(ToolOutput::error(format!("This file is binary format (.{}), cannot be read as text. Use run_python tool with appropriate libraries (e.g. openpyxl for xlsx, Pillow for images).", ext)));
         }
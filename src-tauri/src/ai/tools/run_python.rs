@@ -4,6 +4,7 @@ use async_trait::async_trait;
 use serde_json::Value;
 use tauri::AppHandle;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+use crate::ai::tool::progress::{read_streamed, ProgressStream, ToolProgress};
 use crate::python_setup;
 
 pub struct RunPythonTool;
@@ -32,7 +33,7 @@ impl ToolPlugin for RunPythonTool {
 
         // Prepend sandbox preamble
         let full_code = format!("{}\n{}", sandbox.preamble(), code);
-        let result = run_python(&full_code, &ctx.app_handle, &ctx.workspace_path).await;
+        let result = run_python(&full_code, ctx.app_handle.as_ref(), &ctx.workspace_path, ctx.progress.clone()).await;
         Ok(ToolOutput::success(result))
     }
 }
@@ -42,7 +43,7 @@ fn find_python() -> Option<PathBuf> {
     if p.exists() { Some(p) } else { None }
 }
 
-async fn run_python(code: &str, app: &AppHandle, cwd: &str) -> String {
+async fn run_python(code: &str, app: Option<&AppHandle>, cwd: &str, progress: Option<ToolProgress>) -> String {
     if code.trim().is_empty() {
         return "Please provide Python code to execute".to_string();
     }
@@ -50,7 +51,10 @@ async fn run_python(code: &str, app: &AppHandle, cwd: &str) -> String {
     let python_path = match find_python() {
         Some(p) => p,
         None => {
-            // Auto-trigger Python environment setup
+            // Auto-trigger Python environment setup (needs the app for progress events)
+            let Some(app) = app else {
+                return "Python environment is not installed. Open Inkess once to set it up.".to_string();
+            };
             match python_setup::setup_python_env(app).await {
                 Ok(p) => p,
                 Err(e) => return format!("Python environment setup failed: {}", e),
@@ -90,20 +94,18 @@ async fn run_python(code: &str, app: &AppHandle, cwd: &str) -> String {
     let stderr_handle = child.stderr.take();
 
     // Spawn tasks to drain stdout/stderr concurrently with child.wait()
+    // and stream both as progress
+    let stderr_progress = progress.clone();
     let stdout_task = tokio::spawn(async move {
-        if let Some(mut h) = stdout_handle {
-            let mut buf = Vec::new();
-            let _ = tokio::io::AsyncReadExt::read_to_end(&mut h, &mut buf).await;
-            String::from_utf8_lossy(&buf).to_string()
+        if let Some(h) = stdout_handle {
+            String::from_utf8_lossy(&read_streamed(h, progress, ProgressStream::Stdout).await).to_string()
         } else {
             String::new()
         }
     });
     let stderr_task = tokio::spawn(async move {
-        if let Some(mut h) = stderr_handle {
-            let mut buf = Vec::new();
-            let _ = tokio::io::AsyncReadExt::read_to_end(&mut h, &mut buf).await;
-            String::from_utf8_lossy(&buf).to_string()
+        if let Some(h) = stderr_handle {
+            String::from_utf8_lossy(&read_streamed(h, stderr_progress, ProgressStream::Stderr).await).to_string()
         } else {
             String::new()
         }
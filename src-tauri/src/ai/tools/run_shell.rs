@@ -1,13 +1,14 @@
 use std::time::Duration;
 use async_trait::async_trait;
 use serde_json::Value;
-use tauri::Manager;
-use tauri::Emitter;
+use crate::ai::confirm::ConfirmRequest;
+use crate::ai::permission::{Decision, PermissionRequest};
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+use crate::ai::tool::progress::{read_streamed, ProgressStream, ToolProgress};
 
 pub struct RunShellTool;
 
-// Command security tiers
+// Default command security tiers, used where no permission rule applies
 const AUTO_ALLOW: &[&str] = &[
     "ls", "cat", "head", "tail", "wc", "file", "which", "echo", "pwd", "env", "date",
     "find", "tree", "du", "df", "uname", "whoami", "hostname",
@@ -37,23 +38,53 @@ enum CommandTier {
     Blocked,
 }
 
+impl From<Decision> for CommandTier {
+    fn from(decision: Decision) -> Self {
+        match decision {
+            Decision::Allow => CommandTier::AutoAllow,
+            Decision::Ask => CommandTier::NeedConfirm,
+            Decision::Deny => CommandTier::Blocked,
+        }
+    }
+}
+
 fn classify_command(cmd: &str) -> CommandTier {
+    resolve_tier(cmd, &|_| None)
+}
+
+/// Tier of a command, where `rule_for` gives the user's permission rule
+/// decision for a base command. Each part of a chain is judged on its own,
+/// by its rule or else the default lists, so allowing `cargo test` never
+/// allows `cargo test && rm -rf x`. Rules can't lift a part out of the
+/// blocked list, and only tighten parts that still hold redirects or
+/// background operators.
+fn resolve_tier(cmd: &str, rule_for: &dyn Fn(&str) -> Option<Decision>) -> CommandTier {
     let trimmed = cmd.trim();
+    let tier_of = |part: &str| {
+        let default = classify_single(part);
+        let Some(decision) = rule_for(&extract_base_command(part.trim())) else { return default };
+        let ruled = CommandTier::from(decision);
+        if default == CommandTier::Blocked || part.contains(['&', '\n', '>', '<']) {
+            ruled.max(default)
+        } else {
+            ruled
+        }
+    };
 
     // Commands containing backticks or $() subshells can execute arbitrary code
     if trimmed.contains('`') || trimmed.contains("$(") {
         // At minimum NeedConfirm; check sub-parts for Blocked
-        let inner_tier = classify_single(trimmed);
+        let inner_tier = tier_of(trimmed);
         return if inner_tier > CommandTier::NeedConfirm { inner_tier } else { CommandTier::NeedConfirm };
     }
 
-    // Split on all command chaining operators: |, &&, ||, ;
+    // Split on all command chaining operators: |, &&, ||, ;, &, newline
     // Use the highest risk level across all sub-commands
     let parts = split_command_operators(trimmed);
     if parts.len() > 1 {
         let mut max_tier = CommandTier::AutoAllow;
         for part in &parts {
-            let tier = classify_single(part.trim());
+            let tier = tier_of(part);
             if tier > max_tier {
                 max_tier = tier;
             }
@@ -61,10 +92,11 @@ fn classify_command(cmd: &str) -> CommandTier {
         return max_tier;
     }
 
-    classify_single(trimmed)
+    tier_of(trimmed)
 }
 
-/// Split a command string on shell operators: |, &&, ||, ;
+/// Split a command string on shell operators: |, &&, ||, ;, & and newlines.
+/// A `&` inside a redirect like `2>&1` or `&>` doesn't split.
 fn split_command_operators(cmd: &str) -> Vec<&str> {
     let mut parts = Vec::new();
     let mut last = 0;
@@ -89,7 +121,12 @@ fn split_command_operators(cmd: &str) -> Vec<&str> {
             parts.push(&cmd[last..i]);
             i += 2;
             last = i;
-        } else if bytes[i] == b';' {
+        } else if bytes[i] == b'&' && !is_redirect_amp(bytes, i) {
+            // & (background)
+            parts.push(&cmd[last..i]);
+            i += 1;
+            last = i;
+        } else if bytes[i] == b';' || bytes[i] == b'\n' {
             parts.push(&cmd[last..i]);
             i += 1;
             last = i;
@@ -103,6 +140,11 @@ fn split_command_operators(cmd: &str) -> Vec<&str> {
     parts
 }
 
+/// Whether the `&` at `i` belongs to a redirect (`>&`, `<&`, `&>`)
+fn is_redirect_amp(bytes: &[u8], i: usize) -> bool {
+    (i > 0 && matches!(bytes[i - 1], b'>' | b'<')) || bytes.get(i + 1) == Some(&b'>')
+}
+
 fn classify_single(cmd: &str) -> CommandTier {
     let trimmed = cmd.trim();
 
@@ -199,46 +241,60 @@ fn skip_env_flags<'a>(wrapper: &str, rest: &'a str) -> &'a str {
     remainder
 }
 
-async fn execute_shell(command: &str, cwd: &str) -> String {
+/// Run `command`, streaming its output as progress while collecting it for the result
+async fn execute_shell(command: &str, cwd: &str, progress: Option<ToolProgress>) -> String {
     let shell = if cfg!(target_os = "windows") { "cmd" } else { "sh" };
     let arg = if cfg!(target_os = "windows") { "/C" } else { "-c" };
 
-    let child = match tokio::process::Command::new(shell)
+    let mut child = match tokio::process::Command::new(shell)
         .arg(arg)
         .arg(command)
         .current_dir(cwd)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
         .spawn()
     {
         Ok(c) => c,
         Err(e) => return format!("Failed to execute command: {}", e),
     };
 
-    // 30s timeout — wait_with_output consumes child, so timeout handles kill implicitly
-    // (dropping child kills process on Unix)
-    match tokio::time::timeout(Duration::from_secs(30), child.wait_with_output()).await {
-        Ok(Ok(output)) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if output.status.success() {
-                if stdout.is_empty() && stderr.is_empty() {
-                    "(command completed successfully, no output)".to_string()
-                } else if stderr.is_empty() {
-                    stdout.to_string()
-                } else {
-                    format!("{}\n[stderr]: {}", stdout, stderr)
-                }
-            } else {
-                format!("Command failed (exit code: {:?}):\n{}{}",
-                    output.status.code(), stdout, stderr)
-            }
-        }
-        Ok(Err(e)) => format!("Command execution error: {}", e),
+    // Drain both pipes while waiting, so a chatty command can't fill a pipe buffer and stall
+    let stdout_task = child.stdout.take()
+        .map(|out| tokio::spawn(read_streamed(out, progress.clone(), ProgressStream::Stdout)));
+    let stderr_task = child.stderr.take()
+        .map(|err| tokio::spawn(read_streamed(err, progress, ProgressStream::Stderr)));
+
+    let status = match tokio::time::timeout(Duration::from_secs(30), child.wait()).await {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => return format!("Command execution error: {}", e),
         Err(_) => {
-            // Timeout — dropping the future drops the child, which kills it on Unix.
-            "Command timed out (30s limit). Process terminated.".to_string()
+            let _ = child.kill().await;
+            return "Command timed out (30s limit). Process terminated.".to_string();
         }
+    };
+
+    let stdout = match stdout_task {
+        Some(task) => task.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let stderr = match stderr_task {
+        Some(task) => task.await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let stdout = String::from_utf8_lossy(&stdout);
+    let stderr = String::from_utf8_lossy(&stderr);
+    if status.success() {
+        if stdout.is_empty() && stderr.is_empty() {
+            "(command completed successfully, no output)".to_string()
+        } else if stderr.is_empty() {
+            stdout.to_string()
+        } else {
+            format!("{}\n[stderr]: {}", stdout, stderr)
+        }
+    } else {
+        format!("Command failed (exit code: {:?}):\n{}{}",
+            status.code(), stdout, stderr)
     }
 }
 
@@ -271,61 +327,37 @@ impl ToolPlugin for RunShellTool {
         if cwd.is_empty() {
             return Ok(ToolOutput::error("No workspace directory is open. Open a directory first before running shell commands.".to_string()));
         }
-        let tier = classify_command(command);
-
-        match tier {
-            CommandTier::Blocked => {
-                Ok(ToolOutput::error(format!(
-                    "Command blocked for security: '{}'. This command is not allowed.",
-                    command
-                )))
-            }
-            CommandTier::AutoAllow => {
-                let result = execute_shell(command, cwd).await;
-                Ok(ToolOutput::success(result))
-            }
-            CommandTier::NeedConfirm => {
-                // Request user confirmation via frontend dialog
-                let app = &ctx.app_handle;
-
-                // Create a oneshot channel for the response
-                let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
-
-                // Store the sender in managed state
-                {
-                    let state = app.state::<crate::ai::ShellConfirmState>();
-                    let mut sender = state.sender.lock().map_err(|e|
-                        ToolError::ExecutionFailed(format!("Lock error: {}", e))
-                    )?;
-                    *sender = Some(tx);
-                }
+        if let Err(e) = approve_command(ctx, self.name(), command).await {
+            return Ok(ToolOutput::error(e));
+        }
+        let result = execute_shell(command, cwd, ctx.progress.clone()).await;
+        Ok(ToolOutput::success(result))
+    }
+}
 
-                // Emit event to frontend
-                let _ = app.emit("shell-confirm", serde_json::json!({
-                    "command": command,
-                }));
-
-                // Wait for response with 60s timeout
-                match tokio::time::timeout(Duration::from_secs(60), rx).await {
-                    Ok(Ok(true)) => {
-                        let result = execute_shell(command, cwd).await;
-                        Ok(ToolOutput::success(result))
-                    }
-                    Ok(Ok(false)) => {
-                        Ok(ToolOutput::error("Command denied by user.".to_string()))
-                    }
-                    Ok(Err(_)) => {
-                        Ok(ToolOutput::error("Confirmation channel closed unexpectedly.".to_string()))
-                    }
-                    Err(_) => {
-                        // Timeout — clean up sender
-                        let state = app.state::<crate::ai::ShellConfirmState>();
-                        if let Ok(mut sender) = state.sender.lock() {
-                            *sender = None;
-                        }
-                        Ok(ToolOutput::error("Command confirmation timed out (60s). Command not executed.".to_string()))
-                    }
-                }
+/// Tool name that command rules are stored under, for every tool that runs shell commands
+const SHELL_RULE_TOOL: &str = "run_shell";
+
+/// Check `command` against the default tiers and the `run_shell` permission
+/// rules, asking the user when needed. `tool` only labels the confirmation.
+/// Err explains why it may not run.
+pub(crate) async fn approve_command(ctx: &ToolContext, tool: &str, command: &str) -> Result<(), String> {
+    let tier = resolve_tier(command, &|base| {
+        ctx.permissions.decide(&ctx.workspace_path, &PermissionRequest { tool: SHELL_RULE_TOOL, command: Some(base), path: None })
+    });
+
+    match tier {
+        CommandTier::Blocked => Err(format!(
+            "Command blocked for security: '{}'. This command is not allowed by the default policy or a permission rule.",
+            command
+        )),
+        CommandTier::AutoAllow => Ok(()),
+        CommandTier::NeedConfirm => {
+            // Request user confirmation from the host (dialog in the app, policy in the CLI)
+            match ctx.sink.confirm_command(&ConfirmRequest::new(&ctx.session_id, tool, command)).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("Command denied by user.".to_string()),
+                Err(e) => Err(e),
             }
         }
     }
@@ -493,4 +525,78 @@ mod tests {
         // "env ls" should unwrap to "ls" -> AutoAllow
         assert_eq!(classify_command("env ls"), CommandTier::AutoAllow);
     }
+
+    // --- permission rule tests ---
+
+    fn rules(base: &str) -> Option<Decision> {
+        if base.starts_with("cargo test") {
+            Some(Decision::Allow)
+        } else if base.starts_with("git status") {
+            Some(Decision::Deny)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_rules_override_default_tiers() {
+        assert_eq!(resolve_tier("cargo test --release", &rules), CommandTier::AutoAllow);
+        assert_eq!(resolve_tier("/usr/bin/cargo test", &rules), CommandTier::AutoAllow);
+        assert_eq!(resolve_tier("git status", &rules), CommandTier::Blocked);
+        assert_eq!(resolve_tier("cargo build", &rules), CommandTier::NeedConfirm);
+    }
+
+    #[test]
+    fn test_rules_apply_per_chained_part() {
+        assert_eq!(resolve_tier("cargo test && ls", &rules), CommandTier::AutoAllow);
+        assert_eq!(resolve_tier("cargo test && rm -rf target", &rules), CommandTier::Blocked);
+        assert_eq!(resolve_tier("cargo test $(whoami)", &rules), CommandTier::NeedConfirm);
+    }
+
+    #[test]
+    fn test_rules_do_not_cover_background_or_newline_parts() {
+        assert_eq!(resolve_tier("cargo test & rm -rf ~", &rules), CommandTier::Blocked);
+        assert_eq!(resolve_tier("cargo test\nrm -rf x", &rules), CommandTier::Blocked);
+        assert_eq!(resolve_tier("cargo test &\nmake", &rules), CommandTier::NeedConfirm);
+        assert_eq!(resolve_tier("cargo test > out.txt", &rules), CommandTier::NeedConfirm);
+        assert_eq!(resolve_tier("cargo test 2>&1", &rules), CommandTier::NeedConfirm);
+        assert_eq!(resolve_tier("cargo test &> /etc/passwd", &rules), CommandTier::NeedConfirm);
+    }
+
+    #[test]
+    fn test_rules_cannot_unblock_commands() {
+        let allow_all = |_: &str| Some(Decision::Allow);
+        assert_eq!(resolve_tier("rm -rf target", &allow_all), CommandTier::Blocked);
+        assert_eq!(resolve_tier("sudo ls", &allow_all), CommandTier::Blocked);
+        assert_eq!(resolve_tier("ls & rm -rf ~", &allow_all), CommandTier::Blocked);
+        assert_eq!(resolve_tier("make", &allow_all), CommandTier::AutoAllow);
+    }
+
+    #[test]
+    fn test_split_on_background_and_newline() {
+        assert_eq!(split_command_operators("a & b\nc"), vec!["a ", " b", "c"]);
+        assert_eq!(split_command_operators("a 2>&1 &> log"), vec!["a 2>&1 &> log"]);
+    }
+
+    #[tokio::test]
+    async fn test_start_job_uses_run_shell_rules() {
+        use crate::ai::permission::{PermissionRule, RuleScope};
+        let (_dir, ctx) = crate::ai::tool::registry::tests::test_context();
+        let rule = |command: &str, decision| PermissionRule {
+            id: String::new(),
+            tool: SHELL_RULE_TOOL.into(),
+            command: Some(command.into()),
+            path: None,
+            decision,
+            created_at: 0,
+        };
+        ctx.permissions.add(RuleScope::Global, None, rule("npm run", Decision::Allow)).unwrap();
+        ctx.permissions.add(RuleScope::Global, None, rule("ls", Decision::Deny)).unwrap();
+
+        // The test context denies every confirmation, so Ok means a rule allowed it
+        assert!(approve_command(&ctx, "start_job", "npm run dev").await.is_ok());
+        let denied = approve_command(&ctx, "start_job", "ls").await.unwrap_err();
+        assert!(denied.contains("blocked"), "{}", denied);
+        assert!(approve_command(&ctx, "start_job", "make watch").await.is_err());
+    }
 }
@@ -1,9 +1,7 @@
 use async_trait::async_trait;
 use serde_json::Value;
-use tauri::Manager;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
 use crate::ai::memory::{Memory, MemoryMetadata, MemoryType};
-use crate::ai::MemoryStoreState;
 
 pub struct SaveMemoryTool;
 
@@ -85,8 +83,7 @@ impl ToolPlugin for SaveMemoryTool {
             access_count: 0,
         };
 
-        let memory_store_state = ctx.app_handle.state::<MemoryStoreState>();
-        match memory_store_state.store.save(memory.clone()).await {
+        match ctx.memory_store.save(memory.clone()).await {
             Ok(id) => Ok(ToolOutput::success(format!(
                 "Memory saved successfully (ID: {})\nType: {}\nImportance: {:.2}",
                 id, memory_type_str, importance
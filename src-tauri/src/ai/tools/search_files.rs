@@ -20,6 +20,7 @@ impl ToolPlugin for SearchFilesTool {
             "required": ["dir", "query"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let raw_dir = input["dir"].as_str().unwrap_or(".");
         let dir = match sandbox_path(raw_dir, &ctx.workspace_path) {
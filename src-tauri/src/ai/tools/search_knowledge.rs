@@ -18,9 +18,12 @@ impl ToolPlugin for SearchKnowledgeTool {
             "required": ["query"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let query = input["query"].as_str().unwrap_or("");
-        let bm25_state = ctx.app_handle.state::<crate::bm25::Bm25State>();
+        let Some(bm25_state) = ctx.app_handle.as_ref().and_then(|app| app.try_state::<crate::bm25::Bm25State>()) else {
+            return Ok(ToolOutput::success("Search index not initialized. Open a directory first.".to_string()));
+        };
         let guard = bm25_state.index.lock().map_err(|e|
             ToolError::ExecutionFailed(format!("BM25 index lock poisoned: {}", e))
         )?;
@@ -1,8 +1,6 @@
 use async_trait::async_trait;
 use serde_json::Value;
-use tauri::Manager;
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
-use crate::ai::MemoryStoreState;
 
 pub struct SearchMemoryTool;
 
@@ -33,6 +31,7 @@ impl ToolPlugin for SearchMemoryTool {
         })
     }
 
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let query = input["query"].as_str()
             .ok_or_else(|| ToolError::MissingArgument("query".to_string()))?;
@@ -41,8 +40,7 @@ impl ToolPlugin for SearchMemoryTool {
             .unwrap_or(5)
             .min(20) as usize;
 
-        let memory_store_state = ctx.app_handle.state::<MemoryStoreState>();
-        match memory_store_state.store.search(query, limit).await {
+        match ctx.memory_store.search(query, limit).await {
             Ok(memories) => {
                 if memories.is_empty() {
                     Ok(ToolOutput::success("No matching memories found.".to_string()))
@@ -0,0 +1,44 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+use crate::ai::tools::run_shell::approve_command;
+
+pub struct StartJobTool;
+
+#[async_trait]
+impl ToolPlugin for StartJobTool {
+    fn name(&self) -> &str { "start_job" }
+    fn description(&self) -> &str {
+        "Start a long-running shell command in the background, e.g. a dev server, a watcher or a long test suite, and return its job id. Read its output with job_output and end it with stop_job. Use run_shell for commands that finish quickly. The same approval rules as run_shell apply."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": { "type": "string", "description": "Shell command to run in the workspace directory" }
+            },
+            "required": ["command"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let command = input["command"].as_str()
+            .ok_or_else(|| ToolError::MissingArgument("command".into()))?;
+        if command.trim().is_empty() {
+            return Ok(ToolOutput::error("No command provided.".to_string()));
+        }
+        if ctx.workspace_path.is_empty() {
+            return Ok(ToolOutput::error("No workspace directory is open. Open a directory first before running shell commands.".to_string()));
+        }
+        if let Err(e) = approve_command(ctx, self.name(), command).await {
+            return Ok(ToolOutput::error(e));
+        }
+        match ctx.jobs.start(&ctx.session_id, command, &ctx.workspace_path) {
+            Ok(job) => Ok(ToolOutput::success(format!(
+                "Started job {}: {}\nRead its output with job_output (job_id \"{}\") and stop it with stop_job when done.",
+                job.id, job.command, job.id
+            ))),
+            Err(e) => Ok(ToolOutput::error(e)),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::jobs::JobStatus;
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+
+pub struct StopJobTool;
+
+#[async_trait]
+impl ToolPlugin for StopJobTool {
+    fn name(&self) -> &str { "stop_job" }
+    fn description(&self) -> &str {
+        "Stop a background job started with start_job, including the processes it started."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "job_id": { "type": "string", "description": "Id returned by start_job" }
+            },
+            "required": ["job_id"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let job_id = input["job_id"].as_str()
+            .ok_or_else(|| ToolError::MissingArgument("job_id".into()))?;
+        match ctx.jobs.stop(&ctx.session_id, job_id) {
+            Ok(JobStatus::Stopped) => Ok(ToolOutput::success(format!("Stopped job {}.", job_id))),
+            Ok(status) => Ok(ToolOutput::success(format!("Job {} already {}.", job_id, status))),
+            Err(e) => Ok(ToolOutput::error(e)),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::todo::render;
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+
+pub struct TodoReadTool;
+
+#[async_trait]
+impl ToolPlugin for TodoReadTool {
+    fn name(&self) -> &str { "todo_read" }
+    fn description(&self) -> &str {
+        "Show the current task list with the status of each item."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({ "type": "object", "properties": {} })
+    }
+
+    fn is_concurrency_safe(&self) -> bool { true }
+    async fn execute(&self, ctx: &ToolContext, _input: Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::success(render(&ctx.todos.items())))
+    }
+}
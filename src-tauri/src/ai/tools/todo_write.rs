@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::ai::streaming::AiStreamEvent;
+use crate::ai::todo::{render, TodoItem};
+use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
+
+pub struct TodoWriteTool;
+
+#[async_trait]
+impl ToolPlugin for TodoWriteTool {
+    fn name(&self) -> &str { "todo_write" }
+    fn description(&self) -> &str {
+        "Create or update the task list for multi-step work. Send the complete list every time; it replaces the previous one. Keep exactly one item in_progress while working and mark items done as soon as they are finished. The user sees the list live. Skip it for simple one-step requests."
+    }
+    fn input_schema(&self) -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "todos": {
+                    "type": "array",
+                    "description": "The full task list, in order",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "id": { "type": "string", "description": "Short stable id, e.g. \"1\"" },
+                            "text": { "type": "string", "description": "What this step does" },
+                            "status": { "type": "string", "enum": ["pending", "in_progress", "done"] }
+                        },
+                        "required": ["id", "text", "status"]
+                    }
+                }
+            },
+            "required": ["todos"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
+        let items: Vec<TodoItem> = serde_json::from_value(input["todos"].clone())
+            .map_err(|e| ToolError::InvalidArgument(format!("todos: {}", e)))?;
+        if let Err(e) = ctx.todos.replace(items.clone()) {
+            return Ok(ToolOutput::error(format!("Task list not updated: {}", e)));
+        }
+        ctx.sink.emit(AiStreamEvent {
+            session_id: ctx.session_id.clone(),
+            event_type: "todos".into(),
+            content: serde_json::to_string(&items).unwrap_or_default(),
+        });
+        Ok(ToolOutput::success(render(&items)))
+    }
+}
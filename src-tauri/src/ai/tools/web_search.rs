@@ -18,6 +18,7 @@ impl ToolPlugin for WebSearchTool {
             "required": ["query"]
         })
     }
+    fn is_concurrency_safe(&self) -> bool { true }
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let query = input["query"].as_str().unwrap_or("");
         if query.trim().is_empty() {
@@ -1,6 +1,7 @@
 use std::fs;
 use async_trait::async_trait;
 use serde_json::Value;
+use crate::ai::permission::{self, PermissionRequest};
 use crate::ai::tool::{ToolPlugin, ToolContext, ToolOutput, ToolError};
 use crate::ai::sandbox_path;
 
@@ -23,6 +24,11 @@ impl ToolPlugin for WriteFileTool {
     async fn execute(&self, ctx: &ToolContext, input: Value) -> Result<ToolOutput, ToolError> {
         let raw_path = input["path"].as_str().unwrap_or("");
         let content = input["content"].as_str().unwrap_or("");
+        let rel_path = permission::relative_path(raw_path, &ctx.workspace_path);
+        let request = PermissionRequest { tool: self.name(), command: None, path: Some(&rel_path) };
+        if let Err(e) = permission::check(ctx, &request).await {
+            return Ok(ToolOutput::error(e));
+        }
         let result = write_file_tool(raw_path, content, &ctx.workspace_path);
         Ok(ToolOutput::success(result))
     }
@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::AiConfig;
+use crate::app_warn;
+
+/// Price of a model in USD per million tokens
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    /// Price of prompt tokens read from the provider's cache; None uses the input price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input_per_million: Option<f64>,
+}
+
+/// Token usage reported by the API for one request
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenUsage {
+    /// All prompt tokens, cached ones included
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Prompt tokens read from the provider's prompt cache
+    pub cached_tokens: u64,
+}
+
+/// One ledger line: usage of a single model round
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UsageRecord {
+    /// Unix timestamp (seconds)
+    pub timestamp: i64,
+    pub session_id: String,
+    pub round: u32,
+    pub model: String,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Part of prompt_tokens served from the prompt cache
+    #[serde(default)]
+    pub cached_tokens: u64,
+    /// USD, computed with the prices configured at the time of the request
+    pub cost: f64,
+    /// Set for requests outside the agent rounds: "compaction", "distill"
+    /// or "subagent"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// For sub-agent rounds, the id of the delegate_task call that started
+    /// the sub-agent; `round` counts within that sub-agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subagent: Option<String>,
+}
+
+/// Aggregated usage for one group (day, workspace, model or session)
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct UsageTotal {
+    pub key: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cached_tokens: u64,
+    pub cost: f64,
+}
+
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+fn ledger_path(data_dir: &Path) -> PathBuf {
+    fs::create_dir_all(data_dir).ok();
+    data_dir.join("usage-ledger.jsonl")
+}
+
+/// Price for a model: exact id first, then the longest configured prefix
+/// (e.g. "gpt-4o" also prices "gpt-4o-2024-08-06").
+pub fn price_for<'a>(prices: &'a HashMap<String, ModelPrice>, model: &str) -> Option<&'a ModelPrice> {
+    prices.get(model).or_else(|| {
+        prices.iter()
+            .filter(|(k, _)| !k.is_empty() && model.starts_with(k.as_str()))
+            .max_by_key(|(k, _)| k.len())
+            .map(|(_, p)| p)
+    })
+}
+
+pub fn cost_of(usage: &TokenUsage, price: Option<&ModelPrice>) -> f64 {
+    match price {
+        Some(p) => {
+            let cached = usage.cached_tokens.min(usage.prompt_tokens);
+            ((usage.prompt_tokens - cached) as f64 * p.input_per_million
+                + cached as f64 * p.cached_input_per_million.unwrap_or(p.input_per_million)
+                + usage.completion_tokens as f64 * p.output_per_million) / 1_000_000.0
+        }
+        None => 0.0,
+    }
+}
+
+/// Build the ledger record for one round
+pub fn make_record(
+    config: &AiConfig,
+    session_id: &str,
+    round: u32,
+    workspace: Option<&str>,
+    usage: &TokenUsage,
+) -> UsageRecord {
+    UsageRecord {
+        timestamp: chrono::Utc::now().timestamp(),
+        session_id: session_id.to_string(),
+        round,
+        model: config.model.clone(),
+        workspace: workspace.filter(|w| !w.is_empty()).map(|w| w.to_string()),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        cached_tokens: usage.cached_tokens,
+        cost: cost_of(usage, price_for(&config.model_prices, &config.model)),
+        kind: None,
+        subagent: None,
+    }
+}
+
+/// Where a background completion (compaction, distill) records its usage
+#[derive(Clone, Debug)]
+pub struct UsageTag {
+    pub data_dir: PathBuf,
+    pub session_id: String,
+    /// The agent round the request was made in
+    pub round: u32,
+    pub workspace: Option<String>,
+    /// Ledger kind, see `UsageRecord::kind`
+    pub kind: &'static str,
+}
+
+impl UsageTag {
+    /// Append the usage of one completion to the ledger (best-effort)
+    pub fn record(&self, config: &AiConfig, usage: &TokenUsage) {
+        let mut entry = make_record(config, &self.session_id, self.round, self.workspace.as_deref(), usage);
+        entry.kind = Some(self.kind.to_string());
+        if let Err(e) = record(&self.data_dir, &entry) {
+            app_warn!("ai:usage", "failed to record {} usage: {}", self.kind, e);
+        }
+    }
+}
+
+/// Append a record to the persistent ledger
+pub fn record(data_dir: &Path, entry: &UsageRecord) -> Result<(), String> {
+    append_record(&ledger_path(data_dir), entry)
+}
+
+fn append_record(path: &Path, entry: &UsageRecord) -> Result<(), String> {
+    let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    let _guard = LEDGER_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open usage ledger: {}", e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write usage ledger: {}", e))
+}
+
+fn load_records(path: &Path) -> Vec<UsageRecord> {
+    let _guard = LEDGER_LOCK.lock();
+    fs::read_to_string(path)
+        .map(|data| data.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Group records by "day" (local date), "workspace", "model" or "session".
+/// `since` is an inclusive local date (YYYY-MM-DD).
+pub fn summarize(
+    records: &[UsageRecord],
+    group_by: &str,
+    since: Option<&str>,
+    workspace: Option<&str>,
+) -> Result<Vec<UsageTotal>, String> {
+    if !matches!(group_by, "day" | "workspace" | "model" | "session") {
+        return Err(format!("Unknown group_by '{}': expected day, workspace, model or session", group_by));
+    }
+    let since_ts = match since {
+        Some(day) => {
+            let date = chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map_err(|e| format!("Invalid date '{}': {}", day, e))?;
+            let start = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+            Some(start.and_local_timezone(chrono::Local).earliest()
+                .map(|t| t.timestamp())
+                .unwrap_or_else(|| start.and_utc().timestamp()))
+        }
+        None => None,
+    };
+
+    let mut groups: BTreeMap<String, UsageTotal> = BTreeMap::new();
+    for r in records {
+        if since_ts.map(|ts| r.timestamp < ts).unwrap_or(false) {
+            continue;
+        }
+        if workspace.is_some() && r.workspace.as_deref() != workspace {
+            continue;
+        }
+        let key = match group_by {
+            "day" => local_day(r.timestamp),
+            "workspace" => r.workspace.clone().unwrap_or_default(),
+            "model" => r.model.clone(),
+            _ => r.session_id.clone(),
+        };
+        let total = groups.entry(key.clone()).or_insert_with(|| UsageTotal { key, ..Default::default() });
+        total.requests += 1;
+        total.prompt_tokens += r.prompt_tokens;
+        total.completion_tokens += r.completion_tokens;
+        total.cached_tokens += r.cached_tokens;
+        total.cost += r.cost;
+    }
+    Ok(groups.into_values().collect())
+}
+
+fn local_day(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// Usage totals grouped by day, workspace, model or session
+#[tauri::command]
+pub fn ai_usage_summary(
+    group_by: String,
+    since: Option<String>,
+    workspace: Option<String>,
+) -> Result<Vec<UsageTotal>, String> {
+    let records = load_records(&ledger_path(&crate::app_data_dir().join("inkess")));
+    summarize(&records, &group_by, since.as_deref(), workspace.as_deref())
+}
+
+/// Per-round usage records of one session
+#[tauri::command]
+pub fn ai_usage_session(session_id: String) -> Vec<UsageRecord> {
+    load_records(&ledger_path(&crate::app_data_dir().join("inkess")))
+        .into_iter()
+        .filter(|r| r.session_id == session_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(timestamp: i64, session: &str, model: &str, workspace: Option<&str>, prompt: u64, completion: u64, cost: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp,
+            session_id: session.into(),
+            round: 0,
+            model: model.into(),
+            workspace: workspace.map(|s| s.to_string()),
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            cached_tokens: 0,
+            cost,
+            kind: None,
+            subagent: None,
+        }
+    }
+
+    #[test]
+    fn test_price_lookup_prefers_exact_then_longest_prefix() {
+        let mut prices = HashMap::new();
+        prices.insert("gpt-4o".to_string(), ModelPrice { input_per_million: 2.5, output_per_million: 10.0, cached_input_per_million: None });
+        prices.insert("gpt-4o-mini".to_string(), ModelPrice { input_per_million: 0.15, output_per_million: 0.6, cached_input_per_million: None });
+        assert_eq!(price_for(&prices, "gpt-4o").unwrap().input_per_million, 2.5);
+        assert_eq!(price_for(&prices, "gpt-4o-mini-2024-07-18").unwrap().input_per_million, 0.15);
+        assert_eq!(price_for(&prices, "gpt-4o-2024-08-06").unwrap().input_per_million, 2.5);
+        assert!(price_for(&prices, "claude-sonnet-4-5").is_none());
+    }
+
+    #[test]
+    fn test_cost_of() {
+        let usage = TokenUsage { prompt_tokens: 1_000_000, completion_tokens: 500_000, cached_tokens: 0 };
+        let price = ModelPrice { input_per_million: 2.0, output_per_million: 8.0, cached_input_per_million: None };
+        assert!((cost_of(&usage, Some(&price)) - 6.0).abs() < 1e-9);
+        assert_eq!(cost_of(&usage, None), 0.0);
+
+        // Cached prompt tokens use the cached price, or the input price without one
+        let cached = TokenUsage { cached_tokens: 800_000, ..usage };
+        assert!((cost_of(&cached, Some(&price)) - 6.0).abs() < 1e-9);
+        let price = ModelPrice { cached_input_per_million: Some(0.5), ..price };
+        assert!((cost_of(&cached, Some(&price)) - (0.4 + 0.4 + 4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_append_and_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.jsonl");
+        append_record(&path, &rec(100, "s1", "m", Some("/ws"), 10, 5, 0.1)).unwrap();
+        append_record(&path, &rec(200, "s2", "m", None, 20, 7, 0.2)).unwrap();
+        // A corrupt line is skipped, not fatal
+        fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{broken\n").unwrap();
+        let records = load_records(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].workspace.as_deref(), Some("/ws"));
+        assert_eq!(records[1].completion_tokens, 7);
+    }
+
+    #[test]
+    fn test_summarize_by_workspace_and_model() {
+        let records = vec![
+            rec(100, "s1", "gpt-4o", Some("/a"), 10, 5, 0.1),
+            rec(200, "s1", "gpt-4o", Some("/a"), 20, 5, 0.2),
+            rec(300, "s2", "claude", Some("/b"), 30, 5, 0.3),
+        ];
+        let by_ws = summarize(&records, "workspace", None, None).unwrap();
+        assert_eq!(by_ws.len(), 2);
+        assert_eq!(by_ws[0].key, "/a");
+        assert_eq!(by_ws[0].requests, 2);
+        assert_eq!(by_ws[0].prompt_tokens, 30);
+        assert!((by_ws[0].cost - 0.3).abs() < 1e-9);
+
+        let by_model = summarize(&records, "model", None, Some("/b")).unwrap();
+        assert_eq!(by_model, vec![UsageTotal { key: "claude".into(), requests: 1, prompt_tokens: 30, completion_tokens: 5, cached_tokens: 0, cost: 0.3 }]);
+    }
+
+    #[test]
+    fn test_summarize_by_day_with_since() {
+        let day1 = chrono::NaiveDate::from_ymd_opt(2025, 3, 1).unwrap().and_hms_opt(12, 0, 0).unwrap()
+            .and_local_timezone(chrono::Local).earliest().unwrap().timestamp();
+        let day2 = day1 + 24 * 3600;
+        let records = vec![rec(day1, "s", "m", None, 1, 1, 0.0), rec(day2, "s", "m", None, 2, 2, 0.0)];
+        let all = summarize(&records, "day", None, None).unwrap();
+        assert_eq!(all.iter().map(|t| t.key.as_str()).collect::<Vec<_>>(), vec!["2025-03-01", "2025-03-02"]);
+        let recent = summarize(&records, "day", Some("2025-03-02"), None).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].prompt_tokens, 2);
+    }
+
+    #[test]
+    fn test_summarize_rejects_bad_input() {
+        assert!(summarize(&[], "week", None, None).is_err());
+        assert!(summarize(&[], "day", Some("yesterday"), None).is_err());
+    }
+}
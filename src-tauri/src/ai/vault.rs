@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use argon2::Argon2;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::app_warn;
+
+/// Prefix of a reference to a stored secret, e.g. `vault:api_key`. Config files
+/// and the frontend only ever see references, never the keys themselves.
+pub const SECRET_REF_PREFIX: &str = "vault:";
+
+const KEYRING_SERVICE: &str = "inkess";
+const KEYRING_USER: &str = "ai-key-vault";
+const VAULT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+pub const LOCKED_ERROR: &str = "API keys are locked. Enter your vault passphrase in the AI settings.";
+pub const PASSPHRASE_REQUIRED_ERROR: &str = "No system keyring is available. Set a vault passphrase in the AI settings to store API keys.";
+
+/// Secrets by name (the part after `vault:`)
+pub type Secrets = HashMap<String, String>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protection {
+    /// Random key kept in the OS keyring (Keychain, Credential Manager, Secret Service)
+    Keyring,
+    /// Key derived from a user passphrase with Argon2id
+    Passphrase,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct VaultStatus {
+    /// None until the first key is stored
+    pub protection: Option<Protection>,
+    /// Passphrase-protected and not unlocked in this process
+    pub locked: bool,
+    pub keyring_available: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    protection: Protection,
+    /// Hex; passphrase vaults only
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    /// Hex
+    nonce: String,
+    /// Base64 of the encrypted JSON secrets
+    ciphertext: String,
+}
+
+/// Encrypted store for API keys (ChaCha20-Poly1305), next to the AI config
+pub struct Vault {
+    path: PathBuf,
+    use_keyring: bool,
+    /// (salt, key) of a passphrase vault once unlocked, or of one about to be created
+    passphrase_key: Mutex<Option<(Vec<u8>, [u8; 32])>>,
+}
+
+/// The app's vault in the data dir
+pub fn vault() -> &'static Vault {
+    static VAULT: OnceLock<Vault> = OnceLock::new();
+    VAULT.get_or_init(|| {
+        let dir = crate::app_data_dir().join("inkess");
+        fs::create_dir_all(&dir).ok();
+        Vault::new(dir.join("ai-keys.vault"), true)
+    })
+}
+
+pub fn is_secret_ref(value: &str) -> bool {
+    value.starts_with(SECRET_REF_PREFIX)
+}
+
+pub fn secret_ref(name: &str) -> String {
+    format!("{}{}", SECRET_REF_PREFIX, name)
+}
+
+impl Vault {
+    pub fn new(path: PathBuf, use_keyring: bool) -> Self {
+        Self { path, use_keyring, passphrase_key: Mutex::new(None) }
+    }
+
+    pub fn status(&self) -> VaultStatus {
+        let protection = read_file(&self.path).ok().flatten().map(|f| f.protection);
+        VaultStatus {
+            protection,
+            locked: protection == Some(Protection::Passphrase) && self.session_key().is_none(),
+            keyring_available: self.use_keyring && keyring_available(),
+        }
+    }
+
+    /// Decrypt the stored secrets; empty if nothing was stored yet
+    pub fn read(&self) -> Result<Secrets, String> {
+        let Some(file) = read_file(&self.path)? else {
+            return Ok(Secrets::new());
+        };
+        let key = match file.protection {
+            Protection::Keyring => keyring_key(false)?,
+            Protection::Passphrase => self.session_key().map(|(_, key)| key).ok_or(LOCKED_ERROR)?,
+        };
+        decrypt(&file, &key)
+    }
+
+    /// Encrypt and store `secrets`, replacing what was stored. A new vault uses
+    /// the OS keyring when it works and otherwise the passphrase given to `unlock`.
+    pub fn write(&self, secrets: &Secrets) -> Result<(), String> {
+        let existing = read_file(&self.path)?;
+        let (protection, salt, key) = match existing.map(|f| f.protection) {
+            Some(Protection::Keyring) => (Protection::Keyring, Vec::new(), keyring_key(false)?),
+            Some(Protection::Passphrase) => {
+                let (salt, key) = self.session_key().ok_or(LOCKED_ERROR)?;
+                (Protection::Passphrase, salt, key)
+            }
+            None => match self.new_keyring_key() {
+                Some(key) => (Protection::Keyring, Vec::new(), key),
+                None => {
+                    let (salt, key) = self.session_key().ok_or(PASSPHRASE_REQUIRED_ERROR)?;
+                    (Protection::Passphrase, salt, key)
+                }
+            },
+        };
+
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+        let ciphertext = cipher.encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| "Failed to encrypt API keys".to_string())?;
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            protection,
+            salt: hex::encode(&salt),
+            nonce: hex::encode(nonce),
+            ciphertext: base64::engine::general_purpose::STANDARD.encode(ciphertext),
+        };
+        let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        write_private(&self.path, &json).map_err(|e| format!("Failed to save API keys: {}", e))
+    }
+
+    /// Unlock a passphrase vault for this process. Without a vault yet, the
+    /// passphrase protects the one created on the next write.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        if passphrase.is_empty() {
+            return Err("Passphrase must not be empty".into());
+        }
+        let salt = match read_file(&self.path)? {
+            Some(file) if file.protection == Protection::Keyring => return Ok(()),
+            Some(file) => {
+                let salt = hex::decode(&file.salt).map_err(|_| "Key vault is corrupted (salt)".to_string())?;
+                let key = derive_key(passphrase, &salt)?;
+                decrypt(&file, &key).map_err(|_| "Wrong passphrase".to_string())?;
+                *self.passphrase_key.lock().unwrap_or_else(|e| e.into_inner()) = Some((salt, key));
+                return Ok(());
+            }
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+        let key = derive_key(passphrase, &salt)?;
+        *self.passphrase_key.lock().unwrap_or_else(|e| e.into_inner()) = Some((salt, key));
+        Ok(())
+    }
+
+    fn session_key(&self) -> Option<(Vec<u8>, [u8; 32])> {
+        self.passphrase_key.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn new_keyring_key(&self) -> Option<[u8; 32]> {
+        if !self.use_keyring {
+            return None;
+        }
+        match keyring_key(true) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                app_warn!("ai:vault", "OS keyring unavailable, falling back to a passphrase: {}", e);
+                None
+            }
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Option<VaultFile>, String> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read key vault: {}", e)),
+    };
+    let file: VaultFile = serde_json::from_str(&data).map_err(|e| format!("Key vault is corrupted: {}", e))?;
+    if file.version > VAULT_VERSION {
+        return Err(format!("Key vault version {} is newer than this app supports", file.version));
+    }
+    Ok(Some(file))
+}
+
+fn decrypt(file: &VaultFile, key: &[u8; 32]) -> Result<Secrets, String> {
+    let nonce = hex::decode(&file.nonce).map_err(|_| "Key vault is corrupted (nonce)".to_string())?;
+    if nonce.len() != 12 {
+        return Err("Key vault is corrupted (nonce)".into());
+    }
+    let ciphertext = base64::engine::general_purpose::STANDARD.decode(&file.ciphertext)
+        .map_err(|_| "Key vault is corrupted (data)".to_string())?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Failed to decrypt API keys".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|e| format!("Key vault is corrupted: {}", e))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {}", e))?;
+    Ok(key)
+}
+
+/// The vault key from the OS keyring. With `create`, a missing key is
+/// generated and stored, and read back to make sure the keyring persists it.
+fn keyring_key(create: bool) -> Result<[u8; 32], String> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| e.to_string())?;
+    let stored = match entry.get_password() {
+        Ok(stored) => stored,
+        Err(keyring::Error::NoEntry) if create => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            entry.set_password(&hex::encode(key)).map_err(|e| e.to_string())?;
+            keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+                .and_then(|e| e.get_password())
+                .map_err(|e| format!("keyring did not keep the key: {}", e))?
+        }
+        Err(e) => return Err(format!("Failed to read the vault key from the OS keyring: {}", e)),
+    };
+    let bytes = hex::decode(stored.trim()).map_err(|_| "Vault key in the OS keyring is invalid".to_string())?;
+    bytes.try_into().map_err(|_| "Vault key in the OS keyring is invalid".to_string())
+}
+
+fn keyring_available() -> bool {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).and_then(|e| e.get_password()) {
+        Ok(_) => true,
+        // The store works, there is just no key yet
+        Err(keyring::Error::NoEntry) => true,
+        Err(_) => false,
+    }
+}
+
+/// Write a file readable by the current user only, replacing it atomically
+fn write_private(path: &Path, data: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets(pairs: &[(&str, &str)]) -> Secrets {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn passphrase_vault_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ai-keys.vault");
+        let vault = Vault::new(path.clone(), false);
+        let keys = secrets(&[("api_key", "sk-secret"), ("provider:https://api.deepseek.com", "sk-ds")]);
+
+        assert_eq!(vault.write(&keys).unwrap_err(), PASSPHRASE_REQUIRED_ERROR);
+        vault.unlock("correct horse").unwrap();
+        vault.write(&keys).unwrap();
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("sk-secret"));
+        assert_eq!(vault.read().unwrap(), keys);
+
+        // A new process has to unlock first, and only with the right passphrase
+        let reopened = Vault::new(path, false);
+        assert!(reopened.status().locked);
+        assert_eq!(reopened.read().unwrap_err(), LOCKED_ERROR);
+        assert_eq!(reopened.unlock("wrong").unwrap_err(), "Wrong passphrase");
+        reopened.unlock("correct horse").unwrap();
+        assert_eq!(reopened.read().unwrap(), keys);
+        assert_eq!(reopened.status(), VaultStatus {
+            protection: Some(Protection::Passphrase),
+            locked: false,
+            keyring_available: false,
+        });
+    }
+
+    #[test]
+    fn tampered_vault_fails_to_decrypt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ai-keys.vault");
+        let vault = Vault::new(path.clone(), false);
+        vault.unlock("pw").unwrap();
+        vault.write(&secrets(&[("api_key", "sk-secret")])).unwrap();
+
+        let mut file: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        file["nonce"] = serde_json::json!(hex::encode([0u8; 12]));
+        fs::write(&path, file.to_string()).unwrap();
+        assert!(vault.read().is_err());
+    }
+}
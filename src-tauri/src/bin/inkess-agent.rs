@@ -0,0 +1,5 @@
+// Headless agent: runs a prompt against a workspace without the GUI.
+// See `inkess-agent --help`.
+fn main() {
+    std::process::exit(app_lib::run_agent_cli())
+}
@@ -999,6 +999,12 @@ fn setup_menu(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Entry point of the headless `inkess-agent` binary; returns the exit code
+pub fn run_agent_cli() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    tauri::async_runtime::block_on(ai::headless::run(args))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let initial_file: Option<String> = std::env::args()
@@ -1033,15 +1039,15 @@ pub fn run() {
             health_check_handle: std::sync::Mutex::new(None),
         })
         .manage(ai::AiCancelRegistry::new())
-        .manage(ai::ShellConfirmState {
-            sender: std::sync::Mutex::new(None),
+        .manage(ai::ConfirmState {
+            registry: ai::confirm::ConfirmRegistry::new(),
         })
         .manage({
             let ai_tool_registry = ai::tool::registry::ToolRegistry::new();
             tauri::async_runtime::block_on(async {
                 ai::tools::register_builtin_tools(&ai_tool_registry).await;
             });
-            ai::AiToolRegistryState { registry: ai_tool_registry }
+            ai::AiToolRegistryState { registry: std::sync::Arc::new(ai_tool_registry) }
         })
         .manage({
             let ai_skill_registry = ai::skill::registry::SkillRegistry::new("default");
@@ -1069,6 +1075,39 @@ pub fn run() {
                 store: std::sync::Arc::new(memory_store),
             }
         })
+        .manage({
+            let sessions_dir = app_data_dir().join("inkess").join("sessions");
+            let session_store = ai::session::SessionStore::new(sessions_dir.clone())
+                .unwrap_or_else(|e| {
+                    safe_eprintln!("[session] Failed to initialize session store at {:?}: {}. Using temp fallback.", sessions_dir, e);
+                    ai::session::SessionStore::new(std::env::temp_dir().join("inkess-sessions"))
+                        .expect("Cannot create session store even in temp directory")
+                });
+            ai::SessionStoreState { store: session_store }
+        })
+        .manage({
+            let permissions_path = app_data_dir().join("inkess").join("permissions.json");
+            let permission_store = ai::permission::PermissionStore::new(permissions_path.clone())
+                .unwrap_or_else(|e| {
+                    safe_eprintln!("[permission] Failed to initialize permission store at {:?}: {}. Using temp fallback.", permissions_path, e);
+                    ai::permission::PermissionStore::new(std::env::temp_dir().join("inkess-permissions.json"))
+                        .expect("Cannot create permission store even in temp directory")
+                });
+            ai::PermissionStoreState { store: std::sync::Arc::new(permission_store) }
+        })
+        .manage({
+            let profiles_path = app_data_dir().join("inkess").join("ai-profiles.json");
+            let profile_store = ai::profile::ProfileStore::new(profiles_path.clone(), ai::vault::vault())
+                .unwrap_or_else(|e| {
+                    safe_eprintln!("[profile] Failed to initialize profile store at {:?}: {}. Using temp fallback.", profiles_path, e);
+                    ai::profile::ProfileStore::new(std::env::temp_dir().join("inkess-ai-profiles.json"), ai::vault::vault())
+                        .expect("Cannot create profile store even in temp directory")
+                });
+            ai::ProfileStoreState { store: std::sync::Arc::new(profile_store) }
+        })
+        .manage(ai::JobManagerState {
+            manager: std::sync::Arc::new(ai::jobs::JobManager::new()),
+        })
         .manage(bm25::Bm25State {
             index: Mutex::new(None),
         })
@@ -1085,9 +1124,15 @@ pub fn run() {
             git::git_commit, git::git_push, git::git_pull,
             git::git_remote_add, git::git_remote_list, git::git_log,
             git::git_config_user, git::setup_ssh_key,
-            ai::ai_save_config, ai::ai_load_config, ai::ai_test_connection, ai::ai_test_search, ai::ai_chat,
+            ai::ai_save_config, ai::ai_load_config, ai::ai_vault_status, ai::ai_vault_unlock, ai::ai_complete, ai::ai_test_connection, ai::ai_list_local_models, ai::ai_test_search, ai::ai_chat,
             ai::ai_save_memory, ai::ai_load_memories, ai::ai_cancel_chat,
-            ai::shell_confirm_response, ai::sync_mcp_tools,
+            ai::shell_confirm_response, ai::ai_pending_confirmations, ai::sync_mcp_tools,
+            ai::usage::ai_usage_summary, ai::usage::ai_usage_session,
+            ai::session::ai_session_list, ai::session::ai_session_load, ai::session::ai_session_continue,
+            ai::session::ai_session_rename, ai::session::ai_session_fork, ai::session::ai_session_delete,
+            ai::permission::ai_permission_list, ai::permission::ai_permission_add, ai::permission::ai_permission_revoke,
+            ai::profile::ai_profile_list, ai::profile::ai_profile_create, ai::profile::ai_profile_update,
+            ai::profile::ai_profile_delete, ai::profile::ai_profile_set_default, ai::profile::ai_profile_pin_workspace,
             license::license_load, license::license_activate, license::license_deactivate, license::open_external_url,
             python_setup::check_python_env,
             python_setup::preload_python_env,
@@ -1170,6 +1215,8 @@ pub fn run() {
                             }
                         }
                     }
+                    // Kill background jobs started by the AI
+                    _app.state::<ai::JobManagerState>().manager.stop_all();
                     // Disconnect all MCP servers
                     let mcp_state = _app.state::<mcp::McpState>();
                     let registry = mcp_state.registry.clone();
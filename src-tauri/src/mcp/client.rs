@@ -1,6 +1,6 @@
 use serde_json::Value;
 use super::protocol::{McpClientInfo, McpInitializeParams, McpToolDef, McpToolResult, McpContent, McpTransportType};
-use super::transport::{McpTransport, StdioTransport, HttpTransport};
+use super::transport::{McpTransport, ProgressFn, StdioTransport, HttpTransport};
 use super::registry::McpServerConfig;
 
 pub struct McpClient {
@@ -77,19 +77,24 @@ impl McpClient {
         self.transport.is_alive()
     }
 
-    pub async fn call_tool(&mut self, name: &str, args: Value) -> Result<McpToolResult, String> {
-        let params = serde_json::json!({
+    /// Call a tool. With `on_progress`, a progress token is sent along so the
+    /// server may report progress while the call runs.
+    pub async fn call_tool(&mut self, name: &str, args: Value, on_progress: Option<ProgressFn<'_>>) -> Result<McpToolResult, String> {
+        let mut params = serde_json::json!({
             "name": name,
             "arguments": args,
         });
+        if on_progress.is_some() {
+            params["_meta"] = serde_json::json!({ "progressToken": uuid::Uuid::new_v4().to_string() });
+        }
 
-        let result = match self.transport.send_request("tools/call", Some(params.clone())).await {
+        let result = match self.transport.send_request_with_progress("tools/call", Some(params.clone()), on_progress).await {
             Ok(r) => r,
             Err(e) => {
                 // If transport is dead, try reconnect once
                 if !self.transport.is_alive() {
                     self.reconnect().await?;
-                    self.transport.send_request("tools/call", Some(params)).await?
+                    self.transport.send_request_with_progress("tools/call", Some(params), on_progress).await?
                 } else {
                     return Err(e);
                 }
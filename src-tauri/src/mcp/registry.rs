@@ -6,6 +6,7 @@ use serde_json::Value;
 
 use super::client::McpClient;
 use super::protocol::{McpToolDef, McpToolResult, McpTransportType};
+use super::transport::ProgressFn;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct McpServerConfig {
@@ -199,13 +200,19 @@ impl McpRegistry {
         result
     }
 
-    pub async fn call_tool(&mut self, server_id: &str, tool_name: &str, args: Value) -> Result<McpToolResult, String> {
+    pub async fn call_tool(
+        &mut self,
+        server_id: &str,
+        tool_name: &str,
+        args: Value,
+        on_progress: Option<ProgressFn<'_>>,
+    ) -> Result<McpToolResult, String> {
         let start = std::time::Instant::now();
         let args_str = serde_json::to_string(&args).unwrap_or_default();
 
         let client = self.servers.get_mut(server_id)
             .ok_or_else(|| format!("Server '{}' not connected", server_id))?;
-        let result = client.call_tool(tool_name, args).await;
+        let result = client.call_tool(tool_name, args, on_progress).await;
 
         let duration_ms = start.elapsed().as_millis() as u64;
         self.last_seen.insert(server_id.to_string(), now_ts());
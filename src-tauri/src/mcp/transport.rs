@@ -1,17 +1,25 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use serde_json::Value;
 
 use super::protocol::{JsonRpcRequest, JsonRpcResponse};
 
+/// Receives the params of `notifications/progress` sent while a request runs
+pub type ProgressFn<'a> = &'a (dyn Fn(&Value) + Send + Sync);
+
+/// How long a stdio request may go without a response or progress on it
+const STDIO_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct StdioTransport {
     child: Child,
     stdin: BufWriter<ChildStdin>,
     stdout: BufReader<ChildStdout>,
     next_id: AtomicU64,
     dead: bool,
+    idle_timeout: Duration,
 }
 
 impl StdioTransport {
@@ -55,6 +63,7 @@ impl StdioTransport {
             stdout: BufReader::new(stdout),
             next_id: AtomicU64::new(1),
             dead: false,
+            idle_timeout: STDIO_IDLE_TIMEOUT,
         })
     }
 
@@ -69,12 +78,19 @@ impl StdioTransport {
         }
     }
 
+    /// Send a request and read lines until its response. Other messages sent
+    /// in between are skipped, except progress on this request, which goes to
+    /// `on_progress` and is the only thing that extends the idle timeout.
     pub async fn send_request(
         &mut self,
         method: &str,
         params: Option<Value>,
+        on_progress: Option<ProgressFn<'_>>,
     ) -> Result<Value, String> {
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let progress_token = params.as_ref()
+            .and_then(|p| p.pointer("/_meta/progressToken"))
+            .cloned();
         let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             method: method.to_string(),
@@ -99,20 +115,38 @@ impl StdioTransport {
             return Err(format!("Flush error: {}", e));
         }
 
-        // Read response with 30s timeout
-        let mut line = String::new();
-        let read_result = tokio::time::timeout(
-            std::time::Duration::from_secs(30),
-            self.stdout.read_line(&mut line),
-        )
-        .await
-        .map_err(|_| "MCP request timed out (30s)".to_string())?
-        .map_err(|e| { self.dead = true; format!("Read error: {}", e) })?;
-
-        if read_result == 0 {
-            self.dead = true;
-            return Err("MCP server closed connection".to_string());
-        }
+        // Progress on this request moves the deadline; nothing else does
+        let mut deadline = tokio::time::Instant::now() + self.idle_timeout;
+        let line = loop {
+            let mut line = String::new();
+            let read_result = tokio::time::timeout_at(deadline, self.stdout.read_line(&mut line))
+                .await
+                .map_err(|_| format!("MCP request timed out ({}s without progress)", self.idle_timeout.as_secs()))?
+                .map_err(|e| { self.dead = true; format!("Read error: {}", e) })?;
+
+            if read_result == 0 {
+                self.dead = true;
+                return Err("MCP server closed connection".to_string());
+            }
+
+            let message: Value = serde_json::from_str(line.trim()).unwrap_or(Value::Null);
+            if message.get("method").is_some() {
+                let params = &message["params"];
+                let ours = progress_token.is_some() && params.get("progressToken") == progress_token.as_ref();
+                if message["method"] == "notifications/progress" && ours {
+                    deadline = tokio::time::Instant::now() + self.idle_timeout;
+                    if let Some(on_progress) = on_progress {
+                        on_progress(params);
+                    }
+                }
+                continue;
+            }
+            // Late responses to earlier requests that timed out
+            if message.get("id").is_some_and(|v| v.as_u64() != Some(id)) {
+                continue;
+            }
+            break line;
+        };
 
         let response: JsonRpcResponse = serde_json::from_str(line.trim())
             .map_err(|e| format!("Parse response error: {} (raw: {})", e, line.trim()))?;
@@ -239,8 +273,18 @@ pub enum McpTransport {
 
 impl McpTransport {
     pub async fn send_request(&mut self, method: &str, params: Option<Value>) -> Result<Value, String> {
+        self.send_request_with_progress(method, params, None).await
+    }
+
+    /// Like `send_request`; progress notifications only arrive over stdio
+    pub async fn send_request_with_progress(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+        on_progress: Option<ProgressFn<'_>>,
+    ) -> Result<Value, String> {
         match self {
-            McpTransport::Stdio(t) => t.send_request(method, params).await,
+            McpTransport::Stdio(t) => t.send_request(method, params, on_progress).await,
             McpTransport::Http(t) => t.send_request(method, params).await,
         }
     }
@@ -266,3 +310,54 @@ impl McpTransport {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_request_skips_notifications_and_reports_progress() {
+        let script = r#"read line
+echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"data":"log"}}'
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"other","progress":9}}'
+echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"t1","progress":1,"total":2}}'
+echo '{"jsonrpc":"2.0","result":{"stale":true},"id":99}'
+echo '{"jsonrpc":"2.0","result":{"ok":true},"id":1}'"#;
+        let mut transport = StdioTransport::spawn("sh", &["-c".to_string(), script.to_string()], &HashMap::new(), None)
+            .await
+            .unwrap();
+        let seen = Mutex::new(Vec::new());
+        let on_progress = |params: &Value| seen.lock().unwrap().push(params.clone());
+
+        let params = serde_json::json!({ "_meta": { "progressToken": "t1" } });
+        let result = transport.send_request("tools/call", Some(params), Some(&on_progress)).await.unwrap();
+        assert_eq!(result, serde_json::json!({"ok": true}));
+        assert_eq!(*seen.lock().unwrap(), vec![serde_json::json!({"progressToken": "t1", "progress": 1, "total": 2})]);
+        let _ = transport.close().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_request_times_out_despite_other_notifications() {
+        let script = r#"read line
+while true; do
+  echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"data":"log"}}'
+  echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"other","progress":1}}'
+  sleep 0.1
+done"#;
+        let mut transport = StdioTransport::spawn("sh", &["-c".to_string(), script.to_string()], &HashMap::new(), None)
+            .await
+            .unwrap();
+        transport.idle_timeout = Duration::from_secs(1);
+        let on_progress = |_: &Value| panic!("progress of another request");
+
+        let started = std::time::Instant::now();
+        let params = serde_json::json!({ "_meta": { "progressToken": "t1" } });
+        let err = transport.send_request("tools/call", Some(params), Some(&on_progress)).await.unwrap_err();
+        assert!(err.contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let _ = transport.close().await;
+    }
+}
@@ -1,5 +1,5 @@
 import { useI18n } from '../lib/i18n'
-import { type AiConfig, aiSaveConfig } from '../lib/tauri'
+import { type AiConfig, type AiProfile, type ProfileSet, aiSaveConfig } from '../lib/tauri'
 import { PRESETS } from './AIModelConfig'
 import { SkillIndicator } from './SkillIndicator'
 import { PROMPT_PRESETS, DEFAULT_BASE_PROMPT } from './AIChatPanel'
@@ -10,6 +10,13 @@ export interface AIChatHeaderProps {
   showModelMenu: boolean
   setShowModelMenu: (v: boolean | ((prev: boolean) => boolean)) => void
   setConfig: (cfg: AiConfig) => void
+  profiles: ProfileSet | null
+  profile: AiProfile | null
+  currentDir: string
+  onSelectProfile: (profile: AiProfile | null) => void
+  onSetDefaultProfile: (id: string | null) => void
+  onPinProfile: (id: string | null) => void
+  onDeleteProfile: (id: string) => void
   activeSkill: string
   memories: MemoryEntry[]
   messages: { role: string }[]
@@ -23,10 +30,12 @@ export interface AIChatHeaderProps {
 
 export function AIChatHeader({
   config, showModelMenu, setShowModelMenu, setConfig,
+  profiles, profile, currentDir, onSelectProfile, onSetDefaultProfile, onPinProfile, onDeleteProfile,
   activeSkill, memories, messages,
   onCopyChat, onClear, onShowHistory, onShowConfig, onClose,
 }: AIChatHeaderProps) {
   const { t, lang } = useI18n()
+  const pinnedId = profiles && currentDir ? profiles.workspaces[currentDir.replace(/[\\/]+$/, '')] : undefined
 
   return (
     <div className="ai-panel-header">
@@ -39,7 +48,7 @@ export function AIChatHeader({
             onClick={() => setShowModelMenu(v => !v)}
             title={t('ai.switchModel')}
           >
-            {config.model} ▾
+            {profile ? `${profile.name} · ${config.model}` : config.model} ▾
           </button>
           {showModelMenu && (
             <div
@@ -50,6 +59,66 @@ export function AIChatHeader({
                 zIndex: 100, minWidth: 160, padding: '4px 0',
               }}
             >
+              {profiles && profiles.profiles.length > 0 && (
+                <>
+                  <div style={{ fontSize: 10, color: 'var(--text-3)', padding: '4px 12px' }}>{t('ai.profiles')}</div>
+                  {profiles.profiles.map(p => {
+                    const isActive = profile?.id === p.id
+                    const isDefault = profiles.default_id === p.id
+                    const isPinned = pinnedId === p.id
+                    return (
+                      <div key={p.id} style={{ display: 'flex', alignItems: 'center', background: isActive ? 'var(--accent-subtle)' : 'transparent' }}>
+                        <button
+                          className="ctx-menu-item"
+                          style={{ flex: 1, textAlign: 'left', fontSize: 12, padding: '6px 12px', background: 'transparent' }}
+                          onClick={() => { onSelectProfile(p); setShowModelMenu(false) }}
+                        >
+                          {p.name} <span style={{ color: 'var(--text-3)', marginLeft: 4 }}>{p.config.model}</span>
+                          {isDefault && <span style={{ fontSize: 10, color: 'var(--accent)', marginLeft: 4 }}>{t('ai.profileDefault')}</span>}
+                          {isPinned && <span style={{ fontSize: 10, color: 'var(--accent)', marginLeft: 4 }}>{t('ai.profilePinned')}</span>}
+                        </button>
+                        <button
+                          className="sidebar-action-btn"
+                          onClick={() => onSetDefaultProfile(isDefault ? null : p.id)}
+                          title={t('ai.setDefaultProfile')}
+                          aria-label={t('ai.setDefaultProfile')}
+                        >
+                          {isDefault ? '★' : '☆'}
+                        </button>
+                        {currentDir && (
+                          <button
+                            className="sidebar-action-btn"
+                            onClick={() => onPinProfile(isPinned ? null : p.id)}
+                            title={isPinned ? t('ai.unpinProfile') : t('ai.pinProfile')}
+                            aria-label={isPinned ? t('ai.unpinProfile') : t('ai.pinProfile')}
+                            style={{ opacity: isPinned ? 1 : 0.5 }}
+                          >
+                            📌
+                          </button>
+                        )}
+                        <button
+                          className="sidebar-action-btn"
+                          onClick={() => onDeleteProfile(p.id)}
+                          title={t('ai.deleteProfile')}
+                          aria-label={t('ai.deleteProfile')}
+                        >
+                          ×
+                        </button>
+                      </div>
+                    )
+                  })}
+                  {profile && (
+                    <button
+                      className="ctx-menu-item"
+                      style={{ width: '100%', textAlign: 'left', fontSize: 12, padding: '6px 12px', color: 'var(--text-3)' }}
+                      onClick={() => { onSelectProfile(null); setShowModelMenu(false) }}
+                    >
+                      {t('ai.noProfile')}
+                    </button>
+                  )}
+                  <div style={{ borderTop: '1px solid var(--border)', margin: '4px 0' }} />
+                </>
+              )}
               {PRESETS.filter(p => p.model).map(p => {
                 const hasKey = !!(config.provider_keys?.[p.api_url] || (p.api_url === config.api_url && config.api_key))
                 const isActive = config.model === p.model && config.api_url === p.api_url
@@ -68,7 +137,8 @@ export function AIChatHeader({
                     onClick={async () => {
                       if (!hasKey) return
                       const providerKey = config.provider_keys?.[p.api_url] || config.api_key
-                      const updated = { ...config, model: p.model, api_url: p.api_url || config.api_url, api_key: providerKey }
+                      // Presets are OpenAI-compatible endpoints
+                      const updated = { ...config, model: p.model, api_url: p.api_url || config.api_url, api_key: providerKey, provider: '' }
                       setConfig(updated)
                       setShowModelMenu(false)
                       try { await aiSaveConfig(updated) } catch { /* silent */ }
@@ -8,6 +8,8 @@ export interface UIMessage {
   content: string
   toolName?: string
   toolId?: string
+  /** Output streamed while the tool runs; cleared once its result arrives */
+  progress?: string
 }
 
 export interface AIChatMessagesProps {
@@ -251,6 +253,11 @@ export function AIChatMessages({
                 ) : (
                   <CollapsibleBlock text={msg.content} maxLines={3} />
                 )}
+                {msg.progress && (
+                  <pre className="ai-tool-progress" style={{ fontSize: 11, color: 'var(--text-3)', whiteSpace: 'pre-wrap', wordBreak: 'break-all', maxHeight: 160, overflowY: 'auto', margin: '4px 0 0' }}>
+                    {msg.progress}
+                  </pre>
+                )}
               </div>
             ) : msg.role === 'tool_result' ? (
               <div className="ai-msg-tool">
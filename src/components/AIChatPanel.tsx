@@ -1,5 +1,5 @@
 import { useState, useEffect, useRef, useCallback } from 'react'
-import { type AiConfig, type ChatMessage, type AiStreamEvent, type MemoryEntry, type PythonSetupProgress, type SkillChangedEvent, aiLoadConfig, aiChat, aiSaveMemory, aiLoadMemories, aiSaveConfig } from '../lib/tauri'
+import { type AiConfig, type AiProfile, type ProfileSet, type ChatMessage, type AiStreamEvent, type MemoryEntry, type PythonSetupProgress, type RetryingEvent, type SkillChangedEvent, type TodoItem, type ToolProgressEvent, aiLoadConfig, aiChat, aiComplete, aiSaveMemory, aiLoadMemories, aiSaveConfig, aiProfileList, aiProfileDelete, aiProfileSetDefault, aiProfilePinWorkspace, profileForWorkspace } from '../lib/tauri'
 import { AIModelConfig } from './AIModelConfig'
 import { listen } from '@tauri-apps/api/event'
 import { useI18n } from '../lib/i18n'
@@ -7,6 +7,7 @@ import { UpgradePrompt } from './UpgradePrompt'
 import { AIChatHeader } from './AIChatHeader'
 import { AIChatMessages, type UIMessage } from './AIChatMessages'
 import { AIChatInput } from './AIChatInput'
+import { AITaskList } from './AITaskList'
 
 /** Default base prompt — user can customize in AI config */
 export const DEFAULT_BASE_PROMPT = `You are Inkess AI assistant. Current working directory: {currentDir}. You can use tools to read files, list directories, search files, fetch web pages, write files, and open files for the user.
@@ -114,6 +115,9 @@ const HISTORY_KEY = 'inkess-ai-history'
 const SESSIONS_KEY = 'inkess-ai-sessions'
 const MAX_SESSIONS = 20
 
+// Characters of streamed tool output shown under a running tool call
+const MAX_TOOL_PROGRESS = 4000
+
 // Auto-compact: summarize older messages when conversation gets long
 const COMPACT_MSG_THRESHOLD = 30
 const COMPACT_KEEP_RECENT = 10
@@ -140,37 +144,14 @@ async function compactHistory(
   const conversation = cappedOld.join('\n\n')
   if (!conversation) return null
 
-  const body = {
-    model: config.model,
-    messages: [
-      { role: 'system', content: 'Summarize this conversation history concisely, preserving: 1) User goals and tasks 2) Key decisions made 3) Important code/file references 4) Problems encountered and solutions. Output a structured summary, max 500 words.' },
-      { role: 'user', content: conversation },
-    ],
-    temperature: 0.3,
-    max_tokens: 800,
-  }
-
-  const url = `${config.api_url.replace(/\/$/, '')}/chat/completions`
-  const controller = new AbortController()
-  const timer = setTimeout(() => controller.abort(), 10_000) // 10s timeout
+  const prompt = 'Summarize this conversation history concisely, preserving: 1) User goals and tasks 2) Key decisions made 3) Important code/file references 4) Problems encountered and solutions. Output a structured summary, max 500 words.'
+    + '\n\n' + conversation
   try {
-    const resp = await fetch(url, {
-      method: 'POST',
-      headers: {
-        'Authorization': `Bearer ${config.api_key}`,
-        'Content-Type': 'application/json',
-      },
-      body: JSON.stringify(body),
-      signal: controller.signal,
-    })
-    clearTimeout(timer)
-    if (!resp.ok) return null
-    const data = await resp.json()
-    const summary = data.choices?.[0]?.message?.content
+    // Runs in the backend, which holds the API keys; 10s timeout
+    const summary = await aiComplete(config, prompt, 800, 10)
     if (!summary) return null
     return { summary, kept: recentMsgs }
   } catch {
-    clearTimeout(timer)
     return null
   }
 }
@@ -215,6 +196,11 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
   const [input, setInput] = useState('')
   const [streaming, setStreaming] = useState(false)
   const [config, setConfig] = useState<AiConfig | null>(null)
+  const [profiles, setProfiles] = useState<ProfileSet | null>(null)
+  // Active profile; chats send its id and the backend loads its config
+  const [profile, setProfile] = useState<AiProfile | null>(null)
+  // Task list the agent keeps with todo_write
+  const [todos, setTodos] = useState<TodoItem[]>([])
   const [showConfig, setShowConfig] = useState(false)
   const [sessionId] = useState(() => crypto.randomUUID())
   const [memories, setMemories] = useState<MemoryEntry[]>([])
@@ -282,13 +268,52 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
     document.body.style.userSelect = 'none'
   }, [])
 
-  // Load config on mount
+  // Load config, switching to the workspace's pinned or default profile when the directory changes
   useEffect(() => {
-    aiLoadConfig().then(cfg => {
-      if (cfg) setConfig(cfg)
+    Promise.all([aiLoadConfig(), aiProfileList().catch(() => null)]).then(([cfg, set]) => {
+      const active = set ? profileForWorkspace(set, currentDir) : null
+      setProfiles(set)
+      setProfile(active)
+      const next = active?.config || cfg
+      if (next) setConfig(next)
     }).catch(() => {})
+  }, [currentDir])
+
+  const selectProfile = useCallback((p: AiProfile | null) => {
+    setProfile(p)
+    if (p) {
+      setConfig(p.config)
+    } else {
+      aiLoadConfig().then(cfg => { if (cfg) setConfig(cfg) }).catch(() => {})
+    }
+  }, [])
+
+  const reloadProfiles = useCallback(async () => {
+    try { setProfiles(await aiProfileList()) } catch { /* silent */ }
   }, [])
 
+  const handleProfileSaved = useCallback((p: AiProfile) => {
+    selectProfile(p)
+    reloadProfiles()
+  }, [selectProfile, reloadProfiles])
+
+  const handleSetDefaultProfile = useCallback(async (id: string | null) => {
+    try { await aiProfileSetDefault(id) } catch (e) { onToast(String(e)) }
+    reloadProfiles()
+  }, [onToast, reloadProfiles])
+
+  const handlePinProfile = useCallback(async (id: string | null) => {
+    if (!currentDir) return
+    try { await aiProfilePinWorkspace(currentDir, id) } catch (e) { onToast(String(e)) }
+    reloadProfiles()
+  }, [currentDir, onToast, reloadProfiles])
+
+  const handleDeleteProfile = useCallback(async (id: string) => {
+    try { await aiProfileDelete(id) } catch (e) { onToast(String(e)) }
+    if (profile?.id === id) selectProfile(null)
+    reloadProfiles()
+  }, [profile, onToast, selectProfile, reloadProfiles])
+
   // Load memories when directory changes
   useEffect(() => {
     if (!currentDir) return
@@ -352,10 +377,24 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
           } catch { /* ignore */ }
           break
         }
+        case 'tool_progress': {
+          try {
+            const info: ToolProgressEvent = JSON.parse(content)
+            setMessages(prev => prev.map(m => {
+              if (m.role !== 'tool_call' || m.toolId !== info.call_id) return m
+              // Keep only the tail; the full output arrives with the result
+              const progress = ((m.progress || '') + info.chunk).slice(-MAX_TOOL_PROGRESS)
+              return { ...m, progress }
+            }))
+          } catch { /* ignore */ }
+          break
+        }
         case 'tool_result': {
           try {
             const info = JSON.parse(content)
-            setMessages(prev => [...prev, {
+            setMessages(prev => [...prev.map(m =>
+              m.role === 'tool_call' && m.toolId === info.id && m.progress ? { ...m, progress: undefined } : m
+            ), {
               role: 'tool_result',
               content: info.result,
               toolName: info.name,
@@ -366,6 +405,24 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
           assistantBufferRef.current = ''
           break
         }
+        case 'todos': {
+          try { setTodos(JSON.parse(content)) } catch { /* ignore */ }
+          break
+        }
+        case 'retrying': {
+          // The failed attempt is sent again from scratch: drop its partial answer
+          const hadPartial = assistantBufferRef.current !== ''
+          assistantBufferRef.current = ''
+          try {
+            const info: RetryingEvent = JSON.parse(content)
+            const status = t('ai.retrying', { seconds: Math.ceil(info.delay_ms / 1000), attempt: info.attempt, max: info.max_retries })
+            setMessages(prev => {
+              const kept = hadPartial && prev[prev.length - 1]?.role === 'assistant' ? prev.slice(0, -1) : prev
+              return [...kept, { role: 'system', content: status }]
+            })
+          } catch { /* ignore */ }
+          break
+        }
         case 'done': {
           setStreaming(false)
           assistantBufferRef.current = ''
@@ -380,7 +437,7 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
       }
     }).then(fn => { unlisten = fn })
     return () => { cancelled = true; unlisten?.() }
-  }, [sessionId])
+  }, [sessionId, t])
 
   // Listen for skill-changed events
   useEffect(() => {
@@ -478,7 +535,7 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
     }
 
     try {
-      await aiChat(sessionId, apiMessages, config, deepMode, currentDir || undefined, activeSkillId)
+      await aiChat(sessionId, apiMessages, profile ? null : config, deepMode, currentDir || undefined, activeSkillId, profile?.id)
       // Auto-summarize after 20 user/assistant messages
       const sumMsgs = [...messagesRef.current, userMsg].filter(m => m.role === 'user' || m.role === 'assistant')
       if (sumMsgs.length >= 20 && !summarizedRef.current && currentDir) {
@@ -494,7 +551,7 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
       setStreaming(false)
       onToast(typeof e === 'string' ? e : t('ai.requestFailed'))
     }
-  }, [input, streaming, config, currentDir, sessionId, onToast, memories, deepMode])
+  }, [input, streaming, config, profile, currentDir, sessionId, onToast, memories, deepMode])
 
   const handleKeyDown = (e: React.KeyboardEvent) => {
     if (e.key === 'Enter' && !e.shiftKey) {
@@ -521,6 +578,7 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
       })
     }
     setMessages([])
+    setTodos([])
     setActiveSkill('Default')
     setActiveSkillId('default')
     summarizedRef.current = false
@@ -551,7 +609,14 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
           config={config}
           showModelMenu={showModelMenu}
           setShowModelMenu={setShowModelMenu}
-          setConfig={setConfig}
+          setConfig={cfg => { setProfile(null); setConfig(cfg) }}
+          profiles={profiles}
+          profile={profile}
+          currentDir={currentDir}
+          onSelectProfile={selectProfile}
+          onSetDefaultProfile={handleSetDefaultProfile}
+          onPinProfile={handlePinProfile}
+          onDeleteProfile={handleDeleteProfile}
           activeSkill={activeSkill}
           memories={memories}
           messages={messages}
@@ -581,6 +646,8 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
           messagesEndRef={messagesEndRef}
         />
 
+        <AITaskList todos={todos} />
+
         <AIChatInput
           config={config}
           input={input}
@@ -655,6 +722,8 @@ export function AIChatPanel({ visible, currentDir, onClose, onToast, isPro = tru
         <AIModelConfig
           config={config}
           onSave={setConfig}
+          profile={profile}
+          onSaveProfile={handleProfileSaved}
           onClose={() => setShowConfig(false)}
           onToast={onToast}
         />
@@ -672,28 +741,10 @@ async function triggerSummarize(
 ): Promise<string | null> {
   try {
     const conversation = msgs.slice(-20).map(m => `${m.role}: ${m.content}`).join('\n')
-    const body = {
-      model: config.model,
-      messages: [
-        { role: 'system', content: 'You are a conversation summarizer. Summarize the following conversation into concise bullet points, preserving key information (project structure, user preferences, important decisions), no more than 300 words.' },
-        { role: 'user', content: conversation },
-      ],
-      temperature: 0.3,
-      max_tokens: 512,
-    }
-    const url = `${config.api_url.replace(/\/$/, '')}/chat/completions`
-    // Use a direct fetch since we don't need streaming for summarization
-    const resp = await fetch(url, {
-      method: 'POST',
-      headers: {
-        'Authorization': `Bearer ${config.api_key}`,
-        'Content-Type': 'application/json',
-      },
-      body: JSON.stringify(body),
-    })
-    if (!resp.ok) return null
-    const data = await resp.json()
-    return data.choices?.[0]?.message?.content || null
+    const prompt = 'You are a conversation summarizer. Summarize the following conversation into concise bullet points, preserving key information (project structure, user preferences, important decisions), no more than 300 words.'
+      + '\n\n' + conversation
+    // Non-streamed completion in the backend, which holds the API keys
+    return (await aiComplete(config, prompt, 512)) || null
   } catch {
     return null
   }
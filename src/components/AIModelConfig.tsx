@@ -1,11 +1,14 @@
-import { useState } from 'react'
-import { type AiConfig, aiSaveConfig, aiTestConnection, aiTestSearch } from '../lib/tauri'
+import { useEffect, useState } from 'react'
+import { type AiConfig, type AiProfile, type VaultStatus, aiProfileCreate, aiProfileUpdate, aiSaveConfig, aiLoadConfig, aiTestConnection, aiTestSearch, aiVaultStatus, aiVaultUnlock } from '../lib/tauri'
 import { useI18n } from '../lib/i18n'
 import { DEFAULT_BASE_PROMPT, PROMPT_PRESETS } from './AIChatPanel'
 
 interface AIModelConfigProps {
   config: AiConfig | null
   onSave: (config: AiConfig) => void
+  // Profile being edited; saving updates it instead of the main config
+  profile?: AiProfile | null
+  onSaveProfile?: (profile: AiProfile) => void
   onClose: () => void
   onToast: (msg: string) => void
 }
@@ -20,6 +23,13 @@ export const PRESETS: { label: string; api_url: string; model: string }[] = [
   { label: 'Custom', api_url: '', model: '' },
 ]
 
+// Chat protocol per `AiConfig.provider`; '' is any OpenAI-compatible API
+const PROVIDERS: { id: string; labelKey: string }[] = [
+  { id: '', labelKey: 'aiConfig.providerOpenai' },
+  { id: 'anthropic', labelKey: 'aiConfig.providerAnthropic' },
+  { id: 'local', labelKey: 'aiConfig.providerLocal' },
+]
+
 function maskKey(key: string): string {
   if (!key) return ''
   if (key.length <= 8) return '••••••••'
@@ -38,7 +48,8 @@ function KeyInput({ value, onChange, placeholder }: { value: string; onChange: (
         placeholder={placeholder}
         style={{ paddingRight: 32 }}
       />
-      {value && (
+      {/* Stored keys are `vault:` references; there is nothing to reveal */}
+      {value && !value.startsWith('vault:') && (
         <button
           type="button"
           onClick={() => setShowKey(v => !v)}
@@ -60,11 +71,12 @@ function KeyInput({ value, onChange, placeholder }: { value: string; onChange: (
   )
 }
 
-export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfigProps) {
+export function AIModelConfig({ config, onSave, profile, onSaveProfile, onClose, onToast }: AIModelConfigProps) {
   const { t, lang } = useI18n()
   const [apiUrl, setApiUrl] = useState(config?.api_url || PRESETS[0].api_url)
   const [apiKey, setApiKey] = useState(config?.api_key || '')
   const [model, setModel] = useState(config?.model || PRESETS[0].model)
+  const [provider, setProvider] = useState(config?.provider || '')
   const [temperature, setTemperature] = useState(config?.temperature ?? 0.7)
   const [maxTokens, setMaxTokens] = useState(config?.max_tokens ?? 4096)
   const [systemPrompt, setSystemPrompt] = useState(config?.system_prompt || '')
@@ -74,6 +86,29 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
   const [testing, setTesting] = useState(false)
   const [activeTab, setActiveTab] = useState<'model' | 'shared' | 'search'>('model')
   const [searchProvider, setSearchProvider] = useState(config?.search_provider || 'duckduckgo')
+  const [vault, setVault] = useState<VaultStatus | null>(null)
+  const [passphrase, setPassphrase] = useState('')
+  const [passphraseSet, setPassphraseSet] = useState(false)
+  const [profileName, setProfileName] = useState('')
+
+  useEffect(() => {
+    aiVaultStatus().then(setVault).catch(() => {})
+  }, [])
+
+  // Locked vault, or no keyring and no vault yet: keys can't be used or saved without a passphrase
+  const needsPassphrase = !!vault && (vault.locked || (!vault.protection && !vault.keyring_available && !passphraseSet))
+
+  const handleUnlock = async () => {
+    if (!passphrase) return
+    try {
+      setVault(await aiVaultUnlock(passphrase))
+      setPassphraseSet(true)
+      setPassphrase('')
+      onToast(t('aiConfig.vaultUnlocked'))
+    } catch (e) {
+      onToast(typeof e === 'string' ? e : t('aiConfig.saveFailed'))
+    }
+  }
 
   const SEARCH_PROVIDERS = [
     { id: 'duckduckgo', label: 'DuckDuckGo', desc: lang === 'zh' ? '免费，无需 API Key' : 'Free, no API key needed', placeholder: '' },
@@ -90,6 +125,8 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
     }
     setApiUrl(p.api_url)
     setModel(p.model)
+    // Presets are OpenAI-compatible endpoints
+    setProvider('')
     // Restore key for the new provider
     const savedKey = providerKeys[p.api_url] || (config?.provider_keys?.[p.api_url]) || ''
     setApiKey(savedKey)
@@ -99,7 +136,7 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
     if (!apiUrl || !apiKey) { onToast(t('aiConfig.fillRequired')); return }
     setTesting(true)
     try {
-      const msg = await aiTestConnection({ api_url: apiUrl, api_key: apiKey, model, temperature, max_tokens: maxTokens, system_prompt: systemPrompt, base_prompt: basePrompt, search_api_key: searchApiKey, search_provider: searchProvider, provider_keys: providerKeys })
+      const msg = await aiTestConnection(editedConfig(providerKeys))
       onToast(msg)
     } catch (e) {
       onToast(typeof e === 'string' ? e : t('aiConfig.connFailed'))
@@ -117,8 +154,16 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
     } finally { setTesting(false) }
   }
 
-  const handleSave = async () => {
-    if (!apiUrl || !model) { onToast(t('aiConfig.fillAll')); return }
+  // Fields this dialog doesn't edit (retries, hooks, prices, ...) are kept as they are
+  const editedConfig = (keys: Record<string, string>): AiConfig => ({
+    ...config,
+    api_url: apiUrl, api_key: apiKey, model, provider, temperature, max_tokens: maxTokens,
+    system_prompt: systemPrompt, base_prompt: basePrompt, search_api_key: searchApiKey, search_provider: searchProvider,
+    provider_keys: keys,
+  })
+
+  const buildConfig = (): AiConfig | null => {
+    if (!apiUrl || !model) { onToast(t('aiConfig.fillAll')); return null }
     // Merge current key into provider_keys (remove entry if key is empty)
     const keys = { ...providerKeys }
     if (apiKey) {
@@ -126,10 +171,20 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
     } else {
       delete keys[apiUrl]
     }
-    const cfg: AiConfig = { api_url: apiUrl, api_key: apiKey, model, temperature, max_tokens: maxTokens, system_prompt: systemPrompt, base_prompt: basePrompt, search_api_key: searchApiKey, search_provider: searchProvider, provider_keys: keys }
+    return editedConfig(keys)
+  }
+
+  const handleSave = async () => {
+    const cfg = buildConfig()
+    if (!cfg) return
     try {
-      await aiSaveConfig(cfg)
-      onSave(cfg)
+      if (profile) {
+        onSaveProfile?.(await aiProfileUpdate(profile.id, undefined, cfg))
+      } else {
+        await aiSaveConfig(cfg)
+        // Reload so the panel keeps key references instead of the keys just typed in
+        onSave((await aiLoadConfig()) || cfg)
+      }
       onToast(t('aiConfig.saved'))
       onClose()
     } catch (e) {
@@ -137,11 +192,24 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
     }
   }
 
+  const handleSaveAsProfile = async () => {
+    if (!profileName.trim()) { onToast(t('aiConfig.profileNameRequired')); return }
+    const cfg = buildConfig()
+    if (!cfg) return
+    try {
+      onSaveProfile?.(await aiProfileCreate(profileName.trim(), cfg))
+      onToast(t('aiConfig.profileSaved', { name: profileName.trim() }))
+      onClose()
+    } catch (e) {
+      onToast(typeof e === 'string' ? e : t('aiConfig.saveFailed'))
+    }
+  }
+
   return (
     <div className="shortcuts-backdrop" onClick={onClose}>
       <div className="shortcuts-modal" onClick={e => e.stopPropagation()} style={{ minWidth: 460, maxWidth: 520 }}>
         <div className="flex items-center justify-between mb-1">
-          <h3 style={{ margin: 0 }}>{t('aiConfig.title')}</h3>
+          <h3 style={{ margin: 0 }}>{profile ? t('aiConfig.profileTitle', { name: profile.name }) : t('aiConfig.title')}</h3>
           <button className="sidebar-action-btn" onClick={onClose} aria-label={t('ai.close')}>
             <svg viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" style={{ width: 14, height: 14 }}>
               <line x1="18" y1="6" x2="6" y2="18" /><line x1="6" y1="6" x2="18" y2="18" />
@@ -195,6 +263,25 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
             </div>
 
             <div style={{ display: 'flex', flexDirection: 'column', gap: 12 }}>
+              <div style={{ fontSize: 12, color: 'var(--text-2)' }}>
+                {t('aiConfig.provider')}
+                <div style={{ display: 'flex', gap: 6, marginTop: 4 }}>
+                  {PROVIDERS.map(p => (
+                    <button
+                      key={p.id || 'openai'}
+                      className="git-btn"
+                      style={{
+                        fontSize: 11, padding: '4px 10px',
+                        background: provider === p.id ? 'var(--accent-subtle)' : undefined,
+                        color: provider === p.id ? 'var(--color-accent)' : undefined,
+                      }}
+                      onClick={() => setProvider(p.id)}
+                    >
+                      {t(p.labelKey)}
+                    </button>
+                  ))}
+                </div>
+              </div>
               <label style={{ fontSize: 12, color: 'var(--text-2)' }}>
                 {t('aiConfig.apiUrl')}
                 <input
@@ -209,6 +296,25 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
                 {t('aiConfig.apiKey')}
                 <KeyInput value={apiKey} onChange={setApiKey} placeholder="sk-..." />
               </label>
+              {needsPassphrase && vault && (
+                <div style={{ fontSize: 12, color: 'var(--text-2)' }}>
+                  {vault.locked ? t('aiConfig.vaultLocked') : t('aiConfig.vaultNoKeyring')}
+                  <div style={{ display: 'flex', gap: 6, marginTop: 4 }}>
+                    <input
+                      className="new-file-input"
+                      type="password"
+                      value={passphrase}
+                      onChange={e => setPassphrase(e.target.value)}
+                      onKeyDown={e => { if (e.key === 'Enter') handleUnlock() }}
+                      placeholder={t('aiConfig.vaultPassphrase')}
+                      style={{ flex: 1 }}
+                    />
+                    <button className="git-btn" onClick={handleUnlock} disabled={!passphrase}>
+                      {vault.locked ? t('aiConfig.vaultUnlock') : t('aiConfig.vaultSet')}
+                    </button>
+                  </div>
+                </div>
+              )}
               <label style={{ fontSize: 12, color: 'var(--text-2)' }}>
                 {t('aiConfig.modelName')}
                 <input
@@ -336,6 +442,14 @@ export function AIModelConfig({ config, onSave, onClose, onToast }: AIModelConfi
         )}
 
         <div style={{ display: 'flex', justifyContent: 'flex-end', gap: 8, marginTop: 20 }}>
+          <input
+            className="new-file-input"
+            style={{ flex: 1, minWidth: 0 }}
+            value={profileName}
+            onChange={e => setProfileName(e.target.value)}
+            placeholder={t('aiConfig.profileName')}
+          />
+          <button className="git-btn" onClick={handleSaveAsProfile}>{t('aiConfig.saveAsProfile')}</button>
           {activeTab === 'model' && (
             <button className="git-btn" onClick={handleTestModel} disabled={testing}>
               {testing ? t('aiConfig.testing') : t('aiConfig.test')}
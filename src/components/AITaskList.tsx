@@ -0,0 +1,50 @@
+import { useState } from 'react'
+import { useI18n } from '../lib/i18n'
+import type { TodoItem } from '../lib/tauri'
+
+interface AITaskListProps {
+  todos: TodoItem[]
+}
+
+const STATUS_ICON: Record<TodoItem['status'], string> = {
+  pending: '○',
+  in_progress: '▶',
+  done: '✓',
+}
+
+/** The agent's task list, as kept up to date by the todo_write tool */
+export function AITaskList({ todos }: AITaskListProps) {
+  const { t } = useI18n()
+  const [collapsed, setCollapsed] = useState(false)
+  if (todos.length === 0) return null
+  const done = todos.filter(item => item.status === 'done').length
+
+  return (
+    <div style={{ borderTop: '1px solid var(--border)', padding: '6px 12px', fontSize: 12 }}>
+      <button
+        className="ctx-menu-item"
+        style={{ width: '100%', textAlign: 'left', padding: 0, fontSize: 11, color: 'var(--text-3)', background: 'transparent' }}
+        onClick={() => setCollapsed(v => !v)}
+      >
+        {collapsed ? '▸' : '▾'} {t('ai.taskList', { done, total: todos.length })}
+      </button>
+      {!collapsed && (
+        <ul style={{ listStyle: 'none', margin: '4px 0 0', padding: 0, maxHeight: 140, overflowY: 'auto' }}>
+          {todos.map(item => (
+            <li
+              key={item.id}
+              style={{
+                display: 'flex', gap: 6, padding: '1px 0',
+                color: item.status === 'in_progress' ? 'var(--accent)' : item.status === 'done' ? 'var(--text-3)' : 'var(--text-2)',
+                textDecoration: item.status === 'done' ? 'line-through' : 'none',
+              }}
+            >
+              <span style={{ flexShrink: 0 }}>{STATUS_ICON[item.status]}</span>
+              <span>{item.text}</span>
+            </li>
+          ))}
+        </ul>
+      )}
+    </div>
+  )
+}
@@ -1,40 +1,33 @@
-import { useState, useEffect, useRef } from 'react'
+import { useState, useEffect } from 'react'
 import { listen } from '@tauri-apps/api/event'
-import { invoke } from '@tauri-apps/api/core'
+import { aiPendingConfirmations, shellConfirmResponse, type ConfirmRequest } from '../lib/tauri'
 import { useI18n } from '../lib/i18n'
 
-interface ShellConfirmPayload {
-  command: string
-}
-
 export function ShellConfirm() {
   const { t } = useI18n()
-  const [pending, setPending] = useState<ShellConfirmPayload | null>(null)
-  const timerRef = useRef<ReturnType<typeof setTimeout> | null>(null)
+  // Open requests, oldest first; the backend denies them after its timeout
+  const [queue, setQueue] = useState<ConfirmRequest[]>([])
+  const pending = queue[0]
 
   useEffect(() => {
-    const unlisten = listen<ShellConfirmPayload>('shell-confirm', (event) => {
-      setPending(event.payload)
-      // Auto-deny after 60s
-      if (timerRef.current) clearTimeout(timerRef.current)
-      timerRef.current = setTimeout(() => {
-        invoke('shell_confirm_response', { approved: false }).catch(() => {})
-        setPending(null)
-      }, 60000)
-    })
+    const add = (request: ConfirmRequest) => setQueue(q =>
+      q.some(r => r.request_id === request.request_id) ? q : [...q, request])
+    const drop = (requestId: string) => setQueue(q => q.filter(r => r.request_id !== requestId))
+
+    // Pick up requests raised before this component was mounted
+    aiPendingConfirmations().then(requests => requests.forEach(add)).catch(() => {})
+    const unlisten = listen<ConfirmRequest>('shell-confirm', (event) => add(event.payload))
+    const unlistenExpired = listen<{ request_id: string }>('shell-confirm-expired', (event) => drop(event.payload.request_id))
     return () => {
       unlisten.then(fn => fn())
-      if (timerRef.current) clearTimeout(timerRef.current)
+      unlistenExpired.then(fn => fn())
     }
   }, [])
 
   const respond = (approved: boolean) => {
-    if (timerRef.current) {
-      clearTimeout(timerRef.current)
-      timerRef.current = null
-    }
-    invoke('shell_confirm_response', { approved }).catch(() => {})
-    setPending(null)
+    if (!pending) return
+    shellConfirmResponse(pending.request_id, approved, pending.session_id).catch(() => {})
+    setQueue(q => q.filter(r => r.request_id !== pending.request_id))
   }
 
   if (!pending) return null
@@ -57,7 +50,11 @@ export function ShellConfirm() {
           </button>
         </div>
         <p className="text-[13px] mb-2" style={{ color: 'var(--text-2)', lineHeight: '1.5' }}>
-          {t('shellConfirm.message')}
+          {pending.tool === 'run_shell'
+            ? t('shellConfirm.message')
+            : pending.tool === 'workspace_hooks'
+              ? t('shellConfirm.hooksMessage')
+              : t('shellConfirm.toolMessage', { tool: pending.tool })}
         </p>
         <pre style={{
           background: 'var(--ink-900, #1a1a2e)',
@@ -73,7 +70,12 @@ export function ShellConfirm() {
         }}>
           {pending.command}
         </pre>
-        <div className="flex gap-2.5 justify-end">
+        <div className="flex gap-2.5 justify-end items-center">
+          {queue.length > 1 && (
+            <span className="text-[12px] mr-auto" style={{ color: 'var(--text-3)' }}>
+              {t('shellConfirm.queued', { count: queue.length - 1 })}
+            </span>
+          )}
           <button
             className="toolbar-btn"
             onClick={() => respond(false)}
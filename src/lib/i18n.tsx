@@ -158,6 +158,10 @@ const dict: Record<string, { zh: string; en: string }> = {
   'aiConfig.apiUrl': { zh: 'API 地址', en: 'API URL' },
   'aiConfig.apiKey': { zh: 'API Key', en: 'API Key' },
   'aiConfig.modelName': { zh: '模型名称', en: 'Model Name' },
+  'aiConfig.provider': { zh: '接口协议', en: 'API Protocol' },
+  'aiConfig.providerOpenai': { zh: 'OpenAI 兼容', en: 'OpenAI-compatible' },
+  'aiConfig.providerAnthropic': { zh: 'Anthropic', en: 'Anthropic' },
+  'aiConfig.providerLocal': { zh: '本地 (Ollama)', en: 'Local (Ollama)' },
   'aiConfig.advanced': { zh: '高级设置', en: 'Advanced' },
   'aiConfig.maxTokens': { zh: '最大 Token', en: 'Max Tokens' },
   'aiConfig.basePrompt': { zh: '基础提示词', en: 'Base Prompt' },
@@ -175,6 +179,17 @@ const dict: Record<string, { zh: string; en: string }> = {
   'aiConfig.saveFailed': { zh: '保存失败', en: 'Save failed' },
   'aiConfig.modelSection': { zh: '模型配置', en: 'Model Settings' },
   'aiConfig.sharedSection': { zh: '提示词', en: 'Prompts' },
+  'aiConfig.vaultLocked': { zh: 'API Key 已加密锁定，请输入保险库密码解锁', en: 'API keys are encrypted. Enter the vault passphrase to unlock them' },
+  'aiConfig.vaultNoKeyring': { zh: '系统钥匙串不可用，请设置保险库密码以加密保存 API Key', en: 'No system keyring available. Set a vault passphrase to store API keys encrypted' },
+  'aiConfig.vaultPassphrase': { zh: '保险库密码', en: 'Vault passphrase' },
+  'aiConfig.vaultUnlock': { zh: '解锁', en: 'Unlock' },
+  'aiConfig.vaultSet': { zh: '设置', en: 'Set' },
+  'aiConfig.vaultUnlocked': { zh: '保险库已解锁', en: 'Vault unlocked' },
+  'aiConfig.profileTitle': { zh: '配置方案：{name}', en: 'Profile: {name}' },
+  'aiConfig.profileName': { zh: '方案名称', en: 'Profile name' },
+  'aiConfig.saveAsProfile': { zh: '另存为方案', en: 'Save as Profile' },
+  'aiConfig.profileNameRequired': { zh: '请输入方案名称', en: 'Enter a profile name' },
+  'aiConfig.profileSaved': { zh: '已保存方案 {name}', en: 'Saved profile {name}' },
   // App toasts
   'toast.created': { zh: '已创建: {name}', en: 'Created: {name}' },
   'toast.createdFolder': { zh: '已创建文件夹: {name}', en: 'Created folder: {name}' },
@@ -282,7 +297,17 @@ const dict: Record<string, { zh: string; en: string }> = {
   'gitInit.confirm': { zh: '确认初始化', en: 'Confirm Init' },
   // AI switch model
   'ai.switchModel': { zh: '切换模型', en: 'Switch Model' },
+  'ai.profiles': { zh: '配置方案', en: 'Profiles' },
+  'ai.noProfile': { zh: '不使用方案', en: 'No profile' },
+  'ai.profileDefault': { zh: '默认', en: 'default' },
+  'ai.profilePinned': { zh: '本工作区', en: 'this workspace' },
+  'ai.setDefaultProfile': { zh: '设为默认方案', en: 'Set as default profile' },
+  'ai.pinProfile': { zh: '固定到当前工作区', en: 'Pin to this workspace' },
+  'ai.unpinProfile': { zh: '取消固定', en: 'Unpin from this workspace' },
+  'ai.deleteProfile': { zh: '删除方案', en: 'Delete profile' },
   'ai.keyNotConfigured': { zh: '未配置此服务商的 API Key，请先在设置中配置', en: 'API Key not configured for this provider. Please configure in settings first.' },
+  'ai.taskList': { zh: '任务（{done}/{total}）', en: 'Tasks ({done}/{total})' },
+  'ai.retrying': { zh: '请求失败，{seconds} 秒后重试（{attempt}/{max}）', en: 'Request failed, retrying in {seconds}s ({attempt}/{max})' },
   'ai.workspaceSwitched': { zh: '工作目录已切换到 {dir}', en: 'Workspace switched to {dir}' },
   'ai.copyChat': { zh: '复制对话', en: 'Copy Conversation' },
   'ai.copiedChat': { zh: '对话已复制到剪贴板', en: 'Conversation copied to clipboard' },
@@ -441,6 +466,9 @@ const dict: Record<string, { zh: string; en: string }> = {
   // Shell confirm dialog
   'shellConfirm.title': { zh: 'AI 请求执行命令', en: 'AI wants to run a command' },
   'shellConfirm.message': { zh: 'AI 助手请求执行以下 Shell 命令：', en: 'The AI assistant is requesting to execute the following shell command:' },
+  'shellConfirm.toolMessage': { zh: 'AI 助手请求使用工具 {tool}：', en: 'The AI assistant is requesting to use the tool {tool}:' },
+  'shellConfirm.hooksMessage': { zh: '此工作区的钩子文件会在工具调用前后运行以下命令。是否信任此工作区？', en: 'This workspace\'s hooks file runs the following commands around tool calls. Trust this workspace?' },
+  'shellConfirm.queued': { zh: '还有 {count} 个待确认', en: '{count} more waiting' },
   'shellConfirm.allow': { zh: '允许', en: 'Allow' },
   'shellConfirm.deny': { zh: '拒绝', en: 'Deny' },
 }
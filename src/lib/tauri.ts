@@ -252,6 +252,44 @@ export interface AiConfig {
   search_api_key: string
   search_provider: string
   provider_keys: Record<string, string>
+  provider?: string
+  local_tool_mode?: string
+  max_retries?: number
+  retry_base_delay_ms?: number
+  context_window?: number
+  model_prices?: Record<string, ModelPrice>
+  session_retention_days?: number
+  skill_models?: Record<string, SkillModelConfig>
+  fallback_models?: string[]
+  hooks?: ToolHook[]
+  workspace_hooks?: boolean
+  prompt_caching?: boolean
+}
+
+// Command run before or after tool calls whose name matches `matcher` (glob)
+export interface ToolHook {
+  event: 'pre_tool_use' | 'post_tool_use'
+  matcher?: string
+  command: string
+  timeout_secs?: number
+}
+
+export interface SkillModelConfig {
+  model?: string
+  temperature?: number
+  max_tokens?: number
+}
+
+export interface ModelPrice {
+  input_per_million: number
+  output_per_million: number
+  cached_input_per_million?: number
+}
+
+export interface LocalModelInfo {
+  id: string
+  server: string
+  supports_tools: boolean
 }
 
 export interface ToolCall {
@@ -265,6 +303,19 @@ export interface ChatMessage {
   content: string | null
   tool_calls?: ToolCall[]
   tool_call_id?: string
+  reasoning?: string
+  thinking_blocks?: ThinkingBlock[]
+  images?: ImagePart[]
+}
+
+export type ThinkingBlock =
+  | { type: 'thinking'; thinking: string; signature: string }
+  | { type: 'redacted_thinking'; data: string }
+
+export interface ImagePart {
+  media_type: string
+  data?: string
+  path?: string
 }
 
 export interface AiStreamEvent {
@@ -273,6 +324,30 @@ export interface AiStreamEvent {
   content: string
 }
 
+// Payload of the "subagent" stream event: an event of a delegate_task sub-agent
+export interface SubagentEvent {
+  call_id: string
+  depth: number
+  event_type: string
+  content: string
+}
+
+// Payload of the "tool_progress" stream event: partial output of a running tool
+export interface ToolProgressEvent {
+  call_id: string
+  tool: string
+  stream: 'stdout' | 'stderr' | 'status'
+  chunk: string
+}
+
+// Payload of the "retrying" stream event: a failed round is about to be sent again
+export interface RetryingEvent {
+  attempt: number
+  max_retries: number
+  delay_ms: number
+  reason: string
+}
+
 export interface SkillChangedEvent {
   session_id: string
   skill_id: string
@@ -287,16 +362,215 @@ export async function aiLoadConfig(): Promise<AiConfig | null> {
   return invoke<AiConfig | null>('ai_load_config')
 }
 
+// API keys come back as `vault:` references; the backend resolves them when they are used
+export interface VaultStatus {
+  protection: 'keyring' | 'passphrase' | null
+  locked: boolean
+  keyring_available: boolean
+}
+
+export async function aiVaultStatus(): Promise<VaultStatus> {
+  return invoke<VaultStatus>('ai_vault_status')
+}
+
+export async function aiVaultUnlock(passphrase: string): Promise<VaultStatus> {
+  return invoke<VaultStatus>('ai_vault_unlock', { passphrase })
+}
+
+// --- AI profiles ---
+
+export interface AiProfile {
+  id: string
+  name: string
+  config: AiConfig
+  created_at: number
+  updated_at: number
+}
+
+export interface ProfileSet {
+  profiles: AiProfile[]
+  default_id: string | null
+  // Pinned profile id per workspace path
+  workspaces: Record<string, string>
+}
+
+export async function aiProfileList(): Promise<ProfileSet> {
+  return invoke<ProfileSet>('ai_profile_list')
+}
+
+export async function aiProfileCreate(name: string, config: AiConfig): Promise<AiProfile> {
+  return invoke<AiProfile>('ai_profile_create', { name, config })
+}
+
+export async function aiProfileUpdate(id: string, name?: string, config?: AiConfig): Promise<AiProfile> {
+  return invoke<AiProfile>('ai_profile_update', { id, name: name ?? null, config: config ?? null })
+}
+
+export async function aiProfileDelete(id: string): Promise<boolean> {
+  return invoke<boolean>('ai_profile_delete', { id })
+}
+
+export async function aiProfileSetDefault(id: string | null): Promise<void> {
+  return invoke<void>('ai_profile_set_default', { id })
+}
+
+export async function aiProfilePinWorkspace(workspace: string, id: string | null): Promise<void> {
+  return invoke<void>('ai_profile_pin_workspace', { workspace, id })
+}
+
+/** The profile for a workspace: its pinned one, else the default */
+export function profileForWorkspace(set: ProfileSet, workspace?: string): AiProfile | null {
+  const id = (workspace && set.workspaces[workspace.replace(/[\\/]+$/, '')]) || set.default_id
+  return set.profiles.find(p => p.id === id) || null
+}
+
+export async function aiComplete(config: AiConfig, prompt: string, maxTokens: number, timeoutSecs?: number): Promise<string> {
+  return invoke<string>('ai_complete', { config, prompt, maxTokens, timeoutSecs: timeoutSecs ?? null })
+}
+
 export async function aiTestConnection(config: AiConfig): Promise<string> {
   return invoke<string>('ai_test_connection', { config })
 }
 
+export async function aiListLocalModels(apiUrl: string): Promise<LocalModelInfo[]> {
+  return invoke<LocalModelInfo[]>('ai_list_local_models', { apiUrl })
+}
+
+export interface UsageTotal {
+  key: string
+  requests: number
+  prompt_tokens: number
+  completion_tokens: number
+  cached_tokens: number
+  cost: number
+}
+
+export interface UsageRecord {
+  timestamp: number
+  session_id: string
+  round: number
+  model: string
+  workspace: string | null
+  prompt_tokens: number
+  completion_tokens: number
+  cached_tokens: number
+  cost: number
+  kind?: string
+  subagent?: string
+}
+
+export async function aiUsageSummary(groupBy: 'day' | 'workspace' | 'model' | 'session', since?: string, workspace?: string): Promise<UsageTotal[]> {
+  return invoke<UsageTotal[]>('ai_usage_summary', { groupBy, since: since || null, workspace: workspace || null })
+}
+
+export async function aiUsageSession(sessionId: string): Promise<UsageRecord[]> {
+  return invoke<UsageRecord[]>('ai_usage_session', { sessionId })
+}
+
 export async function aiTestSearch(provider: string, apiKey: string): Promise<string> {
   return invoke<string>('ai_test_search', { provider, apiKey })
 }
 
-export async function aiChat(sessionId: string, messages: ChatMessage[], config: AiConfig, deepMode?: boolean, cwd?: string, currentSkillId?: string): Promise<void> {
-  return invoke<void>('ai_chat', { sessionId, messages, config, deepMode: deepMode || false, cwd: cwd || '', currentSkillId: currentSkillId || null })
+// With a profile id the backend uses that profile's config; with neither, the workspace's pinned or the default profile
+export async function aiChat(sessionId: string, messages: ChatMessage[], config: AiConfig | null, deepMode?: boolean, cwd?: string, currentSkillId?: string, profileId?: string): Promise<void> {
+  return invoke<void>('ai_chat', { sessionId, messages, config, profileId: profileId || null, deepMode: deepMode || false, cwd: cwd || '', currentSkillId: currentSkillId || null })
+}
+
+export interface SessionMeta {
+  id: string
+  title: string
+  workspace: string | null
+  skill_id: string
+  created_at: number
+  updated_at: number
+  message_count: number
+  parent_id?: string | null
+  forked_at?: number | null
+}
+
+export interface ChatSession extends SessionMeta {
+  messages: ChatMessage[]
+  todos?: TodoItem[]
+}
+
+// Payload of the "todos" stream event
+export interface TodoItem {
+  id: string
+  text: string
+  status: 'pending' | 'in_progress' | 'done'
+}
+
+export async function aiSessionList(workspace?: string): Promise<SessionMeta[]> {
+  return invoke<SessionMeta[]>('ai_session_list', { workspace: workspace || null })
+}
+
+export async function aiSessionLoad(id: string): Promise<ChatSession> {
+  return invoke<ChatSession>('ai_session_load', { id })
+}
+
+export async function aiSessionContinue(sessionId: string, messages: ChatMessage[], config: AiConfig | null, deepMode?: boolean, profileId?: string): Promise<void> {
+  return invoke<void>('ai_session_continue', { sessionId, messages, config, profileId: profileId || null, deepMode: deepMode || false })
+}
+
+export async function aiSessionRename(id: string, title: string): Promise<SessionMeta> {
+  return invoke<SessionMeta>('ai_session_rename', { id, title })
+}
+
+export async function aiSessionFork(id: string, messageCount: number): Promise<SessionMeta> {
+  return invoke<SessionMeta>('ai_session_fork', { id, messageCount })
+}
+
+export async function aiSessionDelete(id: string): Promise<void> {
+  return invoke<void>('ai_session_delete', { id })
+}
+
+// --- AI Permission Rules ---
+
+export type PermissionDecision = 'allow' | 'ask' | 'deny'
+export type PermissionScope = 'workspace' | 'global'
+
+export interface PermissionRule {
+  id?: string
+  tool: string
+  command?: string
+  path?: string
+  decision: PermissionDecision
+  created_at?: number
+}
+
+export interface ScopedPermissionRule extends PermissionRule {
+  scope: PermissionScope
+  workspace?: string
+}
+
+export async function aiPermissionList(workspace?: string): Promise<ScopedPermissionRule[]> {
+  return invoke<ScopedPermissionRule[]>('ai_permission_list', { workspace: workspace || null })
+}
+
+export async function aiPermissionAdd(scope: PermissionScope, rule: PermissionRule, workspace?: string): Promise<PermissionRule> {
+  return invoke<PermissionRule>('ai_permission_add', { scope, workspace: workspace || null, rule })
+}
+
+export async function aiPermissionRevoke(id: string): Promise<boolean> {
+  return invoke<boolean>('ai_permission_revoke', { id })
+}
+
+// --- AI Tool Confirmations ---
+
+export interface ConfirmRequest {
+  request_id: string
+  session_id: string
+  tool: string
+  command: string
+  created_at: number
+}
+
+export async function shellConfirmResponse(requestId: string, approved: boolean, sessionId?: string): Promise<void> {
+  return invoke<void>('shell_confirm_response', { requestId, sessionId: sessionId || null, approved })
+}
+
+export async function aiPendingConfirmations(sessionId?: string): Promise<ConfirmRequest[]> {
+  return invoke<ConfirmRequest[]>('ai_pending_confirmations', { sessionId: sessionId || null })
 }
 
 export interface MemoryEntry {